@@ -0,0 +1,160 @@
+//! Stands in for ffmpeg in the integration tests, so runs can be driven end to end without a real
+//! one.
+//!
+//! It answers `-version` and `-encoders` like ffmpeg does, prints a `Duration:` for a lone `-i`,
+//! and for anything with an output writes a small file there, reporting `-progress` on stdout along
+//! the way. What it was run with is appended to a log, one JSON array of arguments per line.
+//!
+//! It's set up by `KEY=VALUE` lines in a `fake-ffmpeg.env` next to the path it was started as, so
+//! each test can have its own copy, and by environment variables of the same names, which win:
+//!
+//! - `FAKE_FFMPEG_LOG`: the log to append to, `fake-ffmpeg.log` next to it by default.
+//! - `FAKE_FFMPEG_EXIT`: the status to exit with after writing an output, 0 by default.
+//! - `FAKE_FFMPEG_FAIL_ON`: only exit with that status when an argument contains this.
+//! - `FAKE_FFMPEG_SLEEP_MS`: how long to take over an output, with progress reported meanwhile.
+//! - `FAKE_FFMPEG_DURATION`: the length of every input, in seconds, 10 by default.
+
+use std::{
+  collections::HashMap,
+  fs::{self, OpenOptions},
+  io::Write,
+  path::{Path, PathBuf},
+  process::ExitCode,
+  time::{Duration, Instant},
+};
+
+const CONFIG_FILE_NAME: &str = "fake-ffmpeg.env";
+const LOG_FILE_NAME: &str = "fake-ffmpeg.log";
+/// How often progress is reported while sleeping.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The settings from the config file next to `program`, with the environment's on top.
+fn load_config(program: &Path) -> HashMap<String, String> {
+  let mut config: HashMap<String, String> = program
+    .parent()
+    .and_then(|dir| fs::read_to_string(dir.join(CONFIG_FILE_NAME)).ok())
+    .unwrap_or_default()
+    .lines()
+    .filter_map(|line| line.split_once('='))
+    .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+    .collect();
+  config.extend(std::env::vars().filter(|(key, _)| key.starts_with("FAKE_FFMPEG_")));
+  config
+}
+
+fn format_timestamp(secs: f64) -> String {
+  let whole = secs as u64;
+  format!(
+    "{:02}:{:02}:{:02}.{:02}",
+    whole / 3600,
+    whole / 60 % 60,
+    whole % 60,
+    ((secs - whole as f64) * 100.0).round() as u64
+  )
+}
+
+fn main() -> ExitCode {
+  let mut args = std::env::args();
+  let program = PathBuf::from(args.next().unwrap_or_default());
+  let args: Vec<String> = args.collect();
+  let config = load_config(&program);
+  let setting = |key: &str| config.get(key).filter(|value| !value.is_empty());
+
+  let log = setting("FAKE_FFMPEG_LOG")
+    .map(PathBuf::from)
+    .unwrap_or_else(|| program.with_file_name(LOG_FILE_NAME));
+  if let Ok(mut log) = OpenOptions::new().create(true).append(true).open(log) {
+    let _ = writeln!(log, "{}", serde_json::to_string(&args).unwrap_or_default());
+  }
+
+  if args.iter().any(|arg| arg == "-version") {
+    println!("ffmpeg version 6.0-fake Copyright (c) 2000-2023 the FFmpeg developers");
+    return ExitCode::SUCCESS;
+  }
+  if args.iter().any(|arg| arg == "-encoders") {
+    println!("Encoders:\n A..... = Audio\n ------");
+    for encoder in ["flac", "pcm_s16le", "aac", "alac"] {
+      println!(" A....D {encoder:<20} {encoder}");
+    }
+    return ExitCode::SUCCESS;
+  }
+
+  let duration = setting("FAKE_FFMPEG_DURATION")
+    .and_then(|value| value.parse::<f64>().ok())
+    .unwrap_or(10.0);
+  // Like ffmpeg, the output is whatever comes last that isn't an input
+  let output = match args.as_slice() {
+    [.., flag, _] if flag == "-i" => None,
+    [.., output] if output == "-" || !output.starts_with('-') => Some(output.as_str()),
+    _ => None,
+  };
+  let Some(output) = output else {
+    eprintln!("Input #0, flac, from 'input':");
+    eprintln!(
+      "  Duration: {}, start: 0.000000, bitrate: 1 kb/s",
+      format_timestamp(duration)
+    );
+    eprintln!("At least one output file must be specified");
+    return ExitCode::FAILURE;
+  };
+
+  let progress = args.iter().any(|arg| arg == "-progress");
+  let report = |out_time: f64, end: bool| {
+    if progress {
+      let state = if end { "end" } else { "continue" };
+      println!(
+        "out_time_us={}\nprogress={state}",
+        (out_time * 1_000_000.0) as u64
+      );
+    }
+  };
+
+  // Something is written straight away, so a run stopped partway leaves a partial file behind
+  let mut file = match output {
+    "-" => None,
+    path => match fs::File::create(path) {
+      Ok(file) => Some(file),
+      Err(e) => {
+        eprintln!("{path}: {e}");
+        return ExitCode::FAILURE;
+      }
+    },
+  };
+  if let Some(file) = &mut file {
+    let _ = writeln!(file, "fake ffmpeg output");
+  }
+
+  let sleep = Duration::from_millis(
+    setting("FAKE_FFMPEG_SLEEP_MS")
+      .and_then(|value| value.parse().ok())
+      .unwrap_or(0),
+  );
+  let start = Instant::now();
+  while start.elapsed() < sleep {
+    let fraction = start.elapsed().as_secs_f64() / sleep.as_secs_f64();
+    report(duration * fraction, false);
+    std::thread::sleep(PROGRESS_INTERVAL.min(sleep.saturating_sub(start.elapsed())));
+  }
+
+  let status = setting("FAKE_FFMPEG_EXIT")
+    .and_then(|value| value.parse::<u8>().ok())
+    .unwrap_or(0);
+  let fails = status != 0
+    && setting("FAKE_FFMPEG_FAIL_ON")
+      .is_none_or(|needle| args.iter().any(|arg| arg.contains(needle.as_str())));
+  if fails {
+    eprintln!("Error while encoding {output}: fake failure");
+    return ExitCode::from(status);
+  }
+
+  if let Some(file) = &mut file {
+    let inputs: Vec<&str> = args
+      .windows(2)
+      .filter(|pair| pair[0] == "-i")
+      .map(|pair| pair[1].as_str())
+      .collect();
+    let _ = writeln!(file, "from {}", inputs.join(", "));
+  }
+  report(duration, true);
+  ExitCode::SUCCESS
+}
@@ -0,0 +1,150 @@
+use std::{
+  fs::{self, File},
+  io::{self, Read, Seek},
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime},
+};
+
+/// Name of the folder inside the platform cache directory that holds every unpacked pack.
+const CACHE_DIR_NAME: &str = "otterpack";
+/// Touched every time a cached pack is used, so eviction can tell which ones are stale.
+const LAST_USED_FILE: &str = ".last-used";
+/// Folder in the cache that ffmpeg from a tools pack is unpacked into, so recordings that don't
+/// bundle their own can share it even once the tools pack is gone.
+const TOOLS_DIR_NAME: &str = "tools";
+/// Cached packs that haven't been used for this long are removed on startup.
+pub const MAX_CACHE_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// The folders packs are unpacked into.
+///
+/// [`Dirs::platform`] is what the app uses, and is also what [`Default`] gives. Anything else,
+/// like tests or a server with its own layout, can point both folders somewhere of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dirs {
+  /// The folder unpacked packs are cached in, or `None` to unpack every pack into a temp folder.
+  pub cache: Option<PathBuf>,
+  /// The folder temp folders are made in, when a pack isn't cached or the cache can't be used.
+  pub temp: PathBuf,
+}
+
+impl Dirs {
+  /// The platform's usual places: an `otterpack` folder in `%LOCALAPPDATA%` on Windows,
+  /// `~/Library/Caches` on macOS and `$XDG_CACHE_HOME` (or `~/.cache`) elsewhere, and the
+  /// system temp folder.
+  pub fn platform() -> Self {
+    Self {
+      cache: dirs::cache_dir().map(|dir| dir.join(CACHE_DIR_NAME)),
+      temp: std::env::temp_dir(),
+    }
+  }
+
+  /// The folder ffmpeg from a tools pack is cached in, if there's a cache.
+  pub fn tools(&self) -> Option<PathBuf> {
+    self.cache.as_ref().map(|cache| cache.join(TOOLS_DIR_NAME))
+  }
+}
+
+impl Default for Dirs {
+  fn default() -> Self {
+    Self::platform()
+  }
+}
+
+/// Identifies the contents of an archive by the names, sizes and CRCs in its central directory.
+///
+/// Reading the central directory is cheap even for packs that are gigabytes in size, and any
+/// change to a file in the pack changes its CRC.
+pub fn payload_key<R: Read + Seek>(
+  archive: &mut zip::ZipArchive<R>,
+) -> zip::result::ZipResult<String> {
+  let mut hasher = crc32fast::Hasher::new();
+  let mut total_size = 0;
+  for i in 0..archive.len() {
+    let file = archive.by_index_raw(i)?;
+    hasher.update(file.name().as_bytes());
+    hasher.update(&[0]);
+    hasher.update(&file.size().to_le_bytes());
+    hasher.update(&file.crc32().to_le_bytes());
+    total_size += file.size();
+  }
+  Ok(format!(
+    "{:08x}-{}-{:x}",
+    hasher.finalize(),
+    archive.len(),
+    total_size
+  ))
+}
+
+/// Records that the cached pack in `dir` was just used.
+pub fn mark_used(dir: &Path) -> io::Result<()> {
+  fs::write(dir.join(LAST_USED_FILE), b"")
+}
+
+/// Whether the file at `path` has the expected size and CRC, so it can be reused as is.
+pub fn cached_file_matches(path: &Path, size: u64, crc32: u32) -> bool {
+  let Ok(mut file) = File::open(path) else {
+    return false;
+  };
+  if file.metadata().map(|m| m.len()).ok() != Some(size) {
+    return false;
+  }
+
+  let mut hasher = crc32fast::Hasher::new();
+  let mut buffer = vec![0u8; 256 * 1024];
+  loop {
+    match file.read(&mut buffer) {
+      Ok(0) => break,
+      Ok(read) => hasher.update(&buffer[..read]),
+      Err(_) => return false,
+    }
+  }
+  hasher.finalize() == crc32
+}
+
+/// Removes packs cached in `dirs` that haven't been used for longer than `max_age`, returning how
+/// many were removed.
+pub fn evict_stale(dirs: &Dirs, max_age: Duration) -> io::Result<usize> {
+  let Some(root) = &dirs.cache else {
+    return Ok(0);
+  };
+  let entries = match fs::read_dir(root) {
+    Ok(entries) => entries,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+    Err(e) => return Err(e),
+  };
+
+  let now = SystemTime::now();
+  let mut removed = 0;
+  for entry in entries {
+    let path = entry?.path();
+    if !path.is_dir() {
+      continue;
+    }
+    // Packs that were never marked fall back to the folder's own timestamp
+    let last_used = fs::metadata(path.join(LAST_USED_FILE))
+      .or_else(|_| fs::metadata(&path))
+      .and_then(|m| m.modified());
+    let stale = last_used
+      .ok()
+      .and_then(|t| now.duration_since(t).ok())
+      .is_some_and(|age| age > max_age);
+    if stale {
+      match fs::remove_dir_all(&path) {
+        Ok(()) => removed += 1,
+        Err(e) => println!("Failed to remove cached pack {}: {}", path.display(), e),
+      }
+    }
+  }
+  Ok(removed)
+}
+
+/// Removes every pack cached in `dirs`.
+pub fn clear(dirs: &Dirs) -> io::Result<()> {
+  let Some(root) = &dirs.cache else {
+    return Ok(());
+  };
+  match fs::remove_dir_all(root) {
+    Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+    _ => Ok(()),
+  }
+}
@@ -0,0 +1,251 @@
+use strum::{EnumIter, IntoEnumIterator};
+
+use crate::{ProcessError, ProcessReport};
+
+/// How a run from the command line ended, which decides the code the process exits with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum CliExit {
+  Success,
+  /// Anything without a code of its own.
+  Failed,
+  BadArguments,
+  NoRecording,
+  Ffmpeg,
+  TracksFailed,
+  Cancelled,
+  OutputUnusable,
+  Password,
+}
+
+impl CliExit {
+  pub fn code(&self) -> i32 {
+    match self {
+      CliExit::Success => 0,
+      CliExit::Failed => 1,
+      // The same code clap exits with when it can't parse the arguments
+      CliExit::BadArguments => 2,
+      CliExit::NoRecording => 3,
+      CliExit::Ffmpeg => 4,
+      CliExit::TracksFailed => 5,
+      CliExit::Cancelled => 6,
+      CliExit::OutputUnusable => 7,
+      CliExit::Password => 8,
+    }
+  }
+
+  pub fn description(&self) -> &'static str {
+    match self {
+      CliExit::Success => "Every track was converted or already done",
+      CliExit::Failed => "Something else went wrong",
+      CliExit::BadArguments => "The arguments or a config file couldn't be understood",
+      CliExit::NoRecording => "There's no recording, or no tracks in it to convert",
+      CliExit::Ffmpeg => "ffmpeg is missing, broken or can't create the format",
+      CliExit::TracksFailed => "The run finished, but one or more tracks failed",
+      CliExit::Cancelled => "The run was cancelled",
+      CliExit::OutputUnusable => "The output folder can't be used or is out of space",
+      CliExit::Password => "The recording's password is missing or wrong",
+    }
+  }
+
+  /// The exit for a run that stopped with `error`.
+  pub fn for_error(error: &ProcessError) -> Self {
+    match error {
+      ProcessError::NoTracksFound | ProcessError::SelectedTracksMissing => CliExit::NoRecording,
+      ProcessError::FfmpegMissing
+      | ProcessError::FfmpegFailed(_)
+      | ProcessError::FfmpegUnusable(_) => CliExit::Ffmpeg,
+      ProcessError::Cancelled => CliExit::Cancelled,
+      ProcessError::OutputNotWritable { .. }
+      | ProcessError::OutputUnusable(_)
+      | ProcessError::OutputExists(_)
+      | ProcessError::NotEnoughSpace { .. } => CliExit::OutputUnusable,
+      ProcessError::PasswordRequired | ProcessError::WrongPassword => CliExit::Password,
+      ProcessError::Io { .. } | ProcessError::NotEnoughSpaceToUnpack { .. } => CliExit::Failed,
+      ProcessError::Other(e) => CliExit::for_anyhow(e),
+    }
+  }
+
+  /// The exit for a run that stopped with `error`, going by the [`ProcessError`] inside it.
+  pub fn for_anyhow(error: &anyhow::Error) -> Self {
+    error
+      .downcast_ref::<ProcessError>()
+      .map_or(CliExit::Failed, CliExit::for_error)
+  }
+
+  /// The exit for a run that got to the end.
+  pub fn for_report(report: &ProcessReport) -> Self {
+    if report.failed.is_empty() {
+      CliExit::Success
+    } else {
+      CliExit::TracksFailed
+    }
+  }
+
+  /// The exit for a batch of runs that ended with `exits`: success if every run succeeded, the
+  /// exit the others have in common if they share one, or [`CliExit::Failed`] if they don't. A
+  /// cancelled batch is always [`CliExit::Cancelled`].
+  pub fn for_batch(exits: impl IntoIterator<Item = CliExit>) -> Self {
+    let mut exits = exits.into_iter().filter(|exit| *exit != CliExit::Success);
+    let Some(first) = exits.next() else {
+      return CliExit::Success;
+    };
+    exits.fold(first, |combined, exit| match (combined, exit) {
+      (CliExit::Cancelled, _) | (_, CliExit::Cancelled) => CliExit::Cancelled,
+      (combined, exit) if combined == exit => combined,
+      _ => CliExit::Failed,
+    })
+  }
+
+  /// Every code and what it means, for the end of `--help`.
+  pub fn help() -> String {
+    let rows: Vec<String> = CliExit::iter()
+      .map(|exit| format!("  {}  {}", exit.code(), exit.description()))
+      .collect();
+    format!("Exit codes:\n{}", rows.join("\n"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{io, path::PathBuf};
+
+  use super::*;
+  use crate::{FfmpegError, TrackFailure};
+
+  fn io_error() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "denied")
+  }
+
+  #[test]
+  fn errors_map_to_their_exits() {
+    let ffmpeg_error = FfmpegError {
+      message: "ffmpeg failed".to_string(),
+      status: None,
+      stderr: String::new(),
+    };
+    let cases = [
+      (ProcessError::FfmpegMissing, CliExit::Ffmpeg),
+      (ProcessError::FfmpegFailed(ffmpeg_error), CliExit::Ffmpeg),
+      (
+        ProcessError::FfmpegUnusable("old".to_string()),
+        CliExit::Ffmpeg,
+      ),
+      (
+        ProcessError::Io {
+          path: PathBuf::from("track.flac"),
+          source: io_error(),
+        },
+        CliExit::Failed,
+      ),
+      (ProcessError::NoTracksFound, CliExit::NoRecording),
+      (ProcessError::SelectedTracksMissing, CliExit::NoRecording),
+      (ProcessError::Cancelled, CliExit::Cancelled),
+      (
+        ProcessError::OutputNotWritable {
+          path: PathBuf::from("out"),
+          source: io_error(),
+        },
+        CliExit::OutputUnusable,
+      ),
+      (
+        ProcessError::OutputUnusable("temp".to_string()),
+        CliExit::OutputUnusable,
+      ),
+      (
+        ProcessError::OutputExists("craig.flac".to_string()),
+        CliExit::OutputUnusable,
+      ),
+      (
+        ProcessError::NotEnoughSpace {
+          needed: 2,
+          available: 1,
+        },
+        CliExit::OutputUnusable,
+      ),
+      (
+        ProcessError::NotEnoughSpaceToUnpack {
+          dir: PathBuf::from("temp"),
+          needed: 2,
+          available: 1,
+        },
+        CliExit::Failed,
+      ),
+      (ProcessError::PasswordRequired, CliExit::Password),
+      (ProcessError::WrongPassword, CliExit::Password),
+      (
+        ProcessError::Other(anyhow::anyhow!("something else")),
+        CliExit::Failed,
+      ),
+      // A ProcessError passed along inside another error keeps its own exit
+      (
+        ProcessError::Other(ProcessError::WrongPassword.into()),
+        CliExit::Password,
+      ),
+    ];
+    for (error, exit) in cases {
+      assert_eq!(CliExit::for_error(&error), exit, "{error:?}");
+    }
+  }
+
+  #[test]
+  fn anyhow_errors_go_by_the_process_error_inside() {
+    let error = anyhow::Error::from(ProcessError::Cancelled).context("while converting");
+    assert_eq!(CliExit::for_anyhow(&error), CliExit::Cancelled);
+    assert_eq!(
+      CliExit::for_anyhow(&anyhow::anyhow!("bad config")),
+      CliExit::Failed
+    );
+  }
+
+  #[test]
+  fn reports_with_failed_tracks_say_so() {
+    let mut report = ProcessReport {
+      converted: vec!["1-alice.flac".to_string()],
+      ..Default::default()
+    };
+    assert_eq!(CliExit::for_report(&report), CliExit::Success);
+    assert_eq!(
+      CliExit::for_report(&ProcessReport::default()),
+      CliExit::Success
+    );
+
+    report.failed.push(TrackFailure {
+      filename: "2-bob.flac".to_string(),
+      message: "ffmpeg failed".to_string(),
+      stderr: None,
+    });
+    assert_eq!(CliExit::for_report(&report), CliExit::TracksFailed);
+  }
+
+  #[test]
+  fn batches_combine_their_exits() {
+    use CliExit::*;
+
+    let cases: [(&[CliExit], CliExit); 8] = [
+      (&[], Success),
+      (&[Success, Success], Success),
+      (&[Success, Password], Password),
+      (&[Ffmpeg, Success, Ffmpeg], Ffmpeg),
+      (&[Ffmpeg, TracksFailed], Failed),
+      (&[Ffmpeg, Cancelled, TracksFailed], Cancelled),
+      (&[Success, Cancelled], Cancelled),
+      (&[NoRecording, OutputUnusable, NoRecording], Failed),
+    ];
+    for (exits, combined) in cases {
+      assert_eq!(
+        CliExit::for_batch(exits.iter().copied()),
+        combined,
+        "{exits:?}"
+      );
+    }
+  }
+
+  #[test]
+  fn every_exit_has_its_own_code() {
+    let mut codes: Vec<i32> = CliExit::iter().map(|exit| exit.code()).collect();
+    codes.sort();
+    codes.dedup();
+    assert_eq!(codes.len(), CliExit::iter().count());
+    assert_eq!(CliExit::Success.code(), 0);
+  }
+}
@@ -0,0 +1,434 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, process::Stdio, sync::Arc, time::Duration};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncRead, BufReader},
+  process::Command,
+};
+
+use crate::ProcessReport;
+
+/// How long the output of a finished command is waited on, for when something it started in the
+/// background still holds it open.
+const OUTPUT_GRACE: Duration = Duration::from_secs(1);
+
+/// A command to run once a run ends, like a script that uploads the outputs somewhere.
+///
+/// The command is whatever the user typed in, and runs with their permissions just as it would
+/// from a terminal. Nothing about the recording is ever put into the command itself, only into
+/// the `OUTPUT_DIR`, `STATUS` and `REPORT_PATH` environment variables it runs with, so a track name
+/// can't sneak anything into it. Without `use_shell` the command is split up by [`split_command`]
+/// and run directly, so nothing in it is expanded. With it, the command goes to `sh -c` or
+/// `cmd /C` as it is, for pipes and redirects.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompletionHook {
+  /// The command, or empty for none.
+  pub command: String,
+  pub use_shell: bool,
+  /// How long the command may run before it's stopped, 0 to wait forever.
+  pub timeout_secs: u64,
+}
+
+impl Default for CompletionHook {
+  fn default() -> Self {
+    Self {
+      command: String::new(),
+      use_shell: false,
+      timeout_secs: 10 * 60,
+    }
+  }
+}
+
+impl CompletionHook {
+  /// Whether there's a command to run.
+  pub fn is_set(&self) -> bool {
+    !self.command.trim().is_empty()
+  }
+}
+
+/// How a run ended, as a [`CompletionHook`] is told in `STATUS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+  Success,
+  /// Some tracks or recordings were converted, and some failed.
+  Partial,
+  /// Nothing was converted, or the run stopped with an error.
+  Failed,
+}
+
+impl RunStatus {
+  pub fn name(&self) -> &'static str {
+    match self {
+      RunStatus::Success => "success",
+      RunStatus::Partial => "partial",
+      RunStatus::Failed => "failed",
+    }
+  }
+
+  /// The status of a run that got to the end with `report`.
+  pub fn for_report(report: &ProcessReport) -> Self {
+    if report.failed.is_empty() {
+      RunStatus::Success
+    } else if report.converted.is_empty() && report.skipped.is_empty() {
+      RunStatus::Failed
+    } else {
+      RunStatus::Partial
+    }
+  }
+
+  /// The status of several runs together, like the recordings of a batch.
+  pub fn combine(statuses: impl IntoIterator<Item = RunStatus>) -> Self {
+    statuses
+      .into_iter()
+      .reduce(|combined, status| match (combined, status) {
+        (a, b) if a == b => a,
+        _ => RunStatus::Partial,
+      })
+      .unwrap_or(RunStatus::Success)
+  }
+}
+
+/// Splits a command into a program and its arguments on whitespace, with single or double quotes
+/// keeping spaces together.
+///
+/// Backslashes aren't escapes, so Windows paths can be written as they are.
+pub fn split_command(command: &str) -> Vec<String> {
+  let mut parts = Vec::new();
+  let mut current = String::new();
+  let mut in_part = false;
+  let mut quote = None;
+  for c in command.chars() {
+    match (quote, c) {
+      (Some(q), c) if c == q => quote = None,
+      (Some(_), c) => current.push(c),
+      (None, '"' | '\'') => {
+        quote = Some(c);
+        in_part = true;
+      }
+      (None, c) if c.is_whitespace() => {
+        if in_part {
+          parts.push(std::mem::take(&mut current));
+          in_part = false;
+        }
+      }
+      (None, c) => {
+        current.push(c);
+        in_part = true;
+      }
+    }
+  }
+  if in_part {
+    parts.push(current);
+  }
+  parts
+}
+
+/// Writes `report` into a temp file as JSON, for a [`CompletionHook`]'s `REPORT_PATH`. The file is
+/// removed when the returned path is dropped.
+pub fn write_temp_report(report: &(impl Serialize + ?Sized)) -> anyhow::Result<tempfile::TempPath> {
+  let mut file = tempfile::Builder::new()
+    .prefix("otterpack-report-")
+    .suffix(".json")
+    .tempfile()
+    .context("Failed to create a file for the report")?;
+  serde_json::to_writer_pretty(&mut file, report).context("Failed to write the report")?;
+  Ok(file.into_temp_path())
+}
+
+/// Passes each line read from `output` to `on_line`.
+async fn forward_lines(output: impl AsyncRead + Unpin, on_line: Arc<impl Fn(&str)>) {
+  let mut reader = BufReader::new(output);
+  let mut line = Vec::new();
+  while let Ok(read) = reader.read_until(b'\n', &mut line).await {
+    if read == 0 {
+      break;
+    }
+    on_line(String::from_utf8_lossy(&line).trim_end());
+    line.clear();
+  }
+}
+
+/// Runs `hook` for a run into `output_dir` that ended with `status`, passing every line it prints
+/// to `on_line`.
+///
+/// `report_path` is a file with the run's report as JSON, if there is one. Fails if the command
+/// can't be started, exits unsuccessfully or runs out of time, in which case it's killed.
+pub async fn run_completion_hook(
+  hook: &CompletionHook,
+  output_dir: &Path,
+  status: RunStatus,
+  report_path: Option<&Path>,
+  on_line: impl Fn(&str) + Send + Sync + 'static,
+) -> anyhow::Result<()> {
+  let mut command = if hook.use_shell {
+    #[cfg(windows)]
+    let command = {
+      let mut command = Command::new("cmd");
+      // cmd has quoting rules of its own, so the command is passed on untouched
+      command.arg("/C").raw_arg(&hook.command);
+      command
+    };
+    #[cfg(not(windows))]
+    let command = {
+      let mut command = Command::new("sh");
+      command.arg("-c").arg(&hook.command);
+      command
+    };
+    command
+  } else {
+    let parts = split_command(&hook.command);
+    let Some((program, args)) = parts.split_first() else {
+      anyhow::bail!("There's no command to run");
+    };
+    let mut command = Command::new(program);
+    command.args(args);
+    command
+  };
+  command
+    .env("OUTPUT_DIR", output_dir)
+    .env("STATUS", status.name())
+    .kill_on_drop(true)
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+  match report_path {
+    Some(path) => command.env("REPORT_PATH", path),
+    None => command.env_remove("REPORT_PATH"),
+  };
+  crate::platform::configure_child(&mut command, false);
+
+  let mut child = command
+    .spawn()
+    .context(format!("Failed to run {}", hook.command))?;
+  let on_line = Arc::new(on_line);
+  let stdout = child.stdout.take().expect("stdout is piped");
+  let stderr = child.stderr.take().expect("stderr is piped");
+  let output = tokio::spawn(async move {
+    tokio::join!(
+      forward_lines(stdout, on_line.clone()),
+      forward_lines(stderr, on_line)
+    );
+  });
+
+  let exit = match hook.timeout_secs {
+    0 => child.wait().await,
+    secs => match tokio::time::timeout(Duration::from_secs(secs), child.wait()).await {
+      Ok(exit) => exit,
+      Err(_) => {
+        let _ = child.kill().await;
+        output.abort();
+        anyhow::bail!("The command was stopped after running for {secs} seconds");
+      }
+    },
+  }
+  .context("Failed to wait for the command")?;
+  if tokio::time::timeout(OUTPUT_GRACE, output).await.is_err() {
+    println!("The command left something running that still prints, which isn't shown");
+  }
+  anyhow::ensure!(exit.success(), "The command failed with {exit}");
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+  };
+
+  use super::*;
+
+  #[test]
+  fn commands_split_on_whitespace_outside_quotes() {
+    assert_eq!(
+      split_command("  upload.sh --to 'my server'  \"a b\"c "),
+      ["upload.sh", "--to", "my server", "a bc"]
+    );
+    assert_eq!(
+      split_command(r#"C:\Tools\upload.exe "C:\My Recordings""#),
+      [r"C:\Tools\upload.exe", r"C:\My Recordings"]
+    );
+    // Quotes of the other kind are kept, and an empty pair is still an argument
+    assert_eq!(
+      split_command(r#"say "it's" '' done"#),
+      ["say", "it's", "", "done"]
+    );
+    // An unclosed quote runs to the end
+    assert_eq!(split_command("say 'hello  there"), ["say", "hello  there"]);
+    assert!(split_command(" \t\n").is_empty());
+  }
+
+  /// A stub for a user's script, printing its first argument and what it was told about the run.
+  #[cfg(unix)]
+  const REPORTING_SCRIPT: &str = r#"echo "arg=$1"
+echo "dir=$OUTPUT_DIR"
+echo "status=$STATUS"
+echo "report=$REPORT_PATH" >&2
+"#;
+  #[cfg(windows)]
+  const REPORTING_SCRIPT: &str = "echo arg=%~1\r\necho dir=%OUTPUT_DIR%\r\necho status=%STATUS%\r\n\
+    echo report=%REPORT_PATH% 1>&2\r\n";
+
+  #[cfg(unix)]
+  const FAILING_SCRIPT: &str = "echo giving up\nexit 3\n";
+  #[cfg(windows)]
+  const FAILING_SCRIPT: &str = "echo giving up\r\nexit /b 3\r\n";
+
+  #[cfg(unix)]
+  const HANGING_SCRIPT: &str = "exec sleep 30\n";
+  #[cfg(windows)]
+  const HANGING_SCRIPT: &str = "ping -n 30 127.0.0.1 >nul\r\n";
+
+  /// Writes a script running `body` into `dir`, returning how to run it as a command.
+  #[cfg(unix)]
+  fn script(dir: &Path, body: &str) -> String {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.join("hook.sh");
+    std::fs::write(&path, format!("#!/bin/sh\n{body}")).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    format!("\"{}\"", path.display())
+  }
+
+  #[cfg(windows)]
+  fn script(dir: &Path, body: &str) -> String {
+    let path = dir.join("hook.cmd");
+    std::fs::write(&path, format!("@echo off\r\n{body}")).unwrap();
+    format!("\"{}\"", path.display())
+  }
+
+  /// Runs `command` for a successful run into `output_dir`, returning how it went and every line
+  /// it printed, sorted since stdout and stderr can interleave either way.
+  async fn run(
+    command: String,
+    use_shell: bool,
+    timeout_secs: u64,
+    output_dir: &Path,
+    report_path: Option<&Path>,
+  ) -> (anyhow::Result<()>, Vec<String>) {
+    let hook = CompletionHook {
+      command,
+      use_shell,
+      timeout_secs,
+    };
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let collected = lines.clone();
+    let result = run_completion_hook(
+      &hook,
+      output_dir,
+      RunStatus::Success,
+      report_path,
+      move |line| collected.lock().unwrap().push(line.to_string()),
+    )
+    .await;
+    let mut lines = lines.lock().unwrap().clone();
+    lines.sort();
+    (result, lines)
+  }
+
+  #[tokio::test]
+  async fn commands_are_told_about_the_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let report = dir.path().join("report.json");
+    let command = format!("{} \"two words\"", script(dir.path(), REPORTING_SCRIPT));
+    let (result, lines) = run(command, false, 0, dir.path(), Some(&report)).await;
+    result.unwrap();
+    assert_eq!(
+      lines,
+      [
+        "arg=two words".to_string(),
+        format!("dir={}", dir.path().display()),
+        format!("report={}", report.display()),
+        "status=success".to_string(),
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn failing_commands_are_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let command = script(dir.path(), FAILING_SCRIPT);
+    let (result, lines) = run(command, false, 0, dir.path(), None).await;
+    let error = result.unwrap_err().to_string();
+    assert!(error.starts_with("The command failed with"), "{error}");
+    // What it printed on the way out still gets shown
+    assert_eq!(lines, ["giving up"]);
+  }
+
+  #[tokio::test]
+  async fn slow_commands_are_stopped() {
+    let dir = tempfile::tempdir().unwrap();
+    let command = script(dir.path(), HANGING_SCRIPT);
+    let start = std::time::Instant::now();
+    let (result, _) = run(command, false, 1, dir.path(), None).await;
+    assert_eq!(
+      result.unwrap_err().to_string(),
+      "The command was stopped after running for 1 seconds"
+    );
+    assert!(start.elapsed() < Duration::from_secs(10));
+  }
+
+  #[tokio::test]
+  async fn only_the_shell_expands_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    #[cfg(unix)]
+    let command = "echo one; echo two >&2";
+    #[cfg(windows)]
+    let command = "echo one& echo two 1>&2";
+    let (result, lines) = run(command.to_string(), true, 0, dir.path(), None).await;
+    result.unwrap();
+    assert_eq!(lines, ["one", "two"]);
+
+    // Without it, the shell's syntax is just more arguments to a program that doesn't exist
+    let command = format!("missing-program-{} ; echo one", std::process::id());
+    let (result, lines) = run(command, false, 0, dir.path(), None).await;
+    assert!(result.unwrap_err().to_string().starts_with("Failed to run"),);
+    assert!(lines.is_empty());
+  }
+
+  #[tokio::test]
+  async fn empty_commands_are_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let (result, _) = run("  ".to_string(), false, 0, dir.path(), None).await;
+    assert_eq!(result.unwrap_err().to_string(), "There's no command to run");
+  }
+
+  #[test]
+  fn statuses_sum_up_reports_and_batches() {
+    let report = |converted: usize, failed: usize| ProcessReport {
+      converted: vec!["1-alice.flac".to_string(); converted],
+      failed: vec![
+        crate::TrackFailure {
+          filename: "2-bob.flac".to_string(),
+          message: "ffmpeg failed".to_string(),
+          stderr: None,
+        };
+        failed
+      ],
+      ..Default::default()
+    };
+    assert_eq!(RunStatus::for_report(&report(2, 0)), RunStatus::Success);
+    assert_eq!(RunStatus::for_report(&report(1, 1)), RunStatus::Partial);
+    assert_eq!(RunStatus::for_report(&report(0, 2)), RunStatus::Failed);
+
+    use RunStatus::*;
+    assert_eq!(RunStatus::combine([]), Success);
+    assert_eq!(RunStatus::combine([Failed, Failed]), Failed);
+    assert_eq!(RunStatus::combine([Success, Failed]), Partial);
+    assert_eq!(RunStatus::combine([Success, Success, Partial]), Partial);
+  }
+
+  #[test]
+  fn reports_are_written_for_the_command_and_removed_after() {
+    let path: PathBuf;
+    {
+      let report = write_temp_report(&ProcessReport::default()).unwrap();
+      path = report.to_path_buf();
+      let written: ProcessReport =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+      assert_eq!(written, ProcessReport::default());
+    }
+    assert!(!path.exists());
+  }
+}
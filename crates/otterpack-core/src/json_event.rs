@@ -0,0 +1,500 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error as _};
+use std::path::Path;
+
+use crate::{ProcessError, ProcessProgress, ProcessReport, ProgressInfo, TrackState};
+
+/// A progress event as printed by `--json`, one JSON object per line on stdout.
+///
+/// Every event has an `event` field naming it, the other fields depend on which one it is:
+///
+/// - `extracting`: `file`, `bytes_done` and `bytes_total` while the recording is unpacked.
+/// - `probing`: `current` and `total` while the track lengths are read.
+/// - `converting`: `file`, `current`, `total`, `file_fraction` (0 to 1, `null` when the length
+///   isn't known), `audio_done_secs` and `audio_total_secs` (`null` when any length isn't known).
+/// - `track`: `file` and `state`, which is `queued`, `started`, `finished` or `skipped`. Finished
+///   tracks also have `output_size` in bytes.
+/// - `file_failed`: `file` and `error`, for a track that couldn't be converted. The run carries on
+///   with the next one.
+/// - `packaging` and `writing_project`, with nothing else.
+/// - `log`: `file` and `line`, a line ffmpeg logged.
+/// - `finished`: `report`, the [`ProcessReport`] as an object with `converted` and `skipped` (lists
+///   of file names), `failed` (objects with `filename`, `message` and `stderr`), `outputs` (pairs
+///   of a file name and its output path) and `project`.
+/// - `error`: `message` and `causes`, a list of what led to it, when the run as a whole failed.
+///
+/// A batch of several recordings starts each one with a `job` event, with `input`, `output_path`,
+/// `current` and `total`, followed by that recording's events up to its `finished` or `error`.
+/// Once they're all done, `batch_finished` has how many recordings `succeeded`, `failed` and, if
+/// the batch was cancelled, were `not_started`.
+///
+/// Events may gain fields, and new events may be added, so unknown ones should be ignored.
+/// [`parse_progress_event`] reads a line back into the [`ProcessProgress`] it was printed for,
+/// skipping the rest.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JsonEvent<'a> {
+  Extracting {
+    file: &'a str,
+    bytes_done: u64,
+    bytes_total: u64,
+  },
+  Probing {
+    current: usize,
+    total: usize,
+  },
+  Converting {
+    file: &'a str,
+    current: usize,
+    total: usize,
+    file_fraction: Option<f32>,
+    audio_done_secs: f64,
+    audio_total_secs: Option<f64>,
+  },
+  Track {
+    file: &'a str,
+    state: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_size: Option<u64>,
+  },
+  FileFailed {
+    file: &'a str,
+    error: &'a str,
+  },
+  Packaging,
+  WritingProject,
+  Log {
+    file: &'a str,
+    line: &'a str,
+  },
+  Finished {
+    report: &'a ProcessReport,
+  },
+  Error {
+    message: String,
+    causes: Vec<String>,
+  },
+  Job {
+    input: &'a str,
+    output_path: &'a Path,
+    current: usize,
+    total: usize,
+  },
+  BatchFinished {
+    succeeded: usize,
+    failed: usize,
+    not_started: usize,
+  },
+}
+
+impl<'a> JsonEvent<'a> {
+  pub fn from_progress(progress: &'a ProcessProgress) -> Self {
+    match progress {
+      ProcessProgress::Finished(report) => JsonEvent::Finished { report },
+      ProcessProgress::Error(error) => JsonEvent::Error {
+        message: error.to_string(),
+        causes: error.causes(),
+      },
+      ProcessProgress::Extracting {
+        file,
+        bytes_done,
+        bytes_total,
+      } => JsonEvent::Extracting {
+        file,
+        bytes_done: *bytes_done,
+        bytes_total: *bytes_total,
+      },
+      ProcessProgress::Probing { current, total } => JsonEvent::Probing {
+        current: *current,
+        total: *total,
+      },
+      ProcessProgress::Converting(info) => JsonEvent::Converting {
+        file: &info.filename,
+        current: info.current,
+        total: info.total,
+        file_fraction: info.file_fraction,
+        audio_done_secs: info.audio_done_secs,
+        audio_total_secs: info.audio_total_secs,
+      },
+      ProcessProgress::Track { filename, state } => {
+        let (state, output_size) = match state {
+          TrackState::Failed { error } => {
+            return JsonEvent::FileFailed {
+              file: filename,
+              error,
+            };
+          }
+          TrackState::Queued => ("queued", None),
+          TrackState::Started => ("started", None),
+          TrackState::Finished { output_size } => ("finished", Some(*output_size)),
+          TrackState::Skipped => ("skipped", None),
+        };
+        JsonEvent::Track {
+          file: filename,
+          state,
+          output_size,
+        }
+      }
+      ProcessProgress::Packaging => JsonEvent::Packaging,
+      ProcessProgress::WritingProject => JsonEvent::WritingProject,
+      ProcessProgress::Log { file, line } => JsonEvent::Log { file, line },
+    }
+  }
+
+  /// The event for a run that failed as a whole.
+  pub fn from_error(error: &anyhow::Error) -> Self {
+    JsonEvent::Error {
+      message: error.to_string(),
+      causes: error
+        .chain()
+        .skip(1)
+        .map(|cause| cause.to_string())
+        .collect(),
+    }
+  }
+}
+
+impl Serialize for ProcessProgress {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    JsonEvent::from_progress(self).serialize(serializer)
+  }
+}
+
+/// The events that are progress, as they're read back. Anything else, like `job` or an event
+/// from a later version, is `Other`.
+#[derive(Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum ProgressEvent {
+  Extracting {
+    file: String,
+    bytes_done: u64,
+    bytes_total: u64,
+  },
+  Probing {
+    current: usize,
+    total: usize,
+  },
+  Converting(ProgressInfo),
+  Track {
+    file: String,
+    state: String,
+    output_size: Option<u64>,
+  },
+  FileFailed {
+    file: String,
+    error: String,
+  },
+  Packaging,
+  WritingProject,
+  Log {
+    file: String,
+    line: String,
+  },
+  Finished {
+    report: ProcessReport,
+  },
+  Error {
+    message: String,
+    #[serde(default)]
+    causes: Vec<String>,
+  },
+  #[serde(other)]
+  Other,
+}
+
+impl ProgressEvent {
+  /// The progress the event was printed for, `None` if it isn't progress.
+  fn into_progress(self) -> Result<Option<ProcessProgress>, String> {
+    Ok(Some(match self {
+      ProgressEvent::Extracting {
+        file,
+        bytes_done,
+        bytes_total,
+      } => ProcessProgress::Extracting {
+        file,
+        bytes_done,
+        bytes_total,
+      },
+      ProgressEvent::Probing { current, total } => ProcessProgress::Probing { current, total },
+      ProgressEvent::Converting(info) => ProcessProgress::Converting(info),
+      ProgressEvent::Track {
+        file,
+        state,
+        output_size,
+      } => {
+        let state = match (state.as_str(), output_size) {
+          ("queued", _) => TrackState::Queued,
+          ("started", _) => TrackState::Started,
+          ("finished", Some(output_size)) => TrackState::Finished { output_size },
+          ("finished", None) => return Err("A finished track is missing its output_size".into()),
+          ("skipped", _) => TrackState::Skipped,
+          // A state added later doesn't say anything this version can show
+          _ => return Ok(None),
+        };
+        ProcessProgress::Track {
+          filename: file,
+          state,
+        }
+      }
+      ProgressEvent::FileFailed { file, error } => ProcessProgress::Track {
+        filename: file,
+        state: TrackState::Failed { error },
+      },
+      ProgressEvent::Packaging => ProcessProgress::Packaging,
+      ProgressEvent::WritingProject => ProcessProgress::WritingProject,
+      ProgressEvent::Log { file, line } => ProcessProgress::Log { file, line },
+      ProgressEvent::Finished { report } => ProcessProgress::Finished(report),
+      ProgressEvent::Error { message, causes } => {
+        // Rebuilt innermost first, so the chain reads the same as it was printed
+        let mut chain = causes.into_iter().rev().chain(std::iter::once(message));
+        let innermost = anyhow::anyhow!(chain.next().unwrap_or_default());
+        ProcessProgress::Error(ProcessError::Other(
+          chain.fold(innermost, |error, context| error.context(context)),
+        ))
+      }
+      ProgressEvent::Other => return Ok(None),
+    }))
+  }
+}
+
+impl<'de> Deserialize<'de> for ProcessProgress {
+  /// Reads the event [`ProcessProgress`] is serialized as, failing on events that aren't progress.
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    ProgressEvent::deserialize(deserializer)?
+      .into_progress()
+      .map_err(D::Error::custom)?
+      .ok_or_else(|| D::Error::custom("The event isn't progress this version knows about"))
+  }
+}
+
+/// Reads a line of `--json` output back into the progress it reports, or `None` for an event
+/// that isn't progress, like `job`, or that was added by a later version.
+pub fn parse_progress_event(line: &str) -> anyhow::Result<Option<ProcessProgress>> {
+  serde_json::from_str::<ProgressEvent>(line)?
+    .into_progress()
+    .map_err(anyhow::Error::msg)
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::{Value, json};
+
+  use super::*;
+  use crate::TrackFailure;
+
+  /// Every kind of progress, with the JSON `--json` prints for it.
+  fn events() -> Vec<(ProcessProgress, Value)> {
+    let report = ProcessReport {
+      converted: vec!["1-alice.flac".into()],
+      skipped: vec!["2-bob.flac".into()],
+      failed: vec![TrackFailure {
+        filename: "3-carol.flac".into(),
+        message: "ffmpeg failed".into(),
+        stderr: Some("Invalid data found".into()),
+      }],
+      outputs: vec![
+        ("1-alice.flac".into(), "out/1-alice.mp3".into()),
+        ("2-bob.flac".into(), "out/2-bob.mp3".into()),
+      ],
+      project: None,
+    };
+    let track = |state| ProcessProgress::Track {
+      filename: "1-alice.flac".into(),
+      state,
+    };
+    let error = anyhow::anyhow!("No space left on device").context("Couldn't write 1-alice.mp3");
+
+    vec![
+      (
+        ProcessProgress::Extracting {
+          file: "ffmpeg.exe".into(),
+          bytes_done: 1024,
+          bytes_total: 4096,
+        },
+        json!({"event": "extracting", "file": "ffmpeg.exe", "bytes_done": 1024, "bytes_total": 4096}),
+      ),
+      (
+        ProcessProgress::Probing {
+          current: 1,
+          total: 3,
+        },
+        json!({"event": "probing", "current": 1, "total": 3}),
+      ),
+      (
+        ProcessProgress::Converting(ProgressInfo {
+          filename: "1-alice.flac".into(),
+          current: 1,
+          total: 3,
+          file_fraction: Some(0.5),
+          audio_done_secs: 15.0,
+          audio_total_secs: Some(90.0),
+        }),
+        json!({
+          "event": "converting",
+          "file": "1-alice.flac",
+          "current": 1,
+          "total": 3,
+          "file_fraction": 0.5,
+          "audio_done_secs": 15.0,
+          "audio_total_secs": 90.0,
+        }),
+      ),
+      (
+        ProcessProgress::Converting(ProgressInfo {
+          filename: "1-alice.flac".into(),
+          current: 0,
+          total: 3,
+          file_fraction: None,
+          audio_done_secs: 0.0,
+          audio_total_secs: None,
+        }),
+        json!({
+          "event": "converting",
+          "file": "1-alice.flac",
+          "current": 0,
+          "total": 3,
+          "file_fraction": null,
+          "audio_done_secs": 0.0,
+          "audio_total_secs": null,
+        }),
+      ),
+      (
+        track(TrackState::Queued),
+        json!({"event": "track", "file": "1-alice.flac", "state": "queued"}),
+      ),
+      (
+        track(TrackState::Started),
+        json!({"event": "track", "file": "1-alice.flac", "state": "started"}),
+      ),
+      (
+        track(TrackState::Finished { output_size: 2048 }),
+        json!({"event": "track", "file": "1-alice.flac", "state": "finished", "output_size": 2048}),
+      ),
+      (
+        track(TrackState::Skipped),
+        json!({"event": "track", "file": "1-alice.flac", "state": "skipped"}),
+      ),
+      (
+        track(TrackState::Failed {
+          error: "ffmpeg failed".into(),
+        }),
+        json!({"event": "file_failed", "file": "1-alice.flac", "error": "ffmpeg failed"}),
+      ),
+      (ProcessProgress::Packaging, json!({"event": "packaging"})),
+      (
+        ProcessProgress::WritingProject,
+        json!({"event": "writing_project"}),
+      ),
+      (
+        ProcessProgress::Log {
+          file: "1-alice.flac".into(),
+          line: "size=1kB".into(),
+        },
+        json!({"event": "log", "file": "1-alice.flac", "line": "size=1kB"}),
+      ),
+      (
+        ProcessProgress::Finished(report),
+        json!({
+          "event": "finished",
+          "report": {
+            "converted": ["1-alice.flac"],
+            "skipped": ["2-bob.flac"],
+            "failed": [
+              {"filename": "3-carol.flac", "message": "ffmpeg failed", "stderr": "Invalid data found"},
+            ],
+            "outputs": [["1-alice.flac", "out/1-alice.mp3"], ["2-bob.flac", "out/2-bob.mp3"]],
+            "project": null,
+          },
+        }),
+      ),
+      (
+        ProcessProgress::Error(ProcessError::Other(error)),
+        json!({
+          "event": "error",
+          "message": "Couldn't write 1-alice.mp3",
+          "causes": ["No space left on device"],
+        }),
+      ),
+    ]
+  }
+
+  #[test]
+  fn progress_is_printed_as_documented() {
+    for (progress, expected) in events() {
+      assert_eq!(serde_json::to_value(&progress).unwrap(), expected);
+    }
+  }
+
+  #[test]
+  fn printed_progress_reads_back_the_same() {
+    for (progress, expected) in events() {
+      let line = serde_json::to_string(&progress).unwrap();
+      let parsed = parse_progress_event(&line)
+        .unwrap()
+        .unwrap_or_else(|| panic!("{line} was skipped"));
+      assert_eq!(serde_json::to_value(&parsed).unwrap(), expected);
+
+      let deserialized: ProcessProgress = serde_json::from_str(&line).unwrap();
+      assert_eq!(serde_json::to_value(&deserialized).unwrap(), expected);
+    }
+  }
+
+  #[test]
+  fn errors_keep_their_chain() {
+    let line = r#"{"event":"error","message":"outer","causes":["middle","inner"]}"#;
+    let Some(ProcessProgress::Error(ProcessError::Other(error))) =
+      parse_progress_event(line).unwrap()
+    else {
+      panic!("{line} isn't an error");
+    };
+    let chain: Vec<String> = error.chain().map(ToString::to_string).collect();
+    assert_eq!(chain, ["outer", "middle", "inner"]);
+
+    // Causes are optional
+    let line = r#"{"event":"error","message":"outer"}"#;
+    assert!(matches!(
+      parse_progress_event(line).unwrap(),
+      Some(ProcessProgress::Error(_))
+    ));
+  }
+
+  #[test]
+  fn events_that_arent_progress_are_skipped() {
+    for line in [
+      r#"{"event":"job","input":"craig.zip","output_path":"out","current":1,"total":2}"#,
+      r#"{"event":"batch_finished","succeeded":2,"failed":0,"not_started":0}"#,
+      // From a later version
+      r#"{"event":"uploading","file":"1-alice.mp3","bytes_done":1}"#,
+      r#"{"event":"track","file":"1-alice.flac","state":"normalizing"}"#,
+    ] {
+      assert!(parse_progress_event(line).unwrap().is_none(), "{line}");
+      assert!(
+        serde_json::from_str::<ProcessProgress>(line).is_err(),
+        "{line}"
+      );
+    }
+  }
+
+  #[test]
+  fn new_fields_are_ignored() {
+    let line = r#"{"event":"probing","current":1,"total":3,"eta_secs":4.5}"#;
+    assert!(matches!(
+      parse_progress_event(line).unwrap(),
+      Some(ProcessProgress::Probing {
+        current: 1,
+        total: 3
+      })
+    ));
+  }
+
+  #[test]
+  fn malformed_events_are_errors() {
+    for line in [
+      "not json",
+      r#"{"file":"1-alice.flac"}"#,
+      r#"{"event":"probing","current":"one"}"#,
+      r#"{"event":"track","file":"1-alice.flac","state":"finished"}"#,
+    ] {
+      assert!(parse_progress_event(line).is_err(), "{line}");
+    }
+  }
+}
@@ -0,0 +1,39 @@
+//! Unpacking and converting Craig recordings, without any GUI.
+//!
+//! A run starts from a [`PackSource`], usually found with [`find_pack_source`]. [`run_processing`]
+//! takes it from there, unpacking the recording with [`setup_resources`] and converting it with
+//! [`process_files`], and reports back over a channel of [`RunEvent`]s.
+//!
+//! To convert a recording from another tokio app, [`run()`] does all of that with a progress
+//! callback instead of the channel, and cleans up after itself however the run ends.
+
+#![warn(clippy::all, rust_2018_idioms)]
+
+pub mod cache;
+mod exit;
+mod hook;
+mod json_event;
+mod lock;
+mod named_option;
+mod pack_format;
+mod packer;
+mod platform;
+mod probe;
+mod process;
+mod progress;
+mod run;
+mod self_extract;
+
+pub use exit::*;
+pub use hook::*;
+pub use json_event::*;
+pub use lock::*;
+pub use named_option::*;
+pub use pack_format::*;
+pub use packer::*;
+pub use platform::{default_output_dir, probe_writable};
+pub use probe::*;
+pub use process::*;
+pub use progress::*;
+pub use run::*;
+pub use self_extract::*;
@@ -0,0 +1,65 @@
+use std::{
+  fs::{self, OpenOptions},
+  io::{ErrorKind, Write},
+  path::{Path, PathBuf},
+};
+
+use crate::platform::process_is_alive;
+
+pub static LOCK_FILE_NAME: &str = ".otterpack.lock";
+
+/// Marks an output folder as in use by this process, so a second instance can't write into it at
+/// the same time.
+///
+/// The lock file holds the owner's PID, so locks left behind by a crashed run can be recognized
+/// and taken over. The file is removed when the guard is dropped, including while unwinding from
+/// a panic.
+#[derive(Debug)]
+pub struct OutputLock {
+  path: PathBuf,
+}
+
+impl OutputLock {
+  /// Locks `output_dir`, creating it if needed.
+  pub fn acquire(output_dir: &Path) -> anyhow::Result<Self> {
+    fs::create_dir_all(output_dir)?;
+    let path = output_dir.join(LOCK_FILE_NAME);
+
+    // One retry is enough: the only reason to go around again is having removed a stale lock
+    for _ in 0..2 {
+      match OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(mut file) => {
+          write!(file, "{}", std::process::id())?;
+          return Ok(Self { path });
+        }
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+          let owner = fs::read_to_string(&path)
+            .ok()
+            .and_then(|pid| pid.trim().parse::<u32>().ok());
+          match owner {
+            Some(pid) if process_is_alive(pid) => {
+              anyhow::bail!(
+                "Another otterpack window is already saving files to {}. Wait for it to finish or choose a different output folder.",
+                output_dir.display()
+              );
+            }
+            // Left behind by a run that didn't get to clean up
+            _ => {
+              println!("Removing stale lock file {}", path.display());
+              fs::remove_file(&path)?;
+            }
+          }
+        }
+        Err(e) => return Err(e.into()),
+      }
+    }
+
+    anyhow::bail!("Could not lock the output folder {}", output_dir.display())
+  }
+}
+
+impl Drop for OutputLock {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.path);
+  }
+}
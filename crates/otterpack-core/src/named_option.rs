@@ -0,0 +1,81 @@
+/// An option that's one of a fixed set of values, written down by name the same way in arguments,
+/// environment variables, config files and saved settings.
+///
+/// The names are snake_case and stable, unlike the display names the window shows, which get
+/// reworded and translated. [`named_option_impls!`] builds `FromStr`, `Display` and serde on top.
+pub trait NamedOption: Copy + PartialEq + 'static {
+  /// Every value, in the order they're offered.
+  fn variants() -> &'static [Self];
+
+  /// The name the value is written as.
+  fn name(&self) -> &'static str;
+
+  /// Other names the value is read from.
+  fn aliases(&self) -> &'static [&'static str] {
+    &[]
+  }
+
+  /// The value called `name` or one of its aliases, ignoring case.
+  ///
+  /// Ignoring case also reads the variant names settings were saved with before there were names.
+  fn from_name(name: &str) -> Result<Self, String> {
+    Self::variants()
+      .iter()
+      .copied()
+      .find(|value| {
+        value.name().eq_ignore_ascii_case(name)
+          || value
+            .aliases()
+            .iter()
+            .any(|alias| alias.eq_ignore_ascii_case(name))
+      })
+      .ok_or_else(|| format!("{name} isn't one of {}", Self::names()))
+  }
+
+  /// The names as a list for help text and errors, like `a, b or c`.
+  fn names() -> String {
+    let names: Vec<&str> = Self::variants().iter().map(Self::name).collect();
+    match names.split_last() {
+      Some((last, [])) => last.to_string(),
+      Some((last, rest)) => format!("{} or {last}", rest.join(", ")),
+      None => String::new(),
+    }
+  }
+}
+
+/// Implements `FromStr`, `Display` and the conversions from and to `String` for a
+/// [`NamedOption`], all going by its names. Add `#[serde(try_from = "String", into = "String")]`
+/// for serde to go by them too.
+macro_rules! named_option_impls {
+  ($option:ty) => {
+    impl std::str::FromStr for $option {
+      type Err = String;
+
+      fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <$option as $crate::NamedOption>::from_name(s)
+      }
+    }
+
+    impl std::fmt::Display for $option {
+      fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str($crate::NamedOption::name(self))
+      }
+    }
+
+    impl TryFrom<String> for $option {
+      type Error = String;
+
+      fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+      }
+    }
+
+    impl From<$option> for String {
+      fn from(value: $option) -> Self {
+        $crate::NamedOption::name(&value).to_string()
+      }
+    }
+  };
+}
+
+pub(crate) use named_option_impls;
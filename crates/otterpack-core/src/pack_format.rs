@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Version of the pack layout, which covers the trailer, the manifest and how files are laid out.
+///
+/// The major version goes up for changes older builds can't read, the minor version for additions
+/// they can safely ignore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FormatVersion {
+  pub major: u32,
+  pub minor: u32,
+}
+
+/// The newest pack layout this build reads, and the one it writes.
+pub const PACK_FORMAT_VERSION: FormatVersion = FormatVersion { major: 1, minor: 0 };
+
+impl FormatVersion {
+  /// Whether a build supporting this version can read a pack made with `pack`.
+  pub fn can_read(self, pack: FormatVersion) -> bool {
+    pack.major <= self.major
+  }
+}
+
+/// A pack made by a newer version of otterpack, with a format this build can't read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedPackVersion(pub FormatVersion);
+
+impl fmt::Display for UnsupportedPackVersion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "This package was created with a newer version of otterpack (pack format {}), please download the latest exe",
+      self.0
+    )
+  }
+}
+
+impl std::error::Error for UnsupportedPackVersion {}
+
+/// Fails with [`UnsupportedPackVersion`] if this build can't read a pack made with `pack`.
+pub fn check_pack_version(pack: FormatVersion) -> Result<(), UnsupportedPackVersion> {
+  if PACK_FORMAT_VERSION.can_read(pack) {
+    Ok(())
+  } else {
+    Err(UnsupportedPackVersion(pack))
+  }
+}
+
+impl fmt::Display for FormatVersion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}", self.major, self.minor)
+  }
+}
+
+impl std::str::FromStr for FormatVersion {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (major, minor) = s.split_once('.').unwrap_or((s, "0"));
+    let parse = |part: &str| {
+      part
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid pack format version: {s}"))
+    };
+    Ok(FormatVersion {
+      major: parse(major)?,
+      minor: parse(minor)?,
+    })
+  }
+}
+
+impl TryFrom<String> for FormatVersion {
+  type Error = String;
+
+  fn try_from(s: String) -> Result<Self, Self::Error> {
+    s.parse()
+  }
+}
+
+impl From<FormatVersion> for String {
+  fn from(version: FormatVersion) -> Self {
+    version.to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn version(major: u32, minor: u32) -> FormatVersion {
+    FormatVersion { major, minor }
+  }
+
+  #[test]
+  fn only_newer_major_versions_are_unreadable() {
+    let reader = version(2, 3);
+    assert!(reader.can_read(version(2, 3)), "equal");
+    assert!(reader.can_read(version(2, 0)), "older minor");
+    assert!(reader.can_read(version(2, 9)), "newer minor");
+    assert!(reader.can_read(version(1, 7)), "older major");
+    assert!(!reader.can_read(version(3, 0)), "newer major");
+  }
+
+  #[test]
+  fn packs_from_a_newer_major_version_are_refused() {
+    let current = PACK_FORMAT_VERSION;
+    assert_eq!(check_pack_version(current), Ok(()));
+    assert_eq!(
+      check_pack_version(version(current.major, current.minor + 1)),
+      Ok(())
+    );
+    if current.minor > 0 {
+      assert_eq!(check_pack_version(version(current.major, 0)), Ok(()));
+    }
+    if current.major > 0 {
+      assert_eq!(check_pack_version(version(current.major - 1, 9)), Ok(()));
+    }
+
+    let newer = version(current.major + 1, 0);
+    let error = check_pack_version(newer).unwrap_err();
+    assert_eq!(error, UnsupportedPackVersion(newer));
+    assert!(error.to_string().contains("newer version of otterpack"));
+    assert!(error.to_string().contains(&newer.to_string()));
+  }
+
+  #[test]
+  fn versions_round_trip_through_text() {
+    for (text, parsed) in [("1.0", version(1, 0)), ("2.13", version(2, 13))] {
+      assert_eq!(text.parse::<FormatVersion>(), Ok(parsed));
+      assert_eq!(parsed.to_string(), text);
+      assert_eq!(
+        serde_json::to_string(&parsed).unwrap(),
+        format!("\"{text}\"")
+      );
+      assert_eq!(
+        serde_json::from_str::<FormatVersion>(&format!("\"{text}\"")).unwrap(),
+        parsed
+      );
+    }
+    // A bare major version is read as its first minor one
+    assert_eq!("3".parse::<FormatVersion>(), Ok(version(3, 0)));
+    for bad in ["", "one", "1.x", "-1.0", "1.2.3"] {
+      assert!(bad.parse::<FormatVersion>().is_err(), "{bad:?}");
+    }
+  }
+}
@@ -0,0 +1,199 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+use zip::{CompressionMethod, ZipArchive, ZipWriter, result::ZipError, write::FileOptions};
+
+use crate::{PackSource, append_payload, find_pack_source_for, list_pack_entries};
+
+/// What goes into a self-extracting executable.
+#[derive(Debug, Clone)]
+pub struct PackOptions {
+  /// The otterpack executable the payload is appended to.
+  pub stub: PathBuf,
+  /// A folder or ZIP holding the tracks and ffmpeg.
+  pub input: PathBuf,
+  /// Where the finished executable is written.
+  pub output: PathBuf,
+  /// Pack without ffmpeg, for recordings that rely on a tools pack or an installed ffmpeg.
+  pub allow_missing_ffmpeg: bool,
+}
+
+/// What ended up in a packed executable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackSummary {
+  pub files: usize,
+  pub tracks: usize,
+  pub payload_size: u64,
+}
+
+/// Whether a file is an ffmpeg build for any platform, since packs are often built on a different
+/// one than they're for.
+fn is_any_ffmpeg(name: &str) -> bool {
+  let stem = name.strip_suffix(".exe").unwrap_or(name);
+  stem == "ffmpeg" || stem.starts_with("ffmpeg-")
+}
+
+fn is_flac(name: &str) -> bool {
+  Path::new(name)
+    .extension()
+    .is_some_and(|ext| ext.eq_ignore_ascii_case("flac"))
+}
+
+/// Writes the files at the top of `dir` into a ZIP, returning their names.
+///
+/// The tracks are stored as they are, since FLAC doesn't compress any further, while everything
+/// else is deflated.
+fn zip_folder(dir: &Path, out: impl Write + Seek) -> Result<Vec<String>> {
+  let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+    .context(format!("Failed to read {}", dir.display()))?
+    .map(|entry| entry.map(|entry| entry.path()))
+    .collect::<std::io::Result<_>>()?;
+  paths.retain(|path| path.is_file());
+  paths.sort();
+
+  let mut zip = ZipWriter::new(out);
+  let mut names = Vec::with_capacity(paths.len());
+  for path in paths {
+    let name = path
+      .file_name()
+      .and_then(|name| name.to_str())
+      .context(format!("{} doesn't have a usable name", path.display()))?
+      .to_string();
+    let mut file = File::open(&path).context(format!("Failed to open {}", path.display()))?;
+    let size = file.metadata()?.len();
+
+    let mut options = FileOptions::default()
+      .large_file(size >= u32::MAX as u64)
+      .compression_method(if is_flac(&name) {
+        CompressionMethod::Stored
+      } else {
+        CompressionMethod::Deflated
+      });
+    if is_any_ffmpeg(&name) {
+      options = options.unix_permissions(0o755);
+    }
+    zip.start_file(name.as_str(), options)?;
+    std::io::copy(&mut file, &mut zip).context(format!("Failed to pack {}", path.display()))?;
+    names.push(name);
+  }
+  zip.finish()?;
+  Ok(names)
+}
+
+/// Copies every entry of the ZIP at `path` into a new ZIP as it is, returning their names.
+///
+/// A ZIP with encrypted entries is copied whole instead, since they'd lose their encryption
+/// copied one by one.
+fn copy_zip(path: &Path, mut out: impl Write + Seek) -> Result<Vec<String>> {
+  let mut archive =
+    ZipArchive::new(File::open(path).context(format!("Failed to open {}", path.display()))?)
+      .context(format!("{} isn't a ZIP file", path.display()))?;
+
+  let mut names = Vec::with_capacity(archive.len());
+  let mut encrypted = false;
+  for i in 0..archive.len() {
+    let file = archive.by_index_raw(i)?;
+    if file.is_dir() {
+      continue;
+    }
+    names.push(file.name().to_string());
+    drop(file);
+    match archive.by_index(i) {
+      Err(ZipError::UnsupportedArchive(message)) if message == ZipError::PASSWORD_REQUIRED => {
+        encrypted = true;
+      }
+      result => {
+        result?;
+      }
+    }
+  }
+  if encrypted {
+    let mut file = File::open(path).context(format!("Failed to open {}", path.display()))?;
+    std::io::copy(&mut file, &mut out).context(format!("Failed to pack {}", path.display()))?;
+    return Ok(names);
+  }
+
+  let mut zip = ZipWriter::new(out);
+  for i in 0..archive.len() {
+    zip.raw_copy_file(archive.by_index_raw(i)?)?;
+  }
+  zip.finish()?;
+  Ok(names)
+}
+
+/// Checks that the executable at `exe` finds its payload and all `tracks` tracks in it.
+fn verify(exe: &Path, tracks: usize) -> Result<()> {
+  let source = match find_pack_source_for(exe.to_path_buf())? {
+    source @ PackSource::EmbeddedZip { .. } => source,
+    source => anyhow::bail!("The packed executable found {source:?} instead of its own payload"),
+  };
+  let found = list_pack_entries(&source)?.len();
+  if found != tracks {
+    anyhow::bail!("The packed executable only has {found} of the {tracks} tracks that were packed");
+  }
+  Ok(())
+}
+
+/// Builds a self-extracting executable out of `options.stub` and the files in `options.input`,
+/// then checks that the result can find its own payload.
+///
+/// Files aren't encrypted while packing. An encrypted recording has to come as a ZIP that was
+/// encrypted with AES beforehand, for example with 7-Zip, which is packed as it is.
+pub fn pack(options: &PackOptions) -> Result<PackSummary> {
+  if crate::platform::canonicalize_lenient(&options.stub)
+    == crate::platform::canonicalize_lenient(&options.output)
+  {
+    anyhow::bail!("The output can't be the stub itself");
+  }
+
+  let output_dir = options
+    .output
+    .parent()
+    .filter(|dir| !dir.as_os_str().is_empty())
+    .unwrap_or(Path::new("."));
+  let mut payload = tempfile::Builder::new()
+    .prefix(".otterpack-payload-")
+    .tempfile_in(output_dir)
+    .context(format!(
+      "Failed to create a temp file in {}",
+      output_dir.display()
+    ))?;
+  let names = if options.input.is_dir() {
+    zip_folder(&options.input, payload.as_file_mut())?
+  } else {
+    copy_zip(&options.input, payload.as_file_mut())?
+  };
+
+  let root_names = || names.iter().filter(|name| !name.contains(['/', '\\']));
+  if !options.allow_missing_ffmpeg && !root_names().any(|name| is_any_ffmpeg(name)) {
+    anyhow::bail!(
+      "There's no ffmpeg in {}. Add ffmpeg.exe to it, or pass --no-ffmpeg to pack without it",
+      options.input.display()
+    );
+  }
+  let tracks = names.iter().filter(|name| is_flac(name)).count();
+  if tracks == 0 {
+    anyhow::bail!("There are no FLAC tracks in {}", options.input.display());
+  }
+
+  std::fs::copy(&options.stub, &options.output).context(format!(
+    "Failed to copy {} to {}",
+    options.stub.display(),
+    options.output.display()
+  ))?;
+  append_payload(&options.output, payload.path())?;
+  let payload_size = payload.as_file().metadata()?.len();
+
+  // Read the result back the same way the stub will, so a broken pack never gets shipped
+  if let Err(e) = verify(&options.output, tracks) {
+    let _ = std::fs::remove_file(&options.output);
+    return Err(e);
+  }
+
+  Ok(PackSummary {
+    files: names.len(),
+    tracks,
+    payload_size,
+  })
+}
@@ -0,0 +1,498 @@
+use std::path::{Path, PathBuf};
+
+/// Free space available to the current user on the volume holding `path`, in bytes.
+///
+/// `path` doesn't need to exist yet, the nearest existing ancestor is checked instead. Returns
+/// `None` if the space couldn't be determined.
+pub fn available_space(path: &Path) -> Option<u64> {
+  let existing = path.ancestors().find(|p| p.exists())?;
+  fs2::available_space(existing).ok()
+}
+
+/// Resolves a path that may not exist yet to its canonical form, by canonicalizing the nearest
+/// existing ancestor and appending the rest.
+pub fn canonicalize_lenient(path: &Path) -> PathBuf {
+  let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+  for ancestor in absolute.ancestors() {
+    if let Ok(canonical) = ancestor.canonicalize() {
+      let rest = absolute.strip_prefix(ancestor).unwrap_or(Path::new(""));
+      return canonical.join(rest);
+    }
+  }
+  absolute
+}
+
+/// Checks that files can be created in `dir` by creating and removing a probe file.
+///
+/// If `dir` doesn't exist yet, the nearest existing ancestor is checked instead, since that's
+/// where it would get created.
+pub fn probe_writable(dir: &Path) -> std::io::Result<()> {
+  let existing = dir
+    .ancestors()
+    .find(|p| p.is_dir())
+    .ok_or(std::io::ErrorKind::NotFound)?;
+  tempfile::Builder::new()
+    .prefix(".otterpack-probe-")
+    .tempfile_in(existing)?
+    .close()
+}
+
+/// The macOS app bundle `exe_path` is the executable of, going by the `<name>.app/Contents/MacOS`
+/// folders it sits in.
+pub fn app_bundle(exe_path: &Path) -> Option<&Path> {
+  let macos = exe_path
+    .parent()
+    .filter(|dir| dir.ends_with("Contents/MacOS"))?;
+  let bundle = macos.parent()?.parent()?;
+  let is_app = bundle
+    .extension()
+    .is_some_and(|ext| ext.eq_ignore_ascii_case("app"));
+  is_app.then_some(bundle)
+}
+
+/// Whether `exe_path` is running from the random read-only folder Gatekeeper moves downloaded
+/// apps into until the user moves them themselves, where nothing beside it is where the user put
+/// it.
+pub fn is_translocated(exe_path: &Path) -> bool {
+  exe_path
+    .components()
+    .any(|component| component.as_os_str() == "AppTranslocation")
+}
+
+/// The folder outputs go into until the user picks one, named after the executable at
+/// `exe_path`.
+///
+/// That's a folder in the working directory, which is where the executable is when it's started
+/// by double-clicking it on Windows. Apps started from Finder get `/` instead, so an app bundle
+/// gets the folder next to it, or in `user_dir` when it's been translocated, like the user's
+/// Downloads folder. `user_dir` is also used when `writable` says the folder would go somewhere
+/// the user can't write to, like Program Files or `/Applications`.
+pub fn default_output_dir(
+  exe_path: &Path,
+  working_dir: &Path,
+  user_dir: Option<&Path>,
+  writable: impl Fn(&Path) -> bool,
+) -> PathBuf {
+  let bundle = app_bundle(exe_path);
+  let name = bundle
+    .unwrap_or(exe_path)
+    .file_stem()
+    .map(|stem| stem.to_string_lossy().to_string())
+    .unwrap_or_else(|| "otterpack-out".to_string());
+  let base = match bundle {
+    Some(_) if is_translocated(exe_path) => None,
+    Some(bundle) => bundle.parent(),
+    None if working_dir.parent().is_none() => None,
+    None => Some(working_dir),
+  };
+  base
+    .filter(|base| writable(base))
+    .or(user_dir)
+    .unwrap_or(working_dir)
+    .join(name)
+}
+
+/// File name of the ffmpeg executable on this platform, both in packs and when installed.
+pub const FFMPEG_BINARY: &str = if cfg!(windows) {
+  "ffmpeg.exe"
+} else {
+  "ffmpeg"
+};
+
+/// File name of the ffprobe executable on this platform, which packs may include next to ffmpeg.
+pub const FFPROBE_BINARY: &str = if cfg!(windows) {
+  "ffprobe.exe"
+} else {
+  "ffprobe"
+};
+
+/// The architecture of the machine itself, named like [`std::env::consts::ARCH`].
+///
+/// This differs from the architecture the app was built for when it runs under emulation, like an
+/// x86_64 build on Windows on ARM.
+#[cfg(windows)]
+pub fn native_arch() -> &'static str {
+  use windows_sys::Win32::System::{
+    SystemInformation::{
+      IMAGE_FILE_MACHINE_AMD64, IMAGE_FILE_MACHINE_ARM64, IMAGE_FILE_MACHINE_I386,
+    },
+    Threading::{GetCurrentProcess, IsWow64Process2},
+  };
+
+  let mut process_machine = 0;
+  let mut native_machine = 0;
+  let known = unsafe {
+    IsWow64Process2(
+      GetCurrentProcess(),
+      &mut process_machine,
+      &mut native_machine,
+    )
+  } != 0;
+  match native_machine {
+    _ if !known => std::env::consts::ARCH,
+    IMAGE_FILE_MACHINE_AMD64 => "x86_64",
+    IMAGE_FILE_MACHINE_ARM64 => "aarch64",
+    IMAGE_FILE_MACHINE_I386 => "x86",
+    _ => std::env::consts::ARCH,
+  }
+}
+
+/// The architecture of the machine itself, named like [`std::env::consts::ARCH`].
+#[cfg(not(windows))]
+pub fn native_arch() -> &'static str {
+  std::env::consts::ARCH
+}
+
+/// Names of the ffmpeg builds a pack may contain, best first for this machine.
+///
+/// A pack can carry one build per architecture, named `ffmpeg-<arch>` after
+/// [`std::env::consts::ARCH`], e.g. `ffmpeg-x86_64.exe` and `ffmpeg-aarch64.exe`. A build for the
+/// machine itself is preferred, then one for the architecture the app was built for, which the
+/// machine can at least emulate. The plain [`FFMPEG_BINARY`] comes last, for packs with one build.
+pub fn ffmpeg_binary_names() -> Vec<String> {
+  ffmpeg_binary_names_for(&[native_arch(), std::env::consts::ARCH])
+}
+
+/// [`ffmpeg_binary_names`] for the given architectures, most preferred first.
+pub fn ffmpeg_binary_names_for(archs: &[&str]) -> Vec<String> {
+  let mut names: Vec<String> = Vec::new();
+  for arch in archs {
+    let name = format!("ffmpeg-{arch}{}", std::env::consts::EXE_SUFFIX);
+    if !names.contains(&name) {
+      names.push(name);
+    }
+  }
+  names.push(FFMPEG_BINARY.to_string());
+  names
+}
+
+/// Places an installed ffmpeg might be, best first.
+///
+/// That's everything on `PATH`, the same places `which` or `where` would look, then the usual
+/// install locations that aren't always on it, like Homebrew's when the app is started from
+/// Finder.
+pub fn ffmpeg_candidates() -> Vec<PathBuf> {
+  let mut dirs: Vec<PathBuf> = std::env::var_os("PATH")
+    .map(|path| std::env::split_paths(&path).collect())
+    .unwrap_or_default();
+
+  #[cfg(unix)]
+  dirs.extend(
+    [
+      "/usr/local/bin",
+      "/opt/homebrew/bin",
+      "/opt/local/bin",
+      "/usr/bin",
+      "/snap/bin",
+    ]
+    .map(PathBuf::from),
+  );
+
+  #[cfg(windows)]
+  {
+    // winget, Chocolatey, Scoop and a manual install under Program Files
+    for (base, sub) in [
+      ("LOCALAPPDATA", r"Microsoft\WinGet\Links"),
+      ("ProgramData", r"chocolatey\bin"),
+      ("USERPROFILE", r"scoop\shims"),
+      ("ProgramFiles", r"ffmpeg\bin"),
+    ] {
+      if let Some(base) = std::env::var_os(base) {
+        dirs.push(PathBuf::from(base).join(sub));
+      }
+    }
+    dirs.push(PathBuf::from(r"C:\ffmpeg\bin"));
+  }
+
+  let mut candidates: Vec<PathBuf> = Vec::new();
+  for dir in dirs {
+    let path = dir.join(FFMPEG_BINARY);
+    if path.is_file() && !candidates.contains(&path) {
+      candidates.push(path);
+    }
+  }
+  candidates
+}
+
+/// The longest path Windows accepts, even in extended-length form.
+const MAX_EXTENDED_PATH: usize = 32_767;
+/// The longest single file or folder name most filesystems accept.
+const MAX_COMPONENT: usize = 255;
+
+/// Rewrites an absolute Windows path into its extended-length (`\\?\`) form, which isn't limited
+/// to 260 characters.
+///
+/// `C:\foo` becomes `\\?\C:\foo` and the UNC path `\\server\share` becomes
+/// `\\?\UNC\server\share`. Returns `None` for paths that are already extended-length or that
+/// aren't absolute.
+pub fn extended_length_path(path: &str) -> Option<String> {
+  if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+    return None;
+  }
+  if let Some(unc) = path.strip_prefix(r"\\") {
+    return Some(format!(r"\\?\UNC\{unc}"));
+  }
+  let bytes = path.as_bytes();
+  if bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\' {
+    return Some(format!(r"\\?\{path}"));
+  }
+  None
+}
+
+/// Makes a path absolute and, on Windows, switches it to the extended-length form so deep output
+/// folders don't run into MAX_PATH.
+pub fn long_path(path: &Path) -> PathBuf {
+  let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+  if cfg!(windows)
+    && let Some(extended) = absolute.to_str().and_then(extended_length_path)
+  {
+    return PathBuf::from(extended);
+  }
+  absolute
+}
+
+/// Fails if a path is too long to be created, even with extended-length support.
+pub fn check_path_length(path: &Path) -> anyhow::Result<()> {
+  if path.as_os_str().len() > MAX_EXTENDED_PATH {
+    anyhow::bail!(
+      "The output path is too long, choose an output folder closer to the root of the drive: {}",
+      path.display()
+    );
+  }
+  if let Some(component) = path
+    .components()
+    .find(|c| c.as_os_str().len() > MAX_COMPONENT)
+  {
+    anyhow::bail!(
+      "The name \"{}\" is too long to be used in the output folder",
+      component.as_os_str().to_string_lossy()
+    );
+  }
+  Ok(())
+}
+
+/// Sets up a child process so it doesn't open a console window, and so it runs at a lower
+/// priority than normal if `low_priority` is set.
+pub fn configure_child(command: &mut tokio::process::Command, low_priority: bool) {
+  #[cfg(windows)]
+  {
+    use windows_sys::Win32::System::Threading::{BELOW_NORMAL_PRIORITY_CLASS, CREATE_NO_WINDOW};
+
+    command.creation_flags(if low_priority {
+      CREATE_NO_WINDOW | BELOW_NORMAL_PRIORITY_CLASS
+    } else {
+      CREATE_NO_WINDOW
+    });
+  }
+
+  #[cfg(unix)]
+  if low_priority {
+    // SAFETY: nice is async-signal-safe and touches nothing but the child's own priority
+    unsafe {
+      command.pre_exec(|| {
+        // -1 is also a valid new niceness, and failing to lower the priority isn't worth
+        // refusing to run over, so the result is ignored
+        libc::nice(10);
+        Ok(())
+      });
+    }
+  }
+}
+
+/// Whether a process with the given ID is still running.
+#[cfg(windows)]
+pub fn process_is_alive(pid: u32) -> bool {
+  use windows_sys::Win32::{
+    Foundation::{CloseHandle, ERROR_ACCESS_DENIED, GetLastError, STILL_ACTIVE},
+    System::Threading::{GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION},
+  };
+
+  unsafe {
+    let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+    if handle.is_null() {
+      // Processes we aren't allowed to look at still exist
+      return GetLastError() == ERROR_ACCESS_DENIED;
+    }
+    let mut exit_code = 0;
+    let alive = GetExitCodeProcess(handle, &mut exit_code) != 0 && exit_code == STILL_ACTIVE as u32;
+    CloseHandle(handle);
+    alive
+  }
+}
+
+/// Whether a process with the given ID is still running.
+#[cfg(unix)]
+pub fn process_is_alive(pid: u32) -> bool {
+  let Ok(pid) = libc::pid_t::try_from(pid) else {
+    return false;
+  };
+  if pid <= 0 {
+    return false;
+  }
+  // Signal 0 only checks whether the process could be signalled
+  let result = unsafe { libc::kill(pid, 0) };
+  result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DOWNLOADS: &str = "/Users/me/Downloads";
+
+  fn output_dir(exe: &str, working_dir: &str, writable: impl Fn(&Path) -> bool) -> PathBuf {
+    default_output_dir(
+      Path::new(exe),
+      Path::new(working_dir),
+      Some(Path::new(DOWNLOADS)),
+      writable,
+    )
+  }
+
+  #[test]
+  fn output_goes_in_the_working_directory() {
+    assert_eq!(
+      output_dir("/home/me/craig-123.exe", "/home/me", |_| true),
+      Path::new("/home/me/craig-123")
+    );
+    // Started from a terminal somewhere else
+    assert_eq!(
+      output_dir("/opt/otterpack/otterpack", "/home/me/recordings", |_| true),
+      Path::new("/home/me/recordings/otterpack")
+    );
+  }
+
+  #[test]
+  fn output_goes_next_to_an_app_bundle() {
+    assert_eq!(
+      output_dir(
+        "/Users/me/Apps/Craig 123.app/Contents/MacOS/otterpack",
+        "/",
+        |_| true
+      ),
+      Path::new("/Users/me/Apps/Craig 123")
+    );
+  }
+
+  #[test]
+  fn translocated_apps_use_the_user_folder() {
+    let exe =
+      "/private/var/folders/x/T/AppTranslocation/1F2E/d/Craig 123.app/Contents/MacOS/otterpack";
+    assert_eq!(
+      output_dir(exe, "/", |_| true),
+      Path::new(DOWNLOADS).join("Craig 123")
+    );
+  }
+
+  #[test]
+  fn unwritable_folders_use_the_user_folder() {
+    let writable = |dir: &Path| dir != Path::new("/Applications");
+    assert_eq!(
+      output_dir(
+        "/Applications/Craig 123.app/Contents/MacOS/otterpack",
+        "/",
+        writable
+      ),
+      Path::new(DOWNLOADS).join("Craig 123")
+    );
+    assert_eq!(
+      output_dir("/Applications/otterpack", "/Applications", writable),
+      Path::new(DOWNLOADS).join("otterpack")
+    );
+
+    // With nowhere better, it's the working directory after all
+    let output = default_output_dir(
+      Path::new("/Applications/otterpack"),
+      Path::new("/Applications"),
+      None,
+      writable,
+    );
+    assert_eq!(output, Path::new("/Applications/otterpack"));
+  }
+
+  #[test]
+  fn the_root_folder_is_never_picked_while_there_is_another() {
+    assert_eq!(
+      output_dir("/usr/local/bin/otterpack", "/", |_| true),
+      Path::new(DOWNLOADS).join("otterpack")
+    );
+    let output = default_output_dir(
+      Path::new("/usr/local/bin/otterpack"),
+      Path::new("/"),
+      None,
+      |_| true,
+    );
+    assert_eq!(output, Path::new("/otterpack"));
+  }
+
+  #[test]
+  fn app_bundles_are_recognized_by_their_layout() {
+    assert_eq!(
+      app_bundle(Path::new(
+        "/Applications/Otterpack.app/Contents/MacOS/otterpack"
+      )),
+      Some(Path::new("/Applications/Otterpack.app"))
+    );
+    assert_eq!(
+      app_bundle(Path::new(
+        "/Applications/Otterpack.APP/Contents/MacOS/otterpack"
+      )),
+      Some(Path::new("/Applications/Otterpack.APP"))
+    );
+    assert_eq!(
+      app_bundle(Path::new(
+        "/Applications/Otterpack/Contents/MacOS/otterpack"
+      )),
+      None
+    );
+    assert_eq!(
+      app_bundle(Path::new("/Applications/Otterpack.app/otterpack")),
+      None
+    );
+    assert!(is_translocated(Path::new(
+      "/private/var/folders/x/T/AppTranslocation/1F2E/d/Otterpack.app/Contents/MacOS/otterpack"
+    )));
+    assert!(!is_translocated(Path::new(
+      "/Applications/Otterpack.app/Contents/MacOS/otterpack"
+    )));
+  }
+
+  #[test]
+  #[cfg(not(windows))]
+  fn ffmpeg_builds_go_by_architecture() {
+    assert_eq!(
+      ffmpeg_binary_names_for(&["aarch64", "x86_64"]),
+      ["ffmpeg-aarch64", "ffmpeg-x86_64", "ffmpeg"]
+    );
+    // Running natively, both architectures are the same
+    assert_eq!(
+      ffmpeg_binary_names_for(&["x86_64", "x86_64"]),
+      ["ffmpeg-x86_64", "ffmpeg"]
+    );
+    assert_eq!(ffmpeg_binary_names_for(&[]), ["ffmpeg"]);
+  }
+
+  #[test]
+  #[cfg(windows)]
+  fn ffmpeg_builds_go_by_architecture() {
+    assert_eq!(
+      ffmpeg_binary_names_for(&["aarch64", "x86_64"]),
+      ["ffmpeg-aarch64.exe", "ffmpeg-x86_64.exe", "ffmpeg.exe"]
+    );
+    assert_eq!(
+      ffmpeg_binary_names_for(&["x86_64", "x86_64"]),
+      ["ffmpeg-x86_64.exe", "ffmpeg.exe"]
+    );
+    assert_eq!(ffmpeg_binary_names_for(&[]), ["ffmpeg.exe"]);
+  }
+
+  #[test]
+  fn this_machine_prefers_its_own_ffmpeg() {
+    let names = ffmpeg_binary_names();
+    assert_eq!(
+      names[0],
+      format!("ffmpeg-{}{}", native_arch(), std::env::consts::EXE_SUFFIX)
+    );
+    assert_eq!(names.last().unwrap(), FFMPEG_BINARY);
+  }
+}
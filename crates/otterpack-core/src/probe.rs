@@ -0,0 +1,140 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::{io::Read, path::Path, process::Stdio};
+
+/// What ffprobe reports with `-print_format json`, only the parts that are used.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ProbeOutput {
+  streams: Vec<StreamInfo>,
+  format: Option<FormatInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FormatInfo {
+  #[serde(default, deserialize_with = "number_string")]
+  duration: Option<f64>,
+}
+
+/// A stream in a media file, as reported by ffprobe.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct StreamInfo {
+  pub index: u32,
+  /// `audio`, `video` and so on.
+  pub codec_type: Option<String>,
+  pub codec_name: Option<String>,
+  #[serde(default, deserialize_with = "number_string")]
+  pub sample_rate: Option<u32>,
+  pub channels: Option<u32>,
+  /// Length in seconds, if the container records it per stream.
+  #[serde(default, deserialize_with = "number_string")]
+  pub duration: Option<f64>,
+}
+
+impl StreamInfo {
+  pub fn is_audio(&self) -> bool {
+    self.codec_type.as_deref() == Some("audio")
+  }
+}
+
+/// ffprobe writes most numbers as strings, and `N/A` when it doesn't know them.
+fn number_string<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+  D: Deserializer<'de>,
+  T: std::str::FromStr,
+{
+  let value = Option::<String>::deserialize(deserializer)?;
+  Ok(value.and_then(|value| value.parse().ok()))
+}
+
+fn parse_output(json: &[u8]) -> Result<ProbeOutput> {
+  serde_json::from_slice(json).context("ffprobe's output couldn't be read")
+}
+
+/// Reads the duration in seconds out of ffprobe's `-show_format` output.
+pub fn parse_format_duration(json: &[u8]) -> Result<Option<f64>> {
+  Ok(
+    parse_output(json)?
+      .format
+      .and_then(|format| format.duration),
+  )
+}
+
+/// Reads the streams out of ffprobe's `-show_streams` output.
+pub fn parse_streams(json: &[u8]) -> Result<Vec<StreamInfo>> {
+  Ok(parse_output(json)?.streams)
+}
+
+/// Reads the length in seconds of a FLAC file from its STREAMINFO block.
+///
+/// That block comes right at the start of the file, so this works on a track still inside a pack
+/// and without ffmpeg, reading only the first few bytes.
+pub fn flac_duration(mut reader: impl Read) -> Result<f64> {
+  let mut header = [0u8; 10];
+  reader
+    .read_exact(&mut header[..4])
+    .context("The file is too short to be a FLAC file")?;
+  // Some taggers put an ID3v2 tag in front of the FLAC stream, with a syncsafe size
+  if header.starts_with(b"ID3") {
+    reader.read_exact(&mut header[4..])?;
+    let size = header[6..]
+      .iter()
+      .fold(0u64, |size, byte| size << 7 | u64::from(byte & 0x7f));
+    std::io::copy(&mut (&mut reader).take(size), &mut std::io::sink())?;
+    reader.read_exact(&mut header[..4])?;
+  }
+  if !header.starts_with(b"fLaC") {
+    anyhow::bail!("This isn't a FLAC file");
+  }
+
+  // The STREAMINFO block always comes first: a 4 byte block header, then 34 bytes of info
+  let mut block = [0u8; 4 + 34];
+  reader
+    .read_exact(&mut block)
+    .context("The FLAC file is cut short")?;
+  if block[0] & 0x7f != 0 {
+    anyhow::bail!("The FLAC file doesn't start with its stream info");
+  }
+  let info = &block[4..];
+  let sample_rate = u32::from(info[10]) << 12 | u32::from(info[11]) << 4 | u32::from(info[12]) >> 4;
+  let total_samples = u64::from(info[13] & 0x0f) << 32
+    | u64::from(u32::from_be_bytes(info[14..18].try_into().unwrap()));
+  if sample_rate == 0 || total_samples == 0 {
+    anyhow::bail!("The FLAC file doesn't say how long it is");
+  }
+  Ok(total_samples as f64 / f64::from(sample_rate))
+}
+
+/// Runs ffprobe on `input` with the given `-show_*` flags, returning its JSON output.
+async fn run_ffprobe(ffprobe: &Path, input: &Path, show: &str) -> Result<Vec<u8>> {
+  let mut command = tokio::process::Command::new(ffprobe);
+  command
+    .args(["-v", "error", "-print_format", "json", show])
+    .arg(input)
+    .kill_on_drop(true)
+    .stdin(Stdio::null());
+  crate::platform::configure_child(&mut command, false);
+
+  let output = command
+    .output()
+    .await
+    .context(format!("Failed to run ffprobe ({})", ffprobe.display()))?;
+  if !output.status.success() {
+    anyhow::bail!(
+      "ffprobe couldn't read {}: {}",
+      input.display(),
+      String::from_utf8_lossy(&output.stderr).trim()
+    );
+  }
+  Ok(output.stdout)
+}
+
+/// Asks ffprobe for the duration of a file, in seconds.
+pub async fn probe_duration(ffprobe: &Path, input: &Path) -> Result<Option<f64>> {
+  parse_format_duration(&run_ffprobe(ffprobe, input, "-show_format").await?)
+}
+
+/// Asks ffprobe for the streams in a file.
+pub async fn probe_streams(ffprobe: &Path, input: &Path) -> Result<Vec<StreamInfo>> {
+  parse_streams(&run_ffprobe(ffprobe, input, "-show_streams").await?)
+}
@@ -0,0 +1,3186 @@
+use std::{
+  cmp::Ordering,
+  collections::{BTreeMap, VecDeque},
+  ffi::{OsStr, OsString},
+  fmt,
+  iter::Peekable,
+  ops::Range,
+  path::{Path, PathBuf},
+  process::{ExitStatus, Output, Stdio},
+  time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+  process::{Child, Command},
+  sync::mpsc,
+  time::Instant,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{NamedOption, RunEvent, cache::Dirs, named_option_impls, self_extract::ExtractLimits};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum AudioFormat {
+  #[default]
+  FLAC,
+  Audacity,
+  WAV,
+  AAC,
+  ALAC,
+}
+
+impl AudioFormat {
+  fn extension(&self) -> &'static str {
+    match self {
+      AudioFormat::FLAC | AudioFormat::Audacity => "flac",
+      AudioFormat::WAV => "wav",
+      AudioFormat::AAC => "m4a",
+      AudioFormat::ALAC => "m4a",
+    }
+  }
+
+  fn ffmpeg_args(&self) -> Vec<&'static str> {
+    match self {
+      AudioFormat::FLAC | AudioFormat::Audacity => vec!["-c:a", "flac", "-f", "flac"],
+      AudioFormat::WAV => vec!["-c:a", "pcm_s16le", "-f", "wav"],
+      AudioFormat::AAC => vec!["-c:a", "aac", "-f", "ipod"],
+      AudioFormat::ALAC => vec!["-c:a", "alac", "-f", "ipod"],
+    }
+  }
+
+  pub fn display_name(&self) -> &'static str {
+    match self {
+      AudioFormat::FLAC => "FLAC",
+      AudioFormat::WAV => "wav",
+      AudioFormat::AAC => "AAC (MPEG-4)",
+      AudioFormat::ALAC => "ALAC (Apple Lossless)",
+      AudioFormat::Audacity => "Audacity Project",
+    }
+  }
+
+  /// Name of the subfolder this format's outputs go into when a run saves several formats.
+  pub fn folder_name(&self) -> &'static str {
+    self.name()
+  }
+
+  pub fn is_project_format(&self) -> bool {
+    matches!(self, AudioFormat::Audacity)
+  }
+
+  /// The ffmpeg encoder this format needs.
+  pub fn encoder(&self) -> &'static str {
+    match self {
+      AudioFormat::FLAC | AudioFormat::Audacity => "flac",
+      AudioFormat::WAV => "pcm_s16le",
+      AudioFormat::AAC => "aac",
+      AudioFormat::ALAC => "alac",
+    }
+  }
+}
+
+impl NamedOption for AudioFormat {
+  fn variants() -> &'static [Self] {
+    &[
+      AudioFormat::FLAC,
+      AudioFormat::Audacity,
+      AudioFormat::WAV,
+      AudioFormat::AAC,
+      AudioFormat::ALAC,
+    ]
+  }
+
+  fn name(&self) -> &'static str {
+    match self {
+      AudioFormat::FLAC => "flac",
+      AudioFormat::Audacity => "audacity",
+      AudioFormat::WAV => "wav",
+      AudioFormat::AAC => "aac",
+      AudioFormat::ALAC => "alac",
+    }
+  }
+
+  fn aliases(&self) -> &'static [&'static str] {
+    match self {
+      AudioFormat::WAV => &["wave"],
+      AudioFormat::AAC => &["m4a"],
+      _ => &[],
+    }
+  }
+}
+
+named_option_impls!(AudioFormat);
+
+/// How track volumes get evened out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Leveling {
+  #[default]
+  Off,
+  /// FFmpeg's dynaudnorm filter.
+  Dynaudnorm,
+}
+
+impl Leveling {
+  /// The audio filter applied to each input, if any.
+  fn filter(&self) -> Option<&'static str> {
+    match self {
+      Leveling::Off => None,
+      Leveling::Dynaudnorm => Some("dynaudnorm"),
+    }
+  }
+}
+
+impl NamedOption for Leveling {
+  fn variants() -> &'static [Self] {
+    &[Leveling::Off, Leveling::Dynaudnorm]
+  }
+
+  fn name(&self) -> &'static str {
+    match self {
+      Leveling::Off => "off",
+      Leveling::Dynaudnorm => "dynaudnorm",
+    }
+  }
+
+  fn aliases(&self) -> &'static [&'static str] {
+    match self {
+      Leveling::Off => &["none"],
+      Leveling::Dynaudnorm => &[],
+    }
+  }
+}
+
+named_option_impls!(Leveling);
+
+/// When a mix ends, going by its inputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum MixDuration {
+  /// Keep going until every track has ended.
+  #[default]
+  Longest,
+  /// Stop as soon as any track ends.
+  Shortest,
+  /// Stop when the first track ends.
+  First,
+}
+
+impl MixDuration {
+  /// The value amix's `duration` option takes.
+  fn amix_value(&self) -> &'static str {
+    match self {
+      MixDuration::Longest => "longest",
+      MixDuration::Shortest => "shortest",
+      MixDuration::First => "first",
+    }
+  }
+
+  pub fn display_name(&self) -> &'static str {
+    match self {
+      MixDuration::Longest => "Longest track",
+      MixDuration::Shortest => "Shortest track",
+      MixDuration::First => "First track",
+    }
+  }
+
+  /// How long the mix of tracks with these durations comes out, if that's known.
+  fn mix_length(&self, durations: &[Option<f64>]) -> Option<f64> {
+    let known = durations.iter().flatten().copied();
+    match self {
+      MixDuration::Longest => known.reduce(f64::max),
+      MixDuration::Shortest => known.reduce(f64::min),
+      MixDuration::First => durations.first().copied().flatten(),
+    }
+  }
+}
+
+impl NamedOption for MixDuration {
+  fn variants() -> &'static [Self] {
+    &[
+      MixDuration::Longest,
+      MixDuration::Shortest,
+      MixDuration::First,
+    ]
+  }
+
+  fn name(&self) -> &'static str {
+    match self {
+      MixDuration::Longest => "longest",
+      MixDuration::Shortest => "shortest",
+      MixDuration::First => "first",
+    }
+  }
+}
+
+named_option_impls!(MixDuration);
+
+/// What to do when an output file already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum OverwritePolicy {
+  #[default]
+  Overwrite,
+  /// Leave the existing file alone and move on.
+  Skip,
+  /// Stop the run with an error.
+  Fail,
+}
+
+impl OverwritePolicy {
+  pub fn display_name(&self) -> &'static str {
+    match self {
+      OverwritePolicy::Overwrite => "Replace it",
+      OverwritePolicy::Skip => "Keep it and skip the track",
+      OverwritePolicy::Fail => "Stop with an error",
+    }
+  }
+}
+
+impl NamedOption for OverwritePolicy {
+  fn variants() -> &'static [Self] {
+    &[
+      OverwritePolicy::Overwrite,
+      OverwritePolicy::Skip,
+      OverwritePolicy::Fail,
+    ]
+  }
+
+  fn name(&self) -> &'static str {
+    match self {
+      OverwritePolicy::Overwrite => "overwrite",
+      OverwritePolicy::Skip => "skip",
+      OverwritePolicy::Fail => "fail",
+    }
+  }
+}
+
+named_option_impls!(OverwritePolicy);
+
+/// Everything that controls how a run converts the tracks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessOptions {
+  pub format: AudioFormat,
+  /// Formats to save the tracks in as well as `format`. With any of these, each format goes into
+  /// its own subfolder of the output folder, named by [`AudioFormat::folder_name`].
+  pub extra_formats: Vec<AudioFormat>,
+  pub leveling: Leveling,
+  /// Mix all tracks into a single file instead of converting them one by one.
+  pub mix: bool,
+  /// Which track decides when the mix ends.
+  pub mix_duration: MixDuration,
+  /// Seconds over which the volume is evened out when a track in the mix ends, 0 for no ramp.
+  /// Recordings are already lined up, so the ramp is only audible as a volume jump.
+  pub dropout_transition: f64,
+  pub overwrite: OverwritePolicy,
+  /// How long ffmpeg may go without reporting anything before it's considered hung, 0 to wait
+  /// forever. This isn't a limit on the total time, long tracks can take a while.
+  pub stall_timeout_secs: u64,
+  /// Stop the whole run as soon as one track fails, instead of carrying on with the rest.
+  pub fail_fast: bool,
+  /// Run ffmpeg at a lower priority and on fewer cores, so the computer stays usable meanwhile.
+  pub low_priority: bool,
+  /// How many threads ffmpeg may use, 0 to let it decide.
+  pub threads: usize,
+  /// Decode every output after it's written to check it plays all the way through.
+  pub verify: bool,
+  /// Convert every track again, even ones an earlier run into the same folder already finished.
+  pub reprocess_all: bool,
+  /// Never fall back to an ffmpeg installed on the system when the recording doesn't bundle one.
+  pub bundled_ffmpeg_only: bool,
+  /// Folder to unpack the recording into instead of the cache, for when the system drive is short
+  /// on space.
+  pub work_dir: Option<PathBuf>,
+  /// Leave the unpacked recording where it is on exit, in `work_dir` or a temp folder, instead of
+  /// removing it. Ignored for encrypted recordings.
+  pub keep_extracted: bool,
+  /// Limits on what a recording may unpack to, to refuse damaged or malicious ones.
+  pub extract_limits: ExtractLimits,
+  /// Where recordings are cached and temp folders made when there's no `work_dir`. Not saved, it's
+  /// always the platform's folders unless a program using the library says otherwise.
+  #[serde(skip)]
+  pub dirs: Dirs,
+  /// Password for a recording whose files are encrypted. Never saved, and a recording opened with
+  /// one is never unpacked into the cache.
+  #[serde(skip)]
+  pub password: Option<String>,
+  /// File names of the tracks to convert, or `None` for every track in the pack.
+  #[serde(skip)]
+  pub tracks: Option<Vec<String>>,
+}
+
+impl Default for ProcessOptions {
+  fn default() -> Self {
+    Self {
+      format: AudioFormat::default(),
+      extra_formats: Vec::new(),
+      leveling: Leveling::default(),
+      mix: false,
+      mix_duration: MixDuration::default(),
+      dropout_transition: 0.0,
+      overwrite: OverwritePolicy::default(),
+      stall_timeout_secs: 10 * 60,
+      fail_fast: false,
+      low_priority: false,
+      threads: 0,
+      verify: false,
+      reprocess_all: false,
+      bundled_ffmpeg_only: false,
+      work_dir: None,
+      keep_extracted: false,
+      extract_limits: ExtractLimits::default(),
+      dirs: Dirs::platform(),
+      password: None,
+      tracks: None,
+    }
+  }
+}
+
+impl ProcessOptions {
+  /// The filter options for mixing all the tracks in one go.
+  pub fn mix_filter_options(&self) -> MixFilterOptions {
+    MixFilterOptions {
+      leveling: self.leveling,
+      duration: self.mix_duration,
+      dropout_transition: self.dropout_transition,
+      cut_off: None,
+    }
+  }
+
+  /// Every format a run saves, `format` first and without repeats.
+  pub fn formats(&self) -> Vec<AudioFormat> {
+    let mut formats = vec![self.format];
+    for format in &self.extra_formats {
+      if !formats.contains(format) {
+        formats.push(*format);
+      }
+    }
+    formats
+  }
+
+  fn stall_timeout(&self) -> Option<Duration> {
+    (self.stall_timeout_secs > 0).then(|| Duration::from_secs(self.stall_timeout_secs))
+  }
+
+  /// The number of threads ffmpeg is limited to, if any.
+  fn thread_count(&self) -> Option<usize> {
+    if self.threads > 0 {
+      Some(self.threads)
+    } else if self.low_priority {
+      // Leave half the cores for everything else
+      let cores = std::thread::available_parallelism().map_or(2, |cores| cores.get());
+      Some((cores / 2).max(1))
+    } else {
+      None
+    }
+  }
+
+  /// Arguments limiting the threads ffmpeg uses for the output they're placed before.
+  fn thread_args(&self) -> Vec<String> {
+    match self.thread_count() {
+      Some(threads) => vec![
+        "-threads".to_string(),
+        threads.to_string(),
+        "-filter_threads".to_string(),
+        threads.to_string(),
+      ],
+      None => Vec::new(),
+    }
+  }
+
+  fn includes_track(&self, filename: &str) -> bool {
+    self
+      .tracks
+      .as_ref()
+      .is_none_or(|tracks| tracks.iter().any(|track| track == filename))
+  }
+}
+
+/// A track that couldn't be converted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackFailure {
+  pub filename: String,
+  pub message: String,
+  /// The end of ffmpeg's log output, if ffmpeg got as far as running.
+  pub stderr: Option<String>,
+}
+
+impl TrackFailure {
+  fn new(filename: String, error: &ProcessError) -> Self {
+    Self {
+      filename,
+      message: error.to_string(),
+      stderr: error.ffmpeg_log().map(str::to_string),
+    }
+  }
+}
+
+/// What a finished run did with each track.
+///
+/// As JSON, `outputs` is a list of pairs of a track's name and its output path.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProcessReport {
+  /// Tracks whose output was written.
+  pub converted: Vec<String>,
+  /// Tracks whose output already existed and was left alone.
+  pub skipped: Vec<String>,
+  pub failed: Vec<TrackFailure>,
+  /// Where the outputs of the converted and skipped tracks are, by their names in those lists.
+  pub outputs: Vec<(String, PathBuf)>,
+  /// The Audacity project that was written, if the format is a project.
+  pub project: Option<PathBuf>,
+}
+
+impl ProcessReport {
+  pub fn total(&self) -> usize {
+    self.converted.len() + self.skipped.len() + self.failed.len()
+  }
+
+  /// Folds in the report of a later run over some of the same tracks, such as a retry of the ones
+  /// that failed. The later run's outcome wins for any track that appears in both.
+  pub fn merge(&mut self, later: ProcessReport) {
+    let redone = |name: &str| {
+      later.converted.iter().any(|track| track == name)
+        || later.skipped.iter().any(|track| track == name)
+        || later.failed.iter().any(|failure| failure.filename == name)
+    };
+    self.converted.retain(|track| !redone(track));
+    self.skipped.retain(|track| !redone(track));
+    self.failed.retain(|failure| !redone(&failure.filename));
+    self.outputs.retain(|(track, _)| !redone(track));
+
+    self.converted.extend(later.converted);
+    self.skipped.extend(later.skipped);
+    self.failed.extend(later.failed);
+    self.outputs.extend(later.outputs);
+    self.project = later.project.or(self.project.take());
+  }
+
+  /// Puts together the reports of converting the same tracks into several formats, one after the
+  /// other. A track only counts as converted if it worked in every format.
+  pub fn combine(reports: Vec<ProcessReport>) -> ProcessReport {
+    let mut combined = ProcessReport::default();
+    for report in reports {
+      for failure in report.failed {
+        if !combined
+          .failed
+          .iter()
+          .any(|failed| failed.filename == failure.filename)
+        {
+          combined.failed.push(failure);
+        }
+      }
+      for track in report.converted {
+        if !combined.converted.contains(&track) {
+          combined.converted.push(track);
+        }
+      }
+      for track in report.skipped {
+        if !combined.skipped.contains(&track) {
+          combined.skipped.push(track);
+        }
+      }
+      combined.outputs.extend(report.outputs);
+      combined.project = combined.project.or(report.project);
+    }
+
+    let failed = combined
+      .failed
+      .iter()
+      .map(|failure| failure.filename.clone())
+      .collect::<Vec<_>>();
+    combined.converted.retain(|track| !failed.contains(track));
+    // Skipped in one format but written in another still means something was done
+    let converted = combined.converted.clone();
+    combined
+      .skipped
+      .retain(|track| !failed.contains(track) && !converted.contains(track));
+    combined
+  }
+
+  /// Where the output of the track or mix called `name` in the report is.
+  pub fn output(&self, name: &str) -> Option<&Path> {
+    self
+      .outputs
+      .iter()
+      .find(|(track, _)| track == name)
+      .map(|(_, path)| path.as_path())
+  }
+}
+
+/// How far a run has got.
+///
+/// Serializes as the [`JsonEvent`](crate::JsonEvent) `--json` prints for it, and deserializes from
+/// one. An `Error` goes through as its message and causes, so it comes back as
+/// [`ProcessError::Other`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ProcessProgress {
+  Finished(ProcessReport),
+  Error(ProcessError),
+  /// Unpacking the bundled resources.
+  Extracting {
+    /// The file being unpacked.
+    file: String,
+    bytes_done: u64,
+    bytes_total: u64,
+  },
+  /// Reading the durations of the input tracks.
+  Probing {
+    current: usize,
+    total: usize,
+  },
+  Converting(ProgressInfo),
+  /// A track moved on to another stage of its conversion.
+  ///
+  /// Only reported when the tracks are converted one by one, a mix has just the one output.
+  Track {
+    filename: String,
+    state: TrackState,
+  },
+  /// Wrapping up the outputs once every conversion is done.
+  Packaging,
+  /// Writing the Audacity project file.
+  WritingProject,
+  /// A line ffmpeg logged while working on `file`.
+  Log {
+    file: String,
+    line: String,
+  },
+}
+
+/// Where a single track is in a run.
+///
+/// As JSON, an object with `state` in snake_case and the variant's fields next to it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum TrackState {
+  /// Waiting for the tracks before it.
+  Queued,
+  /// Being converted right now.
+  Started,
+  /// Converted, into an output of `output_size` bytes.
+  Finished { output_size: u64 },
+  /// Left alone since its output already existed.
+  Skipped,
+  /// Couldn't be converted, the run carries on with the next one.
+  Failed { error: String },
+}
+
+/// Which of the formats a run saves is being worked on, for progress over the whole run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FormatPass {
+  pub index: usize,
+  pub count: usize,
+}
+
+impl FormatPass {
+  /// A run that saves just the one format.
+  pub const ONLY: FormatPass = FormatPass { index: 0, count: 1 };
+
+  /// Turns progress through this pass into progress through every pass, assuming they each take
+  /// as long.
+  pub fn scale(&self, mut info: ProgressInfo) -> ProgressInfo {
+    info.current += self.index * info.total;
+    info.total *= self.count;
+    if let Some(total) = info.audio_total_secs {
+      info.audio_done_secs += self.index as f64 * total;
+      info.audio_total_secs = Some(total * self.count as f64);
+    }
+    info
+  }
+}
+
+/// How far the conversion has got, named as in the `converting` [`JsonEvent`](crate::JsonEvent).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProgressInfo {
+  #[serde(rename = "file")]
+  pub filename: String,
+  pub current: usize,
+  pub total: usize,
+  /// How far through the current file ffmpeg is, if its duration is known.
+  pub file_fraction: Option<f32>,
+  /// Seconds of audio processed so far across the whole run.
+  pub audio_done_secs: f64,
+  /// Seconds of audio the whole run has to get through, if every duration is known.
+  pub audio_total_secs: Option<f64>,
+}
+
+/// A progress report parsed from ffmpeg's `-progress` output.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FfmpegProgress {
+  /// Timestamp of the output written so far, in seconds.
+  pub out_time: Option<f64>,
+  /// Whether this was the final report (`progress=end`).
+  pub finished: bool,
+}
+
+/// Incremental parser for the `key=value` stream written by `ffmpeg -progress`.
+///
+/// Output can arrive split at arbitrary points, so incomplete lines are buffered until the rest of
+/// them comes in.
+#[derive(Debug, Default)]
+pub struct ProgressParser {
+  partial: Vec<u8>,
+  current: FfmpegProgress,
+}
+
+impl ProgressParser {
+  /// Feeds a chunk of output into the parser, returning a report for every block it completed.
+  pub fn feed(&mut self, chunk: &[u8]) -> Vec<FfmpegProgress> {
+    self.partial.extend_from_slice(chunk);
+
+    let mut reports = Vec::new();
+    while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+      let line = self.partial.drain(..=end).collect::<Vec<_>>();
+      let line = String::from_utf8_lossy(&line);
+      let Some((key, value)) = line.trim().split_once('=') else {
+        continue;
+      };
+
+      match key {
+        // Despite the name, out_time_ms is in microseconds as well
+        "out_time_us" | "out_time_ms" => {
+          if let Ok(micros) = value.parse::<i64>() {
+            self.current.out_time = Some(micros.max(0) as f64 / 1_000_000.0);
+          }
+        }
+        // Every block ends with a progress line
+        "progress" => {
+          self.current.finished = value == "end";
+          reports.push(self.current.clone());
+        }
+        _ => {}
+      }
+    }
+
+    reports
+  }
+}
+
+/// Reads the `Duration: HH:MM:SS.ss` that ffmpeg prints for an input, in seconds.
+pub fn parse_duration(stderr: &str) -> Option<f64> {
+  let rest = stderr.split("Duration: ").nth(1)?;
+  let timestamp = rest.split(',').next()?.trim();
+
+  let mut seconds = 0.0;
+  for part in timestamp.split(':') {
+    seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+  }
+  Some(seconds)
+}
+
+pub static AUP_HEADER: &str = concat!(
+  "<?xml version=\"1.0\" standalone=\"no\" ?>\n",
+  "<!DOCTYPE project PUBLIC \"-//audacityproject-1.3.0//DTD//EN\" \"http://audacity.sourceforge.net/xml/audacityproject-1.3.0.dtd\" >\n",
+  "<project xmlns=\"http://audacity.sourceforge.net/xml/\" projname=\"Craig\" version=\"1.3.0\" audacityversion=\"2.2.2\" rate=\"48000.0\">\n"
+);
+
+pub static AUP_FOLDER_NAME: &str = "craig_data";
+
+/// How many lines of ffmpeg's stderr are kept for error reports.
+const STDERR_TAIL_LINES: usize = 50;
+
+/// An ffmpeg run that exited unsuccessfully, along with the end of what it logged to stderr.
+#[derive(Debug)]
+pub struct FfmpegError {
+  pub message: String,
+  /// How ffmpeg exited, if it exited on its own.
+  pub status: Option<ExitStatus>,
+  pub stderr: String,
+}
+
+impl fmt::Display for FfmpegError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.message)
+  }
+}
+
+impl std::error::Error for FfmpegError {}
+
+/// Why a run, or a single track in it, failed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ProcessError {
+  /// The resources don't include ffmpeg.
+  FfmpegMissing,
+  /// ffmpeg exited unsuccessfully, stopped responding or wrote a broken file.
+  FfmpegFailed(FfmpegError),
+  /// There's no ffmpeg that can be used for the run, the message says why.
+  FfmpegUnusable(String),
+  /// Reading or writing a file went wrong.
+  Io {
+    path: PathBuf,
+    source: std::io::Error,
+  },
+  /// The pack doesn't contain any tracks.
+  NoTracksFound,
+  /// None of the tracks picked for the run are in the pack.
+  SelectedTracksMissing,
+  Cancelled,
+  /// Files can't be created in the output folder.
+  OutputNotWritable {
+    path: PathBuf,
+    source: std::io::Error,
+  },
+  /// The output folder can't be used, the message says why.
+  OutputUnusable(String),
+  /// An output already exists and the overwrite policy says to stop.
+  OutputExists(String),
+  /// The output drive doesn't have room for the run, in bytes.
+  NotEnoughSpace {
+    needed: u64,
+    available: u64,
+  },
+  /// The folder the recording is unpacked into doesn't have room for it, in bytes.
+  NotEnoughSpaceToUnpack {
+    dir: PathBuf,
+    needed: u64,
+    available: u64,
+  },
+  /// The pack is encrypted and no password was given.
+  PasswordRequired,
+  /// The password given doesn't decrypt the pack.
+  WrongPassword,
+  /// Something outside the processing itself went wrong.
+  Other(anyhow::Error),
+}
+
+impl ProcessError {
+  /// Wraps an I/O error with the path it happened on.
+  fn io(path: &Path) -> impl FnOnce(std::io::Error) -> Self {
+    move |source| ProcessError::Io {
+      path: path.to_path_buf(),
+      source,
+    }
+  }
+
+  /// Like [`ProcessError::io`], but permission problems are reported as the output folder not
+  /// being writable.
+  fn output_io(path: &Path) -> impl FnOnce(std::io::Error) -> Self {
+    move |source| match source.kind() {
+      std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::ReadOnlyFilesystem => {
+        ProcessError::OutputNotWritable {
+          path: path.to_path_buf(),
+          source,
+        }
+      }
+      _ => ProcessError::Io {
+        path: path.to_path_buf(),
+        source,
+      },
+    }
+  }
+
+  /// The messages of everything that led to this error, outermost first, leaving out its own.
+  pub fn causes(&self) -> Vec<String> {
+    let mut causes: Vec<String> = match self {
+      ProcessError::Other(e) => e.chain().skip(1).map(ToString::to_string).collect(),
+      e => std::iter::successors(std::error::Error::source(e), |e| e.source())
+        .map(ToString::to_string)
+        .collect(),
+    };
+    // Wrappers like FfmpegFailed say the same as the error they wrap
+    let message = self.to_string();
+    causes.dedup();
+    causes.retain(|cause| *cause != message);
+    causes
+  }
+
+  /// The end of ffmpeg's log output, if ffmpeg was what failed and it logged anything.
+  pub fn ffmpeg_log(&self) -> Option<&str> {
+    match self {
+      ProcessError::FfmpegFailed(e) if !e.stderr.is_empty() => Some(&e.stderr),
+      _ => None,
+    }
+  }
+}
+
+impl fmt::Display for ProcessError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ProcessError::FfmpegMissing => write!(
+        f,
+        "{} not found in resources",
+        crate::platform::FFMPEG_BINARY
+      ),
+      ProcessError::FfmpegFailed(e) => e.fmt(f),
+      ProcessError::FfmpegUnusable(message) => f.write_str(message),
+      ProcessError::Io { path, source } => {
+        write!(f, "Couldn't access {}: {source}", path.display())
+      }
+      ProcessError::NoTracksFound => f.write_str("No audio tracks were found in this package"),
+      ProcessError::SelectedTracksMissing => {
+        f.write_str("None of the selected tracks are in this package")
+      }
+      ProcessError::Cancelled => f.write_str("Processing was cancelled"),
+      ProcessError::OutputNotWritable { path, source } => write!(
+        f,
+        "Can't write to the output folder ({source}): {}",
+        path.display()
+      ),
+      ProcessError::OutputUnusable(message) => f.write_str(message),
+      ProcessError::OutputExists(name) => write!(f, "{name} already exists in the output folder"),
+      ProcessError::NotEnoughSpace { needed, available } => write!(
+        f,
+        "Not enough disk space in the output folder: about {} is needed, but only {} is free",
+        format_bytes(*needed),
+        format_bytes(*available)
+      ),
+      ProcessError::NotEnoughSpaceToUnpack {
+        dir,
+        needed,
+        available,
+      } => write!(
+        f,
+        "Not enough disk space to unpack the recording into {}: {} is needed, but only {} is free",
+        dir.display(),
+        format_bytes(*needed),
+        format_bytes(*available)
+      ),
+      ProcessError::PasswordRequired => f.write_str("This package is protected with a password"),
+      ProcessError::WrongPassword => f.write_str("The password for this package is wrong"),
+      ProcessError::Other(e) => e.fmt(f),
+    }
+  }
+}
+
+impl std::error::Error for ProcessError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      ProcessError::FfmpegFailed(e) => Some(e),
+      ProcessError::Io { source, .. } | ProcessError::OutputNotWritable { source, .. } => {
+        Some(source)
+      }
+      _ => None,
+    }
+  }
+}
+
+impl From<anyhow::Error> for ProcessError {
+  /// Unwraps a [`ProcessError`] that was passed along as an [`anyhow::Error`], anything else
+  /// becomes [`ProcessError::Other`].
+  fn from(error: anyhow::Error) -> Self {
+    error.downcast().unwrap_or_else(ProcessError::Other)
+  }
+}
+
+/// How waiting on an ffmpeg child ended.
+enum ChildOutcome {
+  Exited(ExitStatus),
+  /// Nothing came through `activity` within the stall timeout, so the child was killed.
+  Stalled,
+  Cancelled,
+}
+
+/// Waits for an ffmpeg child to exit, killing it if the run gets cancelled or it stalls first.
+///
+/// Every message on `activity` means the child is still making progress and resets the stall
+/// timeout.
+async fn wait_for_child(
+  mut child: Child,
+  cancel: &CancellationToken,
+  activity: mpsc::UnboundedReceiver<()>,
+  stall_timeout: Option<Duration>,
+) -> std::io::Result<ChildOutcome> {
+  match watch_child(child.wait(), cancel, activity, stall_timeout).await {
+    Ok(status) => Ok(ChildOutcome::Exited(status?)),
+    Err(outcome) => {
+      // kill() also reaps the child, so it's gone by the time we return
+      let _ = child.kill().await;
+      Ok(outcome)
+    }
+  }
+}
+
+/// Runs `wait` to completion, unless the run gets cancelled or nothing comes through `activity`
+/// for `stall_timeout` first, in which case that's the error.
+async fn watch_child<T>(
+  wait: impl Future<Output = T>,
+  cancel: &CancellationToken,
+  mut activity: mpsc::UnboundedReceiver<()>,
+  stall_timeout: Option<Duration>,
+) -> Result<T, ChildOutcome> {
+  let watchdog = tokio::time::sleep(stall_timeout.unwrap_or_default());
+  tokio::pin!(watchdog, wait);
+
+  loop {
+    tokio::select! {
+      result = &mut wait => return Ok(result),
+      _ = cancel.cancelled() => return Err(ChildOutcome::Cancelled),
+      Some(()) = activity.recv() => {
+        if let Some(timeout) = stall_timeout {
+          watchdog.as_mut().reset(Instant::now() + timeout);
+        }
+      }
+      _ = &mut watchdog, if stall_timeout.is_some() => return Err(ChildOutcome::Stalled),
+    }
+  }
+}
+
+/// Arguments every ffmpeg command starts with, see [`ffmpeg_command`].
+pub const FFMPEG_BASE_ARGS: [&str; 2] = ["-hide_banner", "-nostdin"];
+
+/// Arguments for long-running commands: progress goes to stdout for [`run_ffmpeg`] instead of the
+/// usual stats on stderr, and the output is overwritten without asking.
+const PROGRESS_ARGS: [&str; 4] = ["-nostats", "-progress", "pipe:1", "-y"];
+
+/// Starts an ffmpeg command with the arguments and process setup every run needs.
+///
+/// ffmpeg gets no stdin at all, so it can't sit waiting on a prompt (like whether to overwrite a
+/// file) that nobody will ever see with the console window hidden.
+fn ffmpeg_command(ffmpeg: &Path, low_priority: bool) -> Command {
+  let mut command = Command::new(ffmpeg);
+  command
+    .args(FFMPEG_BASE_ARGS)
+    .kill_on_drop(true)
+    .stdin(Stdio::null());
+
+  crate::platform::configure_child(&mut command, low_priority);
+  command
+}
+
+/// Runs a short ffmpeg command and collects its output.
+async fn ffmpeg_output(
+  ffmpeg: &Path,
+  args: &[&OsStr],
+  low_priority: bool,
+) -> std::io::Result<Output> {
+  ffmpeg_command(ffmpeg, low_priority)
+    .args(args)
+    .output()
+    .await
+}
+
+/// Finds out the duration of an input file in seconds, with ffprobe if there is one and from
+/// ffmpeg's input summary otherwise.
+async fn probe_duration(
+  ffmpeg: &Path,
+  ffprobe: Option<&Path>,
+  input: &Path,
+  options: &ProcessOptions,
+) -> Option<f64> {
+  if let Some(ffprobe) = ffprobe {
+    match crate::probe::probe_duration(ffprobe, input).await {
+      Ok(Some(duration)) => return Some(duration),
+      Ok(None) => {}
+      Err(e) => println!("{e:#}, asking ffmpeg instead"),
+    }
+  }
+
+  // Without an output ffmpeg exits with an error, but the input summary is printed regardless
+  let output = ffmpeg_output(
+    ffmpeg,
+    &["-i".as_ref(), input.as_ref()],
+    options.low_priority,
+  )
+  .await
+  .ok()?;
+  parse_duration(&String::from_utf8_lossy(&output.stderr))
+}
+
+/// What an ffmpeg binary reports about itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FfmpegInfo {
+  pub version: String,
+  /// Names of the audio encoders the build includes.
+  pub encoders: Vec<String>,
+}
+
+impl FfmpegInfo {
+  pub fn supports(&self, format: AudioFormat) -> bool {
+    self
+      .encoders
+      .iter()
+      .any(|encoder| encoder == format.encoder())
+  }
+}
+
+/// Reads the version out of `ffmpeg -version` output, e.g. `6.0-full_build-www.gyan.dev`.
+pub fn parse_ffmpeg_version(output: &str) -> Option<String> {
+  let rest = output.lines().next()?.strip_prefix("ffmpeg version ")?;
+  rest.split_whitespace().next().map(str::to_string)
+}
+
+/// Lists the audio encoders in `ffmpeg -encoders` output.
+///
+/// Each encoder line after the `------` separator starts with a block of capability flags, the
+/// first of which is `A` for audio.
+pub fn parse_ffmpeg_encoders(output: &str) -> Vec<String> {
+  output
+    .lines()
+    .skip_while(|line| !line.trim_start().starts_with("---"))
+    .skip(1)
+    .filter_map(|line| {
+      let mut parts = line.split_whitespace();
+      let flags = parts.next()?;
+      let name = parts.next()?;
+      flags.starts_with('A').then(|| name.to_string())
+    })
+    .collect()
+}
+
+/// Makes sure ffmpeg actually runs, and finds out its version and which encoders it has.
+pub async fn verify_ffmpeg(ffmpeg: &Path) -> anyhow::Result<FfmpegInfo> {
+  let broken = || {
+    format!(
+      "The bundled ffmpeg ({}) could not be run, the download may be damaged",
+      ffmpeg.display()
+    )
+  };
+
+  let output = ffmpeg_output(ffmpeg, &["-version".as_ref()], false)
+    .await
+    .with_context(broken)?;
+  let version = parse_ffmpeg_version(&String::from_utf8_lossy(&output.stdout))
+    .filter(|_| output.status.success())
+    .ok_or_else(|| anyhow::anyhow!(broken()))?;
+
+  let output = ffmpeg_output(ffmpeg, &["-encoders".as_ref()], false)
+    .await
+    .with_context(broken)?;
+  let encoders = parse_ffmpeg_encoders(&String::from_utf8_lossy(&output.stdout));
+
+  Ok(FfmpegInfo { version, encoders })
+}
+
+/// Runs an ffmpeg command to completion, failing with [`ProcessError::FfmpegFailed`] if it exits
+/// unsuccessfully or stops responding.
+///
+/// The command must have been set up with `-progress pipe:1`; each report is passed to
+/// `on_progress` as it comes in. stderr is drained on its own task while the child runs so a
+/// chatty ffmpeg can't stall on a full pipe, and only the last [`STDERR_TAIL_LINES`] lines are
+/// kept. Every line is also passed to `on_log` as it arrives. `activity` describes what ffmpeg is
+/// doing for error messages, e.g. "converting foo.flac".
+async fn run_ffmpeg(
+  mut command: Command,
+  cancel: &CancellationToken,
+  options: &ProcessOptions,
+  activity: &str,
+  mut on_progress: impl FnMut(FfmpegProgress) + Send + 'static,
+  mut on_log: impl FnMut(&str) + Send + 'static,
+) -> Result<(), ProcessError> {
+  let program = PathBuf::from(command.as_std().get_program());
+  command
+    .kill_on_drop(true)
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped());
+
+  let mut child = command.spawn().map_err(ProcessError::io(&program))?;
+  let (activity_tx, activity_rx) = mpsc::unbounded_channel();
+
+  let mut stdout = child.stdout.take().expect("stdout is piped");
+  let stdout_activity = activity_tx.clone();
+  let progress_task = tokio::spawn(async move {
+    let mut parser = ProgressParser::default();
+    let mut buf = [0u8; 4096];
+    while let Ok(read) = stdout.read(&mut buf).await {
+      if read == 0 {
+        break;
+      }
+      let _ = stdout_activity.send(());
+      for report in parser.feed(&buf[..read]) {
+        on_progress(report);
+      }
+    }
+  });
+
+  let stderr = child.stderr.take().expect("stderr is piped");
+  let stderr_task = tokio::spawn(async move {
+    let mut reader = BufReader::new(stderr);
+    let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+    let mut line = Vec::new();
+    while let Ok(read) = reader.read_until(b'\n', &mut line).await {
+      if read == 0 {
+        break;
+      }
+      let _ = activity_tx.send(());
+      if tail.len() == STDERR_TAIL_LINES {
+        tail.pop_front();
+      }
+      let text = String::from_utf8_lossy(&line).trim_end().to_string();
+      on_log(&text);
+      tail.push_back(text);
+      line.clear();
+    }
+    Vec::from(tail).join("\n")
+  });
+
+  let outcome = wait_for_child(child, cancel, activity_rx, options.stall_timeout())
+    .await
+    .map_err(ProcessError::io(&program))?;
+  let _ = progress_task.await;
+  let stderr = stderr_task.await.unwrap_or_default();
+
+  let (message, status) = match outcome {
+    ChildOutcome::Exited(status) if status.success() => return Ok(()),
+    ChildOutcome::Exited(status) => (
+      format!("ffmpeg failed while {activity} with status: {status}"),
+      Some(status),
+    ),
+    ChildOutcome::Stalled => (
+      format!(
+        "ffmpeg stopped responding while {activity} and was stopped after {} without progress",
+        format_duration(Duration::from_secs(options.stall_timeout_secs))
+      ),
+      None,
+    ),
+    ChildOutcome::Cancelled => return Err(ProcessError::Cancelled),
+  };
+  Err(ProcessError::FfmpegFailed(FfmpegError {
+    message,
+    status,
+    stderr,
+  }))
+}
+
+fn format_duration(duration: Duration) -> String {
+  let secs = duration.as_secs();
+  match (secs / 60, secs % 60) {
+    (0, secs) => format!("{secs} seconds"),
+    (1, 0) => "1 minute".to_string(),
+    (mins, 0) => format!("{mins} minutes"),
+    (mins, secs) => format!("{mins}m {secs}s"),
+  }
+}
+
+/// The most inputs a single amix filter is given.
+const MAX_AMIX_INPUTS: usize = 32;
+
+/// Settings for the filter graph that mixes the tracks together.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MixFilterOptions {
+  /// Applied to each input before mixing, and again to each mix.
+  pub leveling: Leveling,
+  pub duration: MixDuration,
+  /// amix's `dropout_transition`, in seconds.
+  pub dropout_transition: f64,
+  /// Where the mix gets trimmed, in seconds, for when `duration` alone won't end it in the right
+  /// place.
+  pub cut_off: Option<f64>,
+}
+
+/// Builds the `-filter_complex` graph mixing `inputs` input files into a single `[aud]` output.
+///
+/// amix is given at most [`MAX_AMIX_INPUTS`] inputs at once, so bigger mixes are chained: each
+/// full mix becomes the first input of the next one, which keeps `first` and `shortest` meaning the
+/// same as they would for one big amix. Every input and intermediate mix gets a label of its own
+/// (`[in3]`, `[mix0]`), so chunks can't end up reading each other's pads.
+pub fn build_mix_filter(inputs: usize, options: &MixFilterOptions) -> String {
+  let input_filter = options.leveling.filter().unwrap_or("anull");
+  let mix_extra = options
+    .leveling
+    .filter()
+    .map(|filter| format!(",{filter}"))
+    .unwrap_or_default();
+
+  let amix_options = format!(
+    "duration={}:dropout_transition={}",
+    options.duration.amix_value(),
+    options.dropout_transition
+  );
+
+  let mut filter = String::new();
+  let mut pending = Vec::with_capacity(MAX_AMIX_INPUTS);
+  let mut mixes = 0;
+  for i in 0..inputs {
+    filter.push_str(&format!("[{i}:a]{input_filter}[in{i}];"));
+    pending.push(format!("[in{i}]"));
+
+    // Only start another chunk if there are inputs left to go in it
+    if pending.len() == MAX_AMIX_INPUTS && i + 1 < inputs {
+      let label = format!("[mix{mixes}]");
+      mixes += 1;
+      filter.push_str(&format!(
+        "{}amix=inputs={}:{amix_options}{mix_extra}{label};",
+        pending.concat(),
+        pending.len()
+      ));
+      pending = vec![label];
+    }
+  }
+
+  let trim = options
+    .cut_off
+    .map(|cut_off| format!(",atrim=end={cut_off}"))
+    .unwrap_or_default();
+  filter.push_str(&format!(
+    "{}amix=inputs={}:{amix_options}{trim}{mix_extra}[aud]",
+    pending.concat(),
+    pending.len()
+  ));
+  filter
+}
+
+/// Plans how to mix more inputs than one ffmpeg run should take, by mixing them down in batches.
+///
+/// Each tier lists batches of the previous tier's outputs (the inputs themselves, for the first
+/// tier), and each batch gets mixed into an intermediate file. Tiers are added until at most
+/// `batch_size` files are left for the final mix, so an empty plan means the inputs can be mixed
+/// directly.
+pub fn plan_mix_tiers(inputs: usize, batch_size: usize) -> Vec<Vec<Range<usize>>> {
+  // Batches of one would never get any smaller
+  let batch_size = batch_size.max(2);
+
+  let mut tiers = Vec::new();
+  let mut remaining = inputs;
+  while remaining > batch_size {
+    // Spread the inputs evenly rather than leaving a tiny batch at the end
+    let count = remaining.div_ceil(batch_size);
+    let tier = (0..count)
+      .map(|i| i * remaining / count..(i + 1) * remaining / count)
+      .collect::<Vec<_>>();
+    remaining = tier.len();
+    tiers.push(tier);
+  }
+  tiers
+}
+
+/// The filter options for each tier of a mix planned by [`plan_mix_tiers`], followed by the ones
+/// for the final mix, given the `durations` of the original inputs.
+///
+/// Batches only hold some of the tracks, so they always run to the end of the longest one. The
+/// final mix then gets cut where `options.duration` would have ended a mix of all the tracks,
+/// which runs to the end of the longest batch if that isn't known.
+pub fn plan_mix_filters(
+  options: &MixFilterOptions,
+  tiers: usize,
+  durations: &[Option<f64>],
+) -> Vec<MixFilterOptions> {
+  if tiers == 0 {
+    return vec![options.clone()];
+  }
+
+  let batches = MixFilterOptions {
+    leveling: Leveling::Off,
+    duration: MixDuration::Longest,
+    cut_off: None,
+    ..options.clone()
+  };
+  let mut filters = vec![batches; tiers + 1];
+  // Inputs only need leveling once, on their way into the first tier
+  filters[0].leveling = options.leveling;
+  filters[tiers].cut_off = match options.duration {
+    MixDuration::Longest => None,
+    duration => duration.mix_length(durations),
+  };
+  filters
+}
+
+/// The arguments (following [`FFMPEG_BASE_ARGS`]) for converting the track `input` into
+/// `output`.
+pub fn build_convert_command(
+  input: &Path,
+  output: &Path,
+  options: &ProcessOptions,
+) -> Vec<OsString> {
+  let mut args = os_args(PROGRESS_ARGS);
+  args.push("-i".into());
+  args.push(input.into());
+
+  if let Some(filter) = options.leveling.filter() {
+    args.extend(os_args(["-af", filter]));
+  }
+
+  args.extend(os_args(options.thread_args()));
+  args.extend(os_args(options.format.ffmpeg_args()));
+  args.push(output.into());
+  args
+}
+
+/// How much of a track a preview plays, in seconds.
+pub const PREVIEW_SECS: u32 = 20;
+
+/// The arguments (following [`FFMPEG_BASE_ARGS`]) for cutting the first [`PREVIEW_SECS`] of the
+/// track `input` into a WAV at `output`, levelled like a run with `leveling` and at `volume`.
+pub fn build_preview_command(
+  input: &Path,
+  output: &Path,
+  leveling: Leveling,
+  volume: f32,
+) -> Vec<OsString> {
+  let mut args = os_args(["-y", "-t", &PREVIEW_SECS.to_string()]);
+  args.push("-i".into());
+  args.push(input.into());
+
+  let mut filters: Vec<String> = leveling.filter().map(String::from).into_iter().collect();
+  filters.push(format!("volume={volume:.2}"));
+  args.extend(os_args(["-af", &filters.join(",")]));
+
+  args.extend(os_args(["-c:a", "pcm_s16le", "-f", "wav"]));
+  args.push(output.into());
+  args
+}
+
+/// Cuts a preview of the track `input` into the WAV `output`, see [`build_preview_command`].
+pub async fn render_preview(
+  ffmpeg: &Path,
+  input: &Path,
+  output: &Path,
+  leveling: Leveling,
+  volume: f32,
+) -> anyhow::Result<()> {
+  let args = build_preview_command(input, output, leveling, volume);
+  let result = ffmpeg_command(ffmpeg, false)
+    .args(&args)
+    .output()
+    .await
+    .context(format!("Failed to run {}", ffmpeg.display()))?;
+  if !result.status.success() {
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    anyhow::bail!(
+      "ffmpeg couldn't cut a preview of {}: {}",
+      input.display(),
+      stderr.lines().last().unwrap_or("no details")
+    );
+  }
+  Ok(())
+}
+
+/// The arguments (following [`FFMPEG_BASE_ARGS`]) for mixing `inputs` together into `output`
+/// with the filter `mix`.
+pub fn build_mix_command(
+  inputs: &[PathBuf],
+  output: &Path,
+  mix: &MixFilterOptions,
+  options: &ProcessOptions,
+) -> Vec<OsString> {
+  let mut args = os_args(PROGRESS_ARGS);
+  for input in inputs {
+    args.push("-i".into());
+    args.push(input.into());
+  }
+
+  let filter = build_mix_filter(inputs.len(), mix);
+  args.extend(os_args(["-filter_complex", &filter, "-map", "[aud]"]));
+
+  args.extend(os_args(options.thread_args()));
+  args.extend(os_args(options.format.ffmpeg_args()));
+  args.push(output.into());
+  args
+}
+
+/// The full ffmpeg command a run with `options` would start for the first of `tracks`, or for
+/// mixing all of them, written out to be read or pasted into a terminal.
+///
+/// The tracks are named as they are in the recording rather than where they get unpacked to. A mix
+/// of more tracks than one ffmpeg takes is shown as a single command, though it's run in batches.
+pub fn command_preview(tracks: &[String], output_dir: &Path, options: &ProcessOptions) -> String {
+  let mut output_dir = output_dir.to_path_buf();
+  if options.format.is_project_format() {
+    output_dir.push(AUP_FOLDER_NAME);
+  }
+  let extension = options.format.extension();
+
+  let args = if options.mix {
+    let inputs = tracks.iter().map(PathBuf::from).collect::<Vec<_>>();
+    let output = output_dir.join(format!("craig.{extension}"));
+    build_mix_command(
+      &inputs,
+      &part_path(&output),
+      &options.mix_filter_options(),
+      options,
+    )
+  } else {
+    let input = tracks.first().map(String::as_str).unwrap_or("track.flac");
+    let output = output_dir.join(output_file_name(input, extension));
+    build_convert_command(Path::new(input), &part_path(&output), options)
+  };
+
+  std::iter::once(OsString::from("ffmpeg"))
+    .chain(os_args(FFMPEG_BASE_ARGS))
+    .chain(args)
+    .map(|arg| {
+      let arg = arg.to_string_lossy();
+      // Quoted unless it's plainly safe, filters are full of characters shells care about
+      let plain = arg
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_./:=,+@%".contains(c));
+      if arg.is_empty() || !plain {
+        format!("\"{}\"", arg.replace('"', "\\\""))
+      } else {
+        arg.into_owned()
+      }
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+fn os_args<S: AsRef<OsStr>>(args: impl IntoIterator<Item = S>) -> Vec<OsString> {
+  args
+    .into_iter()
+    .map(|arg| arg.as_ref().to_os_string())
+    .collect()
+}
+
+/// Compares file names the way people expect, so `2-foo.flac` comes before `10-bar.flac`.
+///
+/// Runs of digits are compared by their value and everything else case-insensitively. Names that
+/// only differ in case or leading zeros fall back to a plain comparison, so the order is total.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+  fn take_digits(chars: &mut Peekable<impl Iterator<Item = char>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+      digits.push(c);
+    }
+    digits
+  }
+
+  let mut a_chars = a.chars().peekable();
+  let mut b_chars = b.chars().peekable();
+  loop {
+    let ordering = match (a_chars.peek().copied(), b_chars.peek().copied()) {
+      (None, None) => return a.cmp(b),
+      (None, Some(_)) => return Ordering::Less,
+      (Some(_), None) => return Ordering::Greater,
+      (Some(a_char), Some(b_char)) if a_char.is_ascii_digit() && b_char.is_ascii_digit() => {
+        let a_digits = take_digits(&mut a_chars);
+        let b_digits = take_digits(&mut b_chars);
+        let a_number = a_digits.trim_start_matches('0');
+        let b_number = b_digits.trim_start_matches('0');
+        // More significant digits means a bigger number, otherwise compare digit by digit
+        a_number
+          .len()
+          .cmp(&b_number.len())
+          .then_with(|| a_number.cmp(b_number))
+      }
+      (Some(a_char), Some(b_char)) => {
+        a_chars.next();
+        b_chars.next();
+        a_char.to_lowercase().cmp(b_char.to_lowercase())
+      }
+    };
+
+    if ordering != Ordering::Equal {
+      return ordering;
+    }
+  }
+}
+
+/// Roughly how many bytes per second of audio a format produces, assuming 48 kHz stereo.
+fn bytes_per_second(format: AudioFormat) -> f64 {
+  const PCM_16_STEREO: f64 = 48_000.0 * 2.0 * 2.0;
+  match format {
+    AudioFormat::WAV => PCM_16_STEREO,
+    // Lossless compression of speech typically lands around 60% of PCM
+    AudioFormat::FLAC | AudioFormat::Audacity | AudioFormat::ALAC => PCM_16_STEREO * 0.6,
+    // ffmpeg's aac encoder defaults to 128 kbps
+    AudioFormat::AAC => 128_000.0 / 8.0,
+  }
+}
+
+/// Estimates how much disk space converting tracks of the given durations (in seconds) needs.
+///
+/// This leans towards overestimating, so it's suitable for checking free space before a run.
+pub fn estimate_output_size(durations: &[f64], format: AudioFormat, mix: bool) -> u64 {
+  const FUDGE_FACTOR: f64 = 1.1;
+
+  let seconds = if mix {
+    // The mix is as long as the longest track
+    durations.iter().copied().fold(0.0, f64::max)
+  } else {
+    durations.iter().sum()
+  };
+  (seconds * bytes_per_second(format) * FUDGE_FACTOR) as u64
+}
+
+/// Advice on the chosen format for a particular recording, see [`format_hint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FormatHint {
+  /// WAV would take a lot of space, for no better sound than FLAC.
+  LargeWav,
+  /// Separate tracks are usually for editing, which AAC loses quality to.
+  LossyForEditing,
+  /// A long mix is usually for sharing, where AAC is far smaller.
+  LosslessMix,
+  /// A project only makes sense with separate tracks to edit.
+  ProjectOfMix,
+}
+
+impl FormatHint {
+  pub fn message(&self) -> &'static str {
+    match self {
+      FormatHint::LargeWav => {
+        "WAV takes about twice the space of FLAC for the same sound. For editing, FLAC or the Audacity project is recommended"
+      }
+      FormatHint::LossyForEditing => {
+        "AAC loses quality every time it's edited and saved. For editing, FLAC or the Audacity project is recommended"
+      }
+      FormatHint::LosslessMix => {
+        "For sharing or listening to the mix, AAC is recommended and takes a fraction of the space"
+      }
+      FormatHint::ProjectOfMix => {
+        "The Audacity project is for editing the tracks separately, but mixing leaves only one"
+      }
+    }
+  }
+}
+
+/// Advice on converting `tracks` tracks of a recording `length` seconds long into `format`, if
+/// there's any worth giving.
+pub fn format_hint(
+  tracks: usize,
+  length: f64,
+  mix: bool,
+  format: AudioFormat,
+) -> Option<FormatHint> {
+  /// How much audio it takes before the size of the output is worth bringing up.
+  const LONG_OUTPUT_SECS: f64 = 60.0 * 60.0;
+
+  let output_secs = if mix { length } else { length * tracks as f64 };
+  match format {
+    AudioFormat::Audacity if mix => Some(FormatHint::ProjectOfMix),
+    AudioFormat::WAV if mix && output_secs >= LONG_OUTPUT_SECS => Some(FormatHint::LosslessMix),
+    AudioFormat::WAV if output_secs >= LONG_OUTPUT_SECS => Some(FormatHint::LargeWav),
+    AudioFormat::AAC if !mix && tracks > 1 => Some(FormatHint::LossyForEditing),
+    AudioFormat::FLAC | AudioFormat::ALAC if mix && output_secs >= LONG_OUTPUT_SECS => {
+      Some(FormatHint::LosslessMix)
+    }
+    _ => None,
+  }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+  const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+  if bytes < 1024 {
+    return format!("{bytes} B");
+  }
+  let mut size = bytes as f64 / 1024.0;
+  let mut unit = 0;
+  while size >= 1024.0 && unit < UNITS.len() - 1 {
+    size /= 1024.0;
+    unit += 1;
+  }
+  format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Formats a length in seconds like a clock, e.g. 1:02:03 or 2:03.
+pub fn format_track_length(secs: f64) -> String {
+  let secs = secs.round() as u64;
+  match (secs / 3600, secs / 60 % 60, secs % 60) {
+    (0, mins, secs) => format!("{mins}:{secs:02}"),
+    (hours, mins, secs) => format!("{hours}:{mins:02}:{secs:02}"),
+  }
+}
+
+/// Makes sure outputs won't end up somewhere that gets deleted automatically, like the extracted
+/// resources (if they have been extracted) or the system temp folder.
+pub fn check_output_location(
+  output_path: &Path,
+  resource_path: Option<&Path>,
+) -> Result<(), ProcessError> {
+  use crate::platform::canonicalize_lenient;
+
+  let output_path = canonicalize_lenient(output_path);
+  if resource_path
+    .is_some_and(|resource_path| output_path.starts_with(canonicalize_lenient(resource_path)))
+  {
+    return Err(ProcessError::OutputUnusable(
+      "The output folder is inside the unpacked recording files, which are deleted when otterpack closes. Choose a folder like your Documents folder instead.".to_string(),
+    ));
+  }
+  if output_path.starts_with(canonicalize_lenient(&std::env::temp_dir())) {
+    return Err(ProcessError::OutputUnusable(
+      "The output folder is inside the temporary files folder, which can be cleaned up at any time. Choose a folder like your Documents folder instead.".to_string(),
+    ));
+  }
+  Ok(())
+}
+
+/// How many files and folders are already in the output folder `dir`, not counting the ones
+/// otterpack itself leaves there while working, like its lock file and `.part` files.
+///
+/// A folder that doesn't exist yet has nothing in it.
+pub fn existing_output_count(dir: &Path) -> std::io::Result<usize> {
+  let entries = match std::fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+    Err(e) => return Err(e),
+  };
+  let mut count = 0;
+  for entry in entries {
+    let name = entry?.file_name();
+    let name = name.to_string_lossy();
+    if !name.starts_with(".otterpack") && !name.ends_with(".part") {
+      count += 1;
+    }
+  }
+  Ok(count)
+}
+
+/// The first of `<name>-2`, `<name>-3` and so on next to `dir` that doesn't exist yet, for a fresh
+/// output folder.
+///
+/// A number `dir` already ends in is counted on from, so `craig-2` leads to `craig-3` rather than
+/// `craig-2-2`.
+pub fn next_free_folder(dir: &Path) -> PathBuf {
+  let name = dir.file_name().unwrap_or_default().to_string_lossy();
+  // Longer numbers are more likely a year than a count
+  let (base, start) = match name.rsplit_once('-') {
+    Some((base, number))
+      if !base.is_empty()
+        && (1..=3).contains(&number.len())
+        && number.bytes().all(|b| b.is_ascii_digit()) =>
+    {
+      (base, number.parse::<u32>().unwrap_or_default() + 1)
+    }
+    _ => (name.as_ref(), 2),
+  };
+  (start.max(2)..)
+    .map(|number| dir.with_file_name(format!("{base}-{number}")))
+    .find(|candidate| !candidate.exists())
+    .expect("there's always a free number")
+}
+
+/// Names the output for an input track by swapping its `.flac` suffix for `extension`.
+///
+/// Only the known suffix is stripped, so dots elsewhere in the name (`3-v1.2.flac`) are kept.
+pub fn output_file_name(input_name: &str, extension: &str) -> String {
+  let split = input_name.len().saturating_sub(".flac".len());
+  let stem = match input_name.get(split..) {
+    Some(suffix) if suffix.eq_ignore_ascii_case(".flac") => &input_name[..split],
+    _ => input_name,
+  };
+  format!("{stem}.{extension}")
+}
+
+/// Decides whether an output file should be written, going by the overwrite policy.
+///
+/// Returns `false` when an existing file should be left alone.
+async fn should_write_output(path: &Path, policy: OverwritePolicy) -> Result<bool, ProcessError> {
+  if !tokio::fs::try_exists(path)
+    .await
+    .map_err(ProcessError::io(path))?
+  {
+    return Ok(true);
+  }
+
+  match policy {
+    OverwritePolicy::Overwrite => Ok(true),
+    OverwritePolicy::Skip => Ok(false),
+    OverwritePolicy::Fail => Err(ProcessError::OutputExists(
+      path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string(),
+    )),
+  }
+}
+
+/// Where an output is written while it's still being worked on.
+fn part_path(path: &Path) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_owned();
+  name.push(".part");
+  path.with_file_name(name)
+}
+
+/// Moves a finished `.part` file to its final name.
+async fn finish_part(part: &Path, output: &Path) -> Result<(), ProcessError> {
+  tokio::fs::rename(part, output)
+    .await
+    .map_err(ProcessError::output_io(output))
+}
+
+/// Runs an ffmpeg command that writes to [`part_path`] of `output`, see [`run_ffmpeg`].
+///
+/// The `.part` file only gets the real name once ffmpeg has succeeded. If it fails or the run is
+/// cancelled the partial file is deleted, so a file with the final name is always complete.
+async fn run_ffmpeg_into(
+  command: Command,
+  output: &Path,
+  cancel: &CancellationToken,
+  options: &ProcessOptions,
+  activity: &str,
+  on_progress: impl FnMut(FfmpegProgress) + Send + 'static,
+  on_log: impl FnMut(&str) + Send + 'static,
+) -> Result<(), ProcessError> {
+  let part = part_path(output);
+  let result = match run_ffmpeg(command, cancel, options, activity, on_progress, on_log).await {
+    Ok(()) => finish_part(&part, output).await,
+    Err(e) => Err(e),
+  };
+  if result.is_err() {
+    let _ = tokio::fs::remove_file(&part).await;
+  }
+  result
+}
+
+/// How far a decoded output's duration may be off from its input's before it's considered broken.
+const DURATION_TOLERANCE_SECS: f64 = 0.5;
+
+/// Whether a decoded duration is close enough to the expected one, allowing for the padding some
+/// encoders add.
+pub fn durations_match(expected: f64, decoded: f64) -> bool {
+  (expected - decoded).abs() <= DURATION_TOLERANCE_SECS.max(expected * 0.001)
+}
+
+/// Decodes a finished output from start to end, failing if ffmpeg hits an error or the audio
+/// comes out shorter or longer than `expected_duration`.
+///
+/// A broken output is deleted, so it isn't mistaken for a finished one later.
+async fn verify_output(
+  ffmpeg: &Path,
+  output: &Path,
+  expected_duration: Option<f64>,
+  cancel: &CancellationToken,
+  options: &ProcessOptions,
+  on_log: impl FnMut(&str) + Send + 'static,
+) -> Result<(), ProcessError> {
+  let name = output.file_name().unwrap_or_default().to_string_lossy();
+
+  let mut command = ffmpeg_command(ffmpeg, options.low_priority);
+  command
+    .args([
+      "-nostats",
+      "-v",
+      "error",
+      "-xerror",
+      "-progress",
+      "pipe:1",
+      "-i",
+    ])
+    .arg(output)
+    .args(options.thread_args())
+    .args(["-f", "null", "-"]);
+
+  let (decoded_tx, decoded_rx) = tokio::sync::watch::channel(None);
+  let mut result = run_ffmpeg(
+    command,
+    cancel,
+    options,
+    &format!("checking {name}"),
+    move |report| {
+      if report.out_time.is_some() {
+        let _ = decoded_tx.send(report.out_time);
+      }
+    },
+    on_log,
+  )
+  .await;
+
+  if result.is_ok()
+    && let Some(expected) = expected_duration
+  {
+    let decoded = *decoded_rx.borrow();
+    let message = match decoded {
+      Some(decoded) if durations_match(expected, decoded) => None,
+      Some(decoded) => Some(format!(
+        "{name} decodes to {decoded:.1}s of audio, but {expected:.1}s was expected"
+      )),
+      None => Some(format!("{name} couldn't be decoded")),
+    };
+    if let Some(message) = message {
+      result = Err(ProcessError::FfmpegFailed(FfmpegError {
+        message,
+        status: None,
+        stderr: String::new(),
+      }));
+    }
+  }
+
+  // Cancelling a check doesn't say anything about the file
+  if result
+    .as_ref()
+    .is_err_and(|e| !matches!(e, ProcessError::Cancelled))
+  {
+    let _ = tokio::fs::remove_file(output).await;
+  }
+  result
+}
+
+/// Writes a file through a `.part` file, so it's never left half-written.
+async fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), ProcessError> {
+  let part = part_path(path);
+  let result = match tokio::fs::write(&part, contents).await {
+    Ok(()) => finish_part(&part, path).await,
+    Err(e) => Err(ProcessError::output_io(path)(e)),
+  };
+  if result.is_err() {
+    let _ = tokio::fs::remove_file(&part).await;
+  }
+  result
+}
+
+/// Where a run records the outputs it wrote into a folder, next to them.
+static WRITTEN_FILE_NAME: &str = ".otterpack.written.json";
+
+/// The outputs runs have written into an output folder, by file name, each with the options it was
+/// written with, see [`written_options`].
+///
+/// Only these can be resumed from without asking: anything else in the folder may be from another
+/// recording, or from this one with other settings.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct WrittenOutputs(BTreeMap<String, String>);
+
+impl WrittenOutputs {
+  /// The record in `dir`, empty if there's none yet or it can't be read.
+  async fn load(dir: &Path) -> Self {
+    tokio::fs::read(dir.join(WRITTEN_FILE_NAME))
+      .await
+      .ok()
+      .and_then(|json| serde_json::from_slice(&json).ok())
+      .unwrap_or_default()
+  }
+
+  /// Whether `name` was written with the same `options`.
+  fn matches(&self, name: &str, options: &ProcessOptions) -> bool {
+    self.0.get(name) == Some(&written_options(options))
+  }
+
+  /// Records `name` as written with `options`, saving the record in `dir` straight away so it's
+  /// kept even if the run stops partway.
+  async fn record(
+    &mut self,
+    dir: &Path,
+    name: &str,
+    options: &ProcessOptions,
+  ) -> Result<(), ProcessError> {
+    self.0.insert(name.to_string(), written_options(options));
+    let json = serde_json::to_vec_pretty(self).expect("a map of strings serializes");
+    write_atomic(&dir.join(WRITTEN_FILE_NAME), json).await
+  }
+}
+
+/// The options that change what goes into an output, as they're kept in [`WrittenOutputs`].
+fn written_options(options: &ProcessOptions) -> String {
+  format!("{}/{}", options.format.name(), options.leveling.name())
+}
+
+/// Whether an output left behind by an earlier run looks finished, so converting it again can be
+/// skipped.
+///
+/// Outputs only get their final name once ffmpeg succeeds, so this mainly catches files that were
+/// replaced or tampered with since: the output has to be newer than the input, not be empty and
+/// have a header ffmpeg can read.
+async fn is_complete_output(
+  ffmpeg: &Path,
+  input: &Path,
+  output: &Path,
+  options: &ProcessOptions,
+) -> bool {
+  let (Ok(input_meta), Ok(output_meta)) = (
+    tokio::fs::metadata(input).await,
+    tokio::fs::metadata(output).await,
+  ) else {
+    return false;
+  };
+  if output_meta.len() == 0 {
+    return false;
+  }
+  match (input_meta.modified(), output_meta.modified()) {
+    (Ok(input_time), Ok(output_time)) if output_time >= input_time => {}
+    _ => return false,
+  }
+  probe_duration(ffmpeg, None, output, options)
+    .await
+    .is_some()
+}
+
+/// Builds a callback that forwards ffmpeg's progress on one file through the progress channel.
+///
+/// `base` describes the file being worked on, with `audio_done_secs` covering the files before it.
+fn progress_reporter(
+  completion_tx: tokio::sync::mpsc::UnboundedSender<RunEvent>,
+  base: ProgressInfo,
+  duration: Option<f64>,
+) -> impl FnMut(FfmpegProgress) + Send + 'static {
+  move |report| {
+    let mut info = base.clone();
+    info.file_fraction = if report.finished {
+      Some(1.0)
+    } else {
+      duration
+        .zip(report.out_time)
+        .filter(|(duration, _)| *duration > 0.0)
+        .map(|(duration, out_time)| (out_time / duration).clamp(0.0, 1.0) as f32)
+    };
+    if let Some(out_time) = report.out_time {
+      info.audio_done_secs += match duration {
+        Some(duration) => out_time.min(duration),
+        None => out_time,
+      };
+    }
+
+    let _ = completion_tx.send(RunEvent::Process(ProcessProgress::Converting(info)));
+  }
+}
+
+/// Builds a callback that forwards what ffmpeg logs while working on `file` through the progress
+/// channel.
+fn log_reporter(
+  completion_tx: tokio::sync::mpsc::UnboundedSender<RunEvent>,
+  file: String,
+) -> impl FnMut(&str) + Send + 'static {
+  move |line| {
+    let _ = completion_tx.send(RunEvent::Process(ProcessProgress::Log {
+      file: file.clone(),
+      line: line.to_string(),
+    }));
+  }
+}
+
+/// Converts every track in `resource_path` into `root_output_path`, using the ffmpeg at `ffmpeg`.
+/// The tracks' durations are read with `ffprobe` when there is one.
+///
+/// A track that ffmpeg fails on is recorded in the returned report and the run moves on, unless
+/// [`ProcessOptions::fail_fast`] is set. Anything else that goes wrong stops the run.
+///
+/// With [`ProcessOptions::extra_formats`], the tracks are converted once per format, each into its
+/// own subfolder.
+pub async fn process_files(
+  resource_path: PathBuf,
+  ffmpeg: PathBuf,
+  ffprobe: Option<PathBuf>,
+  root_output_path: PathBuf,
+  options: &ProcessOptions,
+  completion_tx: tokio::sync::mpsc::UnboundedSender<RunEvent>,
+  cancel: CancellationToken,
+) -> Result<ProcessReport, ProcessError> {
+  let formats = options.formats();
+  if formats.len() == 1 {
+    return process_format(
+      resource_path,
+      ffmpeg,
+      ffprobe,
+      root_output_path,
+      options,
+      completion_tx,
+      cancel,
+      FormatPass::ONLY,
+    )
+    .await;
+  }
+
+  let mut reports = Vec::with_capacity(formats.len());
+  for (index, format) in formats.iter().enumerate() {
+    let options = ProcessOptions {
+      format: *format,
+      extra_formats: Vec::new(),
+      ..options.clone()
+    };
+    let report = process_format(
+      resource_path.clone(),
+      ffmpeg.clone(),
+      ffprobe.clone(),
+      root_output_path.join(format.folder_name()),
+      &options,
+      completion_tx.clone(),
+      cancel.clone(),
+      FormatPass {
+        index,
+        count: formats.len(),
+      },
+    )
+    .await?;
+    reports.push(report);
+  }
+  Ok(ProcessReport::combine(reports))
+}
+
+/// Converts every track into the one format in `options`, see [`process_files`]. `pass` says which
+/// of the run's formats this is, so progress covers all of them.
+#[allow(clippy::too_many_arguments)]
+async fn process_format(
+  resource_path: PathBuf,
+  ffmpeg: PathBuf,
+  ffprobe: Option<PathBuf>,
+  root_output_path: PathBuf,
+  options: &ProcessOptions,
+  completion_tx: tokio::sync::mpsc::UnboundedSender<RunEvent>,
+  cancel: CancellationToken,
+  pass: FormatPass,
+) -> Result<ProcessReport, ProcessError> {
+  let format = options.format;
+
+  check_output_location(&root_output_path, Some(&resource_path))?;
+
+  // Deep folders with long track names easily go past MAX_PATH on Windows
+  let root_output_path = crate::platform::long_path(&root_output_path);
+  let mut output_path = root_output_path.clone();
+  if format.is_project_format() {
+    output_path.push(AUP_FOLDER_NAME);
+  }
+  // Create output directory if it doesn't exist
+  tokio::fs::create_dir_all(&output_path)
+    .await
+    .map_err(ProcessError::output_io(&output_path))?;
+
+  if !ffmpeg.exists() {
+    return Err(ProcessError::FfmpegMissing);
+  }
+
+  // Collect FLAC files
+  let mut entries = tokio::fs::read_dir(&resource_path)
+    .await
+    .map_err(ProcessError::io(&resource_path))?;
+  let mut flac_files = Vec::new();
+
+  while let Some(entry) = entries
+    .next_entry()
+    .await
+    .map_err(ProcessError::io(&resource_path))?
+  {
+    let path = entry.path();
+    if path.extension().and_then(|s| s.to_str()) == Some("flac") {
+      flac_files.push(path);
+    }
+  }
+
+  if flac_files.is_empty() {
+    return Err(ProcessError::NoTracksFound);
+  }
+
+  // read_dir order depends on the filesystem, sort so runs and project track order are repeatable
+  flac_files.sort_by(|a, b| {
+    natural_cmp(
+      &a.file_name().unwrap_or_default().to_string_lossy(),
+      &b.file_name().unwrap_or_default().to_string_lossy(),
+    )
+  });
+
+  let mut result_files = Vec::new();
+  let (flac_files, left_out): (Vec<_>, Vec<_>) = flac_files.into_iter().partition(|file| {
+    options.includes_track(&file.file_name().unwrap_or_default().to_string_lossy())
+  });
+  if flac_files.is_empty() {
+    return Err(ProcessError::SelectedTracksMissing);
+  }
+  if !options.mix {
+    // Tracks left out of this run still belong in the project if an earlier run converted them
+    result_files.extend(
+      left_out
+        .iter()
+        .map(|file| {
+          output_file_name(
+            &file.file_name().unwrap_or_default().to_string_lossy(),
+            format.extension(),
+          )
+        })
+        .filter(|name| output_path.join(name).exists()),
+    );
+  }
+
+  let output_names = if options.mix {
+    vec![format!("craig.{}", format.extension())]
+  } else {
+    flac_files
+      .iter()
+      .map(|file| {
+        output_file_name(
+          &file.file_name().unwrap_or_default().to_string_lossy(),
+          format.extension(),
+        )
+      })
+      .collect()
+  };
+  for name in &output_names {
+    crate::platform::check_path_length(&output_path.join(name))
+      .map_err(|e| ProcessError::OutputUnusable(e.to_string()))?;
+  }
+
+  let mut durations = Vec::with_capacity(flac_files.len());
+  for (current, file) in flac_files.iter().enumerate() {
+    let _ = completion_tx.send(RunEvent::Process(ProcessProgress::Probing {
+      current,
+      total: flac_files.len(),
+    }));
+    durations.push(probe_duration(&ffmpeg, ffprobe.as_deref(), file, options).await);
+  }
+
+  // Better to refuse now than to run out of space halfway through
+  let known_durations = durations.iter().flatten().copied().collect::<Vec<_>>();
+  let needed = estimate_output_size(&known_durations, format, options.mix);
+  if let Some(available) = crate::platform::available_space(&output_path)
+    && available < needed
+  {
+    return Err(ProcessError::NotEnoughSpace { needed, available });
+  }
+
+  let mut report = ProcessReport::default();
+
+  if options.mix {
+    // Mix all tracks into one file
+    println!("Mixing {} tracks together", flac_files.len());
+
+    let file_output_path = output_path.join(format!("craig.{}", format.extension()));
+    let output_name = file_output_path
+      .file_name()
+      .unwrap()
+      .to_string_lossy()
+      .to_string();
+
+    // There's only the one output, so a failed mix fails the run
+    if should_write_output(&file_output_path, options.overwrite).await? {
+      let duration = options.mix_duration.mix_length(&durations);
+
+      // Too many inputs for one ffmpeg get mixed down in batches first
+      let tiers = plan_mix_tiers(flac_files.len(), MAX_AMIX_INPUTS);
+      let mix_filters = plan_mix_filters(&options.mix_filter_options(), tiers.len(), &durations);
+      let batches = tiers.iter().map(Vec::len).sum::<usize>();
+      let intermediate_dir = if tiers.is_empty() {
+        None
+      } else {
+        // Next to the output, where there's known to be enough space
+        Some(
+          tempfile::Builder::new()
+            .prefix(".otterpack-mix-")
+            .tempdir_in(&output_path)
+            .map_err(ProcessError::output_io(&output_path))?,
+        )
+      };
+
+      let mut progress_info = pass.scale(ProgressInfo {
+        filename: String::new(),
+        current: 0,
+        total: batches + 1,
+        file_fraction: None,
+        audio_done_secs: 0.0,
+        audio_total_secs: duration.map(|duration| duration * (batches + 1) as f64),
+      });
+
+      let mut sources = flac_files.clone();
+      let mut source_durations = durations.clone();
+      let mut batch_number = 0;
+      for (tier_index, tier) in tiers.iter().enumerate() {
+        let mut outputs = Vec::with_capacity(tier.len());
+        let mut output_durations = Vec::with_capacity(tier.len());
+        for batch in tier {
+          let batch_duration = MixDuration::Longest.mix_length(&source_durations[batch.clone()]);
+          batch_number += 1;
+          progress_info.filename = format!("Mix batch {batch_number} of {batches}");
+          let _ = completion_tx.send(RunEvent::Process(ProcessProgress::Converting(
+            progress_info.clone(),
+          )));
+
+          let output = intermediate_dir
+            .as_ref()
+            .expect("there are tiers to mix")
+            .path()
+            .join(format!("tier{tier_index}-{}.flac", outputs.len()));
+          let mut command = ffmpeg_command(&ffmpeg, options.low_priority);
+          command.args(build_mix_command(
+            &sources[batch.clone()],
+            &part_path(&output),
+            &mix_filters[tier_index],
+            &ProcessOptions {
+              format: AudioFormat::FLAC,
+              ..options.clone()
+            },
+          ));
+
+          println!("Mixing batch {batch_number} of {batches}");
+          run_ffmpeg_into(
+            command,
+            &output,
+            &cancel,
+            options,
+            &format!("mixing batch {batch_number} of {batches}"),
+            progress_reporter(completion_tx.clone(), progress_info.clone(), batch_duration),
+            log_reporter(completion_tx.clone(), format!("mix batch {batch_number}")),
+          )
+          .await?;
+          outputs.push(output);
+          output_durations.push(batch_duration);
+
+          progress_info.current += 1;
+          progress_info.audio_done_secs += duration.unwrap_or_default();
+        }
+        sources = outputs;
+        source_durations = output_durations;
+      }
+
+      progress_info.filename = "Mixed output".to_string();
+      let _ = completion_tx.send(RunEvent::Process(ProcessProgress::Converting(
+        progress_info.clone(),
+      )));
+
+      let mut command = ffmpeg_command(&ffmpeg, options.low_priority);
+      command.args(build_mix_command(
+        &sources,
+        &part_path(&file_output_path),
+        &mix_filters[tiers.len()],
+        options,
+      ));
+
+      println!("Running mix command");
+      run_ffmpeg_into(
+        command,
+        &file_output_path,
+        &cancel,
+        options,
+        "mixing tracks",
+        progress_reporter(completion_tx.clone(), progress_info, duration),
+        log_reporter(completion_tx.clone(), output_name.clone()),
+      )
+      .await?;
+      // Deleted as soon as they're not needed, the final output can be big as well
+      drop(intermediate_dir);
+
+      if options.verify {
+        verify_output(
+          &ffmpeg,
+          &file_output_path,
+          duration,
+          &cancel,
+          options,
+          log_reporter(completion_tx.clone(), output_name.clone()),
+        )
+        .await?;
+      }
+      report.converted.push(output_name.clone());
+    } else {
+      report.skipped.push(output_name.clone());
+    }
+    report
+      .outputs
+      .push((output_name.clone(), file_output_path.clone()));
+    result_files.push(output_name);
+  } else {
+    // Process files individually
+    let total_files = flac_files.len();
+    let audio_total_secs = durations.iter().copied().sum::<Option<f64>>();
+    let mut audio_done_secs = 0.0;
+    let mut written = WrittenOutputs::load(&output_path).await;
+
+    let report_track = |filename: &str, state| {
+      let _ = completion_tx.send(RunEvent::Process(ProcessProgress::Track {
+        filename: filename.to_string(),
+        state,
+      }));
+    };
+    // Later formats go over the same tracks again, which are already listed
+    for file in flac_files.iter().filter(|_| pass.index == 0) {
+      report_track(
+        &file.file_name().unwrap_or_default().to_string_lossy(),
+        TrackState::Queued,
+      );
+    }
+
+    for (current_index, (input_path, duration)) in flac_files.into_iter().zip(durations).enumerate()
+    {
+      let filename = input_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+
+      let progress_info = pass.scale(ProgressInfo {
+        filename: filename.clone(),
+        current: current_index,
+        total: total_files,
+        file_fraction: None,
+        audio_done_secs,
+        audio_total_secs,
+      });
+      let _ = completion_tx.send(RunEvent::Process(ProcessProgress::Converting(
+        progress_info.clone(),
+      )));
+
+      let file_output_path = output_path.join(output_file_name(&filename, format.extension()));
+      let output_name = file_output_path
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .to_string();
+
+      // An output from somewhere else is up to the overwrite policy, not taken as already done
+      if !options.reprocess_all
+        && (options.overwrite == OverwritePolicy::Skip || written.matches(&output_name, options))
+        && is_complete_output(&ffmpeg, &input_path, &file_output_path, options).await
+      {
+        println!(
+          "Skipping {:?}, an earlier run already converted it",
+          file_output_path
+        );
+        report_track(&filename, TrackState::Skipped);
+        report.outputs.push((filename.clone(), file_output_path));
+        report.skipped.push(filename);
+        result_files.push(output_name);
+        audio_done_secs += duration.unwrap_or_default();
+        continue;
+      }
+
+      let mut command = ffmpeg_command(&ffmpeg, options.low_priority);
+      command.args(build_convert_command(
+        &input_path,
+        &part_path(&file_output_path),
+        options,
+      ));
+
+      if should_write_output(&file_output_path, options.overwrite).await? {
+        println!("Converting {:?} to {:?}", input_path, file_output_path);
+        report_track(&filename, TrackState::Started);
+        let mut result = run_ffmpeg_into(
+          command,
+          &file_output_path,
+          &cancel,
+          options,
+          &format!("converting {filename}"),
+          progress_reporter(completion_tx.clone(), progress_info, duration),
+          log_reporter(completion_tx.clone(), filename.clone()),
+        )
+        .await;
+        if result.is_ok() && options.verify {
+          result = verify_output(
+            &ffmpeg,
+            &file_output_path,
+            duration,
+            &cancel,
+            options,
+            log_reporter(completion_tx.clone(), output_name.clone()),
+          )
+          .await;
+        }
+
+        match result {
+          Ok(()) => {
+            let output_size = tokio::fs::metadata(&file_output_path)
+              .await
+              .map(|metadata| metadata.len())
+              .unwrap_or_default();
+            written.record(&output_path, &output_name, options).await?;
+            report_track(&filename, TrackState::Finished { output_size });
+            report.outputs.push((filename.clone(), file_output_path));
+            report.converted.push(filename);
+            result_files.push(output_name);
+          }
+          // Only ffmpeg giving up on this one track is worth carrying on from, cancellation and
+          // I/O errors would just happen again on the next file
+          Err(e @ ProcessError::FfmpegFailed(_)) if !options.fail_fast => {
+            println!("Failed to convert {:?}: {}", input_path, e);
+            report_track(
+              &filename,
+              TrackState::Failed {
+                error: e.to_string(),
+              },
+            );
+            report.failed.push(TrackFailure::new(filename, &e));
+          }
+          Err(e) => return Err(e),
+        }
+      } else {
+        println!("Skipping {:?}, it already exists", file_output_path);
+        report_track(&filename, TrackState::Skipped);
+        report.outputs.push((filename.clone(), file_output_path));
+        report.skipped.push(filename);
+        result_files.push(output_name);
+      }
+
+      audio_done_secs += duration.unwrap_or_default();
+    }
+  }
+
+  let _ = completion_tx.send(RunEvent::Process(ProcessProgress::Packaging));
+
+  if format.is_project_format() {
+    let _ = completion_tx.send(RunEvent::Process(ProcessProgress::WritingProject));
+
+    // Create Audacity project file
+    result_files.sort_by(|a, b| natural_cmp(a, b));
+    let mut aup = AUP_HEADER.to_owned();
+    for file in result_files {
+      aup.push_str(&format!(
+        "\t<import filename=\"{}\" offset=\"0.00000000\" mute=\"0\" solo=\"0\" height=\"150\" minimized=\"0\" gain=\"1.0\" pan=\"0.0\"/>\n",
+        file
+      ));
+    }
+    aup.push_str("</project>");
+
+    let project = root_output_path.join("craig.aup");
+    write_atomic(&project, aup).await?;
+    report.project = Some(project);
+  }
+
+  Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// One chain of a `-filter_complex` graph: `[in0][in1]amix=inputs=2[aud]`.
+  #[derive(Debug)]
+  struct FilterChain {
+    inputs: Vec<String>,
+    filter: String,
+    output: String,
+  }
+
+  /// Splits a graph from [`build_mix_filter`] into its chains, each with the pads it reads and the
+  /// one it writes.
+  fn parse_chains(graph: &str) -> Vec<FilterChain> {
+    graph
+      .split(';')
+      .map(|chain| {
+        let mut rest = chain;
+        let mut inputs = Vec::new();
+        while let Some(label) = rest.strip_prefix('[') {
+          let (label, after) = label.split_once(']').expect("labels are closed");
+          inputs.push(label.to_string());
+          rest = after;
+        }
+        let (filter, output) = rest.rsplit_once('[').expect("every chain has an output");
+        FilterChain {
+          inputs,
+          filter: filter.to_string(),
+          output: output
+            .strip_suffix(']')
+            .expect("labels are closed")
+            .to_string(),
+        }
+      })
+      .collect()
+  }
+
+  fn mix_options() -> MixFilterOptions {
+    MixFilterOptions {
+      leveling: Leveling::Dynaudnorm,
+      duration: MixDuration::Longest,
+      dropout_transition: 2.0,
+      cut_off: None,
+    }
+  }
+
+  #[test]
+  fn mix_filter_chains_amix_for_many_inputs() {
+    // How many amix filters there are, and how many inputs the last one takes
+    let cases = [
+      (1, 1, 1),
+      (2, 1, 2),
+      (31, 1, 31),
+      (32, 1, 32),
+      (33, 2, 2),
+      (64, 3, 2),
+      (65, 3, 3),
+      (100, 4, 7),
+    ];
+    for (inputs, amixes, last_inputs) in cases {
+      let chains = parse_chains(&build_mix_filter(inputs, &mix_options()));
+
+      let mut outputs: Vec<&str> = chains.iter().map(|chain| chain.output.as_str()).collect();
+      outputs.sort();
+      outputs.dedup();
+      assert_eq!(outputs.len(), chains.len(), "{inputs} inputs");
+
+      let mixes: Vec<&FilterChain> = chains
+        .iter()
+        .filter(|chain| chain.filter.starts_with("amix="))
+        .collect();
+      assert_eq!(mixes.len(), amixes, "{inputs} inputs");
+      for mix in &mixes {
+        assert!(mix.inputs.len() <= MAX_AMIX_INPUTS, "{inputs} inputs");
+        let expected = format!("amix=inputs={}:", mix.inputs.len());
+        assert!(
+          mix.filter.starts_with(&expected),
+          "{inputs} inputs: {mix:?}"
+        );
+      }
+      assert_eq!(mixes.last().unwrap().inputs.len(), last_inputs);
+
+      let last = chains.last().unwrap();
+      assert_eq!(last.output, "aud", "{inputs} inputs");
+      assert!(last.filter.starts_with("amix="));
+      // Each input is levelled on its way in
+      assert_eq!(chains.len() - mixes.len(), inputs);
+    }
+  }
+
+  /// What's wrong with `graph` as a mix of `inputs` files with `options`, if anything.
+  ///
+  /// Beyond parsing, this follows the pads through the graph: every input and every mix has to be
+  /// read exactly once after it's written, and the one pad nothing reads is the final `[aud]`.
+  fn mix_graph_problems(graph: &str, inputs: usize, options: &MixFilterOptions) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut unread: Vec<String> = (0..inputs).map(|i| format!("{i}:a")).collect();
+    let mut written = Vec::new();
+    let amix_options = format!(
+      ":duration={}:dropout_transition={}",
+      options.duration.amix_value(),
+      options.dropout_transition
+    );
+
+    for chain in parse_chains(graph) {
+      for input in &chain.inputs {
+        match unread.iter().position(|pad| pad == input) {
+          Some(i) => {
+            unread.remove(i);
+          }
+          None => problems.push(format!("[{input}] is read before it's written, or twice")),
+        }
+      }
+      if written.contains(&chain.output) {
+        problems.push(format!("[{}] is written twice", chain.output));
+      }
+      written.push(chain.output.clone());
+      unread.push(chain.output.clone());
+
+      let leveled = |filter: &str| match options.leveling.filter() {
+        Some(leveling) => filter.ends_with(leveling),
+        None => !filter.contains("dynaudnorm"),
+      };
+      match chain.filter.strip_prefix("amix=inputs=") {
+        Some(rest) => {
+          let count = chain.inputs.len();
+          if !(1..=MAX_AMIX_INPUTS).contains(&count) {
+            problems.push(format!("amix into [{}] has {count} inputs", chain.output));
+          }
+          if !rest.starts_with(&format!("{count}{amix_options}")) {
+            problems.push(format!("amix into [{}] is {}", chain.output, chain.filter));
+          }
+          if !leveled(rest) {
+            problems.push(format!("amix into [{}] isn't leveled", chain.output));
+          }
+        }
+        None => {
+          if chain.inputs.len() != 1 || !chain.inputs[0].ends_with(":a") {
+            problems.push(format!("[{}] isn't made from one input", chain.output));
+          }
+          let expected = options.leveling.filter().unwrap_or("anull");
+          if chain.filter != expected || !leveled(&chain.filter) {
+            problems.push(format!("[{}] is made with {}", chain.output, chain.filter));
+          }
+        }
+      }
+    }
+
+    if unread != ["aud"] {
+      problems.push(format!("{unread:?} are left unread instead of just [aud]"));
+    }
+    problems
+  }
+
+  proptest::proptest! {
+    #[test]
+    fn mix_filter_reads_every_pad_once(
+      inputs in 1..=500usize,
+      leveling in proptest::sample::select(Leveling::variants()),
+      duration in proptest::sample::select(MixDuration::variants()),
+      dropout_transition in 0.0..30.0f64,
+    ) {
+      let options = MixFilterOptions {
+        leveling,
+        duration,
+        dropout_transition,
+        cut_off: None,
+      };
+      let problems = mix_graph_problems(&build_mix_filter(inputs, &options), inputs, &options);
+      proptest::prop_assert!(problems.is_empty(), "{:?}", problems);
+    }
+  }
+
+  #[test]
+  fn mix_filter_passes_options_to_amix() {
+    let graph = build_mix_filter(2, &mix_options());
+    assert_eq!(
+      graph,
+      "[0:a]dynaudnorm[in0];[1:a]dynaudnorm[in1];\
+       [in0][in1]amix=inputs=2:duration=longest:dropout_transition=2,dynaudnorm[aud]"
+    );
+
+    let options = MixFilterOptions {
+      leveling: Leveling::Off,
+      duration: MixDuration::Shortest,
+      dropout_transition: 0.5,
+      cut_off: None,
+    };
+    assert_eq!(
+      build_mix_filter(1, &options),
+      "[0:a]anull[in0];[in0]amix=inputs=1:duration=shortest:dropout_transition=0.5[aud]"
+    );
+  }
+
+  #[test]
+  fn natural_cmp_orders_numbers_by_value() {
+    assert_eq!(natural_cmp("2-foo.flac", "10-bar.flac"), Ordering::Less);
+    assert_eq!(natural_cmp("10-bar.flac", "2-foo.flac"), Ordering::Greater);
+    assert_eq!(natural_cmp("track9", "track10"), Ordering::Less);
+    assert_eq!(
+      natural_cmp("99999999999999999999999-a", "100000000000000000000000-a"),
+      Ordering::Less
+    );
+    // Leading zeros don't make a number bigger, and only break ties
+    assert_eq!(natural_cmp("007-bond", "7-bond"), Ordering::Less);
+    assert_eq!(natural_cmp("007-bond", "8-bond"), Ordering::Less);
+  }
+
+  #[test]
+  fn natural_cmp_ignores_case_until_it_has_to_choose() {
+    assert_eq!(natural_cmp("alice.flac", "Bob.flac"), Ordering::Less);
+    assert_eq!(natural_cmp("ALICE.flac", "bob.flac"), Ordering::Less);
+    // Names that only differ in case still get a fixed order
+    assert_eq!(natural_cmp("Alice.flac", "alice.flac"), Ordering::Less);
+    assert_eq!(natural_cmp("alice.flac", "Alice.flac"), Ordering::Greater);
+    assert_eq!(natural_cmp("alice.flac", "alice.flac"), Ordering::Equal);
+  }
+
+  #[test]
+  fn natural_cmp_handles_names_beyond_ascii() {
+    assert_eq!(natural_cmp("Émile", "émile"), Ordering::Less);
+    assert_eq!(natural_cmp("émile", "Zoë"), Ordering::Greater);
+    assert_eq!(natural_cmp("Ärger", "ärger2"), Ordering::Less);
+    // Digits from other scripts are ordinary characters, not numbers
+    assert_eq!(natural_cmp("٣-speaker", "10-speaker"), Ordering::Greater);
+
+    let mut names = vec![
+      "10-Zoë.flac",
+      "2-émile.flac",
+      "1-Ödön.flac",
+      "2-Émile.flac",
+      "1-ödön.flac",
+      "3-小明.flac",
+      "11-bob.flac",
+    ];
+    names.sort_by(|a, b| natural_cmp(a, b));
+    assert_eq!(
+      names,
+      [
+        "1-Ödön.flac",
+        "1-ödön.flac",
+        "2-Émile.flac",
+        "2-émile.flac",
+        "3-小明.flac",
+        "10-Zoë.flac",
+        "11-bob.flac",
+      ]
+    );
+  }
+
+  #[test]
+  fn output_file_name_swaps_the_extension() {
+    assert_eq!(output_file_name("1-alice.flac", "wav"), "1-alice.wav");
+    // Dots before the extension are part of the name
+    assert_eq!(output_file_name("3-v1.2.flac", "wav"), "3-v1.2.wav");
+    assert_eq!(output_file_name("3-v1.2.flac", "flac"), "3-v1.2.flac");
+    assert_eq!(output_file_name("1-alice.FLAC", "m4a"), "1-alice.m4a");
+    assert_eq!(output_file_name("1-alice.Flac", "wav"), "1-alice.wav");
+  }
+
+  #[test]
+  fn output_file_name_keeps_names_without_the_extension() {
+    assert_eq!(output_file_name("1-alice", "wav"), "1-alice.wav");
+    assert_eq!(output_file_name("1-alice.ogg", "wav"), "1-alice.ogg.wav");
+    assert_eq!(output_file_name("flac", "wav"), "flac.wav");
+    assert_eq!(output_file_name(".flac", "wav"), ".wav");
+  }
+
+  #[test]
+  fn output_file_name_handles_unicode() {
+    assert_eq!(output_file_name("2-Zoë.flac", "wav"), "2-Zoë.wav");
+    assert_eq!(output_file_name("3-小明.FLAC", "aac"), "3-小明.aac");
+    // Cutting ".flac" worth of bytes off the end would split a character here
+    assert_eq!(output_file_name("4-日本", "wav"), "4-日本.wav");
+    assert_eq!(output_file_name("é", "wav"), "é.wav");
+  }
+
+  #[test]
+  fn only_what_otterpack_left_is_not_counted_as_existing_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("craig");
+    assert_eq!(existing_output_count(&output).unwrap(), 0);
+
+    std::fs::create_dir(&output).unwrap();
+    assert_eq!(existing_output_count(&output).unwrap(), 0);
+    std::fs::write(output.join(".otterpack.lock"), "").unwrap();
+    std::fs::write(output.join("1-alice.mp3.part"), "").unwrap();
+    assert_eq!(existing_output_count(&output).unwrap(), 0);
+
+    std::fs::write(output.join("1-alice.mp3"), "").unwrap();
+    std::fs::create_dir(output.join("mix")).unwrap();
+    assert_eq!(existing_output_count(&output).unwrap(), 2);
+  }
+
+  #[test]
+  fn fresh_folders_are_numbered_from_the_first_gap() {
+    let dir = tempfile::tempdir().unwrap();
+    let folder = |name: &str| dir.path().join(name);
+    assert_eq!(next_free_folder(&folder("craig")), folder("craig-2"));
+
+    std::fs::create_dir(folder("craig-2")).unwrap();
+    std::fs::create_dir(folder("craig-4")).unwrap();
+    assert_eq!(next_free_folder(&folder("craig")), folder("craig-3"));
+    // Counting goes on from a folder's own number, never back to a gap before it
+    assert_eq!(next_free_folder(&folder("craig-2")), folder("craig-3"));
+    assert_eq!(next_free_folder(&folder("craig-3")), folder("craig-5"));
+    assert_eq!(next_free_folder(&folder("craig-1")), folder("craig-3"));
+
+    // Dates and names with dashes are not counts
+    assert_eq!(
+      next_free_folder(&folder("craig-2024")),
+      folder("craig-2024-2")
+    );
+    assert_eq!(next_free_folder(&folder("-5")), folder("-5-2"));
+    assert_eq!(next_free_folder(&folder("a-b")), folder("a-b-2"));
+  }
+
+  #[test]
+  fn outputs_are_kept_out_of_folders_that_get_cleaned_up() {
+    let home = std::env::current_dir().unwrap().join("craig");
+    assert!(check_output_location(&home, None).is_ok());
+
+    let temp = std::env::temp_dir().join("craig");
+    assert!(matches!(
+      check_output_location(&temp, None),
+      Err(ProcessError::OutputUnusable(_))
+    ));
+
+    let resources = std::env::current_dir().unwrap().join("unpacked");
+    assert!(check_output_location(&home, Some(&resources)).is_ok());
+    assert!(matches!(
+      check_output_location(&resources.join("craig"), Some(&resources)),
+      Err(ProcessError::OutputUnusable(_))
+    ));
+    assert!(matches!(
+      check_output_location(&resources, Some(&resources)),
+      Err(ProcessError::OutputUnusable(_))
+    ));
+  }
+
+  /// Feeds `chunks` to a fresh parser, returning every report it made.
+  fn parse_progress(chunks: &[&str]) -> Vec<FfmpegProgress> {
+    let mut parser = ProgressParser::default();
+    chunks
+      .iter()
+      .flat_map(|chunk| parser.feed(chunk.as_bytes()))
+      .collect()
+  }
+
+  fn at(out_time: f64, finished: bool) -> FfmpegProgress {
+    FfmpegProgress {
+      out_time: Some(out_time),
+      finished,
+    }
+  }
+
+  #[test]
+  fn progress_blocks_are_reported_as_they_end() {
+    let output = "frame=0\nout_time_us=1500000\nout_time=00:00:01.500000\nprogress=continue\n\
+      out_time_us=3000000\nprogress=continue\nout_time_us=4250000\nprogress=end\n";
+    assert_eq!(
+      parse_progress(&[output]),
+      [at(1.5, false), at(3.0, false), at(4.25, true)]
+    );
+  }
+
+  #[test]
+  fn progress_split_anywhere_parses_the_same() {
+    let output = "out_time_us=1500000\nprogress=continue\nout_time_us=3000000\nprogress=end\n";
+    let expected = [at(1.5, false), at(3.0, true)];
+    for split in 0..=output.len() {
+      let (first, second) = output.split_at(split);
+      assert_eq!(
+        parse_progress(&[first, second]),
+        expected,
+        "split at {split}"
+      );
+    }
+    let bytes: Vec<String> = output.chars().map(String::from).collect();
+    let bytes: Vec<&str> = bytes.iter().map(String::as_str).collect();
+    assert_eq!(parse_progress(&bytes), expected);
+  }
+
+  #[test]
+  fn unfinished_progress_blocks_wait_for_the_rest() {
+    let mut parser = ProgressParser::default();
+    assert!(parser.feed(b"out_time_us=1000000\nprogress=con").is_empty());
+    assert_eq!(parser.feed(b"tinue\n"), [at(1.0, false)]);
+  }
+
+  #[test]
+  fn odd_progress_values_are_tolerated() {
+    // Windows line endings, the misnamed out_time_ms, and what ffmpeg writes before any output
+    assert_eq!(
+      parse_progress(&["out_time_ms=2000000\r\nprogress=continue\r\n"]),
+      [at(2.0, false)]
+    );
+    assert_eq!(
+      parse_progress(&["out_time_us=-9223372036854775807\nprogress=continue\n"]),
+      [at(0.0, false)]
+    );
+    assert_eq!(
+      parse_progress(&["out_time_us=N/A\nprogress=continue\n"]),
+      [FfmpegProgress::default()]
+    );
+    // A value that can't be read keeps the last one
+    assert_eq!(
+      parse_progress(&[
+        "out_time_us=1000000\nprogress=continue\n",
+        "out_time_us=N/A\ngarbage\nprogress=end\n"
+      ]),
+      [at(1.0, false), at(1.0, true)]
+    );
+  }
+
+  /// Waits on something that never finishes, sending activity at each of `activity_at`, returning
+  /// how it ended and when.
+  async fn watch_stalling(
+    activity_at: &[u64],
+    stall_timeout: Option<Duration>,
+    cancel: &CancellationToken,
+  ) -> (Result<(), ChildOutcome>, Duration) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let start = Instant::now();
+    for &secs in activity_at {
+      let tx = tx.clone();
+      tokio::spawn(async move {
+        tokio::time::sleep_until(start + Duration::from_secs(secs)).await;
+        let _ = tx.send(());
+      });
+    }
+    let result = watch_child(std::future::pending(), cancel, rx, stall_timeout).await;
+    (result, start.elapsed())
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn quiet_children_are_given_up_on() {
+    let timeout = Some(Duration::from_secs(60));
+    let (result, elapsed) = watch_stalling(&[], timeout, &CancellationToken::new()).await;
+    assert!(matches!(result, Err(ChildOutcome::Stalled)));
+    assert_eq!(elapsed, Duration::from_secs(60));
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn activity_puts_off_the_stall_timeout() {
+    let timeout = Some(Duration::from_secs(60));
+    // Well over the timeout in all, but never that long without any activity
+    let (result, elapsed) =
+      watch_stalling(&[30, 89, 140, 199], timeout, &CancellationToken::new()).await;
+    assert!(matches!(result, Err(ChildOutcome::Stalled)));
+    assert_eq!(elapsed, Duration::from_secs(259));
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn finishing_beats_the_stall_timeout() {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let wait = tokio::time::sleep(Duration::from_secs(90));
+    tokio::spawn(async move {
+      tokio::time::sleep(Duration::from_secs(50)).await;
+      let _ = tx.send(());
+    });
+    let result = watch_child(
+      wait,
+      &CancellationToken::new(),
+      rx,
+      Some(Duration::from_secs(60)),
+    )
+    .await;
+    assert!(result.is_ok());
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn without_a_stall_timeout_only_cancelling_stops_a_child() {
+    let cancel = CancellationToken::new();
+    let canceller = cancel.clone();
+    tokio::spawn(async move {
+      tokio::time::sleep(Duration::from_secs(24 * 60 * 60)).await;
+      canceller.cancel();
+    });
+    let (result, elapsed) = watch_stalling(&[], None, &cancel).await;
+    assert!(matches!(result, Err(ChildOutcome::Cancelled)));
+    assert_eq!(elapsed, Duration::from_secs(24 * 60 * 60));
+  }
+
+  #[test]
+  fn mixes_that_fit_need_no_tiers() {
+    for inputs in [0, 1, 2, 32] {
+      assert!(plan_mix_tiers(inputs, 32).is_empty(), "{inputs} inputs");
+    }
+  }
+
+  #[test]
+  fn mix_tiers_spread_inputs_evenly() {
+    assert_eq!(plan_mix_tiers(33, 32), [vec![0..16, 16..33]]);
+    assert_eq!(
+      plan_mix_tiers(100, 32),
+      [vec![0..25, 25..50, 50..75, 75..100]]
+    );
+    // 63 batches are still too many for one mix, so they get a tier of their own
+    let tiers = plan_mix_tiers(2000, 32);
+    assert_eq!(tiers.len(), 2);
+    assert_eq!(tiers[0].len(), 63);
+    assert_eq!(tiers[1], [0..31, 31..63]);
+  }
+
+  #[test]
+  fn mix_tiers_never_batch_single_files() {
+    let plan = [vec![0..1, 1..3, 3..5], vec![0..1, 1..3]];
+    assert_eq!(plan_mix_tiers(5, 1), plan);
+    assert_eq!(plan_mix_tiers(5, 0), plan);
+  }
+
+  #[test]
+  fn mix_tiers_cover_every_file_once_and_shrink_to_one_mix() {
+    for batch_size in 2..=40 {
+      for inputs in 0..=300 {
+        let mut files = inputs;
+        for tier in plan_mix_tiers(inputs, batch_size) {
+          // Batches follow on from each other with nothing left out
+          assert_eq!(tier.first().map(|batch| batch.start), Some(0));
+          assert_eq!(tier.last().map(|batch| batch.end), Some(files));
+          assert!(tier.windows(2).all(|pair| pair[0].end == pair[1].start));
+          assert!(
+            tier
+              .iter()
+              .all(|batch| (2..=batch_size).contains(&batch.len())
+                || (batch.len() == 1 && batch_size == 2)),
+            "{inputs} inputs in batches of {batch_size}: {tier:?}"
+          );
+          assert!(tier.len() < files);
+          files = tier.len();
+        }
+        assert!(
+          files <= batch_size,
+          "{inputs} inputs in batches of {batch_size}"
+        );
+      }
+    }
+  }
+
+  /// `parts` joined into a path as [`command_preview`] shows it, quoted if it has backslashes.
+  fn previewed_path(parts: &[&str]) -> String {
+    let path: PathBuf = parts.iter().collect();
+    let path = path.to_string_lossy();
+    if path.contains('\\') {
+      format!("\"{}\"", path.replace('"', "\\\""))
+    } else {
+      path.into_owned()
+    }
+  }
+
+  fn preview(tracks: &[&str], options: &ProcessOptions) -> String {
+    let tracks: Vec<String> = tracks.iter().map(|track| track.to_string()).collect();
+    command_preview(&tracks, Path::new("out"), options)
+  }
+
+  #[test]
+  fn command_preview_shows_the_first_track() {
+    let options = ProcessOptions {
+      format: AudioFormat::AAC,
+      ..Default::default()
+    };
+    assert_eq!(
+      preview(&["1-alice.flac", "2-bob.flac"], &options),
+      format!(
+        "ffmpeg -hide_banner -nostdin -nostats -progress pipe:1 -y -i 1-alice.flac \
+          -c:a aac -f ipod {}",
+        previewed_path(&["out", "1-alice.m4a.part"])
+      )
+    );
+
+    let options = ProcessOptions {
+      leveling: Leveling::Dynaudnorm,
+      threads: 4,
+      ..Default::default()
+    };
+    assert_eq!(
+      preview(&[], &options),
+      format!(
+        "ffmpeg -hide_banner -nostdin -nostats -progress pipe:1 -y -i track.flac -af dynaudnorm \
+          -threads 4 -filter_threads 4 -c:a flac -f flac {}",
+        previewed_path(&["out", "track.flac.part"])
+      )
+    );
+  }
+
+  #[test]
+  fn command_preview_shows_the_whole_mix() {
+    let options = ProcessOptions {
+      format: AudioFormat::WAV,
+      mix: true,
+      ..Default::default()
+    };
+    assert_eq!(
+      preview(&["1-alice.flac", "2-bob.flac"], &options),
+      format!(
+        "ffmpeg -hide_banner -nostdin -nostats -progress pipe:1 -y -i 1-alice.flac -i 2-bob.flac \
+          -filter_complex \"[0:a]anull[in0];[1:a]anull[in1];\
+          [in0][in1]amix=inputs=2:duration=longest:dropout_transition=0[aud]\" \
+          -map \"[aud]\" -c:a pcm_s16le -f wav {}",
+        previewed_path(&["out", "craig.wav.part"])
+      )
+    );
+  }
+
+  #[test]
+  fn command_preview_puts_projects_in_their_data_folder() {
+    let options = ProcessOptions {
+      format: AudioFormat::Audacity,
+      ..Default::default()
+    };
+    assert!(
+      preview(&["1-alice.flac"], &options).ends_with(&previewed_path(&[
+        "out",
+        AUP_FOLDER_NAME,
+        "1-alice.flac.part"
+      ]))
+    );
+  }
+
+  #[test]
+  fn command_preview_quotes_what_a_shell_would_split() {
+    let options = ProcessOptions::default();
+    let command = preview(&["1-alice \"ally\" (guest).flac"], &options);
+    assert!(
+      command.contains(r#" -i "1-alice \"ally\" (guest).flac" "#),
+      "{command}"
+    );
+  }
+
+  #[test]
+  fn tiered_first_track_mix_is_cut_only_at_the_end() {
+    // The first track is the shortest, so a batch cut where its own first track ends loses audio
+    let durations: Vec<Option<f64>> = (0..70)
+      .map(|i| Some(if i == 0 { 10.0 } else { 100.0 + i as f64 }))
+      .collect();
+    let options = MixFilterOptions {
+      duration: MixDuration::First,
+      ..mix_options()
+    };
+
+    let tiers = plan_mix_tiers(durations.len(), MAX_AMIX_INPUTS);
+    assert_eq!(tiers, [vec![0..23, 23..46, 46..70]]);
+    let filters = plan_mix_filters(&options, tiers.len(), &durations);
+    assert_eq!(filters.len(), 2);
+
+    for batch in &tiers[0] {
+      let graph = build_mix_filter(batch.len(), &filters[0]);
+      assert!(graph.contains("duration=longest"), "{graph}");
+      assert!(!graph.contains("duration=first"), "{graph}");
+      assert!(!graph.contains("atrim"), "{graph}");
+      // Still levelled on the way in
+      assert!(graph.starts_with("[0:a]dynaudnorm[in0];"), "{graph}");
+    }
+
+    assert_eq!(
+      build_mix_filter(tiers[0].len(), &filters[1]),
+      "[0:a]anull[in0];[1:a]anull[in1];[2:a]anull[in2];\
+       [in0][in1][in2]amix=inputs=3:duration=longest:dropout_transition=2,atrim=end=10[aud]"
+    );
+  }
+
+  #[test]
+  fn tiered_mix_cut_off_comes_from_the_original_tracks() {
+    let durations = [Some(30.0), None, Some(12.5), Some(50.0)];
+    let cut_off = |duration| {
+      let options = MixFilterOptions {
+        duration,
+        ..mix_options()
+      };
+      let filters = plan_mix_filters(&options, 2, &durations);
+      assert_eq!(filters.len(), 3);
+      assert!(
+        filters[..2]
+          .iter()
+          .all(|filter| filter.cut_off.is_none() && filter.duration == MixDuration::Longest)
+      );
+      filters[2].cut_off
+    };
+    assert_eq!(cut_off(MixDuration::Longest), None);
+    assert_eq!(cut_off(MixDuration::Shortest), Some(12.5));
+    assert_eq!(cut_off(MixDuration::First), Some(30.0));
+
+    // Not knowing where the first track ends leaves the mix uncut rather than guessing
+    let options = MixFilterOptions {
+      duration: MixDuration::First,
+      ..mix_options()
+    };
+    let filters = plan_mix_filters(&options, 1, &[None, Some(5.0)]);
+    assert_eq!(filters[1].cut_off, None);
+  }
+
+  #[test]
+  fn untiered_mix_keeps_its_options() {
+    let options = MixFilterOptions {
+      duration: MixDuration::First,
+      ..mix_options()
+    };
+    assert_eq!(plan_mix_filters(&options, 0, &[Some(1.0)]), [options]);
+  }
+
+  #[test]
+  fn chained_first_track_mix_keeps_the_first_track_first() {
+    let options = MixFilterOptions {
+      duration: MixDuration::First,
+      ..mix_options()
+    };
+    let chains = parse_chains(&build_mix_filter(70, &options));
+    let mixes: Vec<&FilterChain> = chains
+      .iter()
+      .filter(|chain| chain.filter.starts_with("amix="))
+      .collect();
+    assert_eq!(mixes.len(), 3);
+    assert_eq!(mixes[0].inputs[0], "in0");
+    // Each mix after the first leads with the one before, so it ends where track 0 does
+    for pair in mixes.windows(2) {
+      assert_eq!(pair[1].inputs[0], pair[0].output);
+    }
+  }
+
+  #[test]
+  fn format_hints_fit_the_recording() {
+    const HOUR: f64 = 60.0 * 60.0;
+    use AudioFormat::*;
+    // Tracks, length of the recording, mixed, format
+    let cases = [
+      // Long WAV, whether it's one long track or adds up over many
+      ((1, 2.0 * HOUR, false, WAV), Some(FormatHint::LargeWav)),
+      ((6, 15.0 * 60.0, false, WAV), Some(FormatHint::LargeWav)),
+      ((2, 10.0 * 60.0, false, WAV), None),
+      // Separate AAC tracks
+      ((2, 60.0, false, AAC), Some(FormatHint::LossyForEditing)),
+      ((1, 60.0, false, AAC), None),
+      ((4, 2.0 * HOUR, true, AAC), None),
+      // Long lossless mixes
+      ((4, 2.0 * HOUR, true, FLAC), Some(FormatHint::LosslessMix)),
+      ((4, 2.0 * HOUR, true, ALAC), Some(FormatHint::LosslessMix)),
+      ((4, 2.0 * HOUR, true, WAV), Some(FormatHint::LosslessMix)),
+      ((4, 30.0 * 60.0, true, FLAC), None),
+      ((4, 2.0 * HOUR, false, FLAC), None),
+      // Audacity projects, which need separate tracks
+      ((1, 60.0, true, Audacity), Some(FormatHint::ProjectOfMix)),
+      (
+        (4, 2.0 * HOUR, true, Audacity),
+        Some(FormatHint::ProjectOfMix),
+      ),
+      ((1, 60.0, false, Audacity), None),
+      ((4, 2.0 * HOUR, false, Audacity), None),
+    ];
+    for ((tracks, length, mix, format), expected) in cases {
+      assert_eq!(
+        format_hint(tracks, length, mix, format),
+        expected,
+        "{tracks} tracks of {length}s, mixed: {mix}, {format:?}"
+      );
+    }
+  }
+
+  #[test]
+  fn output_size_estimates_follow_the_format() {
+    const PCM: f64 = 48_000.0 * 4.0;
+    use AudioFormat::*;
+    // Durations, format, mixed, bytes before the 10% that's added to be safe
+    let cases: [(&[f64], AudioFormat, bool, f64); 8] = [
+      (&[60.0, 30.0], WAV, false, 90.0 * PCM),
+      (&[60.0, 30.0], WAV, true, 60.0 * PCM),
+      (&[100.0], FLAC, false, 100.0 * PCM * 0.6),
+      (&[100.0], ALAC, false, 100.0 * PCM * 0.6),
+      (&[100.0, 100.0], Audacity, false, 200.0 * PCM * 0.6),
+      (&[3600.0], AAC, false, 3600.0 * 16_000.0),
+      (&[3600.0, 1800.0], AAC, true, 3600.0 * 16_000.0),
+      (&[], FLAC, true, 0.0),
+    ];
+    for (durations, format, mix, bytes) in cases {
+      let estimate = estimate_output_size(durations, format, mix) as f64;
+      assert!(
+        (estimate - bytes * 1.1).abs() <= 1.0,
+        "{durations:?} as {format:?}, mixed: {mix}: {estimate} rather than {}",
+        bytes * 1.1
+      );
+    }
+  }
+}
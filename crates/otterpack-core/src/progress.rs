@@ -0,0 +1,188 @@
+use std::{
+  collections::VecDeque,
+  time::{Duration, Instant},
+};
+
+/// How far back samples are kept when averaging the processing speed.
+const ETA_WINDOW: Duration = Duration::from_secs(30);
+
+/// Estimates the time left in a run from how quickly audio is getting processed.
+///
+/// The speed is a rolling average of audio seconds processed per wall-clock second over the last
+/// [`ETA_WINDOW`], so it adapts when some tracks go faster than others.
+#[derive(Debug, Default)]
+pub struct EtaEstimator {
+  samples: VecDeque<(Instant, f64)>,
+  total_secs: f64,
+}
+
+impl EtaEstimator {
+  /// Records how many seconds of audio have been processed out of the total.
+  pub fn update(&mut self, done_secs: f64, total_secs: f64) {
+    self.update_at(Instant::now(), done_secs, total_secs);
+  }
+
+  /// Same as [`Self::update`], with an explicit timestamp for the sample.
+  pub fn update_at(&mut self, now: Instant, done_secs: f64, total_secs: f64) {
+    // Going backwards means a new run started
+    if self
+      .samples
+      .back()
+      .is_some_and(|&(_, last_done)| done_secs < last_done)
+    {
+      self.samples.clear();
+    }
+
+    self.total_secs = total_secs;
+    self.samples.push_back((now, done_secs));
+
+    while self.samples.len() > 2
+      && self
+        .samples
+        .front()
+        .is_some_and(|&(time, _)| now.duration_since(time) > ETA_WINDOW)
+    {
+      self.samples.pop_front();
+    }
+  }
+
+  /// The estimated time left, once enough progress has been seen to make a guess.
+  pub fn eta(&self) -> Option<Duration> {
+    let &(first_time, first_done) = self.samples.front()?;
+    let &(last_time, last_done) = self.samples.back()?;
+
+    let elapsed = last_time.duration_since(first_time).as_secs_f64();
+    if elapsed < 1.0 || last_done <= first_done {
+      return None;
+    }
+
+    let speed = (last_done - first_done) / elapsed;
+    let remaining = (self.total_secs - last_done).max(0.0);
+    Some(Duration::from_secs_f64(remaining / speed))
+  }
+
+  pub fn reset(&mut self) {
+    self.samples.clear();
+    self.total_secs = 0.0;
+  }
+}
+
+/// How many lines [`LogBuffer`] keeps before dropping the oldest.
+pub const MAX_LOG_LINES: usize = 5000;
+
+/// The latest lines ffmpeg logged during a run, oldest first.
+///
+/// Only the last [`MAX_LOG_LINES`] are kept, so a long run can't use up memory on them.
+#[derive(Debug, Default)]
+pub struct LogBuffer {
+  lines: VecDeque<String>,
+}
+
+impl LogBuffer {
+  pub fn push(&mut self, line: String) {
+    if self.lines.len() == MAX_LOG_LINES {
+      self.lines.pop_front();
+    }
+    self.lines.push_back(line);
+  }
+
+  pub fn len(&self) -> usize {
+    self.lines.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.lines.is_empty()
+  }
+
+  pub fn get(&self, index: usize) -> Option<&str> {
+    self.lines.get(index).map(String::as_str)
+  }
+
+  /// Every line joined up, for copying out.
+  pub fn text(&self) -> String {
+    Vec::from(self.lines.clone()).join("\n")
+  }
+
+  pub fn clear(&mut self) {
+    self.lines.clear();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Feeds `samples` of seconds since the start and audio seconds done, out of `total_secs`.
+  fn estimate(samples: &[(u64, f64)], total_secs: f64) -> Option<Duration> {
+    let start = Instant::now();
+    let mut eta = EtaEstimator::default();
+    for &(secs, done_secs) in samples {
+      eta.update_at(start + Duration::from_secs(secs), done_secs, total_secs);
+    }
+    eta.eta()
+  }
+
+  #[test]
+  fn steady_progress_gives_the_time_left_at_that_speed() {
+    // Twice as fast as the audio plays, with 100 seconds of it to go
+    assert_eq!(
+      estimate(&[(0, 0.0), (5, 10.0), (10, 20.0)], 120.0),
+      Some(Duration::from_secs(50))
+    );
+  }
+
+  #[test]
+  fn no_guess_is_made_too_early() {
+    assert_eq!(estimate(&[], 120.0), None);
+    assert_eq!(estimate(&[(0, 0.0)], 120.0), None);
+    assert_eq!(estimate(&[(0, 0.0), (0, 5.0)], 120.0), None);
+  }
+
+  #[test]
+  fn no_guess_is_made_while_nothing_moves() {
+    // Like a mix whose filter graph hasn't written anything yet
+    assert_eq!(estimate(&[(0, 0.0), (20, 0.0), (40, 0.0)], 120.0), None);
+  }
+
+  #[test]
+  fn only_recent_speed_counts() {
+    // Slow for the first minute, then four times as fast
+    let mut samples: Vec<(u64, f64)> = (0..=6).map(|i| (i * 10, i as f64 * 10.0)).collect();
+    samples.extend((1..=4).map(|i| (60 + i * 10, 60.0 + i as f64 * 40.0)));
+    // 180 seconds of audio left at 4 per second
+    assert_eq!(estimate(&samples, 400.0), Some(Duration::from_secs(45)));
+  }
+
+  #[test]
+  fn the_window_keeps_two_samples_however_old() {
+    assert_eq!(
+      estimate(&[(0, 0.0), (100, 50.0)], 100.0),
+      Some(Duration::from_secs(100))
+    );
+  }
+
+  #[test]
+  fn going_backwards_starts_over() {
+    let start = Instant::now();
+    let mut eta = EtaEstimator::default();
+    eta.update_at(start, 0.0, 100.0);
+    eta.update_at(start + Duration::from_secs(10), 50.0, 100.0);
+    assert!(eta.eta().is_some());
+
+    eta.update_at(start + Duration::from_secs(11), 0.0, 60.0);
+    assert_eq!(eta.eta(), None);
+    eta.update_at(start + Duration::from_secs(21), 30.0, 60.0);
+    assert_eq!(eta.eta(), Some(Duration::from_secs(10)));
+
+    eta.reset();
+    assert_eq!(eta.eta(), None);
+  }
+
+  #[test]
+  fn overshooting_the_total_leaves_nothing() {
+    assert_eq!(
+      estimate(&[(0, 0.0), (10, 130.0)], 120.0),
+      Some(Duration::ZERO)
+    );
+  }
+}
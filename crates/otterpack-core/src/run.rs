@@ -0,0 +1,182 @@
+use std::{path::PathBuf, sync::Arc};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+  ExtractedResources, FfmpegInfo, OutputLock, PackSource, ProcessError, ProcessOptions,
+  ProcessProgress, ProcessReport, SharedFfmpeg, extract_tracks, process_files, setup_resources,
+  verify_ffmpeg,
+};
+
+/// Everything a run reports back while it goes.
+pub enum RunEvent {
+  /// How far the unpacking and converting got.
+  Process(ProcessProgress),
+  /// The bundled ffmpeg was checked and can be used.
+  Ffmpeg(FfmpegInfo),
+  /// The resources were unpacked, and can be reused by later runs.
+  Resources(Arc<ExtractedResources>),
+}
+
+/// What [`run()`] can pick up from earlier runs instead of finding it again.
+#[derive(Clone, Default)]
+pub struct RunSetup {
+  /// Resources unpacked by an earlier run of the same recording, to skip extracting them again.
+  pub resources: Option<Arc<ExtractedResources>>,
+  /// The ffmpeg an earlier run found, for a recording that doesn't come with one.
+  pub shared_ffmpeg: Option<SharedFfmpeg>,
+}
+
+/// Converts the recording in `source` into `output_path`, calling `progress` as it goes.
+///
+/// This is [`run_processing`] with a callback instead of a channel. Unless `setup` has resources
+/// from an earlier run, they're unpacked into a temp folder that's removed again before this
+/// returns, unless `options` keeps it or `progress` holds on to the [`RunEvent::Resources`] it
+/// gets. `progress` is called on the calling task with everything but the outcome, which is what's
+/// returned instead.
+///
+/// Cancelling `cancel` stops the run as soon as the ffmpeg children have exited, returning
+/// [`ProcessError::Cancelled`]. Partly written outputs are removed, and finished ones are kept for
+/// the next run to skip. Dropping the returned future cancels the run the same way, which then
+/// finishes cleaning up in the background.
+pub async fn run(
+  source: PackSource,
+  output_path: PathBuf,
+  options: ProcessOptions,
+  setup: RunSetup,
+  mut progress: impl FnMut(RunEvent) + Send,
+  cancel: CancellationToken,
+) -> Result<ProcessReport, ProcessError> {
+  // A token of its own, so the caller's isn't cancelled once the run is over
+  let cancel = cancel.child_token();
+  let _cancel_on_drop = cancel.clone().drop_guard();
+  let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
+  let mut processing = tokio::spawn(async move {
+    run_processing(
+      source,
+      output_path,
+      options,
+      setup.resources,
+      setup.shared_ffmpeg,
+      &progress_tx,
+      cancel,
+    )
+    .await
+  });
+
+  let result = loop {
+    tokio::select! {
+      result = &mut processing => break result,
+      Some(event) = progress_rx.recv() => progress(event),
+    }
+  };
+  while let Ok(event) = progress_rx.try_recv() {
+    progress(event);
+  }
+  result
+    .map_err(|e| ProcessError::Other(anyhow::anyhow!("The run stopped unexpectedly: {e}")))?
+    .map_err(ProcessError::from)
+}
+
+/// Extracts the tracks into `output_path` as they are, without converting anything.
+pub async fn extract_original_tracks(
+  source: PackSource,
+  output_path: PathBuf,
+  options: ProcessOptions,
+  progress_tx: &mpsc::UnboundedSender<RunEvent>,
+) -> anyhow::Result<ProcessReport> {
+  let _lock = OutputLock::acquire(&output_path)?;
+
+  let progress_tx = progress_tx.clone();
+  let dest = output_path.clone();
+  let tracks = tokio::task::spawn_blocking(move || {
+    extract_tracks(
+      &source,
+      &dest,
+      options.extract_limits,
+      options.password.as_deref(),
+      |file, bytes_done, bytes_total| {
+        let _ = progress_tx.send(RunEvent::Process(ProcessProgress::Extracting {
+          file: file.to_string(),
+          bytes_done,
+          bytes_total,
+        }));
+      },
+    )
+  })
+  .await??;
+
+  Ok(ProcessReport {
+    outputs: tracks
+      .iter()
+      .map(|track| (track.clone(), output_path.join(track)))
+      .collect(),
+    converted: tracks,
+    ..Default::default()
+  })
+}
+
+/// Unpacks the resources and converts everything, reporting back over `progress_tx`.
+///
+/// Resources unpacked by an earlier run can be passed in to skip extracting them again, or just the
+/// ffmpeg an earlier run found for a recording that doesn't come with one, like [`RunSetup`] does
+/// for [`run()`], which is simpler to call.
+pub async fn run_processing(
+  source: PackSource,
+  output_path: PathBuf,
+  options: ProcessOptions,
+  resources: Option<Arc<ExtractedResources>>,
+  shared_ffmpeg: Option<SharedFfmpeg>,
+  progress_tx: &mpsc::UnboundedSender<RunEvent>,
+  cancel: CancellationToken,
+) -> anyhow::Result<ProcessReport> {
+  // Held for the whole run so another instance can't write into the same folder
+  let _lock = OutputLock::acquire(&output_path)
+    .map_err(|e| ProcessError::OutputUnusable(format!("{e:#}")))?;
+
+  // The extracted resources are held until process_files returns, which only happens once every
+  // ffmpeg child has exited
+  let resources = match resources {
+    Some(resources) => resources,
+    None => {
+      let resources =
+        Arc::new(setup_resources(source, &options, shared_ffmpeg, progress_tx.clone()).await?);
+
+      // Catch a broken ffmpeg before the batch starts rather than on the first file
+      let ffmpeg_info = verify_ffmpeg(&resources.ffmpeg_path)
+        .await
+        .map_err(|e| ProcessError::FfmpegUnusable(format!("{e:#}")))?;
+      let _ = progress_tx.send(RunEvent::Ffmpeg(ffmpeg_info.clone()));
+      if let Some(format) = options
+        .formats()
+        .into_iter()
+        .find(|format| !ffmpeg_info.supports(*format))
+      {
+        return Err(
+          ProcessError::FfmpegUnusable(format!(
+            "ffmpeg {} can't create {} files",
+            ffmpeg_info.version,
+            format.display_name()
+          ))
+          .into(),
+        );
+      }
+
+      let _ = progress_tx.send(RunEvent::Resources(resources.clone()));
+      resources
+    }
+  };
+
+  Ok(
+    process_files(
+      resources.audio_dir.clone(),
+      resources.ffmpeg_path.clone(),
+      resources.ffprobe_path.clone(),
+      output_path,
+      &options,
+      progress_tx.clone(),
+      cancel,
+    )
+    .await?,
+  )
+}
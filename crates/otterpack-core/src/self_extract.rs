@@ -0,0 +1,2390 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{
+  RunEvent,
+  cache::{self, Dirs},
+  pack_format::{FormatVersion, PACK_FORMAT_VERSION, check_pack_version},
+  platform::{
+    FFMPEG_BINARY, FFPROBE_BINARY, app_bundle, available_space, ffmpeg_binary_names,
+    process_is_alive,
+  },
+  probe::flac_duration,
+  process::{ProcessError, ProcessOptions, ProcessProgress, format_bytes, verify_ffmpeg},
+};
+
+#[derive(Debug, Clone)]
+pub enum PackSource {
+  DebugFolder(PathBuf),
+  /// A payload shipped as a separate file next to the executable.
+  SidecarZip(PathBuf),
+  /// A recording ZIP the user picked themselves, such as one downloaded from Craig.
+  UserZip(PathBuf),
+  /// A folder of tracks the user picked themselves, used in place like the debug folder.
+  UserFolder(PathBuf),
+  EmbeddedZip {
+    exe_path: PathBuf,
+    zip_start: u64,
+    zip_size: u64,
+  },
+}
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const MAX_SEARCH_SIZE: u64 = 10 * 1024 * 1024; // Look for ZIP signature in the first 10MB
+/// How much of the executable is read at a time while searching for the ZIP signature.
+const SCAN_CHUNK_SIZE: usize = 1024 * 1024;
+/// Size of each entry in a PE file's section table.
+const PE_SECTION_HEADER_SIZE: u64 = 40;
+/// Signature at the start of an ELF file, the executable format on Linux.
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+/// Type of an ELF section that takes up no room in the file, like `.bss`.
+const ELF_SECTION_NOBITS: u32 = 8;
+/// Signatures at the start of a 32 and 64-bit Mach-O file, the executable format on macOS, read
+/// as little-endian like everything macOS runs on.
+const MACHO_MAGIC: u32 = 0xfeedface;
+const MACHO_MAGIC_64: u32 = 0xfeedfacf;
+/// Signatures at the start of a universal Mach-O file, which holds one per architecture, read as
+/// big-endian like the rest of its header.
+const FAT_MAGIC: u32 = 0xcafebabe;
+const FAT_MAGIC_64: u32 = 0xcafebabf;
+/// Load commands describing a 32 and 64-bit segment of a Mach-O file.
+const MACHO_SEGMENT: u32 = 0x1;
+const MACHO_SEGMENT_64: u32 = 0x19;
+/// Signature of the End Of Central Directory record, which closes every ZIP file.
+const EOCD_MAGIC: &[u8] = b"PK\x05\x06";
+/// Size of the End Of Central Directory record, not counting the comment after it.
+const EOCD_SIZE: usize = 22;
+/// Signature of the ZIP64 End Of Central Directory locator, which comes right before the regular
+/// record in archives too big for its 32-bit fields.
+const ZIP64_LOCATOR_MAGIC: &[u8] = b"PK\x06\x07";
+const ZIP64_LOCATOR_SIZE: u64 = 20;
+/// Signature of the ZIP64 End Of Central Directory record, which holds the 64-bit values.
+const ZIP64_EOCD_MAGIC: &[u8] = b"PK\x06\x06";
+/// Size of the ZIP64 End Of Central Directory record, without any extensible data.
+const ZIP64_EOCD_SIZE: u64 = 56;
+const EXTRACT_BUFFER_SIZE: usize = 256 * 1024;
+/// Prefix of the temp folders packs are extracted into when the cache can't be used.
+const TEMP_DIR_PREFIX: &str = "otterpack-";
+/// Written into each temp folder with the PID of the process using it, so cleanup can tell the
+/// folders still in use from ones left behind by a crash.
+const PID_FILE_NAME: &str = ".otterpack.pid";
+/// Marks a temp folder the user asked to keep, so it's never cleaned up as stale.
+const KEPT_FILE_NAME: &str = ".otterpack.kept";
+/// Temp folders that have been around for longer than this are removed on startup, unless the
+/// process that made them is still running.
+pub const STALE_TEMP_DIR_AGE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+/// Optional list of checksums at the root of a pack, see [`Manifest`].
+const MANIFEST_NAME: &str = "manifest.json";
+/// Starts the trailer the packer appends after the payload.
+const TRAILER_MAGIC: &[u8] = b"OTTERPACK";
+/// The magic, the major [`PACK_FORMAT_VERSION`] as an ASCII digit, the payload's offset and length
+/// as little-endian u64s, then a CRC32 of all of that.
+const TRAILER_SIZE: usize = TRAILER_MAGIC.len() + 1 + 8 + 8 + 4;
+/// Extensions a payload next to the executable may have, in order of preference.
+const SIDECAR_EXTENSIONS: &[&str] = &["pack", "zip"];
+/// Name of a pack next to the executable holding just ffmpeg, for recordings that leave it out to
+/// stay small. Any of [`SIDECAR_EXTENSIONS`] may follow it.
+const TOOLS_PACK_STEM: &str = "otterpack-tools";
+
+/// Finds the recording belonging to the running executable, see [`find_pack_source_for`].
+///
+/// Debug builds use a `_otterpack` folder in the working directory instead when there is one.
+pub fn find_pack_source() -> Result<PackSource> {
+  if cfg!(debug_assertions) {
+    // In debug mode, look for _otterpack folder relative to executable
+    let debug_folder = std::env::current_dir()?.to_path_buf().join("_otterpack");
+
+    if debug_folder.is_dir() {
+      return Ok(PackSource::DebugFolder(debug_folder));
+    }
+  }
+
+  find_pack_source_for(std::env::current_exe()?)
+}
+
+/// Finds the payload belonging to the executable at `exe_path`.
+///
+/// A sidecar file next to the executable wins over a payload embedded in it, since it's the one
+/// that was shipped on purpose for platforms that don't tolerate appended data.
+pub fn find_pack_source_for(exe_path: PathBuf) -> Result<PackSource> {
+  if let Some(sidecar) = find_sidecar(&exe_path) {
+    return Ok(PackSource::SidecarZip(sidecar));
+  }
+
+  // Try to find embedded zip
+  let mut file = File::open(&exe_path)?;
+  let file_size = file.metadata()?.len();
+
+  // A trailer gives the exact location, anything packed without one has to be searched for
+  if let Some((zip_start, zip_size)) = read_trailer(&mut file, file_size)? {
+    return Ok(PackSource::EmbeddedZip {
+      exe_path,
+      zip_start,
+      zip_size,
+    });
+  }
+
+  // The archive's own end record says where it starts, the forward scan is only a fallback for
+  // archives that don't line up with it
+  let zip_start = match find_zip_start(&mut file, file_size)? {
+    Some(zip_start) => Some(zip_start),
+    // The magic also turns up in the program's own code, so a match only counts if an archive
+    // can actually be read from there
+    None => scan_for_local_header(&mut file, file_size)?.filter(|&zip_start| {
+      PayloadReader::new(&mut file, zip_start, file_size - zip_start)
+        .is_ok_and(|payload| zip::ZipArchive::new(payload).is_ok())
+    }),
+  };
+  if let Some(zip_start) = zip_start {
+    return Ok(PackSource::EmbeddedZip {
+      exe_path,
+      zip_start,
+      zip_size: file_size - zip_start,
+    });
+  }
+
+  if cfg!(debug_assertions) {
+    anyhow::bail!(
+      "No _otterpack folder or embedded ZIP found. In debug mode, place {FFMPEG_BINARY} in the _otterpack folder."
+    )
+  } else {
+    anyhow::bail!(
+      "This executable does not have a bundled ZIP file. Please use a properly packaged version."
+    )
+  }
+}
+
+/// Looks for `<exe-stem>.pack` or `<exe-stem>.zip` next to the executable, or in the `Resources`
+/// folder of the macOS app bundle it's in.
+pub fn find_sidecar(exe_path: &Path) -> Option<PathBuf> {
+  let stem = exe_path.file_stem()?.to_str()?;
+  find_pack_named(exe_path, stem)
+}
+
+/// Looks for `otterpack-tools.pack` or `otterpack-tools.zip` next to the executable, or in the
+/// `Resources` folder of the macOS app bundle it's in.
+pub fn find_tools_pack(exe_path: &Path) -> Option<PathBuf> {
+  find_pack_named(exe_path, TOOLS_PACK_STEM)
+}
+
+fn find_pack_named(exe_path: &Path, stem: &str) -> Option<PathBuf> {
+  // Appending to an executable in a bundle breaks its signature, so packs for macOS go with the
+  // bundle's other resources instead
+  let dirs = exe_path
+    .parent()
+    .map(Path::to_path_buf)
+    .into_iter()
+    .chain(app_bundle(exe_path).map(|bundle| bundle.join("Contents").join("Resources")));
+  dirs
+    .flat_map(|dir| {
+      SIDECAR_EXTENSIONS
+        .iter()
+        .map(move |ext| dir.join(format!("{stem}.{ext}")))
+    })
+    .find(|path| path.is_file())
+}
+
+fn encode_trailer(zip_start: u64, zip_size: u64) -> [u8; TRAILER_SIZE] {
+  let mut trailer = [0u8; TRAILER_SIZE];
+  let (magic, rest) = trailer.split_at_mut(TRAILER_MAGIC.len());
+  magic.copy_from_slice(TRAILER_MAGIC);
+  rest[0] = b'0' + PACK_FORMAT_VERSION.major as u8;
+  rest[1..9].copy_from_slice(&zip_start.to_le_bytes());
+  rest[9..17].copy_from_slice(&zip_size.to_le_bytes());
+  let crc = crc32fast::hash(&trailer[..TRAILER_SIZE - 4]);
+  trailer[TRAILER_SIZE - 4..].copy_from_slice(&crc.to_le_bytes());
+  trailer
+}
+
+/// Reads the payload's offset and length from the trailer at the end of `reader`.
+///
+/// Returns `None` if there's no trailer, it's damaged, or it points outside the file, and fails if
+/// the pack was made by a newer version of otterpack with a format this one can't read.
+pub fn read_trailer<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<Option<(u64, u64)>> {
+  let Some(trailer_start) = file_size.checked_sub(TRAILER_SIZE as u64) else {
+    return Ok(None);
+  };
+  let mut trailer = [0u8; TRAILER_SIZE];
+  reader.seek(SeekFrom::Start(trailer_start))?;
+  reader.read_exact(&mut trailer)?;
+
+  let (body, crc) = trailer.split_at(TRAILER_SIZE - 4);
+  if !body.starts_with(TRAILER_MAGIC) || crc32fast::hash(body).to_le_bytes() != crc {
+    return Ok(None);
+  }
+  let fields = &body[TRAILER_MAGIC.len()..];
+  // Anything past 9 can't be written as a digit, but is certainly newer than this build
+  let major = fields[0].checked_sub(b'0').map_or(u32::MAX, u32::from);
+  check_pack_version(FormatVersion { major, minor: 0 })?;
+  let zip_start = u64::from_le_bytes(fields[1..9].try_into().unwrap());
+  let zip_size = u64::from_le_bytes(fields[9..17].try_into().unwrap());
+
+  let fits = zip_start
+    .checked_add(zip_size)
+    .is_some_and(|end| end <= trailer_start);
+  Ok(fits.then_some((zip_start, zip_size)))
+}
+
+/// Appends the ZIP archive at `zip` to the executable at `exe`, followed by a trailer recording
+/// where it is, so [`find_pack_source`] can find it without searching.
+///
+/// This is the packer's side of the format. If anything fails, `exe` is cut back to its original
+/// size.
+pub fn append_payload(exe: &Path, zip: &Path) -> Result<()> {
+  let mut payload =
+    File::open(zip).context(format!("Failed to open payload: {}", zip.display()))?;
+  let mut out = std::fs::OpenOptions::new()
+    .append(true)
+    .open(exe)
+    .context(format!("Failed to open executable: {}", exe.display()))?;
+  let zip_start = out.metadata()?.len();
+
+  let result = (|| -> std::io::Result<()> {
+    let zip_size = std::io::copy(&mut payload, &mut out)?;
+    out.write_all(&encode_trailer(zip_start, zip_size))?;
+    out.sync_all()
+  })();
+  if let Err(e) = result {
+    let _ = out.set_len(zip_start);
+    return Err(e).context(format!("Failed to append payload to {}", exe.display()));
+  }
+  Ok(())
+}
+
+/// Finds where a ZIP archive appended to the end of `reader` starts, going by its End Of Central
+/// Directory record.
+///
+/// The record sits at the very end (before an optional comment) and gives the size of the central
+/// directory and its offset from the start of the archive. The central directory ends right where
+/// the record begins, so subtracting both gives the start of the archive. Returns `None` if there's
+/// no record, or the archive it describes doesn't start with a local file header.
+pub fn find_zip_start<R: Read + Seek>(
+  reader: &mut R,
+  file_size: u64,
+) -> std::io::Result<Option<u64>> {
+  // The record can be followed by a comment of up to 65535 bytes
+  let tail_size = file_size.min((EOCD_SIZE + u16::MAX as usize) as u64);
+  let tail_start = file_size - tail_size;
+  let mut tail = vec![0u8; tail_size as usize];
+  reader.seek(SeekFrom::Start(tail_start))?;
+  reader.read_exact(&mut tail)?;
+
+  let read_u16 = |at: usize| u16::from_le_bytes([tail[at], tail[at + 1]]) as u64;
+  let read_u32 =
+    |at: usize| u32::from_le_bytes([tail[at], tail[at + 1], tail[at + 2], tail[at + 3]]) as u64;
+
+  for pos in (0..tail.len().saturating_sub(EOCD_SIZE - 1)).rev() {
+    if &tail[pos..pos + EOCD_MAGIC.len()] != EOCD_MAGIC {
+      continue;
+    }
+    // A match inside some other data usually has a comment length that runs past the end
+    let comment_len = read_u16(pos + 20);
+    if pos as u64 + EOCD_SIZE as u64 + comment_len > tail_size {
+      continue;
+    }
+
+    let mut directory_end = tail_start + pos as u64;
+    let mut directory_size = read_u32(pos + 12);
+    let mut directory_offset = read_u32(pos + 16);
+    // Archives over 4 GB max these out and keep the real values in a ZIP64 record
+    if directory_size == u32::MAX as u64 || directory_offset == u32::MAX as u64 {
+      let Some(zip64) = read_zip64_end(reader, directory_end)? else {
+        continue;
+      };
+      (directory_end, directory_size, directory_offset) = zip64;
+    }
+    let Some(zip_start) = directory_end
+      .checked_sub(directory_size)
+      .and_then(|directory_start| directory_start.checked_sub(directory_offset))
+    else {
+      continue;
+    };
+
+    let mut magic = [0u8; 4];
+    reader.seek(SeekFrom::Start(zip_start))?;
+    if reader.read_exact(&mut magic).is_ok() && magic == ZIP_MAGIC {
+      return Ok(Some(zip_start));
+    }
+  }
+
+  Ok(None)
+}
+
+/// Reads the ZIP64 End Of Central Directory record that goes with the regular record at
+/// `eocd_pos`, returning where it starts (which is where the central directory ends) along with
+/// the central directory's size and offset.
+fn read_zip64_end<R: Read + Seek>(
+  reader: &mut R,
+  eocd_pos: u64,
+) -> std::io::Result<Option<(u64, u64, u64)>> {
+  let read_u64 =
+    |bytes: &[u8], at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+
+  let Some(locator_pos) = eocd_pos.checked_sub(ZIP64_LOCATOR_SIZE) else {
+    return Ok(None);
+  };
+  let mut locator = [0u8; ZIP64_LOCATOR_SIZE as usize];
+  reader.seek(SeekFrom::Start(locator_pos))?;
+  reader.read_exact(&mut locator)?;
+  if &locator[..4] != ZIP64_LOCATOR_MAGIC {
+    return Ok(None);
+  }
+  let record_offset = read_u64(&locator, 8);
+
+  // The locator gives the record's offset from the start of the archive, which is what we're
+  // looking for, but a record without extensible data ends right where the locator begins
+  let Some(record_pos) = locator_pos.checked_sub(ZIP64_EOCD_SIZE) else {
+    return Ok(None);
+  };
+  let mut record = [0u8; ZIP64_EOCD_SIZE as usize];
+  reader.seek(SeekFrom::Start(record_pos))?;
+  reader.read_exact(&mut record)?;
+  if &record[..4] != ZIP64_EOCD_MAGIC {
+    return Ok(None);
+  }
+  let directory_size = read_u64(&record, 40);
+  let directory_offset = read_u64(&record, 48);
+
+  // Both offsets count from the start of the archive, so they have to agree on where it is
+  if directory_offset.checked_add(directory_size) != Some(record_offset) {
+    return Ok(None);
+  }
+  Ok(Some((record_pos, directory_size, directory_offset)))
+}
+
+/// Reads `N` bytes at `pos`.
+fn read_at<R: Read + Seek, const N: usize>(reader: &mut R, pos: u64) -> std::io::Result<[u8; N]> {
+  let mut buffer = [0u8; N];
+  reader.seek(SeekFrom::Start(pos))?;
+  reader.read_exact(&mut buffer)?;
+  Ok(buffer)
+}
+
+/// Finds where the overlay of an executable starts, the data appended after everything the
+/// program itself is made of, or `None` if `reader` isn't a Windows, Linux or macOS executable.
+///
+/// Anything appended to the executable, like the payload, lives in the overlay, while the rest
+/// holds the program's own code and resources.
+fn overlay_offset<R: Read + Seek>(reader: &mut R) -> std::io::Result<Option<u64>> {
+  let result = match read_at::<_, 4>(reader, 0) {
+    Ok([b'M', b'Z', ..]) => pe_overlay_offset(reader),
+    Ok(magic) if magic == *ELF_MAGIC => elf_overlay_offset(reader),
+    Ok(magic) if matches!(u32::from_le_bytes(magic), MACHO_MAGIC | MACHO_MAGIC_64) => {
+      macho_overlay_offset(reader, 0)
+    }
+    Ok(magic) if matches!(u32::from_be_bytes(magic), FAT_MAGIC | FAT_MAGIC_64) => {
+      fat_overlay_offset(reader)
+    }
+    Ok(_) => Ok(None),
+    Err(e) => Err(e),
+  };
+  match result {
+    Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+    result => result,
+  }
+}
+
+/// Where the overlay of a Windows executable starts, the end of its last section.
+fn pe_overlay_offset<R: Read + Seek>(reader: &mut R) -> std::io::Result<Option<u64>> {
+  // The DOS header points to the PE header, which says how many sections follow the optional
+  // header
+  let pe_offset = u32::from_le_bytes(read_at(reader, 0x3c)?) as u64;
+  if read_at::<_, 4>(reader, pe_offset)? != *b"PE\0\0" {
+    return Ok(None);
+  }
+  let section_count = u16::from_le_bytes(read_at(reader, pe_offset + 6)?) as u64;
+  let optional_header_size = u16::from_le_bytes(read_at(reader, pe_offset + 20)?) as u64;
+
+  let sections_start = pe_offset + 24 + optional_header_size;
+  let mut end = sections_start + section_count * PE_SECTION_HEADER_SIZE;
+  for i in 0..section_count {
+    let header: [u8; 8] = read_at(reader, sections_start + i * PE_SECTION_HEADER_SIZE + 16)?;
+    let raw_size = u32::from_le_bytes(header[0..4].try_into().unwrap()) as u64;
+    let raw_offset = u32::from_le_bytes(header[4..8].try_into().unwrap()) as u64;
+    end = end.max(raw_offset + raw_size);
+  }
+  Ok(Some(end))
+}
+
+/// Where the overlay of a Linux executable starts, the end of whichever comes last of its
+/// segments, its sections and the tables listing them.
+///
+/// Both 32 and 64-bit ELF files are read, in either byte order.
+fn elf_overlay_offset<R: Read + Seek>(reader: &mut R) -> std::io::Result<Option<u64>> {
+  let [class, data] = read_at(reader, 4)?;
+  let wide = match class {
+    1 => false,
+    2 => true,
+    _ => return Ok(None),
+  };
+  let big_endian = match data {
+    1 => false,
+    2 => true,
+    _ => return Ok(None),
+  };
+  let u16_at = |reader: &mut R, pos| -> std::io::Result<u64> {
+    let bytes = read_at(reader, pos)?;
+    Ok(match big_endian {
+      true => u16::from_be_bytes(bytes),
+      false => u16::from_le_bytes(bytes),
+    } as u64)
+  };
+  let u32_at = |reader: &mut R, pos| -> std::io::Result<u32> {
+    let bytes = read_at(reader, pos)?;
+    Ok(match big_endian {
+      true => u32::from_be_bytes(bytes),
+      false => u32::from_le_bytes(bytes),
+    })
+  };
+  // Offsets and sizes are 32 bits wide in 32-bit files and 64 in 64-bit ones
+  let word_at = |reader: &mut R, pos| -> std::io::Result<u64> {
+    Ok(match (wide, big_endian) {
+      (true, true) => u64::from_be_bytes(read_at(reader, pos)?),
+      (true, false) => u64::from_le_bytes(read_at(reader, pos)?),
+      (false, _) => u32_at(reader, pos)? as u64,
+    })
+  };
+
+  let (program_table, section_table, counts) = match wide {
+    true => (0x20, 0x28, 0x36),
+    false => (0x1c, 0x20, 0x2a),
+  };
+  let program_offset = word_at(reader, program_table)?;
+  let section_offset = word_at(reader, section_table)?;
+  let program_entry_size = u16_at(reader, counts)?;
+  let program_count = u16_at(reader, counts + 2)?;
+  let section_entry_size = u16_at(reader, counts + 4)?;
+  let section_count = u16_at(reader, counts + 6)?;
+
+  // Made-up offsets in a damaged file shouldn't overflow, they just put the end past the file
+  let mut end = program_offset
+    .saturating_add(program_count * program_entry_size)
+    .max(section_offset.saturating_add(section_count * section_entry_size));
+  let (segment_offset, segment_size) = match wide {
+    true => (0x08, 0x20),
+    false => (0x04, 0x10),
+  };
+  for i in 0..program_count {
+    let entry = program_offset.saturating_add(i * program_entry_size);
+    let segment_end = word_at(reader, entry.saturating_add(segment_offset))?
+      .saturating_add(word_at(reader, entry.saturating_add(segment_size))?);
+    end = end.max(segment_end);
+  }
+  let (section_offset_field, section_size_field) = match wide {
+    true => (0x18, 0x20),
+    false => (0x10, 0x14),
+  };
+  for i in 0..section_count {
+    let entry = section_offset.saturating_add(i * section_entry_size);
+    // Sections like .bss only take up room once loaded, not in the file
+    if u32_at(reader, entry.saturating_add(4))? == ELF_SECTION_NOBITS {
+      continue;
+    }
+    let section_end = word_at(reader, entry.saturating_add(section_offset_field))?
+      .saturating_add(word_at(reader, entry.saturating_add(section_size_field))?);
+    end = end.max(section_end);
+  }
+  Ok(Some(end))
+}
+
+/// Where the overlay of a macOS executable starting at `start` begins, the end of its last
+/// segment.
+///
+/// The `__LINKEDIT` segment comes last and holds the code signature, so this is also where the
+/// signed part of the file ends.
+fn macho_overlay_offset<R: Read + Seek>(
+  reader: &mut R,
+  start: u64,
+) -> std::io::Result<Option<u64>> {
+  let u32_at = |reader: &mut R, pos: u64| -> std::io::Result<u32> {
+    Ok(u32::from_le_bytes(read_at(
+      reader,
+      start.saturating_add(pos),
+    )?))
+  };
+  let u64_at = |reader: &mut R, pos: u64| -> std::io::Result<u64> {
+    Ok(u64::from_le_bytes(read_at(
+      reader,
+      start.saturating_add(pos),
+    )?))
+  };
+  let header_size = match u32_at(reader, 0)? {
+    MACHO_MAGIC => 28,
+    MACHO_MAGIC_64 => 32,
+    _ => return Ok(None),
+  };
+  let command_count = u32_at(reader, 16)?;
+  let commands_size = u32_at(reader, 20)? as u64;
+
+  let mut end = header_size + commands_size;
+  let mut command = header_size;
+  for _ in 0..command_count {
+    let (offset, size) = match u32_at(reader, command)? {
+      MACHO_SEGMENT => (
+        u32_at(reader, command + 32)? as u64,
+        u32_at(reader, command + 36)? as u64,
+      ),
+      MACHO_SEGMENT_64 => (u64_at(reader, command + 40)?, u64_at(reader, command + 48)?),
+      _ => (0, 0),
+    };
+    end = end.max(offset.saturating_add(size));
+    let command_size = u32_at(reader, command + 4)? as u64;
+    // Every command is at least as big as its type and size, anything less would loop forever
+    if command_size < 8 {
+      return Ok(None);
+    }
+    command += command_size;
+  }
+  Ok(Some(start.saturating_add(end)))
+}
+
+/// Where the overlay of a universal macOS executable begins, the end of whichever of the
+/// executables in it comes last.
+fn fat_overlay_offset<R: Read + Seek>(reader: &mut R) -> std::io::Result<Option<u64>> {
+  let u32_at = |reader: &mut R, pos| -> std::io::Result<u64> {
+    Ok(u32::from_be_bytes(read_at(reader, pos)?) as u64)
+  };
+  let u64_at =
+    |reader: &mut R, pos| -> std::io::Result<u64> { Ok(u64::from_be_bytes(read_at(reader, pos)?)) };
+  let wide = u32_at(reader, 0)? == FAT_MAGIC_64 as u64;
+  let arch_count = u32_at(reader, 4)?;
+  let entry_size = if wide { 32 } else { 20 };
+
+  let mut end = 8 + arch_count * entry_size;
+  for i in 0..arch_count {
+    let entry = 8 + i * entry_size;
+    let (offset, size) = match wide {
+      true => (u64_at(reader, entry + 8)?, u64_at(reader, entry + 16)?),
+      false => (u32_at(reader, entry + 8)?, u32_at(reader, entry + 12)?),
+    };
+    end = end.max(offset.saturating_add(size));
+  }
+  Ok(Some(end))
+}
+
+/// Looks for the first local file header in the [`MAX_SEARCH_SIZE`] bytes of `reader` after the
+/// end of the executable, or from the start for anything but a Windows, Linux or macOS
+/// executable.
+fn scan_for_local_header<R: Read + Seek>(
+  reader: &mut R,
+  file_size: u64,
+) -> std::io::Result<Option<u64>> {
+  // The magic turns up in the executable's own code and resources, so skip past them when they
+  // can be told apart from the payload
+  let mut pos = overlay_offset(reader)?.unwrap_or(0);
+
+  // Search for ZIP magic number from there (only search MAX_SEARCH_SIZE bytes)
+  let search_end = file_size.min(pos.saturating_add(MAX_SEARCH_SIZE));
+
+  let finder = memchr::memmem::Finder::new(ZIP_MAGIC);
+  let mut buffer = vec![0u8; SCAN_CHUNK_SIZE];
+  while pos < search_end {
+    let len = (search_end - pos).min(SCAN_CHUNK_SIZE as u64) as usize;
+    reader.seek(SeekFrom::Start(pos))?;
+    reader.read_exact(&mut buffer[..len])?;
+
+    if let Some(offset) = finder.find(&buffer[..len]) {
+      return Ok(Some(pos + offset as u64));
+    }
+    if pos + len as u64 >= search_end {
+      break;
+    }
+    // Chunks overlap by one byte less than the magic, so one split across two chunks is still found
+    pos += (len - (ZIP_MAGIC.len() - 1)) as u64;
+  }
+
+  Ok(None)
+}
+
+/// A view of the part of a file that holds the payload, so the archive can be read in place
+/// without copying it out first.
+///
+/// Positions are relative to the start of the payload, and reads stop at its end.
+pub struct PayloadReader<R> {
+  inner: R,
+  start: u64,
+  len: u64,
+  pos: u64,
+}
+
+impl<R: Seek> PayloadReader<R> {
+  pub fn new(mut inner: R, start: u64, len: u64) -> std::io::Result<Self> {
+    inner.seek(SeekFrom::Start(start))?;
+    Ok(Self {
+      inner,
+      start,
+      len,
+      pos: 0,
+    })
+  }
+}
+
+impl<R: Read> Read for PayloadReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    let remaining = self.len.saturating_sub(self.pos);
+    let max = (buf.len() as u64).min(remaining) as usize;
+    if max == 0 {
+      return Ok(0);
+    }
+    let read = self.inner.read(&mut buf[..max])?;
+    self.pos += read as u64;
+    Ok(read)
+  }
+}
+
+impl<R: Seek> Seek for PayloadReader<R> {
+  fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+    let target = match pos {
+      SeekFrom::Start(offset) => Some(offset),
+      SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+      SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+    }
+    .ok_or_else(|| {
+      std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "Tried to seek before the start of the payload",
+      )
+    })?;
+
+    self.inner.seek(SeekFrom::Start(self.start + target))?;
+    self.pos = target;
+    Ok(target)
+  }
+}
+
+type PayloadArchive = zip::ZipArchive<std::io::BufReader<PayloadReader<File>>>;
+
+/// Opens the payload archive, along with the time the file holding it was written.
+fn open_payload(source: &PackSource) -> Result<(PayloadArchive, Option<std::time::SystemTime>)> {
+  let (path, zip_start, zip_size) = match source {
+    PackSource::EmbeddedZip {
+      exe_path,
+      zip_start,
+      zip_size,
+    } => (exe_path, *zip_start, *zip_size),
+    PackSource::SidecarZip(path) | PackSource::UserZip(path) => {
+      (path, 0, std::fs::metadata(path)?.len())
+    }
+    PackSource::DebugFolder(_) | PackSource::UserFolder(_) => {
+      anyhow::bail!("Cannot extract from a folder - resources should be used directly")
+    }
+  };
+
+  let file = File::open(path).context(format!("Failed to open {}", path.display()))?;
+  let packed_at = file.metadata()?.modified().ok();
+
+  // Read the archive in place, packs with long recordings can be gigabytes
+  let payload = PayloadReader::new(file, zip_start, zip_size)?;
+  let archive =
+    zip::ZipArchive::new(std::io::BufReader::new(payload)).context("Failed to read ZIP data")?;
+  Ok((archive, packed_at))
+}
+
+/// Start of the error for packs that break the [`ExtractLimits`] or would write outside the
+/// folder they're extracted into.
+const UNSAFE_PACK: &str = "This package appears to be corrupted or unsafe";
+/// Files smaller than this aren't held to [`ExtractLimits::max_compression_ratio`], since small
+/// text files can legitimately compress very well.
+const MIN_RATIO_CHECKED_SIZE: u64 = 1024 * 1024;
+
+/// Limits on what a pack may unpack to, so a damaged or malicious one can't fill the drive.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractLimits {
+  /// Most bytes the extracted files may add up to, 0 for no limit.
+  pub max_total_size: u64,
+  /// Most a file may have shrunk by when it was packed, as its size over its compressed size, 0
+  /// for no limit. Audio and ffmpeg barely compress, while a ZIP bomb shrinks a thousandfold.
+  pub max_compression_ratio: u64,
+}
+
+impl Default for ExtractLimits {
+  fn default() -> Self {
+    Self {
+      max_total_size: 100 * 1024 * 1024 * 1024,
+      max_compression_ratio: 100,
+    }
+  }
+}
+
+/// A file in the pack and where it gets extracted to.
+struct ArchiveEntry {
+  /// Position in the archive.
+  index: usize,
+  /// Name it's extracted under. Tracks in subfolders are flattened into the top folder.
+  name: String,
+  size: u64,
+  compressed_size: u64,
+  /// Whether it's at the top of the archive rather than in a subfolder.
+  root: bool,
+}
+
+/// Works out the name an archive entry is extracted under and whether it's at the top of the
+/// archive, or `None` for folders.
+///
+/// Fails for names that would end up outside the folder being extracted into, like `../evil.flac`
+/// or `/etc/passwd`, which no honest pack contains. Backslashes count as separators too, since
+/// packs made on Windows use them.
+fn entry_target(file: &zip::read::ZipFile<'_>) -> Result<Option<(String, bool)>> {
+  let name = file.name();
+  let parts: Vec<&str> = name
+    .split(['/', '\\'])
+    .filter(|part| !part.is_empty() && *part != ".")
+    .collect();
+  let absolute = name.starts_with(['/', '\\']) || name.as_bytes().get(1) == Some(&b':');
+  if file.enclosed_name().is_none() || absolute || parts.contains(&"..") {
+    anyhow::bail!("{UNSAFE_PACK}, it contains a file outside its folder: {name}");
+  }
+
+  match parts.last() {
+    Some(file_name) if !name.ends_with(['/', '\\']) => {
+      Ok(Some((file_name.to_string(), parts.len() == 1)))
+    }
+    _ => Ok(None),
+  }
+}
+
+/// Whether a file is one of the recorded tracks.
+fn is_track(name: &str) -> bool {
+  Path::new(name)
+    .extension()
+    .is_some_and(|ext| ext.eq_ignore_ascii_case("flac"))
+}
+
+/// Checksums a pack can list for its files, to catch a damaged download with a clear message
+/// rather than a broken ffmpeg or an odd decoding error later on.
+///
+/// ```json
+/// {
+///   "format_version": "1.0",
+///   "files": [{ "name": "ffmpeg.exe", "size": 123456, "sha256": "9f86d08..." }]
+/// }
+/// ```
+///
+/// Tracks can also give their `duration` in seconds, which is shown before anything is unpacked.
+/// Packs without a `format_version` are treated as 1.0.
+#[derive(Debug, Deserialize)]
+struct Manifest {
+  files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestEntry {
+  name: String,
+  size: u64,
+  sha256: String,
+  #[serde(default)]
+  duration: Option<f64>,
+}
+
+/// Reads the pack's manifest, if it has one, keyed by file name.
+fn read_manifest(
+  archive: &mut PayloadArchive,
+  password: Option<&str>,
+) -> Result<Option<HashMap<String, ManifestEntry>>> {
+  let Some(index) = (0..archive.len()).find(|&index| {
+    archive
+      .by_index_raw(index)
+      .is_ok_and(|file| file.name() == MANIFEST_NAME)
+  }) else {
+    return Ok(None);
+  };
+  parse_manifest(open_entry(archive, index, password)?).map(Some)
+}
+
+fn parse_manifest(reader: impl Read) -> Result<HashMap<String, ManifestEntry>> {
+  let damaged = "The pack is damaged, its manifest can't be read";
+  let value: serde_json::Value = serde_json::from_reader(reader).context(damaged)?;
+  // Check the version before anything else, a newer manifest may not have the fields read below
+  let version: Option<FormatVersion> =
+    serde_json::from_value(value["format_version"].clone()).context(damaged)?;
+  check_pack_version(version.unwrap_or(PACK_FORMAT_VERSION))?;
+  let manifest: Manifest = serde_json::from_value(value).context(damaged)?;
+  Ok(
+    manifest
+      .files
+      .into_iter()
+      .map(|entry| (entry.name.clone(), entry))
+      .collect(),
+  )
+}
+
+/// Opens entry `index` of `archive` for reading, decrypting it with `password` if it's encrypted.
+///
+/// Fails with [`ProcessError::PasswordRequired`] for an encrypted entry without a password, and
+/// with [`ProcessError::WrongPassword`] if the password doesn't fit.
+fn open_entry<'a>(
+  archive: &'a mut PayloadArchive,
+  index: usize,
+  password: Option<&str>,
+) -> Result<zip::read::ZipFile<'a>> {
+  match password {
+    Some(password) => match archive.by_index_decrypt(index, password.as_bytes())? {
+      Ok(file) => Ok(file),
+      Err(zip::result::InvalidPassword) => Err(ProcessError::WrongPassword.into()),
+    },
+    None => match archive.by_index(index) {
+      Err(zip::result::ZipError::UnsupportedArchive(message))
+        if message == zip::result::ZipError::PASSWORD_REQUIRED =>
+      {
+        Err(ProcessError::PasswordRequired.into())
+      }
+      result => Ok(result?),
+    },
+  }
+}
+
+/// Checks that `password` decrypts every encrypted file in `source` by reading them all the way
+/// through, returning how many there are.
+pub fn check_pack_password(source: &PackSource, password: &str) -> Result<usize> {
+  let (mut archive, _) = open_payload(source)?;
+  let mut encrypted = 0;
+  for index in 0..archive.len() {
+    let is_encrypted = match open_entry(&mut archive, index, None) {
+      Err(e) if matches!(e.downcast_ref(), Some(ProcessError::PasswordRequired)) => true,
+      result => {
+        result?;
+        false
+      }
+    };
+    if is_encrypted {
+      let mut file = open_entry(&mut archive, index, Some(password))?;
+      let name = file.name().to_string();
+      // A wrong password can slip past the quick check, but not the checksum at the end
+      std::io::copy(&mut file, &mut std::io::sink())
+        .context(format!("{name} can't be decrypted with this password"))?;
+      encrypted += 1;
+    }
+  }
+  Ok(encrypted)
+}
+
+/// Lists the files in `archive` under the names they're extracted as, skipping folders.
+fn archive_entries(archive: &mut PayloadArchive) -> Result<Vec<ArchiveEntry>> {
+  let mut entries = Vec::new();
+  for index in 0..archive.len() {
+    let file = archive.by_index_raw(index)?;
+    if let Some((name, root)) = entry_target(&file)? {
+      entries.push(ArchiveEntry {
+        index,
+        name,
+        size: file.size(),
+        compressed_size: file.compressed_size(),
+        root,
+      });
+    }
+  }
+  Ok(entries)
+}
+
+/// A track in a pack, as listed without unpacking anything.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PackEntry {
+  /// File name the track is extracted as.
+  pub name: String,
+  pub size: u64,
+  /// How much room the track takes up in the pack, the same as `size` for folders.
+  pub compressed_size: u64,
+  /// Length in seconds, if the pack's manifest gives it.
+  pub duration: Option<f64>,
+}
+
+/// Lists the tracks in `source`, sorted by name, reading only the ZIP's central directory.
+pub fn list_pack_entries(source: &PackSource) -> Result<Vec<PackEntry>> {
+  let (mut tracks, manifest) = match source {
+    PackSource::DebugFolder(dir) | PackSource::UserFolder(dir) => {
+      let mut tracks = Vec::new();
+      for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if is_track(&name) && entry.file_type()?.is_file() {
+          let size = entry.metadata()?.len();
+          tracks.push(PackEntry {
+            name,
+            size,
+            compressed_size: size,
+            duration: None,
+          });
+        }
+      }
+      let manifest = match File::open(dir.join(MANIFEST_NAME)) {
+        Ok(file) => Some(parse_manifest(file)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+      };
+      (tracks, manifest)
+    }
+    _ => {
+      let (mut archive, _) = open_payload(source)?;
+      let tracks = archive_entries(&mut archive)?
+        .into_iter()
+        .filter(|entry| is_track(&entry.name))
+        .map(|entry| PackEntry {
+          name: entry.name,
+          size: entry.size,
+          compressed_size: entry.compressed_size,
+          duration: None,
+        })
+        .collect();
+      // The durations in an encrypted manifest have to wait for the password
+      let manifest = match read_manifest(&mut archive, None) {
+        Err(e) if matches!(e.downcast_ref(), Some(ProcessError::PasswordRequired)) => None,
+        manifest => manifest?,
+      };
+      (tracks, manifest)
+    }
+  };
+
+  if let Some(manifest) = manifest {
+    for track in &mut tracks {
+      track.duration = manifest.get(&track.name).and_then(|entry| entry.duration);
+    }
+  }
+  tracks.sort_by(|a, b| a.name.cmp(&b.name));
+  Ok(tracks)
+}
+
+/// Reads the length of each of the tracks called `names` in `source`, passing it to `on_duration`
+/// as soon as it's known.
+///
+/// Only the start of each track is read, see [`flac_duration`]. A track that can't be read is
+/// passed along with the error rather than stopping the others.
+pub fn read_track_durations(
+  source: &PackSource,
+  names: &[String],
+  password: Option<&str>,
+  mut on_duration: impl FnMut(&str, Result<f64>),
+) -> Result<()> {
+  match source {
+    PackSource::DebugFolder(dir) | PackSource::UserFolder(dir) => {
+      for name in names {
+        let duration = File::open(dir.join(name))
+          .context(format!("Failed to open {name}"))
+          .and_then(|file| flac_duration(std::io::BufReader::new(file)));
+        on_duration(name, duration);
+      }
+    }
+    _ => {
+      let (mut archive, _) = open_payload(source)?;
+      let entries = archive_entries(&mut archive)?;
+      for name in names {
+        let duration = match entries.iter().find(|entry| entry.name == *name) {
+          Some(entry) => open_entry(&mut archive, entry.index, password).and_then(flac_duration),
+          None => Err(anyhow::anyhow!("{name} isn't in the pack")),
+        };
+        on_duration(name, duration);
+      }
+    }
+  }
+  Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Whether a file in a pack is one of the ffmpeg builds, see [`ffmpeg_binary_names`].
+fn is_ffmpeg_binary(name: &str) -> bool {
+  name == FFMPEG_BINARY
+    || (name.starts_with("ffmpeg-") && name.ends_with(std::env::consts::EXE_SUFFIX))
+}
+
+/// Finds the ffmpeg in `dir` that suits this machine best.
+pub fn select_ffmpeg(dir: &Path) -> Option<PathBuf> {
+  ffmpeg_binary_names()
+    .into_iter()
+    .map(|name| dir.join(name))
+    .find(|path| path.is_file())
+}
+
+/// Which files of a pack get extracted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PackContents {
+  /// The files at the top of the pack, along with any tracks in subfolders.
+  All,
+  /// Just ffmpeg and ffprobe.
+  Tools,
+  /// Just the tracks, wherever they are in the pack.
+  Tracks,
+}
+
+/// Extracts `contents` of `archive` into `dest`, returning the names of the files it holds now.
+///
+/// Only the ffmpeg build that suits this machine is extracted when there are several. Every file is
+/// checked against the pack's manifest if it has one, and against the ZIP's own CRCs regardless,
+/// so a damaged download fails naming the broken file. Packs that break `limits` fail before
+/// anything is written. With `reuse_existing`, files already in `dest` with the right size and CRC
+/// are kept instead of being extracted again. Encrypted files are decrypted with `password`.
+/// `on_progress` is called with the file being extracted, the number of bytes done so far and the
+/// total.
+#[allow(clippy::too_many_arguments)]
+fn extract_pack_files(
+  archive: &mut PayloadArchive,
+  dest: &Path,
+  packed_at: Option<std::time::SystemTime>,
+  reuse_existing: bool,
+  contents: PackContents,
+  limits: ExtractLimits,
+  password: Option<&str>,
+  mut on_progress: impl FnMut(&str, u64, u64),
+) -> Result<Vec<String>> {
+  let all_entries = archive_entries(archive)?;
+
+  let ffmpeg = ffmpeg_binary_names().into_iter().find(|name| {
+    all_entries
+      .iter()
+      .any(|entry| entry.root && entry.name == *name)
+  });
+  let is_tool = |name: &str| is_ffmpeg_binary(name) || name == FFPROBE_BINARY;
+  let wanted_name = |name: &str| match contents {
+    PackContents::All => name != MANIFEST_NAME,
+    PackContents::Tools => is_tool(name),
+    PackContents::Tracks => is_track(name),
+  };
+  // Some downloads keep the tracks in a subfolder, anything else in one is of no use
+  let wanted = |entry: &ArchiveEntry| {
+    if !entry.root {
+      contents != PackContents::Tools && is_track(&entry.name)
+    } else if is_ffmpeg_binary(&entry.name) && ffmpeg.as_deref() != Some(entry.name.as_str()) {
+      false
+    } else {
+      wanted_name(&entry.name)
+    }
+  };
+  let entries: Vec<&ArchiveEntry> = all_entries.iter().filter(|entry| wanted(entry)).collect();
+
+  let mut names = std::collections::HashSet::new();
+  if let Some(duplicate) = entries.iter().find(|entry| !names.insert(&entry.name)) {
+    anyhow::bail!(
+      "The pack contains more than one file called {}",
+      duplicate.name
+    );
+  }
+
+  let bytes_total = entries.iter().map(|entry| entry.size).sum::<u64>();
+  if limits.max_total_size > 0 && bytes_total > limits.max_total_size {
+    anyhow::bail!(
+      "{UNSAFE_PACK}, it would unpack to {}, more than the limit of {}",
+      format_bytes(bytes_total),
+      format_bytes(limits.max_total_size)
+    );
+  }
+  if limits.max_compression_ratio > 0
+    && let Some(entry) = entries.iter().find(|entry| {
+      entry.size >= MIN_RATIO_CHECKED_SIZE
+        && entry.size / entry.compressed_size.max(1) > limits.max_compression_ratio
+    })
+  {
+    anyhow::bail!(
+      "{UNSAFE_PACK}, {} unpacks to {} from only {}",
+      entry.name,
+      format_bytes(entry.size),
+      format_bytes(entry.compressed_size)
+    );
+  }
+
+  // Stopping before anything is written beats filling the drive and failing halfway through
+  let needed = entries
+    .iter()
+    .filter(|entry| {
+      !(reuse_existing
+        && std::fs::metadata(dest.join(&entry.name)).is_ok_and(|meta| meta.len() == entry.size))
+    })
+    .map(|entry| entry.size)
+    .sum::<u64>();
+  if let Some(available) = available_space(dest)
+    && available < needed
+  {
+    return Err(
+      ProcessError::NotEnoughSpaceToUnpack {
+        dir: dest.to_path_buf(),
+        needed,
+        available,
+      }
+      .into(),
+    );
+  }
+
+  let manifest = read_manifest(archive, password)?;
+  if let Some(manifest) = &manifest
+    && let Some(missing) = manifest
+      .keys()
+      .find(|name| wanted_name(name) && !all_entries.iter().any(|entry| entry.name == **name))
+  {
+    anyhow::bail!(
+      "The pack is incomplete, {missing} is missing from it. Try downloading it again."
+    );
+  }
+
+  let mut bytes_done = 0;
+  let mut buffer = vec![0u8; EXTRACT_BUFFER_SIZE];
+  let mut extracted = Vec::with_capacity(entries.len());
+  for entry in entries {
+    let mut file = open_entry(archive, entry.index, password)?;
+    let name = &entry.name;
+    on_progress(name, bytes_done, bytes_total);
+    extracted.push(name.clone());
+
+    let out_path = dest.join(name);
+    if reuse_existing && cache::cached_file_matches(&out_path, file.size(), file.crc32()) {
+      bytes_done += file.size();
+      on_progress(name, bytes_done, bytes_total);
+      continue;
+    }
+
+    // Written under a temporary name first, so an interrupted extraction never leaves a truncated
+    // file behind under the real name
+    let mut outfile = tempfile::Builder::new()
+      .prefix(".otterpack-extract-")
+      .tempfile_in(dest)
+      .context(format!("Failed to create file: {}", out_path.display()))?;
+    let expected = manifest.as_ref().and_then(|manifest| manifest.get(name));
+    let mut hasher = Sha256::new();
+    let mut written = 0;
+    loop {
+      // The zip crate checks the entry's CRC32 once it reaches the end, so a damaged entry fails
+      // here even without a manifest
+      let read = file.read(&mut buffer).context(format!(
+        "Couldn't extract {name}, the pack may be damaged and need downloading again"
+      ))?;
+      if read == 0 {
+        break;
+      }
+      // The limits were checked against the sizes the archive claims, so hold it to them
+      if written + read as u64 > entry.size {
+        anyhow::bail!("{UNSAFE_PACK}, {name} is bigger than the archive says");
+      }
+      outfile
+        .write_all(&buffer[..read])
+        .context(format!("Failed to write file: {}", out_path.display()))?;
+      if expected.is_some() {
+        hasher.update(&buffer[..read]);
+      }
+      written += read as u64;
+      bytes_done += read as u64;
+      on_progress(name, bytes_done, bytes_total);
+    }
+    if let Some(expected) = expected
+      && (written != expected.size
+        || !to_hex(&hasher.finalize()).eq_ignore_ascii_case(&expected.sha256))
+    {
+      anyhow::bail!(
+        "The pack is damaged, {name} doesn't match its checksum. Try downloading it again."
+      );
+    }
+    // The tracks are as old as the pack, not the extraction, so outputs from an earlier run
+    // still count as newer than their inputs
+    if let Some(packed_at) = packed_at {
+      let _ = outfile.as_file().set_modified(packed_at);
+    }
+    #[cfg(unix)]
+    restore_permissions(outfile.as_file(), name, file.unix_mode()).context(format!(
+      "Failed to set permissions on {}",
+      out_path.display()
+    ))?;
+    outfile
+      .persist(&out_path)
+      .context(format!("Failed to write file: {}", out_path.display()))?;
+  }
+
+  Ok(extracted)
+}
+
+/// Applies the permissions a file was packed with, since extracting only creates files readable
+/// by their owner. ffmpeg is always made executable, even from archives made on Windows that don't
+/// record any permissions, and so is ffprobe.
+#[cfg(unix)]
+fn restore_permissions(file: &File, name: &str, mode: Option<u32>) -> std::io::Result<()> {
+  use std::os::unix::fs::PermissionsExt;
+
+  let mut mode = mode.map_or(0o644, |mode| mode & 0o777);
+  if is_ffmpeg_binary(name) || name == FFPROBE_BINARY {
+    mode |= 0o755;
+  }
+  file.set_permissions(std::fs::Permissions::from_mode(mode))
+}
+
+/// Extracts the bundled files into a new temp directory, decrypting them with `password` if the
+/// pack is encrypted.
+///
+/// `on_progress` is called with the file being extracted, the number of bytes written so far and
+/// the total to write.
+pub fn extract_zip_contents(
+  source: &PackSource,
+  dirs: &Dirs,
+  limits: ExtractLimits,
+  password: Option<&str>,
+  on_progress: impl FnMut(&str, u64, u64),
+) -> Result<tempfile::TempDir> {
+  extract_to_temp(
+    source,
+    dirs,
+    PackContents::All,
+    limits,
+    password,
+    on_progress,
+  )
+}
+
+fn extract_to_temp(
+  source: &PackSource,
+  dirs: &Dirs,
+  contents: PackContents,
+  limits: ExtractLimits,
+  password: Option<&str>,
+  on_progress: impl FnMut(&str, u64, u64),
+) -> Result<tempfile::TempDir> {
+  let (mut archive, packed_at) = open_payload(source)?;
+  let temp_dir = tempfile::Builder::new()
+    .prefix(TEMP_DIR_PREFIX)
+    .tempdir_in(&dirs.temp)
+    .context(format!(
+      "Failed to create a temp folder in {}",
+      dirs.temp.display()
+    ))?;
+  std::fs::write(
+    temp_dir.path().join(PID_FILE_NAME),
+    std::process::id().to_string(),
+  )?;
+  extract_pack_files(
+    &mut archive,
+    temp_dir.path(),
+    packed_at,
+    false,
+    contents,
+    limits,
+    password,
+    on_progress,
+  )?;
+  Ok(temp_dir)
+}
+
+/// Turns a temp folder into one that's left behind on exit and never cleaned up as stale,
+/// returning its path.
+fn keep_temp_dir(temp_dir: tempfile::TempDir) -> std::io::Result<PathBuf> {
+  std::fs::write(temp_dir.path().join(KEPT_FILE_NAME), b"")?;
+  let _ = std::fs::remove_file(temp_dir.path().join(PID_FILE_NAME));
+  Ok(temp_dir.keep())
+}
+
+/// Extracts just the tracks of `source` into `dest` as they are, returning their names.
+///
+/// Tracks in a folder are copied instead. `on_progress` is called with the track being extracted,
+/// the number of bytes done so far and the total.
+pub fn extract_tracks(
+  source: &PackSource,
+  dest: &Path,
+  limits: ExtractLimits,
+  password: Option<&str>,
+  mut on_progress: impl FnMut(&str, u64, u64),
+) -> Result<Vec<String>> {
+  std::fs::create_dir_all(dest).context(format!("Failed to create {}", dest.display()))?;
+  match source {
+    PackSource::DebugFolder(dir) | PackSource::UserFolder(dir) => {
+      if crate::platform::canonicalize_lenient(dir) == crate::platform::canonicalize_lenient(dest) {
+        anyhow::bail!("The tracks are already in {}", dest.display());
+      }
+      let tracks = list_pack_entries(source)?;
+      let bytes_total = tracks.iter().map(|track| track.size).sum();
+      let mut bytes_done = 0;
+      for track in &tracks {
+        on_progress(&track.name, bytes_done, bytes_total);
+        std::fs::copy(dir.join(&track.name), dest.join(&track.name))
+          .context(format!("Failed to copy {}", track.name))?;
+        bytes_done += track.size;
+      }
+      Ok(tracks.into_iter().map(|track| track.name).collect())
+    }
+    _ => {
+      let (mut archive, packed_at) = open_payload(source)?;
+      extract_pack_files(
+        &mut archive,
+        dest,
+        packed_at,
+        false,
+        PackContents::Tracks,
+        limits,
+        password,
+        on_progress,
+      )
+    }
+  }
+}
+
+/// Removes temp folders in `temp_root` that runs which crashed or were killed left behind,
+/// returning how many bytes that freed.
+///
+/// A folder is only removed once it's older than `max_age` and the process named in its PID file
+/// is gone, so folders of this or another running instance are never touched.
+pub fn remove_stale_temp_dirs(
+  temp_root: &Path,
+  max_age: std::time::Duration,
+) -> std::io::Result<u64> {
+  let now = std::time::SystemTime::now();
+  let mut reclaimed = 0;
+  for entry in std::fs::read_dir(temp_root)? {
+    let Ok(entry) = entry else {
+      continue;
+    };
+    let is_ours = entry
+      .file_name()
+      .to_str()
+      .is_some_and(|name| name.starts_with(TEMP_DIR_PREFIX));
+    if !is_ours || !entry.file_type().is_ok_and(|t| t.is_dir()) {
+      continue;
+    }
+    if entry.path().join(KEPT_FILE_NAME).exists() {
+      continue;
+    }
+    let stale = entry
+      .metadata()
+      .and_then(|meta| meta.modified())
+      .ok()
+      .and_then(|modified| now.duration_since(modified).ok())
+      .is_some_and(|age| age > max_age);
+    if !stale {
+      continue;
+    }
+
+    // However old it is, a folder whose owner is still running is in use
+    let path = entry.path();
+    let owner = std::fs::read_to_string(path.join(PID_FILE_NAME))
+      .ok()
+      .and_then(|pid| pid.trim().parse::<u32>().ok());
+    if owner.is_some_and(|pid| pid == std::process::id() || process_is_alive(pid)) {
+      continue;
+    }
+
+    let size = dir_size(&path);
+    match std::fs::remove_dir_all(&path) {
+      Ok(()) => {
+        println!(
+          "Removed stale temp folder {} ({})",
+          path.display(),
+          format_bytes(size)
+        );
+        reclaimed += size;
+      }
+      Err(e) => println!(
+        "Failed to remove stale temp folder {}: {}",
+        path.display(),
+        e
+      ),
+    }
+  }
+  Ok(reclaimed)
+}
+
+/// Total size of the files under `path`, without following links.
+fn dir_size(path: &Path) -> u64 {
+  let Ok(entries) = std::fs::read_dir(path) else {
+    return 0;
+  };
+  entries
+    .flatten()
+    .map(|entry| match entry.file_type() {
+      Ok(t) if t.is_dir() => dir_size(&entry.path()),
+      Ok(t) if t.is_file() => entry.metadata().map_or(0, |meta| meta.len()),
+      _ => 0,
+    })
+    .sum()
+}
+
+/// Extracts the bundled files into a folder under `cache_root` named after the pack's contents,
+/// returning that folder.
+///
+/// Files left there by an earlier launch are reused if they're intact, so only missing or damaged
+/// files are extracted again.
+pub fn extract_zip_to_cache(
+  source: &PackSource,
+  cache_root: &Path,
+  contents: PackContents,
+  limits: ExtractLimits,
+  password: Option<&str>,
+  on_progress: impl FnMut(&str, u64, u64),
+) -> Result<PathBuf> {
+  let (mut archive, packed_at) = open_payload(source)?;
+  let dir = cache_root.join(cache::payload_key(&mut archive)?);
+  std::fs::create_dir_all(&dir)
+    .context(format!("Failed to create cache folder: {}", dir.display()))?;
+  // Marked before extracting, so eviction from another instance can't pull it out from under us
+  cache::mark_used(&dir)?;
+  extract_pack_files(
+    &mut archive,
+    &dir,
+    packed_at,
+    true,
+    contents,
+    limits,
+    password,
+    on_progress,
+  )?;
+  Ok(dir)
+}
+
+/// Extracts `source` into the cache in `dirs`, returning the folder it ended up in and the temp
+/// folder to keep alive if the cache couldn't be used.
+///
+/// Packs opened with a password always go into a temp folder instead, so their decrypted files
+/// aren't left lying around in the cache after the run.
+fn extract_payload(
+  source: &PackSource,
+  dirs: &Dirs,
+  contents: PackContents,
+  limits: ExtractLimits,
+  password: Option<&str>,
+  on_progress: impl FnMut(&str, u64, u64) + Copy,
+) -> Result<(Option<tempfile::TempDir>, PathBuf)> {
+  // Prefer the cache so later launches don't have to extract everything again, but a cache that
+  // can't be written to shouldn't stop the pack from opening
+  let cache = dirs.cache.as_ref().filter(|_| password.is_none());
+  let cached = match cache
+    .map(|root| extract_zip_to_cache(source, root, contents, limits, password, on_progress))
+  {
+    // A temp folder won't get any further without the password either
+    Some(Err(e)) if matches!(e.downcast_ref(), Some(ProcessError::PasswordRequired)) => {
+      return Err(e);
+    }
+    Some(Err(e)) => {
+      println!("Failed to use the resource cache, using a temp folder: {e:#}");
+      None
+    }
+    Some(Ok(dir)) => Some(dir),
+    None => None,
+  };
+  match cached {
+    Some(dir) => Ok((None, dir)),
+    None => {
+      let temp_dir = extract_to_temp(source, dirs, contents, limits, password, on_progress)?;
+      let dir = temp_dir.path().to_owned();
+      Ok((Some(temp_dir), dir))
+    }
+  }
+}
+
+/// Finds the ffmpeg packed with this executable, for a recording the user picked that doesn't
+/// bundle its own.
+fn packed_ffmpeg(
+  dirs: &Dirs,
+  limits: ExtractLimits,
+  on_progress: impl FnMut(&str, u64, u64) + Copy,
+) -> Result<Option<(Option<tempfile::TempDir>, PathBuf)>> {
+  match find_pack_source() {
+    Ok(PackSource::DebugFolder(path)) => Ok(select_ffmpeg(&path).map(|ffmpeg| (None, ffmpeg))),
+    Ok(source @ (PackSource::EmbeddedZip { .. } | PackSource::SidecarZip(_))) => {
+      let (temp_dir, dir) = extract_payload(
+        &source,
+        dirs,
+        PackContents::Tools,
+        limits,
+        None,
+        on_progress,
+      )?;
+      Ok(select_ffmpeg(&dir).map(|ffmpeg| (temp_dir, ffmpeg)))
+    }
+    _ => Ok(None),
+  }
+}
+
+/// Finds ffmpeg in a tools pack next to the executable, unpacking it into the tools cache so that
+/// later recordings can use it too.
+fn tools_pack_ffmpeg(
+  dirs: &Dirs,
+  limits: ExtractLimits,
+  on_progress: impl FnMut(&str, u64, u64) + Copy,
+) -> Result<Option<(Option<tempfile::TempDir>, PathBuf)>> {
+  let Some(tools_pack) = std::env::current_exe()
+    .ok()
+    .and_then(|exe| find_tools_pack(&exe))
+  else {
+    return Ok(None);
+  };
+  let source = PackSource::SidecarZip(tools_pack);
+
+  // Unpacked into the same folder every time, so recordings without a tools pack beside them
+  // can find it there later
+  let unpack_cached = |dir: PathBuf| -> Result<PathBuf> {
+    let (mut archive, packed_at) = open_payload(&source)?;
+    std::fs::create_dir_all(&dir)?;
+    cache::mark_used(&dir)?;
+    extract_pack_files(
+      &mut archive,
+      &dir,
+      packed_at,
+      true,
+      PackContents::Tools,
+      limits,
+      None,
+      on_progress,
+    )?;
+    Ok(dir)
+  };
+  let cached = dirs.tools().and_then(|dir| {
+    unpack_cached(dir)
+      .inspect_err(|e| println!("Failed to use the tools cache, using a temp folder: {e:#}"))
+      .ok()
+  });
+  let (temp_dir, dir) = match cached {
+    Some(dir) => (None, dir),
+    None => {
+      let temp_dir = extract_to_temp(
+        &source,
+        dirs,
+        PackContents::Tools,
+        limits,
+        None,
+        on_progress,
+      )?;
+      let dir = temp_dir.path().to_owned();
+      (Some(temp_dir), dir)
+    }
+  };
+  Ok(select_ffmpeg(&dir).map(|ffmpeg| (temp_dir, ffmpeg)))
+}
+
+/// Finds ffmpeg left in the tools cache by an earlier recording that had a tools pack beside it.
+fn cached_tools_ffmpeg(dirs: &Dirs) -> Option<PathBuf> {
+  let dir = dirs.tools()?;
+  let ffmpeg = select_ffmpeg(&dir)?;
+  let _ = cache::mark_used(&dir);
+  Some(ffmpeg)
+}
+
+/// Finds a working ffmpeg installed on the system, skipping any that fail to run.
+pub async fn find_system_ffmpeg() -> Option<PathBuf> {
+  for candidate in crate::platform::ffmpeg_candidates() {
+    match verify_ffmpeg(&candidate).await {
+      Ok(_) => return Some(candidate),
+      Err(e) => println!("Skipping {}: {:#}", candidate.display(), e),
+    }
+  }
+  None
+}
+
+/// A folder the user chose to unpack into, removed when dropped like a temp folder unless it's
+/// meant to be kept.
+#[derive(Debug)]
+pub struct WorkDir {
+  pub path: PathBuf,
+  pub keep: bool,
+}
+
+impl Drop for WorkDir {
+  fn drop(&mut self) {
+    if !self.keep
+      && let Err(e) = std::fs::remove_dir_all(&self.path)
+    {
+      println!("Failed to clean up {}: {}", self.path.display(), e);
+    }
+  }
+}
+
+/// An ffmpeg found outside the recording, which can be handed to [`setup_resources`] for the next
+/// recording of a batch so it isn't unpacked or looked for again.
+#[derive(Debug, Clone)]
+pub struct SharedFfmpeg {
+  pub ffmpeg_path: PathBuf,
+  /// Whether `ffmpeg_path` was installed on the system rather than packed with this program.
+  pub system_ffmpeg: bool,
+  /// Temp folders ffmpeg was unpacked into, removed once the last recording using it is done.
+  _temp_dirs: Arc<Vec<tempfile::TempDir>>,
+}
+
+pub struct ExtractedResources {
+  /// Temp folders the resources were extracted into, removed when this is dropped.
+  pub temp_dirs: Vec<tempfile::TempDir>,
+  /// Where the resources were extracted if the user picked a folder for it.
+  pub work_dir: Option<WorkDir>,
+  /// The folder holding the tracks.
+  pub audio_dir: PathBuf,
+  /// The ffmpeg to convert with, which isn't necessarily in `audio_dir`.
+  pub ffmpeg_path: PathBuf,
+  /// Whether `ffmpeg_path` was installed on the system rather than packed with the recording.
+  pub system_ffmpeg: bool,
+  /// ffprobe from the same place as ffmpeg, if it's there. Without it, ffmpeg is used instead.
+  pub ffprobe_path: Option<PathBuf>,
+  /// The ffmpeg in use, unless it came with the recording.
+  pub shared_ffmpeg: Option<SharedFfmpeg>,
+}
+
+/// Unpacks `source` and finds the ffmpeg to convert it with.
+///
+/// ffmpeg is looked for in this order, and the first one found is used:
+///
+/// 1. bundled with the recording itself
+/// 2. for recordings the user picked, packed with this executable
+/// 3. in a tools pack next to the executable, see [`find_tools_pack`]
+/// 4. left in the cache by an earlier tools pack
+/// 5. installed on the system, unless [`ProcessOptions::bundled_ffmpeg_only`] is set
+///
+/// Packs are unpacked into the cache in [`ProcessOptions::dirs`], or into
+/// [`ProcessOptions::work_dir`] if one is set. Packs opened with a password go into a temp
+/// folder or the work folder instead of the cache, and are removed after the run. A
+/// `shared_ffmpeg` from an earlier recording takes the place of steps 2 to 5.
+pub async fn setup_resources(
+  source: PackSource,
+  options: &ProcessOptions,
+  shared_ffmpeg: Option<SharedFfmpeg>,
+  completion_tx: tokio::sync::mpsc::UnboundedSender<RunEvent>,
+) -> Result<ExtractedResources> {
+  let work_dir = options.work_dir.clone();
+  let dirs = options.dirs.clone();
+  let limits = options.extract_limits;
+  let password = options.password.clone();
+  // Decrypted files are cleaned up after the run, whatever was asked for
+  let keep_extracted = options.keep_extracted && password.is_none();
+  if options.keep_extracted && !keep_extracted {
+    println!("Not keeping the unpacked files, since the recording is encrypted");
+  }
+  let (temp_dirs, work_dir, audio_dir, bundled, shared_ffmpeg) =
+    tokio::task::spawn_blocking(move || -> Result<_> {
+      let password = password.as_deref();
+      let report = |file: &str, bytes_done, bytes_total| {
+        let _ = completion_tx.send(RunEvent::Process(ProcessProgress::Extracting {
+          file: file.to_string(),
+          bytes_done,
+          bytes_total,
+        }));
+      };
+
+      let (temp_dir, work_dir, audio_dir) = match (&source, work_dir) {
+        (PackSource::DebugFolder(path) | PackSource::UserFolder(path), _) => {
+          (None, None, path.clone())
+        }
+        (_, Some(work_dir)) => {
+          let path = extract_zip_to_cache(
+            &source,
+            &work_dir,
+            PackContents::All,
+            limits,
+            password,
+            report,
+          )?;
+          let work_dir = WorkDir {
+            path: path.clone(),
+            keep: keep_extracted,
+          };
+          (None, Some(work_dir), path)
+        }
+        (_, None) => {
+          let (temp_dir, path) =
+            extract_payload(&source, &dirs, PackContents::All, limits, password, report)?;
+          let temp_dir = match temp_dir {
+            Some(temp_dir) if keep_extracted => {
+              keep_temp_dir(temp_dir)?;
+              None
+            }
+            temp_dir => temp_dir,
+          };
+          (temp_dir, None, path)
+        }
+      };
+      let temp_dirs: Vec<_> = temp_dir.into_iter().collect();
+
+      if let Some(bundled) = select_ffmpeg(&audio_dir) {
+        println!(
+          "Using the ffmpeg bundled with the recording: {}",
+          bundled.display()
+        );
+        return Ok((temp_dirs, work_dir, audio_dir, Some(bundled), None));
+      }
+      if let Some(shared) = shared_ffmpeg {
+        println!(
+          "Using the same ffmpeg as before: {}",
+          shared.ffmpeg_path.display()
+        );
+        return Ok((temp_dirs, work_dir, audio_dir, None, Some(shared)));
+      }
+      // A recording downloaded from Craig only has the tracks, and so do packs that leave ffmpeg
+      // out to stay small
+      let packed = match source {
+        PackSource::UserZip(_) | PackSource::UserFolder(_) => packed_ffmpeg(&dirs, limits, report)?,
+        _ => None,
+      };
+      let found = match packed {
+        Some(found) => Some(("packed with this program", found)),
+        None => match tools_pack_ffmpeg(&dirs, limits, report)? {
+          Some(found) => Some(("from the tools pack", found)),
+          None => cached_tools_ffmpeg(&dirs)
+            .map(|ffmpeg_path| ("cached from an earlier tools pack", (None, ffmpeg_path))),
+        },
+      };
+      let shared = found.map(|(origin, (temp_dir, ffmpeg_path))| {
+        println!("Using the ffmpeg {origin}: {}", ffmpeg_path.display());
+        SharedFfmpeg {
+          ffmpeg_path,
+          system_ffmpeg: false,
+          _temp_dirs: Arc::new(temp_dir.into_iter().collect()),
+        }
+      });
+      Ok((temp_dirs, work_dir, audio_dir, None, shared))
+    })
+    .await??;
+
+  let (ffmpeg_path, shared_ffmpeg) = match (bundled, shared_ffmpeg) {
+    (Some(bundled), _) => (bundled, None),
+    (None, Some(shared)) => (shared.ffmpeg_path.clone(), Some(shared)),
+    (None, None) if !options.bundled_ffmpeg_only => {
+      let ffmpeg_path = find_system_ffmpeg().await.ok_or_else(|| {
+        ProcessError::FfmpegUnusable(
+          "The recording doesn't include ffmpeg, and no working ffmpeg is installed on this computer"
+            .to_string(),
+        )
+      })?;
+      println!("Using the installed ffmpeg: {}", ffmpeg_path.display());
+      let shared = SharedFfmpeg {
+        ffmpeg_path: ffmpeg_path.clone(),
+        system_ffmpeg: true,
+        _temp_dirs: Arc::default(),
+      };
+      (ffmpeg_path, Some(shared))
+    }
+    (None, None) => {
+      return Err(
+        ProcessError::FfmpegUnusable(
+          "The recording doesn't include ffmpeg, and only a bundled ffmpeg is allowed to be used"
+            .to_string(),
+        )
+        .into(),
+      );
+    }
+  };
+
+  let system_ffmpeg = shared_ffmpeg
+    .as_ref()
+    .is_some_and(|shared| shared.system_ffmpeg);
+  let ffprobe_path = Some(ffmpeg_path.with_file_name(FFPROBE_BINARY)).filter(|path| path.is_file());
+  if let Some(ffprobe_path) = &ffprobe_path {
+    println!("Using ffprobe: {}", ffprobe_path.display());
+  }
+
+  Ok(ExtractedResources {
+    temp_dirs,
+    work_dir,
+    audio_dir,
+    ffmpeg_path,
+    system_ffmpeg,
+    ffprobe_path,
+    shared_ffmpeg,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use zip::{CompressionMethod, write::FileOptions};
+
+  use super::*;
+
+  /// Writes a ZIP holding `files` into `dir`, compressing them with `method`.
+  fn write_pack(dir: &Path, method: CompressionMethod, files: &[(&str, &[u8])]) -> PathBuf {
+    let path = dir.join("pack.zip");
+    let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+    for (name, contents) in files {
+      zip
+        .start_file(*name, FileOptions::default().compression_method(method))
+        .unwrap();
+      zip.write_all(contents).unwrap();
+    }
+    zip.finish().unwrap();
+    path
+  }
+
+  /// Extracts everything in the ZIP at `path` into `dest`.
+  fn extract(path: &Path, dest: &Path, limits: ExtractLimits) -> Result<Vec<String>> {
+    let (mut archive, _) = open_payload(&PackSource::UserZip(path.to_path_buf()))?;
+    extract_pack_files(
+      &mut archive,
+      dest,
+      None,
+      false,
+      PackContents::All,
+      limits,
+      None,
+      |_, _, _| {},
+    )
+  }
+
+  /// Whether nothing at all ended up in `dir`.
+  fn is_empty(dir: &Path) -> bool {
+    std::fs::read_dir(dir).unwrap().next().is_none()
+  }
+
+  #[test]
+  fn extracts_tracks_and_flattens_subfolders() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = tempfile::tempdir().unwrap();
+    let pack = write_pack(
+      dir.path(),
+      CompressionMethod::Deflated,
+      &[
+        ("1-alice.flac", b"alice"),
+        ("tracks/2-bob.flac", b"bob"),
+        ("tracks/notes.txt", b"not a track"),
+        ("info.txt", b"notes"),
+      ],
+    );
+
+    let names = extract(&pack, dest.path(), ExtractLimits::default()).unwrap();
+
+    assert_eq!(names, ["1-alice.flac", "2-bob.flac", "info.txt"]);
+    assert_eq!(
+      std::fs::read(dest.path().join("2-bob.flac")).unwrap(),
+      b"bob"
+    );
+    assert!(!dest.path().join("notes.txt").exists());
+  }
+
+  #[test]
+  fn refuses_entries_outside_the_folder() {
+    for name in [
+      "../evil.flac",
+      "tracks/../../evil.flac",
+      "..\\evil.flac",
+      "/tmp/evil.flac",
+      "\\evil.flac",
+      "C:\\evil.flac",
+    ] {
+      let dir = tempfile::tempdir().unwrap();
+      let dest = dir.path().join("dest");
+      std::fs::create_dir(&dest).unwrap();
+      let pack = write_pack(
+        dir.path(),
+        CompressionMethod::Stored,
+        &[("1-alice.flac", b"alice"), (name, b"evil")],
+      );
+
+      let error = extract(&pack, &dest, ExtractLimits::default()).unwrap_err();
+
+      let message = format!("{error:#}");
+      assert!(message.starts_with(UNSAFE_PACK), "{name}: {message}");
+      assert!(message.contains(name), "{name}: {message}");
+      assert!(is_empty(&dest), "{name}");
+      assert!(!dir.path().join("evil.flac").exists(), "{name}");
+    }
+  }
+
+  #[test]
+  fn refuses_packs_over_the_total_size_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = tempfile::tempdir().unwrap();
+    let pack = write_pack(
+      dir.path(),
+      CompressionMethod::Stored,
+      &[("1-alice.flac", &[1; 600]), ("2-bob.flac", &[2; 600])],
+    );
+    let limits = ExtractLimits {
+      max_total_size: 1000,
+      ..Default::default()
+    };
+
+    let message = format!("{:#}", extract(&pack, dest.path(), limits).unwrap_err());
+
+    assert!(message.starts_with(UNSAFE_PACK), "{message}");
+    assert!(is_empty(dest.path()));
+    // Right at the limit is fine
+    let limits = ExtractLimits {
+      max_total_size: 1200,
+      ..Default::default()
+    };
+    assert!(extract(&pack, dest.path(), limits).is_ok());
+  }
+
+  #[test]
+  fn refuses_entries_that_shrank_too_much() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = tempfile::tempdir().unwrap();
+    // Zeros deflate about a thousandfold, like a ZIP bomb
+    let zeros = vec![0; 4 * 1024 * 1024];
+    let pack = write_pack(
+      dir.path(),
+      CompressionMethod::Deflated,
+      &[("1-alice.flac", b"alice"), ("2-bomb.flac", &zeros)],
+    );
+
+    let error = extract(&pack, dest.path(), ExtractLimits::default()).unwrap_err();
+
+    let message = format!("{error:#}");
+    assert!(message.starts_with(UNSAFE_PACK), "{message}");
+    assert!(message.contains("2-bomb.flac"), "{message}");
+    assert!(is_empty(dest.path()));
+    // Without a ratio limit it's just a big file
+    let limits = ExtractLimits {
+      max_compression_ratio: 0,
+      ..Default::default()
+    };
+    assert!(extract(&pack, dest.path(), limits).is_ok());
+  }
+
+  #[test]
+  fn small_files_may_compress_well() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = tempfile::tempdir().unwrap();
+    let zeros = vec![0; 64 * 1024];
+    let pack = write_pack(
+      dir.path(),
+      CompressionMethod::Deflated,
+      &[("notes.txt", &zeros)],
+    );
+
+    assert!(extract(&pack, dest.path(), ExtractLimits::default()).is_ok());
+  }
+
+  /// A ZIP holding a couple of tracks, with `comment` after its End Of Central Directory record.
+  fn zip_bytes(comment: &[u8]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, contents) in [("1-alice.flac", b"alice"), ("2-carol.flac", b"carol")] {
+      zip.start_file(name, FileOptions::default()).unwrap();
+      zip.write_all(contents).unwrap();
+    }
+    zip.set_raw_comment(comment.to_vec());
+    zip.finish().unwrap().into_inner()
+  }
+
+  /// Stands in for an executable: some code with a local file header signature in the middle of
+  /// it, like one in a bundled resource would be.
+  fn stub_bytes() -> Vec<u8> {
+    let mut stub = b"MZ fake program".to_vec();
+    stub.extend([0x90; 100]);
+    stub.extend(ZIP_MAGIC);
+    stub.extend(b"not really a zip");
+    stub.extend([0xcc; 100]);
+    stub
+  }
+
+  fn zip_start(file: &[u8]) -> Option<u64> {
+    find_zip_start(&mut std::io::Cursor::new(file), file.len() as u64).unwrap()
+  }
+
+  #[test]
+  fn finds_a_zip_appended_after_a_stray_signature() {
+    let stub = stub_bytes();
+    let mut exe = stub.clone();
+    exe.extend(zip_bytes(b""));
+
+    let start = zip_start(&exe).unwrap();
+
+    assert_eq!(start, stub.len() as u64);
+    let payload =
+      PayloadReader::new(std::io::Cursor::new(&exe), start, exe.len() as u64 - start).unwrap();
+    let mut archive = zip::ZipArchive::new(payload).unwrap();
+    let mut track = String::new();
+    archive
+      .by_name("2-carol.flac")
+      .unwrap()
+      .read_to_string(&mut track)
+      .unwrap();
+    assert_eq!(track, "carol");
+  }
+
+  #[test]
+  fn finds_a_zip_with_a_trailing_comment() {
+    let stub = stub_bytes();
+    for comment in [
+      b"made by otterpack".to_vec(),
+      // A comment can look like the end of another archive
+      [EOCD_MAGIC, &[0; 18]].concat(),
+      vec![b'x'; u16::MAX as usize],
+    ] {
+      let mut exe = stub.clone();
+      exe.extend(zip_bytes(&comment));
+      assert_eq!(zip_start(&exe), Some(stub.len() as u64), "{comment:?}");
+    }
+  }
+
+  #[test]
+  fn finds_a_zip_through_its_zip64_record() {
+    let zip = zip_bytes(b"");
+    let eocd = zip.len() - EOCD_SIZE;
+    let read_u16 = |at: usize| u16::from_le_bytes(zip[at..at + 2].try_into().unwrap()) as u64;
+    let read_u32 = |at: usize| u32::from_le_bytes(zip[at..at + 4].try_into().unwrap()) as u64;
+    let entries = read_u16(eocd + 10);
+    let directory_size = read_u32(eocd + 12);
+    let directory_offset = read_u32(eocd + 16);
+
+    // The same archive as a big one would be written: the real sizes in a ZIP64 record, and
+    // the regular record maxed out
+    let mut zip64 = zip[..eocd].to_vec();
+    zip64.extend(ZIP64_EOCD_MAGIC);
+    zip64.extend((ZIP64_EOCD_SIZE - 12).to_le_bytes());
+    zip64.extend([45, 0, 45, 0]);
+    zip64.extend([0; 8]);
+    zip64.extend(entries.to_le_bytes());
+    zip64.extend(entries.to_le_bytes());
+    zip64.extend(directory_size.to_le_bytes());
+    zip64.extend(directory_offset.to_le_bytes());
+    zip64.extend(ZIP64_LOCATOR_MAGIC);
+    zip64.extend([0; 4]);
+    zip64.extend((directory_offset + directory_size).to_le_bytes());
+    zip64.extend(1u32.to_le_bytes());
+    zip64.extend(EOCD_MAGIC);
+    zip64.extend([0xff; 8]);
+    zip64.extend([0xff; 8]);
+    zip64.extend([0; 2]);
+
+    let stub = stub_bytes();
+    let mut exe = stub.clone();
+    exe.extend(&zip64);
+    assert_eq!(zip_start(&exe), Some(stub.len() as u64));
+
+    // A locator that disagrees with the record isn't trusted
+    let locator_offset = exe.len() - EOCD_SIZE - ZIP64_LOCATOR_SIZE as usize + 8;
+    exe[locator_offset] ^= 1;
+    assert_eq!(zip_start(&exe), None);
+  }
+
+  #[test]
+  fn finds_no_zip_in_a_plain_executable() {
+    assert_eq!(zip_start(&stub_bytes()), None);
+    assert_eq!(zip_start(b""), None);
+    // The end of an archive whose start isn't there
+    let zip = zip_bytes(b"");
+    assert_eq!(zip_start(&zip[100..]), None);
+  }
+
+  #[test]
+  #[cfg(unix)]
+  fn permissions_are_restored_and_ffmpeg_made_executable() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let cases = [
+      ("1-alice.flac", None, 0o644),
+      ("notes.txt", Some(0o100_600), 0o600),
+      ("run.sh", Some(0o100_755), 0o755),
+      // Only the permission bits are kept, not setuid or the file type
+      ("setuid.sh", Some(0o104_755), 0o755),
+      // Packs made on Windows don't say, and some tools forget the executable bit
+      ("ffmpeg", None, 0o755),
+      ("ffmpeg", Some(0o100_644), 0o755),
+      ("ffmpeg-aarch64", Some(0o100_600), 0o755),
+      ("ffprobe", None, 0o755),
+    ];
+    for (name, mode, expected) in cases {
+      let file = tempfile::tempfile_in(dir.path()).unwrap();
+      restore_permissions(&file, name, mode).unwrap();
+      let actual = file.metadata().unwrap().permissions().mode() & 0o7777;
+      assert_eq!(actual, expected, "{name} packed as {mode:?}");
+    }
+  }
+
+  fn trailer_of(file: &[u8]) -> Result<Option<(u64, u64)>> {
+    read_trailer(&mut std::io::Cursor::new(file), file.len() as u64)
+  }
+
+  /// `trailer` with the CRC at its end worked out again, as if it had been written that way.
+  fn with_fresh_crc(mut trailer: [u8; TRAILER_SIZE]) -> [u8; TRAILER_SIZE] {
+    let crc = crc32fast::hash(&trailer[..TRAILER_SIZE - 4]);
+    trailer[TRAILER_SIZE - 4..].copy_from_slice(&crc.to_le_bytes());
+    trailer
+  }
+
+  #[test]
+  fn trailers_read_back_what_was_written() {
+    let stub = stub_bytes();
+    let zip = zip_bytes(b"");
+    let mut file = [stub.clone(), zip.clone()].concat();
+    file.extend(encode_trailer(stub.len() as u64, zip.len() as u64));
+    assert_eq!(
+      trailer_of(&file).unwrap(),
+      Some((stub.len() as u64, zip.len() as u64))
+    );
+
+    // Right up against the trailer, and empty
+    let file = [vec![0; 10], encode_trailer(0, 10).to_vec()].concat();
+    assert_eq!(trailer_of(&file).unwrap(), Some((0, 10)));
+    assert_eq!(trailer_of(&encode_trailer(0, 0)).unwrap(), Some((0, 0)));
+  }
+
+  #[test]
+  fn damaged_or_missing_trailers_are_ignored() {
+    let file = [vec![0; 100], encode_trailer(10, 50).to_vec()].concat();
+    assert!(trailer_of(&file).unwrap().is_some());
+
+    assert_eq!(trailer_of(&file[..file.len() - 1]).unwrap(), None);
+    assert_eq!(trailer_of(&file[..TRAILER_SIZE - 1]).unwrap(), None);
+    assert_eq!(trailer_of(&stub_bytes()).unwrap(), None);
+    // Any byte changed, whether the CRC or what it covers
+    for i in 0..TRAILER_SIZE {
+      let mut damaged = file.clone();
+      damaged[100 + i] ^= 0x01;
+      assert_eq!(trailer_of(&damaged).unwrap(), None, "byte {i} changed");
+    }
+    // A different magic, even with a CRC to match
+    let mut trailer = encode_trailer(10, 50);
+    trailer[0] = b'X';
+    let file = [vec![0; 100], with_fresh_crc(trailer).to_vec()].concat();
+    assert_eq!(trailer_of(&file).unwrap(), None);
+  }
+
+  #[test]
+  fn trailers_pointing_outside_the_file_are_ignored() {
+    for (zip_start, zip_size) in [(0, 101), (90, 11), (101, 0), (u64::MAX, 2), (1, u64::MAX)] {
+      let file = [vec![0; 100], encode_trailer(zip_start, zip_size).to_vec()].concat();
+      assert_eq!(
+        trailer_of(&file).unwrap(),
+        None,
+        "{zip_size} bytes at {zip_start}"
+      );
+    }
+  }
+
+  #[test]
+  fn trailers_from_newer_versions_are_refused() {
+    let version = TRAILER_MAGIC.len();
+    for major in [PACK_FORMAT_VERSION.major as u8 + 1, 9, 42] {
+      let mut trailer = encode_trailer(0, 10);
+      trailer[version] = b'0' + major;
+      let file = [vec![0; 10], with_fresh_crc(trailer).to_vec()].concat();
+      let error = trailer_of(&file).unwrap_err();
+      assert!(
+        error
+          .downcast_ref::<crate::UnsupportedPackVersion>()
+          .is_some(),
+        "{error:#}"
+      );
+    }
+  }
+
+  #[test]
+  fn appended_payloads_are_found_by_their_trailer() {
+    let dir = tempfile::tempdir().unwrap();
+    let exe = dir.path().join("craig-abc123.exe");
+    let zip = dir.path().join("payload.bin");
+    let stub = stub_bytes();
+    // Trailing data after the archive would throw off searching for it, but not the trailer
+    let payload = [zip_bytes(b""), b"trailing junk".to_vec()].concat();
+    std::fs::write(&exe, &stub).unwrap();
+    std::fs::write(&zip, &payload).unwrap();
+
+    append_payload(&exe, &zip).unwrap();
+    let PackSource::EmbeddedZip {
+      zip_start,
+      zip_size,
+      ..
+    } = find_pack_source_for(exe.clone()).unwrap()
+    else {
+      panic!("the payload wasn't found as embedded");
+    };
+    assert_eq!(zip_start, stub.len() as u64);
+    assert_eq!(zip_size, payload.len() as u64);
+
+    let file = std::fs::read(&exe).unwrap();
+    assert_eq!(file.len(), stub.len() + payload.len() + TRAILER_SIZE);
+    assert_eq!(&file[..stub.len()], stub);
+    assert_eq!(&file[stub.len()..stub.len() + payload.len()], payload);
+  }
+
+  #[test]
+  fn appending_a_missing_payload_leaves_the_executable_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let exe = dir.path().join("craig-abc123.exe");
+    std::fs::write(&exe, stub_bytes()).unwrap();
+    assert!(append_payload(&exe, &dir.path().join("missing.zip")).is_err());
+    assert_eq!(std::fs::read(&exe).unwrap(), stub_bytes());
+  }
+
+  fn sha256(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+  }
+
+  /// A manifest listing `files` by their name, size and checksum.
+  fn manifest(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let files: Vec<serde_json::Value> = files
+      .iter()
+      .map(|(name, contents)| {
+        serde_json::json!({"name": name, "size": contents.len(), "sha256": sha256(contents)})
+      })
+      .collect();
+    serde_json::to_vec(&serde_json::json!({"format_version": "1.0", "files": files})).unwrap()
+  }
+
+  /// Extracts a pack of `files` with `manifest` next to them, making sure a failure leaves nothing
+  /// behind.
+  fn extract_with_manifest(files: &[(&str, &[u8])], manifest: &[u8]) -> Result<Vec<String>> {
+    let dir = tempfile::tempdir().unwrap();
+    let mut files = files.to_vec();
+    files.push((MANIFEST_NAME, manifest));
+    let pack = write_pack(dir.path(), CompressionMethod::Deflated, &files);
+    let dest = dir.path().join("out");
+    std::fs::create_dir(&dest).unwrap();
+    let result = extract(&pack, &dest, ExtractLimits::default());
+    if result.is_err() {
+      assert!(is_empty(&dest), "a damaged pack left files behind");
+    }
+    result
+  }
+
+  const ALICE: (&str, &[u8]) = ("1-alice.flac", b"alice's track");
+  const BOB: (&str, &[u8]) = ("2-bob.flac", b"bob's track");
+
+  #[test]
+  fn packs_matching_their_manifest_are_extracted() {
+    let extracted = extract_with_manifest(&[ALICE, BOB], &manifest(&[ALICE, BOB])).unwrap();
+    // The manifest itself isn't part of the recording
+    assert_eq!(extracted, ["1-alice.flac", "2-bob.flac"]);
+
+    // Checksums may be in either case
+    let upper = String::from_utf8(manifest(&[ALICE]))
+      .unwrap()
+      .replace(&sha256(ALICE.1), &sha256(ALICE.1).to_uppercase());
+    extract_with_manifest(&[ALICE], upper.as_bytes()).unwrap();
+  }
+
+  #[test]
+  fn files_not_matching_the_manifest_are_named() {
+    let corrupted = ("1-alice.flac", b"alice's trask" as &[u8]);
+    let error = extract_with_manifest(&[corrupted, BOB], &manifest(&[ALICE, BOB])).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "The pack is damaged, 1-alice.flac doesn't match its checksum. Try downloading it again."
+    );
+
+    let truncated = ("1-alice.flac", b"alice's" as &[u8]);
+    let error = extract_with_manifest(&[truncated], &manifest(&[ALICE])).unwrap_err();
+    assert!(error.to_string().contains("1-alice.flac"), "{error:#}");
+  }
+
+  #[test]
+  fn files_missing_from_the_manifest_are_named() {
+    let error = extract_with_manifest(&[ALICE], &manifest(&[ALICE, BOB])).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "The pack is incomplete, 2-bob.flac is missing from it. Try downloading it again."
+    );
+  }
+
+  #[test]
+  fn unreadable_or_newer_manifests_are_refused() {
+    let error = extract_with_manifest(&[ALICE], b"{\"files\": [").unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "The pack is damaged, its manifest can't be read"
+    );
+
+    let newer = serde_json::json!({"format_version": "2.0", "files": "in some new layout"});
+    let error = extract_with_manifest(&[ALICE], newer.to_string().as_bytes()).unwrap_err();
+    assert!(
+      error
+        .downcast_ref::<crate::UnsupportedPackVersion>()
+        .is_some(),
+      "{error:#}"
+    );
+  }
+
+  #[test]
+  fn damaged_entries_fail_on_their_crc_without_a_manifest() {
+    let dir = tempfile::tempdir().unwrap();
+    let pack = write_pack(dir.path(), CompressionMethod::Stored, &[ALICE, BOB]);
+    let mut bytes = std::fs::read(&pack).unwrap();
+    let at = memchr::memmem::find(&bytes, ALICE.1).unwrap();
+    bytes[at] ^= 0x01;
+    std::fs::write(&pack, bytes).unwrap();
+
+    let dest = dir.path().join("out");
+    std::fs::create_dir(&dest).unwrap();
+    let error = extract(&pack, &dest, ExtractLimits::default()).unwrap_err();
+    assert!(
+      error
+        .to_string()
+        .starts_with("Couldn't extract 1-alice.flac"),
+      "{error:#}"
+    );
+    assert!(is_empty(&dest));
+  }
+
+  /// A ZIP with `info.txt` as it is and `1-alice.flac` and `2-bob.flac` encrypted with AES-256
+  /// under the password `otter`. It was written out by hand in WinZip's AE-2 format, since the
+  /// zip crate can only read encrypted files.
+  fn encrypted_pack() -> PackSource {
+    PackSource::UserZip(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/encrypted.zip"))
+  }
+
+  fn is_process_error(e: &anyhow::Error, expected: ProcessError) -> bool {
+    e.downcast_ref::<ProcessError>()
+      .is_some_and(|e| std::mem::discriminant(e) == std::mem::discriminant(&expected))
+  }
+
+  #[test]
+  fn encrypted_entries_need_the_right_password() {
+    let (mut archive, _) = open_payload(&encrypted_pack()).unwrap();
+    // 1-alice.flac
+    let index = 1;
+
+    let Err(e) = open_entry(&mut archive, index, None) else {
+      panic!("opened without a password");
+    };
+    assert!(
+      is_process_error(&e, ProcessError::PasswordRequired),
+      "{e:#}"
+    );
+    let Err(e) = open_entry(&mut archive, index, Some("beaver")) else {
+      panic!("opened with the wrong password");
+    };
+    assert!(is_process_error(&e, ProcessError::WrongPassword), "{e:#}");
+
+    let mut contents = Vec::new();
+    open_entry(&mut archive, index, Some("otter"))
+      .unwrap()
+      .read_to_end(&mut contents)
+      .unwrap();
+    assert_eq!(contents, b"fLaC alice's track\n".repeat(4));
+
+    // info.txt isn't encrypted, so it doesn't need one
+    assert!(open_entry(&mut archive, 0, None).is_ok());
+  }
+
+  #[test]
+  fn pack_password_is_checked_against_every_encrypted_file() {
+    assert_eq!(check_pack_password(&encrypted_pack(), "otter").unwrap(), 2);
+
+    let e = check_pack_password(&encrypted_pack(), "beaver").unwrap_err();
+    assert!(is_process_error(&e, ProcessError::WrongPassword), "{e:#}");
+  }
+
+  #[test]
+  fn encrypted_packs_are_never_unpacked_into_the_cache() {
+    let cache = tempfile::tempdir().unwrap();
+    let temp = tempfile::tempdir().unwrap();
+    let dirs = Dirs {
+      cache: Some(cache.path().to_path_buf()),
+      temp: temp.path().to_path_buf(),
+    };
+    let unpack = |password| {
+      extract_payload(
+        &encrypted_pack(),
+        &dirs,
+        PackContents::All,
+        ExtractLimits::default(),
+        password,
+        |_, _, _| {},
+      )
+    };
+
+    let e = unpack(None).unwrap_err();
+    assert!(
+      is_process_error(&e, ProcessError::PasswordRequired),
+      "{e:#}"
+    );
+    let e = unpack(Some("beaver")).unwrap_err();
+    assert!(is_process_error(&e, ProcessError::WrongPassword), "{e:#}");
+
+    std::fs::remove_dir_all(cache.path()).unwrap();
+    let (temp_dir, dir) = unpack(Some("otter")).unwrap();
+    let temp_dir = temp_dir.expect("unpacked into a temp folder");
+    assert_eq!(dir, temp_dir.path());
+    assert_eq!(
+      std::fs::read(dir.join("2-bob.flac")).unwrap(),
+      b"fLaC bob's track\n".repeat(4)
+    );
+    assert!(!cache.path().exists());
+
+    drop(temp_dir);
+    assert!(!dir.exists());
+  }
+}
@@ -0,0 +1,202 @@
+use std::{
+  collections::BTreeMap,
+  fs,
+  io::{Cursor, Read, Seek, SeekFrom},
+  path::{Path, PathBuf},
+};
+
+use otterpack_core::{
+  ExtractLimits, PackOptions, PackSource, cache::Dirs, extract_zip_contents, find_pack_source_for,
+  pack,
+};
+use zip::CompressionMethod;
+
+/// What goes in the recording folder that's packed, by name.
+const FILES: &[(&str, &[u8])] = &[
+  ("1-alice.flac", b"fLaC alice's track\n"),
+  ("2-bob.flac", b"fLaC bob's track\n"),
+  (
+    "ffmpeg.exe",
+    b"MZ not really ffmpeg, but it squeezes down fine fine fine fine fine\n",
+  ),
+  ("info.txt", b"Recorded by Craig\n"),
+];
+
+/// A stub to pack onto and a recording folder to pack, in a folder of their own.
+struct Packing {
+  root: tempfile::TempDir,
+  stub: PathBuf,
+  input: PathBuf,
+}
+
+impl Packing {
+  fn new(files: &[(&str, &[u8])]) -> Self {
+    let root = tempfile::tempdir_in(env!("CARGO_TARGET_TMPDIR")).unwrap();
+    // Anything does as a stub, as long as it doesn't look like a ZIP itself
+    let stub = root.path().join("stub");
+    fs::write(
+      &stub,
+      b"MZ a stand-in for the otterpack executable\n".repeat(64),
+    )
+    .unwrap();
+    let input = root.path().join("recording");
+    fs::create_dir(&input).unwrap();
+    for (name, contents) in files {
+      fs::write(input.join(name), contents.repeat(16)).unwrap();
+    }
+    fs::create_dir(root.path().join("temp")).unwrap();
+    Self { root, stub, input }
+  }
+
+  fn options(&self) -> PackOptions {
+    PackOptions {
+      stub: self.stub.clone(),
+      input: self.input.clone(),
+      output: self.root.path().join("packed"),
+      allow_missing_ffmpeg: false,
+    }
+  }
+
+  fn dirs(&self) -> Dirs {
+    Dirs {
+      cache: None,
+      temp: self.root.path().join("temp"),
+    }
+  }
+}
+
+/// The files at the top of `dir` and what's in them, leaving out what otterpack keeps track of
+/// the folder in.
+fn read_files(dir: &Path) -> BTreeMap<String, Vec<u8>> {
+  fs::read_dir(dir)
+    .unwrap()
+    .flatten()
+    .map(|entry| entry.file_name().to_string_lossy().to_string())
+    .filter(|name| !name.starts_with(".otterpack."))
+    .map(|name| {
+      let contents = fs::read(dir.join(&name)).unwrap();
+      (name, contents)
+    })
+    .collect()
+}
+
+/// The payload appended to the executable at `exe`, read as a ZIP on its own.
+fn payload(exe: &Path) -> zip::ZipArchive<Cursor<Vec<u8>>> {
+  let PackSource::EmbeddedZip {
+    zip_start,
+    zip_size,
+    ..
+  } = find_pack_source_for(exe.to_path_buf()).unwrap()
+  else {
+    panic!("{} has no embedded payload", exe.display());
+  };
+  let mut file = fs::File::open(exe).unwrap();
+  file.seek(SeekFrom::Start(zip_start)).unwrap();
+  let mut bytes = Vec::new();
+  file.take(zip_size).read_to_end(&mut bytes).unwrap();
+  zip::ZipArchive::new(Cursor::new(bytes)).unwrap()
+}
+
+#[test]
+fn packed_files_come_back_out_the_same() {
+  let packing = Packing::new(FILES);
+  let options = packing.options();
+  let summary = pack(&options).unwrap();
+  assert_eq!(summary.files, FILES.len());
+  assert_eq!(summary.tracks, 2);
+
+  // The stub is left at the front as it was
+  let stub = fs::read(&packing.stub).unwrap();
+  assert!(fs::read(&options.output).unwrap().starts_with(&stub));
+
+  let source = find_pack_source_for(options.output.clone()).unwrap();
+  assert!(
+    matches!(&source, PackSource::EmbeddedZip { exe_path, zip_start, .. }
+      if *exe_path == options.output && *zip_start >= stub.len() as u64),
+    "{source:?}"
+  );
+  let extracted = extract_zip_contents(
+    &source,
+    &packing.dirs(),
+    ExtractLimits::default(),
+    None,
+    |_, _, _| {},
+  )
+  .unwrap();
+  assert_eq!(read_files(extracted.path()), read_files(&packing.input));
+}
+
+#[test]
+fn tracks_are_stored_and_the_rest_deflated() {
+  let packing = Packing::new(FILES);
+  let options = packing.options();
+  pack(&options).unwrap();
+
+  let mut archive = payload(&options.output);
+  let mut methods: Vec<_> = (0..archive.len())
+    .map(|i| {
+      let file = archive.by_index_raw(i).unwrap();
+      (file.name().to_string(), file.compression())
+    })
+    .collect();
+  methods.sort_by(|a, b| a.0.cmp(&b.0));
+  assert_eq!(
+    methods,
+    [
+      ("1-alice.flac".to_string(), CompressionMethod::Stored),
+      ("2-bob.flac".to_string(), CompressionMethod::Stored),
+      ("ffmpeg.exe".to_string(), CompressionMethod::Deflated),
+      ("info.txt".to_string(), CompressionMethod::Deflated),
+    ]
+  );
+}
+
+#[test]
+fn packing_without_ffmpeg_has_to_be_asked_for() {
+  let packing = Packing::new(&[FILES[0], FILES[1]]);
+  let mut options = packing.options();
+  let e = pack(&options).unwrap_err();
+  assert!(e.to_string().contains("--no-ffmpeg"), "{e}");
+  assert!(!options.output.exists());
+
+  options.allow_missing_ffmpeg = true;
+  let summary = pack(&options).unwrap();
+  assert_eq!(summary.files, 2);
+  assert_eq!(summary.tracks, 2);
+}
+
+#[test]
+fn packing_onto_the_stub_itself_is_refused() {
+  let packing = Packing::new(FILES);
+  let stub = fs::read(&packing.stub).unwrap();
+  let options = PackOptions {
+    output: packing.stub.clone(),
+    ..packing.options()
+  };
+  let e = pack(&options).unwrap_err();
+  assert_eq!(e.to_string(), "The output can't be the stub itself");
+  assert_eq!(fs::read(&packing.stub).unwrap(), stub);
+}
+
+#[test]
+fn encrypted_zips_are_packed_as_they_are() {
+  let packing = Packing::new(&[]);
+  let encrypted = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/encrypted.zip");
+  let options = PackOptions {
+    input: encrypted.clone(),
+    allow_missing_ffmpeg: true,
+    ..packing.options()
+  };
+  let summary = pack(&options).unwrap();
+  assert_eq!(summary.tracks, 2);
+  assert_eq!(
+    summary.payload_size,
+    fs::metadata(&encrypted).unwrap().len()
+  );
+
+  let mut archive = payload(&options.output);
+  let mut alice = archive.by_index_decrypt(1, b"otter").unwrap().unwrap();
+  let mut contents = Vec::new();
+  alice.read_to_end(&mut contents).unwrap();
+  assert_eq!(contents, b"fLaC alice's track\n".repeat(4));
+}
@@ -0,0 +1,414 @@
+mod support;
+
+use std::fs;
+
+use otterpack_core::{
+  OverwritePolicy, ProcessError, ProcessOptions, ProcessProgress, TrackState, build_mix_filter,
+};
+use support::{Fixture, convert_args};
+
+const FLAC_ARGS: [&str; 4] = ["-c:a", "flac", "-f", "flac"];
+
+fn track_states(events: &[ProcessProgress], track: &str) -> Vec<TrackState> {
+  events
+    .iter()
+    .filter_map(|event| match event {
+      ProcessProgress::Track { filename, state } if filename == track => Some(state.clone()),
+      _ => None,
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn converts_each_track() {
+  let fixture = Fixture::new(&["10-carol.flac", "1-alice.flac", "2-bob.flac"]);
+
+  let (result, events) = fixture.process(&ProcessOptions::default()).await;
+  let report = result.unwrap();
+
+  assert_eq!(
+    report.converted,
+    ["1-alice.flac", "2-bob.flac", "10-carol.flac"]
+  );
+  assert!(report.skipped.is_empty() && report.failed.is_empty());
+  assert_eq!(
+    fixture.output_files(),
+    ["1-alice.flac", "10-carol.flac", "2-bob.flac"]
+  );
+  let output = fs::read_to_string(fixture.output.join("2-bob.flac")).unwrap();
+  assert!(output.ends_with("2-bob.flac\n"), "{output}");
+
+  assert_eq!(
+    fixture.conversions(),
+    [
+      convert_args("1-alice.flac", "1-alice.flac", &FLAC_ARGS),
+      convert_args("2-bob.flac", "2-bob.flac", &FLAC_ARGS),
+      convert_args("10-carol.flac", "10-carol.flac", &FLAC_ARGS),
+    ]
+  );
+  let states = track_states(&events, "2-bob.flac");
+  assert_eq!(states[..2], [TrackState::Queued, TrackState::Started]);
+  assert!(matches!(states[2], TrackState::Finished { output_size } if output_size > 0));
+}
+
+#[tokio::test]
+async fn passes_format_and_leveling_to_ffmpeg() {
+  let fixture = Fixture::new(&["1-alice.flac"]);
+  let options = ProcessOptions {
+    format: "wav".parse().unwrap(),
+    leveling: "dynaudnorm".parse().unwrap(),
+    threads: 2,
+    ..Default::default()
+  };
+
+  fixture.process(&options).await.0.unwrap();
+
+  assert_eq!(
+    fixture.conversions(),
+    [convert_args(
+      "1-alice.flac",
+      "1-alice.wav",
+      &[
+        "-af",
+        "dynaudnorm",
+        "-threads",
+        "2",
+        "-filter_threads",
+        "2",
+        "-c:a",
+        "pcm_s16le",
+        "-f",
+        "wav"
+      ]
+    )]
+  );
+  assert_eq!(fixture.output_files(), ["1-alice.wav"]);
+}
+
+#[tokio::test]
+async fn carries_on_after_one_track_fails() {
+  let fixture = Fixture::new(&["1-alice.flac", "2-bob.flac", "3-carol.flac"]);
+  fixture
+    .set("FAKE_FFMPEG_EXIT", 1)
+    .set("FAKE_FFMPEG_FAIL_ON", "2-bob");
+
+  let (result, events) = fixture.process(&ProcessOptions::default()).await;
+  let report = result.unwrap();
+
+  assert_eq!(report.converted, ["1-alice.flac", "3-carol.flac"]);
+  assert_eq!(report.failed.len(), 1);
+  assert_eq!(report.failed[0].filename, "2-bob.flac");
+  let stderr = report.failed[0].stderr.as_deref().unwrap_or_default();
+  assert!(stderr.contains("fake failure"), "{stderr}");
+  // The partial output is gone, rather than left looking like a finished one
+  assert_eq!(fixture.output_files(), ["1-alice.flac", "3-carol.flac"]);
+  assert_eq!(fixture.conversions().len(), 3);
+  assert!(matches!(
+    track_states(&events, "2-bob.flac").last(),
+    Some(TrackState::Failed { .. })
+  ));
+}
+
+#[tokio::test]
+async fn stops_at_the_first_failure_with_fail_fast() {
+  let fixture = Fixture::new(&["1-alice.flac", "2-bob.flac", "3-carol.flac"]);
+  fixture
+    .set("FAKE_FFMPEG_EXIT", 3)
+    .set("FAKE_FFMPEG_FAIL_ON", "2-bob");
+  let options = ProcessOptions {
+    fail_fast: true,
+    ..Default::default()
+  };
+
+  let (result, _) = fixture.process(&options).await;
+
+  let Err(ProcessError::FfmpegFailed(error)) = result else {
+    panic!("expected ffmpeg to fail, got {result:?}");
+  };
+  assert_eq!(error.status.and_then(|status| status.code()), Some(3));
+  assert_eq!(fixture.conversions().len(), 2);
+  assert_eq!(fixture.output_files(), ["1-alice.flac"]);
+}
+
+#[tokio::test]
+async fn mixes_tracks_into_one_file() {
+  let fixture = Fixture::new(&["1-alice.flac", "2-bob.flac", "3-carol.flac"]);
+  let options = ProcessOptions {
+    mix: true,
+    ..Default::default()
+  };
+
+  let (result, _) = fixture.process(&options).await;
+  let report = result.unwrap();
+
+  assert_eq!(report.converted, ["craig.flac"]);
+  assert_eq!(fixture.output_files(), ["craig.flac"]);
+
+  let filter = build_mix_filter(3, &options.mix_filter_options());
+  let mut expected: Vec<String> = [
+    "-hide_banner",
+    "-nostdin",
+    "-nostats",
+    "-progress",
+    "pipe:1",
+    "-y",
+  ]
+  .map(String::from)
+  .to_vec();
+  for track in ["1-alice.flac", "2-bob.flac", "3-carol.flac"] {
+    expected.push("-i".to_string());
+    expected.push(format!("<root>/tracks/{track}"));
+  }
+  expected.extend(
+    [
+      "-filter_complex",
+      &filter,
+      "-map",
+      "[aud]",
+      "-c:a",
+      "flac",
+      "-f",
+      "flac",
+      "<root>/output/craig.flac.part",
+    ]
+    .map(String::from),
+  );
+  assert_eq!(fixture.conversions(), [expected]);
+}
+
+#[tokio::test]
+async fn mixes_many_tracks_in_batches() {
+  let names: Vec<String> = (1..=40).map(|i| format!("{i}-speaker.flac")).collect();
+  let fixture = Fixture::new(&names.iter().map(String::as_str).collect::<Vec<_>>());
+  let options = ProcessOptions {
+    mix: true,
+    ..Default::default()
+  };
+
+  fixture.process(&options).await.0.unwrap();
+
+  let conversions = fixture.conversions();
+  let inputs = |args: &Vec<String>| args.iter().filter(|arg| *arg == "-i").count();
+  assert_eq!(
+    conversions.iter().map(inputs).collect::<Vec<_>>(),
+    [20, 20, 2]
+  );
+  // The final mix reads the batches, which are gone once it's done
+  let last = conversions.last().unwrap();
+  assert!(last.iter().any(|arg| arg.contains(".otterpack-mix-")));
+  assert_eq!(fixture.output_files(), ["craig.flac"]);
+}
+
+#[tokio::test]
+async fn cancelling_stops_ffmpeg_and_removes_the_partial_output() {
+  let fixture = Fixture::new(&["1-alice.flac", "2-bob.flac"]);
+  fixture.set("FAKE_FFMPEG_SLEEP_MS", 20_000);
+  let started = std::time::Instant::now();
+
+  let (result, events) = fixture
+    .process_until(
+      &ProcessOptions::default(),
+      // Once ffmpeg reports progress, it's surely partway through the first track
+      |event| matches!(event, ProcessProgress::Converting(info) if info.file_fraction.is_some()),
+      Default::default(),
+    )
+    .await;
+
+  assert!(matches!(result, Err(ProcessError::Cancelled)), "{result:?}");
+  assert!(started.elapsed().as_secs() < 10, "ffmpeg wasn't stopped");
+  assert_eq!(fixture.conversions().len(), 1);
+  assert!(
+    fixture.output_files().is_empty(),
+    "{:?}",
+    fixture.output_files()
+  );
+  assert_eq!(
+    track_states(&events, "2-bob.flac"),
+    [TrackState::Queued],
+    "the second track was never started"
+  );
+}
+
+/// Runs with `policy` over an output folder that already has `1-alice.flac` from elsewhere.
+///
+/// The file is newer than its track and ffmpeg can read it, so it would pass for an earlier run's.
+async fn run_with_existing_output(
+  policy: OverwritePolicy,
+) -> (Fixture, Result<otterpack_core::ProcessReport, ProcessError>) {
+  let fixture = Fixture::new(&["1-alice.flac", "2-bob.flac"]);
+  fs::create_dir(&fixture.output).unwrap();
+  fs::write(fixture.output.join("1-alice.flac"), "old").unwrap();
+  let options = ProcessOptions {
+    overwrite: policy,
+    ..Default::default()
+  };
+  let (result, _) = fixture.process(&options).await;
+  (fixture, result)
+}
+
+#[tokio::test]
+async fn overwrite_policy_replaces_existing_outputs() {
+  let (fixture, result) = run_with_existing_output(OverwritePolicy::Overwrite).await;
+
+  assert_eq!(result.unwrap().converted, ["1-alice.flac", "2-bob.flac"]);
+  assert_eq!(fixture.conversions().len(), 2);
+  let output = fs::read_to_string(fixture.output.join("1-alice.flac")).unwrap();
+  assert!(output.starts_with("fake ffmpeg output"), "{output}");
+}
+
+#[tokio::test]
+async fn skip_policy_keeps_existing_outputs() {
+  let (fixture, result) = run_with_existing_output(OverwritePolicy::Skip).await;
+
+  let report = result.unwrap();
+  assert_eq!(report.skipped, ["1-alice.flac"]);
+  assert_eq!(report.converted, ["2-bob.flac"]);
+  assert_eq!(
+    fixture.conversions(),
+    [convert_args("2-bob.flac", "2-bob.flac", &FLAC_ARGS)]
+  );
+  assert_eq!(
+    fs::read_to_string(fixture.output.join("1-alice.flac")).unwrap(),
+    "old"
+  );
+}
+
+#[tokio::test]
+async fn fail_policy_stops_at_an_existing_output() {
+  let (fixture, result) = run_with_existing_output(OverwritePolicy::Fail).await;
+
+  assert!(
+    matches!(&result, Err(ProcessError::OutputExists(name)) if name == "1-alice.flac"),
+    "{result:?}"
+  );
+  assert!(fixture.conversions().is_empty());
+  assert_eq!(
+    fs::read_to_string(fixture.output.join("1-alice.flac")).unwrap(),
+    "old"
+  );
+}
+
+#[tokio::test]
+async fn resumes_from_outputs_an_earlier_run_wrote() {
+  let fixture = Fixture::new(&["1-alice.flac", "2-bob.flac"]);
+  fixture.process(&ProcessOptions::default()).await.0.unwrap();
+  fs::remove_file(fixture.output.join("2-bob.flac")).unwrap();
+
+  let (result, events) = fixture.process(&ProcessOptions::default()).await;
+
+  let report = result.unwrap();
+  assert_eq!(report.skipped, ["1-alice.flac"]);
+  assert_eq!(report.converted, ["2-bob.flac"]);
+  assert_eq!(fixture.conversions().len(), 3);
+  assert_eq!(
+    track_states(&events, "1-alice.flac"),
+    [TrackState::Queued, TrackState::Skipped]
+  );
+}
+
+#[tokio::test]
+async fn converts_again_when_the_options_changed() {
+  let fixture = Fixture::new(&["1-alice.flac"]);
+  fixture.process(&ProcessOptions::default()).await.0.unwrap();
+  let options = ProcessOptions {
+    leveling: "dynaudnorm".parse().unwrap(),
+    ..Default::default()
+  };
+
+  let report = fixture.process(&options).await.0.unwrap();
+
+  assert_eq!(report.converted, ["1-alice.flac"]);
+  assert_eq!(fixture.conversions().len(), 2);
+}
+
+#[tokio::test]
+async fn reprocess_all_converts_outputs_an_earlier_run_wrote() {
+  let fixture = Fixture::new(&["1-alice.flac"]);
+  fixture.process(&ProcessOptions::default()).await.0.unwrap();
+  let options = ProcessOptions {
+    reprocess_all: true,
+    ..Default::default()
+  };
+
+  let report = fixture.process(&options).await.0.unwrap();
+
+  assert_eq!(report.converted, ["1-alice.flac"]);
+  assert_eq!(fixture.conversions().len(), 2);
+}
+
+/// Whether `name` is in the output folder under its own name or as a `.part` file.
+fn has_output(fixture: &Fixture, name: &str) -> bool {
+  let part = format!("{name}.part");
+  fixture
+    .output_files()
+    .iter()
+    .any(|file| *file == name || *file == part)
+}
+
+#[tokio::test]
+async fn failing_midway_leaves_no_partial_output() {
+  let fixture = Fixture::new(&["1-alice.flac", "2-bob.flac"]);
+  // ffmpeg has written part of the output by the time it fails
+  fixture
+    .set("FAKE_FFMPEG_SLEEP_MS", 200)
+    .set("FAKE_FFMPEG_EXIT", 1)
+    .set("FAKE_FFMPEG_FAIL_ON", "2-bob");
+
+  let report = fixture.process(&ProcessOptions::default()).await.0.unwrap();
+
+  assert_eq!(report.failed.len(), 1);
+  assert!(has_output(&fixture, "1-alice.flac"));
+  assert!(
+    !has_output(&fixture, "2-bob.flac"),
+    "{:?}",
+    fixture.output_files()
+  );
+}
+
+#[tokio::test]
+async fn failing_mix_leaves_no_partial_output() {
+  let fixture = Fixture::new(&["1-alice.flac", "2-bob.flac"]);
+  fixture.set("FAKE_FFMPEG_EXIT", 1);
+  let options = ProcessOptions {
+    mix: true,
+    ..Default::default()
+  };
+
+  let (result, _) = fixture.process(&options).await;
+
+  assert!(
+    matches!(result, Err(ProcessError::FfmpegFailed(_))),
+    "{result:?}"
+  );
+  assert!(
+    fixture.output_files().is_empty(),
+    "{:?}",
+    fixture.output_files()
+  );
+}
+
+#[tokio::test]
+async fn cancelling_a_mix_leaves_no_partial_output() {
+  let fixture = Fixture::new(&["1-alice.flac", "2-bob.flac"]);
+  fixture.set("FAKE_FFMPEG_SLEEP_MS", 20_000);
+  let options = ProcessOptions {
+    mix: true,
+    ..Default::default()
+  };
+
+  let (result, _) = fixture
+    .process_until(
+      &options,
+      |event| matches!(event, ProcessProgress::Converting(info) if info.file_fraction.is_some()),
+      Default::default(),
+    )
+    .await;
+
+  assert!(matches!(result, Err(ProcessError::Cancelled)), "{result:?}");
+  assert_eq!(fixture.conversions().len(), 1);
+  assert!(
+    fixture.output_files().is_empty(),
+    "{:?}",
+    fixture.output_files()
+  );
+}
@@ -0,0 +1,163 @@
+mod support;
+
+use std::{path::PathBuf, sync::Mutex};
+
+use otterpack_core::{
+  PackSource, ProcessError, ProcessOptions, ProcessProgress, RunEvent, RunSetup, TrackState, run,
+};
+use support::Fixture;
+use tokio_util::sync::CancellationToken;
+
+/// What a run reported, each boiled down to what it was and the track it was about.
+#[derive(Debug, Default)]
+struct Reported {
+  /// Runs of the same kind of event are only listed once.
+  events: Vec<String>,
+  /// Where the resources were unpacked, when they were.
+  audio_dir: Option<PathBuf>,
+}
+
+impl Reported {
+  fn push(&mut self, event: RunEvent) {
+    let event = match event {
+      RunEvent::Ffmpeg(info) => format!("ffmpeg {}", info.version),
+      RunEvent::Resources(resources) => {
+        self.audio_dir = Some(resources.audio_dir.clone());
+        "resources".to_string()
+      }
+      RunEvent::Process(progress) => match progress {
+        ProcessProgress::Extracting { .. } => "extracting".to_string(),
+        ProcessProgress::Probing { .. } => "probing".to_string(),
+        ProcessProgress::Converting(info) => format!("converting {}", info.filename),
+        ProcessProgress::Track { filename, state } => {
+          let state = match state {
+            TrackState::Queued => "queued",
+            TrackState::Started => "started",
+            TrackState::Finished { .. } => "finished",
+            TrackState::Skipped => "skipped",
+            TrackState::Failed { .. } => "failed",
+          };
+          format!("{filename} {state}")
+        }
+        ProcessProgress::Packaging => "packaging".to_string(),
+        ProcessProgress::Log { .. } => return,
+        other => format!("{other:?}"),
+      },
+    };
+    if self.events.last() != Some(&event) {
+      self.events.push(event);
+    }
+  }
+}
+
+async fn run_pack(
+  fixture: &Fixture,
+  setup: RunSetup,
+  cancel: CancellationToken,
+) -> (
+  Result<otterpack_core::ProcessReport, ProcessError>,
+  Reported,
+) {
+  let reported = Mutex::new(Reported::default());
+  let result = run(
+    PackSource::UserZip(fixture.pack()),
+    fixture.output.clone(),
+    ProcessOptions {
+      dirs: fixture.dirs(),
+      ..Default::default()
+    },
+    setup,
+    |event| reported.lock().unwrap().push(event),
+    cancel,
+  )
+  .await;
+  (result, reported.into_inner().unwrap())
+}
+
+#[tokio::test]
+async fn reports_each_step_of_a_run() {
+  let fixture = Fixture::new(&["1-alice.flac", "2-bob.flac"]);
+
+  let (result, reported) = run_pack(&fixture, RunSetup::default(), CancellationToken::new()).await;
+
+  assert_eq!(result.unwrap().converted, ["1-alice.flac", "2-bob.flac"]);
+  assert_eq!(
+    reported.events,
+    [
+      "extracting",
+      "ffmpeg 6.0-fake",
+      "resources",
+      "probing",
+      "1-alice.flac queued",
+      "2-bob.flac queued",
+      "converting 1-alice.flac",
+      "1-alice.flac started",
+      "converting 1-alice.flac",
+      "1-alice.flac finished",
+      "converting 2-bob.flac",
+      "2-bob.flac started",
+      "converting 2-bob.flac",
+      "2-bob.flac finished",
+      "packaging",
+    ]
+  );
+  assert_eq!(fixture.conversions().len(), 2);
+  assert_eq!(fixture.output_files(), ["1-alice.flac", "2-bob.flac"]);
+  // Nothing held on to the resources, so they're gone along with the run
+  assert!(!reported.audio_dir.unwrap().exists());
+}
+
+#[tokio::test]
+async fn reuses_resources_from_an_earlier_run() {
+  let fixture = Fixture::new(&["1-alice.flac"]);
+  let resources = Mutex::new(None);
+  run(
+    PackSource::UserZip(fixture.pack()),
+    fixture.output.clone(),
+    ProcessOptions {
+      dirs: fixture.dirs(),
+      ..Default::default()
+    },
+    RunSetup::default(),
+    |event| {
+      if let RunEvent::Resources(unpacked) = event {
+        *resources.lock().unwrap() = Some(unpacked);
+      }
+    },
+    CancellationToken::new(),
+  )
+  .await
+  .unwrap();
+  let resources = resources.into_inner().unwrap().unwrap();
+  let audio_dir = resources.audio_dir.clone();
+  let setup = RunSetup {
+    resources: Some(resources),
+    ..Default::default()
+  };
+  std::fs::remove_dir_all(&fixture.output).unwrap();
+
+  let (result, reported) = run_pack(&fixture, setup, CancellationToken::new()).await;
+
+  assert_eq!(result.unwrap().converted, ["1-alice.flac"]);
+  assert!(!reported.events.contains(&"extracting".to_string()));
+  assert!(!reported.events.contains(&"resources".to_string()));
+  assert_eq!(fixture.conversions().len(), 2);
+  // The run had its own hold on them, which is let go of again
+  assert!(!audio_dir.exists());
+}
+
+#[tokio::test]
+async fn stops_when_cancelled() {
+  let fixture = Fixture::new(&["1-alice.flac", "2-bob.flac"]);
+  let cancel = CancellationToken::new();
+  cancel.cancel();
+
+  let (result, reported) = run_pack(&fixture, RunSetup::default(), cancel).await;
+
+  assert!(matches!(result, Err(ProcessError::Cancelled)), "{result:?}");
+  assert!(fixture.conversions().is_empty());
+  assert!(fixture.output_files().is_empty());
+  if let Some(audio_dir) = reported.audio_dir {
+    assert!(!audio_dir.exists());
+  }
+}
@@ -0,0 +1,230 @@
+//! Runs against the fake ffmpeg from `src/bin/fake-ffmpeg.rs`, each in a folder of its own.
+
+#![allow(dead_code)]
+
+use std::{
+  fs,
+  io::Write,
+  path::{Path, PathBuf},
+  time::Duration,
+};
+
+use otterpack_core::{
+  ProcessError, ProcessOptions, ProcessProgress, ProcessReport, RunEvent, cache::Dirs,
+  process_files,
+};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// A test's own copy of the fake ffmpeg, with the tracks to convert and a folder to convert them
+/// into.
+pub struct Fixture {
+  root: tempfile::TempDir,
+  /// The fake ffmpeg, named like the real one.
+  pub ffmpeg: PathBuf,
+  /// The folder of tracks, standing in for an unpacked recording.
+  pub tracks: PathBuf,
+  pub output: PathBuf,
+}
+
+impl Fixture {
+  /// A fixture with a track for each of `names`.
+  ///
+  /// It lives in Cargo's temp folder for tests rather than the system one, which runs refuse to
+  /// write into.
+  pub fn new(names: &[&str]) -> Self {
+    let root = tempfile::tempdir_in(env!("CARGO_TARGET_TMPDIR")).unwrap();
+    let ffmpeg = root
+      .path()
+      .join(format!("ffmpeg{}", std::env::consts::EXE_SUFFIX));
+    // A link can't be caught half-written by a child spawned meanwhile, unlike a copy
+    let fake = Path::new(env!("CARGO_BIN_EXE_fake-ffmpeg"));
+    if fs::hard_link(fake, &ffmpeg).is_err() {
+      fs::copy(fake, &ffmpeg).unwrap();
+    }
+
+    fs::create_dir(root.path().join("temp")).unwrap();
+    let tracks = root.path().join("tracks");
+    fs::create_dir(&tracks).unwrap();
+    for name in names {
+      fs::write(tracks.join(name), b"fLaC").unwrap();
+    }
+    let output = root.path().join("output");
+    Self {
+      root,
+      ffmpeg,
+      tracks,
+      output,
+    }
+  }
+
+  /// Sets one of the fake ffmpeg's `FAKE_FFMPEG_*` settings for every run from here on.
+  pub fn set(&self, key: &str, value: impl std::fmt::Display) -> &Self {
+    let config = self.root.path().join("fake-ffmpeg.env");
+    let mut text = fs::read_to_string(&config).unwrap_or_default();
+    text.push_str(&format!("{key}={value}\n"));
+    fs::write(config, text).unwrap();
+    self
+  }
+
+  /// Folders of the fixture's own to unpack packs into, rather than the user's cache.
+  pub fn dirs(&self) -> Dirs {
+    Dirs {
+      cache: None,
+      temp: self.root.path().join("temp"),
+    }
+  }
+
+  /// Packs the tracks into a ZIP along with the fake ffmpeg, like a recording that comes with
+  /// ffmpeg, returning where it is.
+  ///
+  /// ffmpeg is unpacked somewhere else, so its settings so far go in the pack too, along with the
+  /// log being here like it is for the fixture's own copy.
+  pub fn pack(&self) -> PathBuf {
+    let path = self.root.path().join("craig.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+    let options = zip::write::FileOptions::default();
+
+    let config = self.root.path().join("fake-ffmpeg.env");
+    let mut settings = fs::read_to_string(&config).unwrap_or_default();
+    let log = self.root.path().join("fake-ffmpeg.log");
+    settings.push_str(&format!("FAKE_FFMPEG_LOG={}\n", log.display()));
+    zip.start_file("fake-ffmpeg.env", options).unwrap();
+    zip.write_all(settings.as_bytes()).unwrap();
+
+    let ffmpeg_name = self.ffmpeg.file_name().unwrap().to_string_lossy();
+    zip.start_file(ffmpeg_name, options).unwrap();
+    zip.write_all(&fs::read(&self.ffmpeg).unwrap()).unwrap();
+    let mut tracks: Vec<_> = fs::read_dir(&self.tracks).unwrap().flatten().collect();
+    tracks.sort_by_key(|entry| entry.file_name());
+    for track in tracks {
+      zip
+        .start_file(track.file_name().to_string_lossy(), options)
+        .unwrap();
+      zip.write_all(&fs::read(track.path()).unwrap()).unwrap();
+    }
+    zip.finish().unwrap();
+    path
+  }
+
+  /// Every command the fake ffmpeg was run with so far, with paths in the fixture written relative
+  /// to it.
+  pub fn calls(&self) -> Vec<Vec<String>> {
+    let log = fs::read_to_string(self.root.path().join("fake-ffmpeg.log")).unwrap_or_default();
+    log
+      .lines()
+      .map(|line| {
+        let args: Vec<String> = serde_json::from_str(line).unwrap();
+        args.iter().map(|arg| self.relative(arg)).collect()
+      })
+      .collect()
+  }
+
+  /// The commands that wrote an output, leaving out probing and checking ffmpeg.
+  pub fn conversions(&self) -> Vec<Vec<String>> {
+    self
+      .calls()
+      .into_iter()
+      .filter(|args| args.iter().any(|arg| arg == "-y"))
+      .collect()
+  }
+
+  /// `arg` with the fixture's folder replaced by `<root>`, if it's a path in it.
+  fn relative(&self, arg: &str) -> String {
+    let arg = arg.strip_prefix(r"\\?\").unwrap_or(arg);
+    match Path::new(arg).strip_prefix(self.root.path()) {
+      Ok(rest) => format!("<root>/{}", rest.to_string_lossy().replace('\\', "/")),
+      Err(_) => arg.to_string(),
+    }
+  }
+
+  /// The names of the files in the output folder, sorted, leaving out what otterpack keeps track
+  /// of the folder in.
+  pub fn output_files(&self) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(&self.output)
+      .map(|entries| {
+        entries
+          .flatten()
+          .map(|entry| entry.file_name().to_string_lossy().to_string())
+          .filter(|name| !name.starts_with(".otterpack."))
+          .collect()
+      })
+      .unwrap_or_default();
+    names.sort();
+    names
+  }
+
+  /// Converts the tracks with `options`, returning the outcome and everything reported meanwhile.
+  pub async fn process(
+    &self,
+    options: &ProcessOptions,
+  ) -> (Result<ProcessReport, ProcessError>, Vec<ProcessProgress>) {
+    self
+      .process_until(options, |_| false, CancellationToken::new())
+      .await
+  }
+
+  /// Like [`Fixture::process`], but cancels `cancel` as soon as `stop` returns `true` for
+  /// something reported.
+  pub async fn process_until(
+    &self,
+    options: &ProcessOptions,
+    stop: impl Fn(&ProcessProgress) -> bool,
+    cancel: CancellationToken,
+  ) -> (Result<ProcessReport, ProcessError>, Vec<ProcessProgress>) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let run = process_files(
+      self.tracks.clone(),
+      self.ffmpeg.clone(),
+      None,
+      self.output.clone(),
+      options,
+      tx,
+      cancel.clone(),
+    );
+    tokio::pin!(run);
+
+    let mut events = Vec::new();
+    let result = tokio::time::timeout(Duration::from_secs(30), async {
+      loop {
+        tokio::select! {
+          result = &mut run => break result,
+          Some(RunEvent::Process(event)) = rx.recv() => {
+            if stop(&event) {
+              cancel.cancel();
+            }
+            events.push(event);
+          }
+        }
+      }
+    })
+    .await
+    .expect("the run finished in time");
+    while let Ok(event) = rx.try_recv() {
+      if let RunEvent::Process(event) = event {
+        events.push(event);
+      }
+    }
+    (result, events)
+  }
+}
+
+/// The command a run converts `track` into `output` with, relative to the fixture, given the
+/// format's own arguments.
+pub fn convert_args(track: &str, output: &str, format_args: &[&str]) -> Vec<String> {
+  let mut args: Vec<String> = [
+    "-hide_banner",
+    "-nostdin",
+    "-nostats",
+    "-progress",
+    "pipe:1",
+    "-y",
+    "-i",
+  ]
+  .map(String::from)
+  .to_vec();
+  args.push(format!("<root>/tracks/{track}"));
+  args.extend(format_args.iter().map(|arg| arg.to_string()));
+  args.push(format!("<root>/output/{output}.part"));
+  args
+}
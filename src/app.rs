@@ -1,258 +1,4418 @@
-use std::path::PathBuf;
+use std::{
+  collections::{HashMap, HashSet},
+  path::{Path, PathBuf},
+  sync::Arc,
+};
 use strum::IntoEnumIterator;
-use tokio::sync::mpsc;
+use tokio::{
+  sync::{mpsc, oneshot},
+  task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+  AudioFormat, CompletionHook, EtaEstimator, ExtractedResources, FfmpegInfo, FormatHint, Leveling,
+  LogBuffer, MixDuration, NamedOption, OverwritePolicy, PREVIEW_SECS, PackEntry, PackSource,
+  ProcessError, ProcessOptions, ProcessProgress, ProcessReport, RunEvent, RunSetup, RunStatus,
+  TrackState, command_preview, estimate_output_size, existing_output_count,
+  extract_original_tracks, format_bytes, format_track_length,
+  i18n::{Language, t, t_n},
+  natural_cmp, next_free_folder, probe_writable, render_preview, run_completion_hook,
+  settings::{OptionsLayout, Preset, Settings, Theme},
+  setup_resources, split_command, verify_ffmpeg,
+  webhook::{WebhookMessage, send_webhook},
+  write_temp_report,
+};
+
+/// Name of the app, as the window title and on notifications.
+pub const APP_TITLE: &str = "Craig Audio Processor";
+
+/// Starts converting from the options, or goes on to the next step in the guided layout, as long
+/// as no text field has the keyboard.
+const GO_SHORTCUT: egui::KeyboardShortcut =
+  egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Enter);
+/// Closes the window once a run is done or has failed, or asks to cancel one that's going.
+const CLOSE_SHORTCUT: egui::KeyboardShortcut =
+  egui::KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Escape);
+const BROWSE_OUTPUT_SHORTCUT: egui::KeyboardShortcut =
+  egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::O);
+const FOCUS_OUTPUT_SHORTCUT: egui::KeyboardShortcut =
+  egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::L);
+const ZOOM_IN_SHORTCUTS: [egui::KeyboardShortcut; 2] = [
+  egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Equals),
+  egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Plus),
+];
+const ZOOM_OUT_SHORTCUT: egui::KeyboardShortcut =
+  egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Minus);
+/// The smallest the window can get at normal scale, matching what it's opened with.
+const MIN_WINDOW_SIZE: egui::Vec2 = egui::vec2(500.0, 300.0);
+
+#[derive(PartialEq)]
+enum AppStatus {
+  /// Looking for a pack bundled with the app, which can take a while on a slow drive.
+  Checking,
+  Ready,
+  /// The recording is encrypted, so the run waits for its password before starting again.
+  NeedsPassword,
+  Processing,
+  Error {
+    message: String,
+    /// Extra diagnostics (such as ffmpeg's log output) shown in an expandable section.
+    details: Option<String>,
+    /// What the error screen offers to do about it.
+    action: Option<ErrorAction>,
+  },
+  Done,
+}
+
+impl AppStatus {
+  fn error(message: String) -> Self {
+    AppStatus::Error {
+      message,
+      details: None,
+      action: None,
+    }
+  }
+}
+
+/// A column the track list can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, strum::EnumIter)]
+enum TrackColumn {
+  Name,
+  Size,
+  Length,
+}
+
+impl TrackColumn {
+  fn label(&self) -> &'static str {
+    match self {
+      TrackColumn::Name => t!("Name"),
+      TrackColumn::Size => t!("Size"),
+      TrackColumn::Length => t!("Length"),
+    }
+  }
+}
+
+/// A recording waiting in the queue, with what it's converted with and where to.
+#[derive(Clone)]
+struct QueuedJob {
+  source: PackSource,
+  options: ProcessOptions,
+  output_path: PathBuf,
+}
+
+impl QueuedJob {
+  /// What the recording is called in the queue.
+  fn name(&self) -> String {
+    recording_name(&self.source)
+  }
+}
+
+/// How a recording the queue got through went.
+struct JobResult {
+  name: String,
+  output_path: PathBuf,
+  outcome: Result<ProcessReport, String>,
+}
+
+/// A run that's waiting on what to do about an output folder that already has files in it.
+struct OutputPrompt {
+  /// Whether the run only extracts the original tracks.
+  extracting_tracks: bool,
+  /// How many files are in the folder.
+  files: usize,
+  /// A fresh folder next to it the run could go into instead.
+  new_folder: PathBuf,
+}
+
+/// A track being previewed, from cutting the clip until the player is done with it.
+///
+/// Dropping it stops the preview wherever it's got to, so no player is left running.
+struct Preview {
+  /// The track being previewed.
+  track: String,
+  /// The folder the clip is cut into, removed along with it.
+  clip_dir: tempfile::TempDir,
+  task: JoinHandle<()>,
+  events_rx: mpsc::UnboundedReceiver<PreviewEvent>,
+  /// The player, once the clip is cut and playing.
+  player: Option<std::process::Child>,
+}
+
+impl Preview {
+  fn clip_path(&self) -> PathBuf {
+    self.clip_dir.path().join("preview.wav")
+  }
+}
+
+impl Drop for Preview {
+  fn drop(&mut self) {
+    self.task.abort();
+    if let Some(player) = &mut self.player {
+      let _ = player.kill();
+      let _ = player.wait();
+    }
+  }
+}
+
+/// What the task cutting a preview reports back.
+enum PreviewEvent {
+  /// The recording had to be unpacked for the preview, and its ffmpeg was checked.
+  Ffmpeg(FfmpegInfo),
+  /// The recording was unpacked for the preview, so runs can reuse it.
+  Resources(Arc<ExtractedResources>),
+  /// The clip is cut and ready to play.
+  Cut,
+  Failed(String),
+}
+
+/// What's been typed into the preset manager.
+#[derive(Default)]
+struct PresetManager {
+  /// The name to save the current options under.
+  new_name: String,
+  /// The saved preset being renamed, by its index, and its new name so far.
+  renaming: Option<(usize, String)>,
+}
+
+/// Where closing the window stands while a run might be going.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum CloseState {
+  #[default]
+  Open,
+  /// Asking whether to cancel the run and quit.
+  Confirming,
+  /// The run was cancelled, the window closes once it has stopped and cleaned up.
+  Stopping,
+}
+
+/// Something that moves [`CloseState`] along.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CloseEvent {
+  /// The window was asked to close, by its X button or the OS.
+  Requested,
+  /// Cancelling the run and quitting was confirmed.
+  Confirmed,
+  /// Quitting was called off, the run carries on.
+  Dismissed,
+  /// The run has stopped.
+  Stopped,
+}
+
+impl CloseState {
+  /// The state after `event` while a run is going or not, and whether the window may close now.
+  fn next(self, event: CloseEvent, running: bool) -> (CloseState, bool) {
+    match (self, event) {
+      (_, CloseEvent::Requested) if !running => (CloseState::Open, true),
+      (CloseState::Stopping, CloseEvent::Requested) => (CloseState::Stopping, false),
+      (_, CloseEvent::Requested) => (CloseState::Confirming, false),
+      (CloseState::Confirming, CloseEvent::Confirmed) => (CloseState::Stopping, false),
+      (CloseState::Confirming, CloseEvent::Dismissed) => (CloseState::Open, false),
+      (CloseState::Stopping, CloseEvent::Stopped) => (CloseState::Open, true),
+      (state, _) => (state, false),
+    }
+  }
+}
+
+/// Which step of the guided layout's options is showing.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum WizardStep {
+  /// The output folder and format.
+  #[default]
+  Output,
+  /// Which tracks to convert and how.
+  Tracks,
+  /// A summary of what the run will make, with the Go button.
+  Confirm,
+}
+
+/// Something that moves [`WizardStep`] along.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WizardEvent {
+  /// Next was pressed.
+  Next,
+  /// Back was pressed.
+  Back,
+  /// A different recording was picked, or the layout was switched.
+  Restart,
+}
+
+impl WizardStep {
+  const COUNT: usize = 3;
+
+  /// The step after `event`, where `can_continue` says whether the options on this one are usable.
+  fn next(self, event: WizardEvent, can_continue: bool) -> WizardStep {
+    match (self, event) {
+      (_, WizardEvent::Restart) => WizardStep::Output,
+      (WizardStep::Output, WizardEvent::Next) if can_continue => WizardStep::Tracks,
+      (WizardStep::Tracks, WizardEvent::Next) if can_continue => WizardStep::Confirm,
+      (WizardStep::Tracks, WizardEvent::Back) => WizardStep::Output,
+      (WizardStep::Confirm, WizardEvent::Back) => WizardStep::Tracks,
+      (step, _) => step,
+    }
+  }
+
+  /// Where the step comes, counting from 1.
+  fn number(&self) -> usize {
+    match self {
+      WizardStep::Output => 1,
+      WizardStep::Tracks => 2,
+      WizardStep::Confirm => 3,
+    }
+  }
+
+  fn title(&self) -> &'static str {
+    match self {
+      WizardStep::Output => "Where to save",
+      WizardStep::Tracks => "What to convert",
+      WizardStep::Confirm => "Check and start",
+    }
+  }
+}
+
+/// A way out of the error screen that might fix the problem.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ErrorAction {
+  /// Pick another output folder and go back to the options.
+  ChooseFolder,
+  /// Pick a folder with more room to unpack the recording into and start again.
+  ChooseWorkDir,
+  /// Start the same run again.
+  Retry,
+}
+
+impl ErrorAction {
+  fn for_error(error: &ProcessError) -> Option<Self> {
+    match error {
+      ProcessError::OutputNotWritable { .. }
+      | ProcessError::OutputUnusable(_)
+      | ProcessError::OutputExists(_)
+      | ProcessError::NotEnoughSpace { .. } => Some(ErrorAction::ChooseFolder),
+      ProcessError::NotEnoughSpaceToUnpack { .. } => Some(ErrorAction::ChooseWorkDir),
+      // These tend to be one-offs, like antivirus holding on to a file
+      ProcessError::FfmpegFailed(_) | ProcessError::Io { .. } => Some(ErrorAction::Retry),
+      _ => None,
+    }
+  }
+
+  fn label(&self) -> &'static str {
+    match self {
+      ErrorAction::ChooseFolder => t!("📁 Choose another folder..."),
+      ErrorAction::ChooseWorkDir => t!("📁 Unpack somewhere else..."),
+      ErrorAction::Retry => t!("Try again"),
+    }
+  }
+}
+
+pub struct TemplateApp {
+  status: AppStatus,
+  output_path: PathBuf,
+  runtime: tokio::runtime::Handle,
+  source: Option<PackSource>,
+  progress_rx: Option<mpsc::UnboundedReceiver<RunEvent>>,
+  /// The latest stage reported by the processing task.
+  progress: Option<ProcessProgress>,
+  eta: EtaEstimator,
+  cancel: Option<CancellationToken>,
+  task: Option<JoinHandle<()>>,
+  options: ProcessOptions,
+  /// What the bundled ffmpeg supports, once it has been checked.
+  ffmpeg_info: Option<FfmpegInfo>,
+  /// Why the output folder can't be used, shown next to the field.
+  output_error: Option<String>,
+  /// How many tracks have failed so far in the current run.
+  failed_tracks: usize,
+  /// The title last given to the window, so it's only changed when there's something new.
+  window_title: String,
+  /// Whether to show a desktop notification when a run ends while the window isn't focused.
+  notifications: bool,
+  /// Whether the computer may go to sleep on its own in the middle of a run.
+  allow_sleep: bool,
+  /// Whether closing the window during a run minimizes it instead, leaving the run going.
+  minimize_while_processing: bool,
+  /// Keeps the computer from sleeping while a run is going.
+  keep_awake: Option<crate::platform::KeepAwake>,
+  /// Command to run when a run or the queue finishes.
+  on_complete: CompletionHook,
+  /// Lines printed by the command run when the last run finished, for the log.
+  hook_rx: Option<mpsc::UnboundedReceiver<String>>,
+  /// Discord webhook to post a summary to when a run or the queue finishes, or empty for none.
+  webhook_url: String,
+  /// The test message being sent to the webhook, if one is.
+  webhook_test_rx: Option<oneshot::Receiver<Result<(), String>>>,
+  /// Whether the last test message got through, or why it didn't.
+  webhook_test: Option<Result<(), String>>,
+  theme: Theme,
+  layout: OptionsLayout,
+  /// The step of the guided layout that's showing.
+  wizard_step: WizardStep,
+  /// The language the window is shown in, or `None` to follow the system's.
+  language: Option<Language>,
+  /// How much bigger than normal everything in the window is drawn.
+  ui_scale: f32,
+  /// Output folders runs finished in, most recent first.
+  recent_outputs: Vec<PathBuf>,
+  /// The presets the user saved, on top of the built-in ones.
+  presets: Vec<Preset>,
+  /// The preset manager, while it's open.
+  preset_manager: Option<PresetManager>,
+  /// Recordings waiting to be converted one after the other, each with its own options.
+  queue: Vec<QueuedJob>,
+  /// The queued recording being converted right now.
+  active_job: Option<QueuedJob>,
+  /// How each recording the queue got through went.
+  queue_results: Vec<JobResult>,
+  /// Stop the queue at the first recording that fails, instead of moving on to the next one.
+  stop_queue_on_error: bool,
+  /// A run waiting on whether the output folder with files in it is fine.
+  output_prompt: Option<OutputPrompt>,
+  /// The output folder that was last used or confirmed, which isn't asked about again.
+  confirmed_output: Option<PathBuf>,
+  /// Whether the window is waiting on a run before it closes.
+  close_state: CloseState,
+  /// Where each track is in the current run, in the order they're converted.
+  track_states: Vec<(String, TrackState)>,
+  /// Failed tracks that were unticked, which "Retry failed" leaves alone.
+  retry_excluded: HashSet<String>,
+  /// What the last run did, shown once it's done.
+  report: Option<ProcessReport>,
+  /// The options the last run was started with, for retrying its failed tracks.
+  last_options: Option<ProcessOptions>,
+  /// Resources unpacked by an earlier run, kept so retries don't have to extract them again.
+  resources: Option<Arc<ExtractedResources>>,
+  /// Why the last thing dropped onto the window couldn't be used.
+  source_error: Option<String>,
+  /// Where the bundled pack turned up, once the search started on launch finishes.
+  pack_rx: Option<oneshot::Receiver<anyhow::Result<PackSource>>>,
+  /// The tracks in the current recording, once they've been listed.
+  tracks: Option<Vec<PackEntry>>,
+  tracks_rx: Option<oneshot::Receiver<anyhow::Result<Vec<PackEntry>>>>,
+  /// Lengths read from the tracks themselves, for the ones the pack's manifest doesn't give, or
+  /// why they couldn't be read.
+  durations: HashMap<String, Result<f64, String>>,
+  durations_rx: Option<mpsc::UnboundedReceiver<(String, Result<f64, String>)>>,
+  /// The track being previewed, if any.
+  preview: Option<Preview>,
+  /// How loud previews play, from 0 to 1.
+  preview_volume: f32,
+  /// Why previews can't be played, once one has failed.
+  preview_unavailable: Option<String>,
+  /// Format advice the user has closed, which isn't shown again until the next launch.
+  dismissed_hints: HashSet<FormatHint>,
+  /// Tracks the user unticked, which are left out of runs.
+  excluded_tracks: HashSet<String>,
+  /// The column the track list is sorted by, and whether it's ascending.
+  track_sort: (TrackColumn, bool),
+  /// Whether the current or last run only extracted the original tracks.
+  extracting_tracks: bool,
+  /// Whether the output folder field should take the keyboard on the next frame.
+  focus_output_field: bool,
+  /// What's been typed into the password prompt so far.
+  password_input: String,
+  /// How many wrong passwords have been tried for the current recording.
+  password_attempts: u32,
+  /// What ffmpeg logged during the current or last run.
+  log: LogBuffer,
+}
+
+impl Default for TemplateApp {
+  fn default() -> Self {
+    let runtime = tokio::runtime::Handle::current();
+    let mut app = Self {
+      status: AppStatus::Checking,
+      runtime,
+      source: None,
+      progress_rx: None,
+      progress: None,
+      eta: EtaEstimator::default(),
+      cancel: None,
+      task: None,
+      output_path: default_output_path(),
+      options: ProcessOptions::default(),
+      ffmpeg_info: None,
+      output_error: None,
+      failed_tracks: 0,
+      track_states: Vec::new(),
+      queue: Vec::new(),
+      active_job: None,
+      queue_results: Vec::new(),
+      stop_queue_on_error: false,
+      output_prompt: None,
+      confirmed_output: None,
+      close_state: CloseState::Open,
+      notifications: true,
+      allow_sleep: false,
+      minimize_while_processing: true,
+      keep_awake: None,
+      on_complete: CompletionHook::default(),
+      hook_rx: None,
+      webhook_url: String::new(),
+      webhook_test_rx: None,
+      webhook_test: None,
+      theme: Theme::default(),
+      layout: OptionsLayout::default(),
+      wizard_step: WizardStep::default(),
+      language: None,
+      ui_scale: 1.0,
+      recent_outputs: Vec::new(),
+      presets: Vec::new(),
+      preset_manager: None,
+      window_title: APP_TITLE.to_string(),
+      retry_excluded: HashSet::new(),
+      report: None,
+      last_options: None,
+      resources: None,
+      source_error: None,
+      pack_rx: None,
+      tracks: None,
+      tracks_rx: None,
+      durations: HashMap::new(),
+      durations_rx: None,
+      preview: None,
+      preview_volume: 1.0,
+      preview_unavailable: None,
+      dismissed_hints: HashSet::new(),
+      excluded_tracks: HashSet::new(),
+      track_sort: (TrackColumn::Name, true),
+      extracting_tracks: false,
+      focus_output_field: false,
+      password_input: String::new(),
+      password_attempts: 0,
+      log: LogBuffer::default(),
+    };
+
+    // Packs that haven't been opened in a while shouldn't sit in the cache forever, and crashed
+    // runs leave their temp folders behind
+    let dirs = app.options.dirs.clone();
+    app.runtime.spawn_blocking(move || {
+      if let Err(e) = crate::cache::evict_stale(&dirs, crate::cache::MAX_CACHE_AGE) {
+        println!("Failed to clean up the resource cache: {}", e);
+      }
+      if let Err(e) = crate::remove_stale_temp_dirs(&dirs.temp, crate::STALE_TEMP_DIR_AGE) {
+        println!("Failed to clean up old temp folders: {}", e);
+      }
+    });
+
+    // Searching a big executable on a slow drive shouldn't hold up the window appearing
+    let (pack_tx, pack_rx) = oneshot::channel();
+    app.pack_rx = Some(pack_rx);
+    app.runtime.spawn_blocking(move || {
+      let _ = pack_tx.send(crate::find_pack_source());
+    });
+
+    app
+  }
+}
+
+/// What a recording is called in messages about it, like its ZIP's file name.
+fn recording_name(source: &PackSource) -> String {
+  match source {
+    PackSource::UserZip(path) | PackSource::UserFolder(path) | PackSource::DebugFolder(path) => {
+      path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+    }
+    PackSource::SidecarZip(_) | PackSource::EmbeddedZip { .. } => {
+      t!("Packed recording").to_string()
+    }
+  }
+}
+
+/// How much room the outputs in `report` take up, leaving out any that can't be read.
+fn output_size(report: &ProcessReport) -> u64 {
+  report
+    .outputs
+    .iter()
+    .map(|(_, path)| path)
+    .chain(&report.project)
+    .filter_map(|path| std::fs::metadata(path).ok())
+    .map(|metadata| metadata.len())
+    .sum()
+}
+
+/// The deepest folder all of `paths` are in or are, like the output folder the recordings of a
+/// queue each got a subfolder of.
+fn common_folder(paths: &[PathBuf]) -> PathBuf {
+  let Some((first, rest)) = paths.split_first() else {
+    return PathBuf::new();
+  };
+  first
+    .ancestors()
+    .find(|ancestor| rest.iter().all(|path| path.starts_with(ancestor)))
+    .unwrap_or(Path::new(""))
+    .to_path_buf()
+}
+
+/// The output folder used until the user picks one, see [`crate::default_output_dir`].
+fn default_output_path() -> PathBuf {
+  let working_dir = std::env::current_dir().unwrap_or_default();
+  if cfg!(debug_assertions) {
+    return working_dir.join("out");
+  }
+  let exe_path = std::env::current_exe().unwrap_or_default();
+  let user_dir = dirs::download_dir()
+    .or_else(dirs::document_dir)
+    .or_else(dirs::home_dir);
+  crate::default_output_dir(&exe_path, &working_dir, user_dir.as_deref(), |dir| {
+    crate::probe_writable(dir).is_ok()
+  })
+}
+
+impl TemplateApp {
+  /// Called once before the first frame.
+  pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    let mut app = Self::default();
+    let settings = crate::settings::load();
+    app.options = settings.options;
+    app.notifications = settings.notifications;
+    app.allow_sleep = settings.allow_sleep;
+    app.minimize_while_processing = settings.minimize_while_processing;
+    app.on_complete = settings.on_complete;
+    app.webhook_url = settings.webhook_url;
+    app.theme = settings.theme;
+    apply_theme(&cc.egui_ctx, app.theme);
+    app.layout = settings.layout;
+    app.language = settings.language;
+    crate::i18n::set_language(app.language.unwrap_or_else(Language::detect));
+    // Zooming goes through the UI scale setting instead, so it's kept within range and saved
+    cc.egui_ctx
+      .options_mut(|options| options.zoom_with_keyboard = false);
+    app.ui_scale = settings.ui_scale;
+    apply_ui_scale(&cc.egui_ctx, app.ui_scale);
+    app.recent_outputs = settings.recent_outputs;
+    app.presets = settings.presets;
+    if let Some(output_path) = settings.output_path {
+      app.output_path = output_path;
+    }
+    app
+  }
+
+  /// Remembers the options and output folder for the next launch.
+  fn save_settings(&self) {
+    let settings = Settings {
+      options: self.options.clone(),
+      // The default follows the executable's name, so it's only saved once the user picks another
+      output_path: Some(self.output_path.clone()).filter(|path| *path != default_output_path()),
+      notifications: self.notifications,
+      allow_sleep: self.allow_sleep,
+      minimize_while_processing: self.minimize_while_processing,
+      on_complete: self.on_complete.clone(),
+      webhook_url: self.webhook_url.clone(),
+      theme: self.theme,
+      layout: self.layout,
+      language: self.language,
+      ui_scale: self.ui_scale,
+      recent_outputs: self.recent_outputs.clone(),
+      presets: self.presets.clone(),
+    };
+    if let Err(e) = crate::settings::save(&settings) {
+      println!("Failed to save the settings: {}", e);
+    }
+  }
+
+  /// Puts every option and the output folder back to how they are on a first launch.
+  fn reset_settings(&mut self) {
+    self.options = ProcessOptions {
+      password: self.options.password.take(),
+      ..Default::default()
+    };
+    self.output_path = default_output_path();
+    self.output_error = None;
+    self.notifications = Settings::default().notifications;
+    self.allow_sleep = Settings::default().allow_sleep;
+    self.minimize_while_processing = Settings::default().minimize_while_processing;
+    self.on_complete = CompletionHook::default();
+    self.webhook_url = String::new();
+    self.webhook_test = None;
+    self.theme = Theme::default();
+    self.layout = OptionsLayout::default();
+    self.wizard_step = self.wizard_step.next(WizardEvent::Restart, true);
+    self.language = None;
+    crate::i18n::set_language(Language::detect());
+    self.ui_scale = Settings::default().ui_scale;
+    // The unpacked files may have been set up for a different work folder
+    self.resources = None;
+    self.save_settings();
+  }
+
+  /// Picks up the pack found by the search started on launch, once it's done.
+  fn poll_pack_source(&mut self, ctx: &egui::Context) {
+    let Some(rx) = &mut self.pack_rx else {
+      return;
+    };
+    let result = match rx.try_recv() {
+      Ok(result) => result,
+      Err(oneshot::error::TryRecvError::Empty) => {
+        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        return;
+      }
+      Err(oneshot::error::TryRecvError::Closed) => Err(anyhow::anyhow!("The search was aborted")),
+    };
+    self.pack_rx = None;
+    self.status = AppStatus::Ready;
+
+    // Without a payload the app can still open a recording ZIP, so this isn't an error
+    match result {
+      Ok(source) => {
+        self.source = Some(source);
+        self.list_tracks();
+      }
+      // Opening a recording ZIP instead won't help when the app itself is out of date
+      Err(e) if e.is::<crate::UnsupportedPackVersion>() => {
+        self.status = AppStatus::error(e.to_string());
+      }
+      Err(e) => {
+        println!(
+          "No pack found, waiting for a recording ZIP to be chosen: {}",
+          e
+        );
+      }
+    }
+  }
+
+  /// Starts the run that was waiting for a password again, with the one typed into the prompt.
+  fn submit_password(&mut self) {
+    self.options.password = Some(std::mem::take(&mut self.password_input));
+    // Lengths of encrypted tracks can only be read now
+    self.read_durations();
+    self.status = AppStatus::Ready;
+    if self.extracting_tracks {
+      self.start_extracting_tracks();
+    } else {
+      self.start_processing();
+    }
+  }
+
+  /// Asks for the recording's password on top of the options, until one works or it's cancelled.
+  fn show_password_prompt(&mut self, ctx: &egui::Context) {
+    let mut submitted = false;
+    let mut cancelled = false;
+    let response = egui::Modal::new(egui::Id::new("password_prompt")).show(ctx, |ui| {
+      ui.heading(t!("Password required"));
+      let label = ui.label(t!("This recording is protected with a password."));
+      if self.password_attempts > 0 {
+        ui.colored_label(
+          egui::Color32::RED,
+          t_n!(
+            self.password_attempts,
+            "That password is wrong, try again",
+            "That password is wrong, try again ({n} attempts)"
+          ),
+        );
+      }
+      let field = ui
+        .add(
+          egui::TextEdit::singleline(&mut self.password_input)
+            .password(true)
+            .hint_text(t!("Password")),
+        )
+        .labelled_by(label.id);
+      if field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+        submitted = true;
+      } else {
+        field.request_focus();
+      }
+      ui.add_space(8.0);
+      ui.horizontal(|ui| {
+        if ui
+          .add_enabled(
+            !self.password_input.is_empty(),
+            egui::Button::new(t!("Unlock")),
+          )
+          .clicked()
+        {
+          submitted = true;
+        }
+        if ui.button(t!("Cancel")).clicked() {
+          cancelled = true;
+        }
+      });
+    });
+
+    if submitted && !self.password_input.is_empty() {
+      self.submit_password();
+    } else if cancelled || response.should_close() {
+      self.password_input.clear();
+      self.status = AppStatus::Ready;
+    }
+  }
+
+  /// Holds off closing the window while a run is going, until it's confirmed and the run has
+  /// stopped, so ffmpeg isn't left running and no half-written outputs are left behind.
+  fn handle_close(&mut self, ctx: &egui::Context) {
+    let running = self.status == AppStatus::Processing;
+    let mut events = Vec::new();
+    if ctx.input(|i| i.viewport().close_requested()) {
+      if running && self.minimize_while_processing && self.close_state == CloseState::Open {
+        // Out of the way on the taskbar, where the title still shows how far the run has got
+        ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+        ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true));
+      } else {
+        events.push(CloseEvent::Requested);
+      }
+    }
+    if self.close_state == CloseState::Stopping && !running {
+      events.push(CloseEvent::Stopped);
+    }
+
+    match self.close_state {
+      CloseState::Open => {}
+      CloseState::Confirming => {
+        let response = egui::Modal::new(egui::Id::new("close_prompt")).show(ctx, |ui| {
+          ui.heading(t!("Processing is still running"));
+          ui.label(t!(
+            "Cancel it and quit? Tracks that are already done are kept."
+          ));
+          ui.add_space(8.0);
+          ui.horizontal(|ui| {
+            if ui.button(t!("Cancel and quit")).clicked() {
+              events.push(CloseEvent::Confirmed);
+            }
+            if ui.button(t!("Keep processing")).clicked() {
+              events.push(CloseEvent::Dismissed);
+            }
+          });
+        });
+        if response.should_close() {
+          events.push(CloseEvent::Dismissed);
+        }
+      }
+      CloseState::Stopping => {
+        egui::Modal::new(egui::Id::new("close_prompt")).show(ctx, |ui| {
+          ui.horizontal(|ui| {
+            ui.spinner();
+            ui.label(t!("Stopping ffmpeg and cleaning up..."));
+          });
+        });
+        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+      }
+    }
+
+    for event in events {
+      let (state, close) = self.close_state.next(event, running);
+      if event == CloseEvent::Requested && !close {
+        ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+      }
+      if state == CloseState::Stopping
+        && self.close_state != CloseState::Stopping
+        && let Some(cancel) = &self.cancel
+      {
+        cancel.cancel();
+      }
+      if close && event != CloseEvent::Requested {
+        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+      }
+      self.close_state = state;
+    }
+  }
+
+  /// Posts `message` to the Discord webhook in the background, if one is set.
+  ///
+  /// Anything that goes wrong is only logged, since the run went the same either way.
+  fn notify_webhook(&self, message: WebhookMessage) {
+    let url = self.webhook_url.trim().to_string();
+    if url.is_empty() {
+      return;
+    }
+    self.runtime.spawn(async move {
+      if let Err(e) = send_webhook(&url, &message).await {
+        println!("Failed to post to the Discord webhook: {e:#}");
+      }
+    });
+  }
+
+  /// Posts a test message to the Discord webhook, for the result to show next to the button.
+  fn send_test_webhook(&mut self) {
+    let url = self.webhook_url.trim().to_string();
+    let (tx, rx) = oneshot::channel();
+    self.webhook_test_rx = Some(rx);
+    self.webhook_test = None;
+    self.runtime.spawn(async move {
+      let result = send_webhook(&url, &WebhookMessage::test()).await;
+      let _ = tx.send(result.map_err(|e| format!("{e:#}")));
+    });
+  }
+
+  /// Picks up whether the test message got through, once it's been sent.
+  fn poll_webhook_test(&mut self, ctx: &egui::Context) {
+    let Some(rx) = &mut self.webhook_test_rx else {
+      return;
+    };
+    let result = match rx.try_recv() {
+      Ok(result) => result,
+      Err(oneshot::error::TryRecvError::Empty) => {
+        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        return;
+      }
+      Err(oneshot::error::TryRecvError::Closed) => Err("The message was never sent".to_string()),
+    };
+    self.webhook_test_rx = None;
+    self.webhook_test = Some(result);
+  }
+
+  /// How long the current recording is, going by its longest track whose length is known.
+  fn recording_length(&self) -> Option<f64> {
+    self
+      .tracks
+      .as_deref()?
+      .iter()
+      .filter_map(|track| self.track_duration(track)?.ok())
+      .reduce(f64::max)
+  }
+
+  /// Runs the command set to run when a run finishes, if there is one, in the background with what
+  /// it prints going into the log.
+  ///
+  /// `report` is written into a temp file for the command's `REPORT_PATH`, which is removed once
+  /// the command is done.
+  fn start_completion_hook(
+    &mut self,
+    output_dir: PathBuf,
+    status: RunStatus,
+    report: Option<serde_json::Value>,
+  ) {
+    if !self.on_complete.is_set() {
+      return;
+    }
+    let hook = self.on_complete.clone();
+    // Lines are tagged with the program like ffmpeg's are with the track
+    let tag = split_command(&hook.command)
+      .first()
+      .and_then(|program| Path::new(program).file_name())
+      .map_or("command".to_string(), |name| {
+        name.to_string_lossy().into_owned()
+      });
+    let (line_tx, line_rx) = mpsc::unbounded_channel();
+    self.hook_rx = Some(line_rx);
+    self.runtime.spawn(async move {
+      let send = move |line: &str| {
+        let _ = line_tx.send(format!("[{tag}] {line}"));
+      };
+      let report_file = match report.map(|report| write_temp_report(&report)).transpose() {
+        Ok(file) => file,
+        Err(e) => {
+          send(&format!("{e:#}"));
+          None
+        }
+      };
+      let result = run_completion_hook(&hook, &output_dir, status, report_file.as_deref(), {
+        let send = send.clone();
+        move |line| send(line)
+      })
+      .await;
+      if let Err(e) = result {
+        println!("The command run when finished failed: {e:#}");
+        send(&format!("{e:#}"));
+      }
+    });
+  }
+
+  /// Runs the command set to run when a run finishes for the queue, once it's through, with a
+  /// report of how each recording went.
+  fn start_queue_hook(&mut self) {
+    let status = RunStatus::combine(self.queue_results.iter().map(
+      |result| match &result.outcome {
+        Ok(report) => RunStatus::for_report(report),
+        Err(_) => RunStatus::Failed,
+      },
+    ));
+    // Shaped like the report of a batch from the command line
+    let report = self
+      .queue_results
+      .iter()
+      .map(|result| {
+        let mut entry = serde_json::json!({
+          "input": result.name,
+          "output_path": result.output_path,
+        });
+        match &result.outcome {
+          Ok(report) => entry["report"] = serde_json::to_value(report).unwrap_or_default(),
+          Err(error) => entry["error"] = error.as_str().into(),
+        }
+        entry
+      })
+      .collect();
+    let output_paths: Vec<PathBuf> = self
+      .queue_results
+      .iter()
+      .map(|result| result.output_path.clone())
+      .collect();
+    self.start_completion_hook(common_folder(&output_paths), status, Some(report));
+  }
+
+  /// Moves what the command run when the last run finished has printed into the log.
+  fn poll_hook(&mut self, ctx: &egui::Context) {
+    let Some(rx) = &mut self.hook_rx else {
+      return;
+    };
+    loop {
+      match rx.try_recv() {
+        Ok(line) => self.log.push(line),
+        Err(mpsc::error::TryRecvError::Empty) => {
+          ctx.request_repaint_after(std::time::Duration::from_millis(100));
+          return;
+        }
+        Err(mpsc::error::TryRecvError::Disconnected) => {
+          self.hook_rx = None;
+          return;
+        }
+      }
+    }
+  }
+
+  /// Lets the user know a run ended with a desktop notification, if they've looked away from the
+  /// window and haven't turned them off.
+  fn notify_if_unfocused(&self, ctx: &egui::Context, body: &str) {
+    let focused = ctx.input(|i| i.viewport().focused).unwrap_or(true);
+    // Nobody's waiting on a run that was cancelled to close the window
+    if self.notifications && !focused && self.close_state == CloseState::Open {
+      crate::platform::notify(APP_TITLE, body);
+    }
+  }
+
+  /// Applies an event from the running task to what the window shows, window title included.
+  fn apply_progress(&mut self, ctx: &egui::Context, event: RunEvent) {
+    let progress = match event {
+      RunEvent::Process(progress) => progress,
+      RunEvent::Ffmpeg(info) => {
+        self.ffmpeg_info = Some(info);
+        return;
+      }
+      RunEvent::Resources(resources) => {
+        self.resources = Some(resources);
+        return;
+      }
+    };
+    match progress {
+      ProcessProgress::Finished(report) if self.active_job.is_some() => {
+        self.finish_job(ctx, Ok(report));
+      }
+      ProcessProgress::Error(e) if self.active_job.is_some() => {
+        self.finish_job(ctx, Err(e));
+      }
+      ProcessProgress::Error(
+        e @ (ProcessError::PasswordRequired | ProcessError::WrongPassword),
+      ) => {
+        self.progress_rx = None;
+        self.progress = None;
+        self.cancel = None;
+        self.task = None;
+        if matches!(e, ProcessError::WrongPassword) {
+          self.password_attempts += 1;
+        }
+        self.status = AppStatus::NeedsPassword;
+      }
+      ProcessProgress::Error(e) => {
+        self.progress_rx = None;
+        self.progress = None;
+        self.cancel = None;
+        self.task = None;
+        if !matches!(e, ProcessError::Cancelled) {
+          self.start_completion_hook(self.output_path.clone(), RunStatus::Failed, None);
+          if let Some(source) = &self.source {
+            self.notify_webhook(WebhookMessage::failed(
+              &recording_name(source),
+              &e.to_string(),
+            ));
+          }
+        }
+        self.notify_if_unfocused(ctx, &t!("Failed: {error}", error = e));
+        self.status = AppStatus::Error {
+          message: t!("Failed to process: {error}", error = e),
+          details: error_details(&e),
+          action: ErrorAction::for_error(&e),
+        };
+        ctx.send_viewport_cmd(egui::viewport::ViewportCommand::RequestUserAttention(
+          egui::UserAttentionType::Critical,
+        ));
+      }
+      ProcessProgress::Finished(report) => {
+        self.progress_rx = None;
+        self.progress = None;
+        self.cancel = None;
+        self.task = None;
+        let message =
+          completion_message(&report, self.last_options.as_ref(), self.extracting_tracks);
+        self.notify_if_unfocused(ctx, &message);
+        if let Some(source) = &self.source {
+          self.notify_webhook(WebhookMessage::finished(
+            &recording_name(source),
+            message,
+            &report,
+            self.recording_length(),
+            output_size(&report),
+          ));
+        }
+        self.start_completion_hook(
+          self.output_path.clone(),
+          RunStatus::for_report(&report),
+          serde_json::to_value(&report).ok(),
+        );
+        self.report = Some(report);
+        self.status = AppStatus::Done;
+        crate::settings::remember_output(&mut self.recent_outputs, &self.output_path);
+        self.save_settings();
+        ctx.send_viewport_cmd(egui::viewport::ViewportCommand::RequestUserAttention(
+          egui::UserAttentionType::Critical,
+        ));
+      }
+      ProcessProgress::Converting(info) => {
+        if let Some(total) = info.audio_total_secs {
+          self.eta.update(info.audio_done_secs, total);
+        }
+        self.progress = Some(ProcessProgress::Converting(info));
+      }
+      ProcessProgress::Track { filename, state } => {
+        if matches!(state, TrackState::Failed { .. }) {
+          self.failed_tracks += 1;
+        }
+        match self
+          .track_states
+          .iter_mut()
+          .find(|(name, _)| *name == filename)
+        {
+          // A track that failed in one format stays failed while the next format is converted
+          Some((_, TrackState::Failed { .. })) if state != TrackState::Queued => {}
+          Some((_, current)) => *current = state,
+          None => self.track_states.push((filename, state)),
+        }
+      }
+      ProcessProgress::Log { file, line } => {
+        self.log.push(format!("[{file}] {line}"));
+      }
+      stage => {
+        self.progress = Some(stage);
+      }
+    }
+    self.sync_title(ctx);
+  }
+
+  /// Puts the run's progress or outcome into the window title, for when the window is in the
+  /// taskbar or alt-tab.
+  fn sync_title(&mut self, ctx: &egui::Context) {
+    let title = window_title(&self.status, self.progress.as_ref());
+    // The percentage is whole, so this only changes every percent rather than every event
+    if title != self.window_title {
+      ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+      self.window_title = title;
+    }
+  }
+
+  /// Holds off sleep while a run is going, and lets go of it once it isn't or sleep is allowed.
+  fn sync_keep_awake(&mut self) {
+    let wanted = self.status == AppStatus::Processing && !self.allow_sleep;
+    if wanted && self.keep_awake.is_none() {
+      self.keep_awake = crate::platform::keep_awake(t!("Converting a recording"));
+    } else if !wanted {
+      self.keep_awake = None;
+    }
+  }
+
+  /// Checks the output folder and kicks off a run in the background.
+  fn start_processing(&mut self) {
+    if self.output_folder_usable() && self.output_folder_confirmed(false) {
+      let options = ProcessOptions {
+        tracks: self.selected_tracks(),
+        ..self.options.clone()
+      };
+      self.spawn_run(options, None);
+    }
+  }
+
+  /// The tracks ticked in the list, or `None` if that's all of them.
+  fn selected_tracks(&self) -> Option<Vec<String>> {
+    if self.excluded_tracks.is_empty() {
+      return None;
+    }
+    let tracks = self.tracks.as_deref().unwrap_or_default();
+    Some(
+      tracks
+        .iter()
+        .filter(|track| !self.excluded_tracks.contains(&track.name))
+        .map(|track| track.name.clone())
+        .collect(),
+    )
+  }
+
+  /// Checks the output folder and starts extracting the original tracks into it in the background.
+  fn start_extracting_tracks(&mut self) {
+    if !self.output_folder_usable() || !self.output_folder_confirmed(true) {
+      return;
+    }
+    let Some(source) = self.source.clone() else {
+      return;
+    };
+    let (progress_tx, _) = self.begin_task(None);
+    self.extracting_tracks = true;
+
+    let output_path = self.output_path.clone();
+    let options = self.options.clone();
+    let task = self.runtime.spawn(async move {
+      let result = extract_original_tracks(source, output_path, options, &progress_tx).await;
+      let _ = progress_tx.send(RunEvent::Process(match result {
+        Ok(report) => ProcessProgress::Finished(report),
+        Err(e) => ProcessProgress::Error(e.into()),
+      }));
+    });
+    self.task = Some(task);
+  }
+
+  /// Whether the output folder can be written to, showing why next to the field if it can't.
+  fn output_folder_usable(&mut self) -> bool {
+    // Permission problems are easy to fix by picking another folder, so they're shown next to the
+    // field rather than ending up in the error screen
+    if let Err(e) = probe_writable(&self.output_path) {
+      self.output_error = Some(writable_error_message(&e));
+      return false;
+    }
+    self.output_error = None;
+    true
+  }
+
+  /// Whether the run can go into the output folder without asking, which it can unless there are
+  /// already files in it. Otherwise the user is asked first and the run waits.
+  fn output_folder_confirmed(&mut self, extracting_tracks: bool) -> bool {
+    if self.confirmed_output.as_ref() == Some(&self.output_path) {
+      return true;
+    }
+    match existing_output_count(&self.output_path) {
+      Ok(0) => true,
+      Ok(files) => {
+        self.output_prompt = Some(OutputPrompt {
+          extracting_tracks,
+          files,
+          new_folder: next_free_folder(&self.output_path),
+        });
+        false
+      }
+      // If it can't be listed the run will say why soon enough
+      Err(_) => true,
+    }
+  }
+
+  /// Asks what to do about an output folder that already has files in it, then starts the waiting
+  /// run unless it's cancelled.
+  fn show_output_prompt(&mut self, ctx: &egui::Context) {
+    let Some(prompt) = &self.output_prompt else {
+      return;
+    };
+    let new_folder_name = prompt
+      .new_folder
+      .file_name()
+      .unwrap_or_default()
+      .to_string_lossy()
+      .to_string();
+
+    let mut chosen = None;
+    let mut cancelled = false;
+    let response = egui::Modal::new(egui::Id::new("output_prompt")).show(ctx, |ui| {
+      ui.heading(t!("The output folder isn't empty"));
+      ui.label(t_n!(
+        prompt.files,
+        "{folder} already has 1 file in it.",
+        "{folder} already has {n} files in it.",
+        folder = self.output_path.display()
+      ));
+      ui.add_space(8.0);
+      ui.horizontal(|ui| {
+        if ui.button(t!("Use anyway")).clicked() {
+          chosen = Some(self.output_path.clone());
+        }
+        if ui
+          .button(t!(
+            "Create a new folder ({folder})",
+            folder = new_folder_name
+          ))
+          .clicked()
+        {
+          chosen = Some(prompt.new_folder.clone());
+        }
+        if ui.button(t!("Cancel")).clicked() {
+          cancelled = true;
+        }
+      });
+    });
+
+    if cancelled || response.should_close() {
+      self.output_prompt = None;
+    } else if let Some(path) = chosen
+      && let Some(prompt) = self.output_prompt.take()
+    {
+      self.output_path = path.clone();
+      self.output_error = None;
+      self.confirmed_output = Some(path);
+      if prompt.extracting_tracks {
+        self.start_extracting_tracks();
+      } else {
+        self.start_processing();
+      }
+    }
+  }
+
+  /// Moves the UI scale `steps` sizes up or down, staying within the ones on offer.
+  fn step_ui_scale(&mut self, ctx: &egui::Context, steps: isize) {
+    let scales = crate::settings::UI_SCALES;
+    let current = scales
+      .iter()
+      .position(|scale| *scale == self.ui_scale)
+      .unwrap_or(2);
+    let next = current.saturating_add_signed(steps).min(scales.len() - 1);
+    if scales[next] != self.ui_scale {
+      self.ui_scale = scales[next];
+      apply_ui_scale(ctx, self.ui_scale);
+      self.save_settings();
+    }
+  }
+
+  /// Asks for another output folder, starting from the current one.
+  fn browse_output_folder(&mut self) {
+    if let Some(path) = rfd::FileDialog::new()
+      .set_directory(&self.output_path)
+      .pick_folder()
+    {
+      self.output_path = path;
+      self.output_error = None;
+    }
+  }
+
+  /// Whether a run can be started from the options as they are.
+  fn can_start(&self) -> bool {
+    self.output_usable() && self.any_track_selected()
+  }
+
+  /// Whether the output folder is one a run could write into.
+  fn output_usable(&self) -> bool {
+    !matches!(
+      check_output_path(
+        &self.output_path,
+        self
+          .resources
+          .as_ref()
+          .map(|resources| resources.audio_dir.as_path()),
+      ),
+      OutputPathCheck::Error(_)
+    )
+  }
+
+  /// Whether any track is left to convert, which there always is while they're being listed.
+  fn any_track_selected(&self) -> bool {
+    !self
+      .tracks
+      .as_ref()
+      .is_some_and(|tracks| !tracks.is_empty() && self.excluded_tracks.len() == tracks.len())
+  }
+
+  /// Whether the options on the guided layout's current step are usable.
+  fn wizard_step_complete(&self) -> bool {
+    match self.wizard_step {
+      WizardStep::Output => self.output_usable(),
+      WizardStep::Tracks => self.any_track_selected(),
+      WizardStep::Confirm => self.can_start(),
+    }
+  }
+
+  /// Handles the keyboard shortcuts, before anything is drawn so a text field that's about to give
+  /// up the keyboard on Enter still counts as having it.
+  fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+    let zoom = if ctx.input_mut(|i| ZOOM_IN_SHORTCUTS.iter().any(|s| i.consume_shortcut(s))) {
+      1
+    } else if ctx.input_mut(|i| i.consume_shortcut(&ZOOM_OUT_SHORTCUT)) {
+      -1
+    } else {
+      0
+    };
+    if zoom != 0 {
+      self.step_ui_scale(ctx, zoom);
+    }
+
+    // The prompts have their own keys, like Esc to dismiss them
+    let prompting = self.status == AppStatus::NeedsPassword
+      || self.output_prompt.is_some()
+      || self.preset_manager.is_some()
+      || self.close_state != CloseState::Open;
+    if prompting {
+      return;
+    }
+
+    if self.status == AppStatus::Ready && self.source.is_some() {
+      if ctx.input_mut(|i| i.consume_shortcut(&BROWSE_OUTPUT_SHORTCUT)) {
+        self.browse_output_folder();
+      }
+      if ctx.input_mut(|i| i.consume_shortcut(&FOCUS_OUTPUT_SHORTCUT)) {
+        self.focus_output_field = true;
+      }
+      let typing = ctx.wants_keyboard_input();
+      if self.layout == OptionsLayout::Guided && self.wizard_step != WizardStep::Confirm {
+        if !typing
+          && self.wizard_step_complete()
+          && ctx.input_mut(|i| i.consume_shortcut(&GO_SHORTCUT))
+        {
+          self.wizard_step = self.wizard_step.next(WizardEvent::Next, true);
+        }
+      } else if !typing && self.can_start() && ctx.input_mut(|i| i.consume_shortcut(&GO_SHORTCUT)) {
+        self.start_processing();
+      }
+    }
+
+    let closes = matches!(
+      self.status,
+      AppStatus::Done | AppStatus::Error { .. } | AppStatus::Processing
+    );
+    if closes && ctx.input_mut(|i| i.consume_shortcut(&CLOSE_SHORTCUT)) {
+      // While processing, this asks whether to cancel first like the window's X button does
+      ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+    }
+  }
+
+  /// Asks for a recording ZIP to convert instead of the one packed with the app.
+  fn choose_recording_zip(&mut self) {
+    if let Some(path) = rfd::FileDialog::new()
+      .add_filter(t!("ZIP archive"), &["zip"])
+      .pick_file()
+    {
+      self.set_source(PackSource::UserZip(path));
+    }
+  }
+
+  /// Switches to converting a different recording.
+  fn set_source(&mut self, source: PackSource) {
+    self.source = Some(source);
+    self.source_error = None;
+    // Whatever was unpacked before belongs to another recording
+    self.resources = None;
+    self.ffmpeg_info = None;
+    self.options.password = None;
+    self.password_attempts = 0;
+    self.preview = None;
+    self.preview_unavailable = None;
+    self.wizard_step = self.wizard_step.next(WizardEvent::Restart, true);
+    self.list_tracks();
+  }
+
+  /// Starts listing the tracks in the current recording in the background.
+  fn list_tracks(&mut self) {
+    self.tracks = None;
+    self.tracks_rx = None;
+    self.durations.clear();
+    self.durations_rx = None;
+    self.excluded_tracks.clear();
+    let Some(source) = self.source.clone() else {
+      return;
+    };
+    let (tracks_tx, tracks_rx) = oneshot::channel();
+    self.tracks_rx = Some(tracks_rx);
+    self.runtime.spawn_blocking(move || {
+      let _ = tracks_tx.send(crate::list_pack_entries(&source));
+    });
+  }
+
+  /// Picks up the track list once it's ready.
+  fn poll_tracks(&mut self, ctx: &egui::Context) {
+    let Some(rx) = &mut self.tracks_rx else {
+      return;
+    };
+    let result = match rx.try_recv() {
+      Ok(result) => result,
+      Err(oneshot::error::TryRecvError::Empty) => {
+        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        return;
+      }
+      Err(oneshot::error::TryRecvError::Closed) => Err(anyhow::anyhow!("The listing was aborted")),
+    };
+    self.tracks_rx = None;
+    match result {
+      Ok(tracks) => {
+        self.tracks = Some(tracks);
+        self.read_durations();
+      }
+      Err(e) => {
+        self.source_error = Some(t!(
+          "Couldn't read the recording: {error}",
+          error = format!("{e:#}")
+        ))
+      }
+    }
+  }
+
+  /// Starts reading the lengths of the tracks that don't have one yet in the background.
+  fn read_durations(&mut self) {
+    let (Some(source), Some(tracks)) = (self.source.clone(), &self.tracks) else {
+      return;
+    };
+    let names: Vec<String> = tracks
+      .iter()
+      .filter(|track| track.duration.is_none())
+      .filter(|track| !self.durations.get(&track.name).is_some_and(Result::is_ok))
+      .map(|track| track.name.clone())
+      .collect();
+    if names.is_empty() {
+      return;
+    }
+
+    let (durations_tx, durations_rx) = mpsc::unbounded_channel();
+    self.durations_rx = Some(durations_rx);
+    let password = self.options.password.clone();
+    self.runtime.spawn_blocking(move || {
+      let result =
+        crate::read_track_durations(&source, &names, password.as_deref(), |name, duration| {
+          let _ = durations_tx.send((name.to_string(), duration.map_err(|e| format!("{e:#}"))));
+        });
+      if let Err(e) = result {
+        println!("Failed to read the track lengths: {e:#}");
+      }
+    });
+  }
+
+  /// Picks up track lengths as they're read.
+  fn poll_durations(&mut self, ctx: &egui::Context) {
+    let Some(rx) = &mut self.durations_rx else {
+      return;
+    };
+    loop {
+      match rx.try_recv() {
+        Ok((name, duration)) => {
+          self.durations.insert(name, duration);
+        }
+        Err(mpsc::error::TryRecvError::Empty) => {
+          ctx.request_repaint_after(std::time::Duration::from_millis(100));
+          return;
+        }
+        Err(mpsc::error::TryRecvError::Disconnected) => {
+          self.durations_rx = None;
+          return;
+        }
+      }
+    }
+  }
+
+  /// Starts cutting a preview of `track` with the current leveling, stopping any other first.
+  ///
+  /// If the recording hasn't been unpacked yet it's unpacked now, and runs reuse it afterwards.
+  fn start_preview(&mut self, track: String) {
+    self.preview = None;
+    let Some(source) = self.source.clone() else {
+      return;
+    };
+    let clip_dir = match tempfile::Builder::new()
+      .prefix("otterpack-preview-")
+      .tempdir()
+    {
+      Ok(dir) => dir,
+      Err(e) => {
+        self.preview_unavailable = Some(t!("Can't play previews: {error}", error = e));
+        return;
+      }
+    };
+
+    let (events_tx, events_rx) = mpsc::unbounded_channel();
+    let resources = self.resources.clone();
+    let options = self.options.clone();
+    let volume = self.preview_volume;
+    let input = track.clone();
+    let clip = clip_dir.path().join("preview.wav");
+    let task = self.runtime.spawn(async move {
+      let result = async {
+        let resources = match resources {
+          Some(resources) => resources,
+          None => {
+            // Nothing shows how far unpacking has got for a preview, so its progress goes nowhere
+            let (progress_tx, _) = mpsc::unbounded_channel();
+            let resources = Arc::new(setup_resources(source, &options, None, progress_tx).await?);
+            let _ = events_tx.send(PreviewEvent::Ffmpeg(
+              verify_ffmpeg(&resources.ffmpeg_path).await?,
+            ));
+            let _ = events_tx.send(PreviewEvent::Resources(resources.clone()));
+            resources
+          }
+        };
+        render_preview(
+          &resources.ffmpeg_path,
+          &resources.audio_dir.join(&input),
+          &clip,
+          options.leveling,
+          volume,
+        )
+        .await
+      }
+      .await;
+      let _ = events_tx.send(match result {
+        Ok(()) => PreviewEvent::Cut,
+        Err(e) => PreviewEvent::Failed(format!("{e:#}")),
+      });
+    });
+
+    self.preview = Some(Preview {
+      track,
+      clip_dir,
+      task,
+      events_rx,
+      player: None,
+    });
+  }
+
+  /// Starts playing the preview once it's cut, and notices when it's over or couldn't be played.
+  fn poll_preview(&mut self, ctx: &egui::Context) {
+    let Some(preview) = &mut self.preview else {
+      return;
+    };
+    ctx.request_repaint_after(std::time::Duration::from_millis(200));
+    while let Ok(event) = preview.events_rx.try_recv() {
+      match event {
+        PreviewEvent::Ffmpeg(info) => self.ffmpeg_info = Some(info),
+        PreviewEvent::Resources(resources) => self.resources = Some(resources),
+        PreviewEvent::Cut => match crate::platform::play_audio(&preview.clip_path()) {
+          Ok(player) => preview.player = Some(player),
+          Err(e) => {
+            self.preview_unavailable = Some(t!("Can't play previews: {error}", error = e));
+            self.preview = None;
+            return;
+          }
+        },
+        PreviewEvent::Failed(e) => {
+          self.preview_unavailable = Some(t!("Can't play previews: {error}", error = e));
+          self.preview = None;
+          return;
+        }
+      }
+    }
+
+    let Some(player) = &mut preview.player else {
+      return;
+    };
+    match player.try_wait() {
+      Ok(None) => {}
+      Ok(Some(status)) => {
+        // Players fail when there's no sound device to play on
+        if !status.success() {
+          self.preview_unavailable = Some(t!(
+            "Can't play previews: the player stopped with {status}",
+            status = status
+          ));
+        }
+        self.preview = None;
+      }
+      Err(e) => {
+        println!("Failed to check on the preview player: {e}");
+        self.preview = None;
+      }
+    }
+  }
+
+  /// Shows the ffmpeg command the current options make for the first selected track, or for the
+  /// mix, with a button to copy it.
+  fn show_command_preview(&self, ui: &mut egui::Ui) {
+    egui::CollapsingHeader::new(t!("FFmpeg command")).show(ui, |ui| {
+      let mut tracks: Vec<String> = self
+        .tracks
+        .iter()
+        .flatten()
+        .filter(|track| !self.excluded_tracks.contains(&track.name))
+        .map(|track| track.name.clone())
+        .collect();
+      tracks.sort_by(|a, b| natural_cmp(a, b));
+      let preview = command_preview(&tracks, &self.output_path, &self.options);
+
+      ui.add(
+        egui::TextEdit::multiline(&mut preview.as_str())
+          .font(egui::TextStyle::Monospace)
+          .desired_rows(3)
+          .desired_width(f32::INFINITY),
+      );
+      if ui.button(t!("Copy command")).clicked() {
+        ui.ctx().copy_text(preview);
+      }
+    });
+  }
+
+  /// Shows which recording is being converted, if the user picked it, and why the last one they
+  /// dropped couldn't be used.
+  fn show_source(&mut self, ui: &mut egui::Ui) {
+    if let Some(PackSource::UserZip(path) | PackSource::UserFolder(path)) = &self.source {
+      ui.horizontal(|ui| {
+        ui.label(t!("Recording:"));
+        ui.label(
+          path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default(),
+        )
+        .on_hover_text(path.display().to_string());
+      });
+    }
+    if let Some(error) = &self.source_error {
+      ui.colored_label(ui.visuals().error_fg_color, error);
+    }
+  }
+
+  /// Shows the output folder field with its browse and recent folder buttons, and whether the
+  /// folder can be used.
+  fn show_output_folder(&mut self, ui: &mut egui::Ui) {
+    let output_check = check_output_path(
+      &self.output_path,
+      self
+        .resources
+        .as_ref()
+        .map(|resources| resources.audio_dir.as_path()),
+    );
+    ui.horizontal(|ui| {
+      let label = ui.label(t!("Output folder:"));
+      let mut path_string = self.output_path.to_string_lossy().to_string();
+      let output_field = ui
+        .text_edit_singleline(&mut path_string)
+        .labelled_by(label.id);
+      if std::mem::take(&mut self.focus_output_field) {
+        output_field.request_focus();
+      }
+      let browse = ui.button(t!("📁 Browse..."));
+      if with_name(
+        browse,
+        egui::WidgetType::Button,
+        t!("Choose the output folder"),
+      )
+      .on_hover_text(t!(
+        "Choose the output folder ({shortcut})",
+        shortcut = ui.ctx().format_shortcut(&BROWSE_OUTPUT_SHORTCUT)
+      ))
+      .clicked()
+      {
+        self.browse_output_folder();
+      }
+      ui.add_enabled_ui(!self.recent_outputs.is_empty(), |ui| {
+        let menu = ui.menu_button("🕘", |ui| {
+          for path in &self.recent_outputs {
+            if ui.button(path.display().to_string()).clicked() {
+              self.output_path = path.clone();
+              self.output_error = None;
+              ui.close_menu();
+            }
+          }
+        });
+        with_name(
+          menu.response,
+          egui::WidgetType::Button,
+          t!("Recent output folders"),
+        )
+        .on_hover_text(t!("Output folders used recently"));
+      });
+      match &output_check {
+        OutputPathCheck::Fine => {}
+        OutputPathCheck::Note(note) => {
+          with_name(ui.weak("ℹ"), egui::WidgetType::Label, note).on_hover_text(note);
+        }
+        OutputPathCheck::Warning(warning) => {
+          let icon = ui.colored_label(ui.visuals().warn_fg_color, "⚠");
+          with_name(icon, egui::WidgetType::Label, warning).on_hover_text(warning);
+        }
+        OutputPathCheck::Error(error) => {
+          let icon = ui.colored_label(ui.visuals().error_fg_color, "❌");
+          with_name(icon, egui::WidgetType::Label, error).on_hover_text(error);
+        }
+      }
+      // Update PathBuf if text was manually edited
+      if output_field.changed() {
+        self.output_path = PathBuf::from(&path_string);
+        self.output_error = None;
+      }
+      output_field.on_hover_text(t!(
+        "The folder where extracted files will be saved ({shortcut})",
+        shortcut = ui.ctx().format_shortcut(&FOCUS_OUTPUT_SHORTCUT)
+      ));
+    });
+
+    if let Some(error) = &self.output_error {
+      ui.colored_label(ui.visuals().error_fg_color, error);
+    } else if let OutputPathCheck::Error(error) = &output_check {
+      ui.colored_label(ui.visuals().error_fg_color, error);
+    }
+  }
+
+  /// Shows the output format, the extra formats to also save as and how big the result will be.
+  fn show_formats(&mut self, ui: &mut egui::Ui) {
+    ui.horizontal(|ui| {
+      let label = ui.label(t!("Format:"));
+      egui::ComboBox::from_id_salt("format_combo")
+        .selected_text(crate::i18n::tr(self.options.format.display_name()))
+        .width(ui.available_width())
+        .show_ui(ui, |ui| {
+          for &format in AudioFormat::variants() {
+            let supported = self
+              .ffmpeg_info
+              .as_ref()
+              .is_none_or(|info| info.supports(format));
+            ui.add_enabled_ui(supported, |ui| {
+              ui.selectable_value(
+                &mut self.options.format,
+                format,
+                crate::i18n::tr(format.display_name()),
+              )
+              .on_disabled_hover_text(t!("The bundled ffmpeg can't create this format"));
+            });
+          }
+        })
+        .response
+        .labelled_by(label.id);
+    });
+    ui.horizontal_wrapped(|ui| {
+      ui.label(t!("Also save as:")).on_hover_text(t!(
+        "Each format goes into its own subfolder of the output folder"
+      ));
+      for &format in AudioFormat::variants()
+        .iter()
+        .filter(|format| **format != self.options.format)
+      {
+        let supported = self
+          .ffmpeg_info
+          .as_ref()
+          .is_none_or(|info| info.supports(format));
+        let mut selected = self.options.extra_formats.contains(&format);
+        if ui
+          .add_enabled(
+            supported,
+            egui::Checkbox::new(&mut selected, crate::i18n::tr(format.display_name())),
+          )
+          .on_disabled_hover_text(t!("The bundled ffmpeg can't create this format"))
+          .changed()
+        {
+          if selected {
+            self.options.extra_formats.push(format);
+          } else {
+            self.options.extra_formats.retain(|extra| *extra != format);
+          }
+        }
+      }
+    });
+    if let Some(estimate) = self.output_size_estimate() {
+      ui.weak(estimate);
+    }
+    if let Some(hint) = self
+      .format_hint()
+      .filter(|hint| !self.dismissed_hints.contains(hint))
+    {
+      ui.horizontal(|ui| {
+        let close = ui.small_button("✖");
+        if with_name(close, egui::WidgetType::Button, t!("Dismiss"))
+          .on_hover_text(t!("Dismiss"))
+          .clicked()
+        {
+          self.dismissed_hints.insert(hint);
+        }
+        ui.add(
+          egui::Label::new(egui::RichText::new(crate::i18n::tr(hint.message())).weak()).wrap(),
+        );
+      });
+    }
+  }
+
+  /// Advice on the chosen format for the selected tracks, once all their lengths are known.
+  fn format_hint(&self) -> Option<FormatHint> {
+    let mut tracks = 0;
+    let mut length: f64 = 0.0;
+    for track in self.tracks.iter().flatten() {
+      if self.excluded_tracks.contains(&track.name) {
+        continue;
+      }
+      tracks += 1;
+      // The tracks all start together, so the longest one is as long as the recording
+      length = length.max(self.track_duration(track)?.ok()?);
+    }
+    crate::format_hint(tracks, length, self.options.mix, self.options.format)
+  }
+
+  /// Shows the checkboxes for how the tracks are processed.
+  fn show_processing_options(&mut self, ui: &mut egui::Ui) {
+    ui.checkbox(&mut self.options.mix, t!("Mix into single track"))
+      .on_hover_text(t!("Mix all tracks into one file"));
+
+    let mut dynaudnorm = self.options.leveling == Leveling::Dynaudnorm;
+    if ui
+      .checkbox(&mut dynaudnorm, t!("Automatically level volume"))
+      .on_hover_text(t!(
+        "Normalize audio volume using FFmpeg's dynaudnorm filter"
+      ))
+      .changed()
+    {
+      self.options.leveling = if dynaudnorm {
+        Leveling::Dynaudnorm
+      } else {
+        Leveling::Off
+      };
+    }
+
+    ui.checkbox(&mut self.options.fail_fast, t!("Stop if a track fails"))
+      .on_hover_text(t!(
+        "Stop at the first track that can't be converted instead of skipping it"
+      ));
+
+    ui.checkbox(&mut self.options.verify, t!("Verify outputs"))
+      .on_hover_text(t!(
+        "Play back every file after it's written to check it isn't damaged. This takes about twice as long"
+      ));
+
+    ui.checkbox(
+      &mut self.options.reprocess_all,
+      t!("Force reprocess everything"),
+    )
+    .on_hover_text(t!(
+      "Convert every track again, even ones already converted into this folder by an earlier run"
+    ));
+
+    ui.checkbox(
+      &mut self.options.low_priority,
+      t!("Run in background (low priority)"),
+    )
+    .on_hover_text(t!(
+      "Use fewer resources so the computer stays usable, at the cost of speed"
+    ));
+  }
+
+  /// Shows the advanced options, the app's own settings and the ffmpeg command preview, folded
+  /// away.
+  fn show_advanced_options(&mut self, ui: &mut egui::Ui) {
+    egui::CollapsingHeader::new(t!("Advanced options")).show(ui, |ui| {
+      ui.horizontal(|ui| {
+        let label = ui.label(t!("FFmpeg threads:"));
+        ui.add(
+          egui::DragValue::new(&mut self.options.threads)
+            .range(0..=64)
+            .custom_formatter(|threads, _| {
+              if threads == 0.0 {
+                t!("Auto").to_string()
+              } else {
+                threads.to_string()
+              }
+            }),
+        )
+        .labelled_by(label.id)
+        .on_hover_text(t!("How many threads FFmpeg may use, 0 to pick automatically"));
+      });
+
+      if ui
+        .checkbox(
+          &mut self.options.bundled_ffmpeg_only,
+          t!("Only use the bundled FFmpeg"),
+        )
+        .on_hover_text(t!(
+          "Don't fall back to an FFmpeg installed on this computer if the recording has none"
+        ))
+        .changed()
+      {
+        // The next run has to look for ffmpeg again under the new rule
+        self.resources = None;
+        self.ffmpeg_info = None;
+      }
+
+      ui.horizontal(|ui| {
+        let label = ui.label(t!("Unpack into:"));
+        match &self.options.work_dir {
+          Some(dir) => ui.label(dir.display().to_string()),
+          None => ui.weak(t!("Default location")),
+        }
+        .on_hover_text(t!(
+          "Where the recording is unpacked before converting. Pick a drive with more room if the system drive is nearly full"
+        ));
+        let browse = ui.button(t!("📁 Browse...")).labelled_by(label.id);
+        if with_name(browse, egui::WidgetType::Button, t!("Choose a folder to unpack into"))
+          .clicked()
+          && let Some(path) = rfd::FileDialog::new().pick_folder()
+        {
+          self.options.work_dir = Some(path);
+          self.resources = None;
+        }
+        if self.options.work_dir.is_some() && ui.button(t!("Reset")).clicked() {
+          self.options.work_dir = None;
+          self.resources = None;
+        }
+      });
+      if ui
+        .checkbox(&mut self.options.keep_extracted, t!("Keep unpacked files"))
+        .on_hover_text(t!(
+          "Leave the unpacked recording on disk on exit instead of removing it, to get at the original files"
+        ))
+        .changed()
+      {
+        // Whatever was already unpacked was set up to be removed or kept
+        self.resources = None;
+      }
+
+      if ui
+        .button(t!("Clear cached files"))
+        .on_hover_text(t!(
+          "Remove the files unpacked by earlier launches. They'll be unpacked again next time"
+        ))
+        .clicked()
+      {
+        // The cached folder is about to go away, so the next run has to unpack again
+        self.resources = None;
+        if let Err(e) = crate::cache::clear(&self.options.dirs) {
+          self.status = AppStatus::error(t!("Failed to clear the cache: {error}", error = e));
+        }
+      }
+
+      ui.checkbox(&mut self.notifications, t!("Notify when done"))
+        .on_hover_text(t!(
+          "Show a desktop notification when processing finishes or fails while the window is in the background"
+        ));
+
+      ui.horizontal(|ui| {
+        let label = ui.label(t!("Run when finished:"));
+        ui.add(
+          egui::TextEdit::singleline(&mut self.on_complete.command)
+            .hint_text(t!("Command"))
+            .desired_width(f32::INFINITY),
+        )
+        .labelled_by(label.id)
+        .on_hover_text(t!(
+          "A command or script to run when processing finishes, like one that uploads the files. It gets the output folder in OUTPUT_DIR, success, partial or failed in STATUS and a JSON report in REPORT_PATH, and what it prints goes into the log. It runs as you, so only put in commands you trust"
+        ));
+      });
+      ui.add_enabled(
+        self.on_complete.is_set(),
+        egui::Checkbox::new(&mut self.on_complete.use_shell, t!("Run it through the shell")),
+      )
+      .on_hover_text(t!(
+        "Lets the command use pipes, redirects and variables, through sh or cmd. Otherwise it's run directly, with quotes keeping spaces together"
+      ));
+
+      ui.horizontal(|ui| {
+        let label = ui.label(t!("Discord webhook:"));
+        let id = ui.make_persistent_id("webhook_url");
+        // The URL is all it takes to post to the channel, so it's hidden unless it's being typed
+        let editing = ui.memory(|memory| memory.has_focus(id));
+        ui.add(
+          egui::TextEdit::singleline(&mut self.webhook_url)
+            .id(id)
+            .password(!editing)
+            .hint_text("https://discord.com/api/webhooks/…")
+            .desired_width(240.0),
+        )
+        .labelled_by(label.id)
+        .on_hover_text(t!(
+          "Posts a summary to a Discord channel when processing finishes. Create a webhook in the channel's settings under Integrations and paste its URL here"
+        ));
+      });
+      ui.horizontal(|ui| {
+        let sending = self.webhook_test_rx.is_some();
+        if ui
+          .add_enabled(
+            !self.webhook_url.trim().is_empty() && !sending,
+            egui::Button::new(t!("Send test notification")),
+          )
+          .clicked()
+        {
+          self.send_test_webhook();
+        }
+        match &self.webhook_test {
+          _ if sending => {
+            ui.spinner();
+          }
+          Some(Ok(())) => {
+            ui.label(t!("Sent"));
+          }
+          Some(Err(error)) => {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+          }
+          None => {}
+        }
+      });
+
+      ui.checkbox(
+        &mut self.minimize_while_processing,
+        t!("Minimize instead of closing while processing"),
+      )
+      .on_hover_text(t!(
+        "Closing the window during a run minimizes it and the run carries on. Cancel the run to close it"
+      ));
+
+      ui.checkbox(&mut self.allow_sleep, t!("Allow sleep while processing"))
+        .on_hover_text(t!(
+          "Let the computer go to sleep on its own during a run, which pauses it until the computer wakes up"
+        ));
+
+      if ui
+        .button(t!("Reset to defaults"))
+        .on_hover_text(t!("Put every option and the output folder back to how they started"))
+        .clicked()
+      {
+        self.reset_settings();
+        apply_theme(ui.ctx(), self.theme);
+        apply_ui_scale(ui.ctx(), self.ui_scale);
+      }
+
+      ui.horizontal(|ui| {
+        let label = ui.label(t!("Theme:"));
+        let before = self.theme;
+        egui::ComboBox::from_id_salt("theme_combo")
+          .selected_text(crate::i18n::tr(self.theme.display_name()))
+          .show_ui(ui, |ui| {
+            for theme in Theme::iter() {
+              ui.selectable_value(
+                &mut self.theme,
+                theme,
+                crate::i18n::tr(theme.display_name()),
+              );
+            }
+          })
+          .response
+          .labelled_by(label.id);
+        if self.theme != before {
+          apply_theme(ui.ctx(), self.theme);
+          self.save_settings();
+        }
+      });
+
+      ui.horizontal(|ui| {
+        let label = ui.label(t!("Zoom:"));
+        let before = self.ui_scale;
+        egui::ComboBox::from_id_salt("ui_scale_combo")
+          .selected_text(format!("{:.0}%", self.ui_scale * 100.0))
+          .show_ui(ui, |ui| {
+            for scale in crate::settings::UI_SCALES {
+              ui.selectable_value(&mut self.ui_scale, scale, format!("{:.0}%", scale * 100.0));
+            }
+          })
+          .response
+          .labelled_by(label.id)
+          .on_hover_text(t!(
+            "How big everything in the window is drawn ({zoom_in} and {zoom_out})",
+            zoom_in = ui.ctx().format_shortcut(&ZOOM_IN_SHORTCUTS[0]),
+            zoom_out = ui.ctx().format_shortcut(&ZOOM_OUT_SHORTCUT)
+          ));
+        if self.ui_scale != before {
+          apply_ui_scale(ui.ctx(), self.ui_scale);
+          self.save_settings();
+        }
+      });
+
+      ui.horizontal(|ui| {
+        let label = ui.label(t!("Language:"));
+        let before = self.language;
+        let system = t!(
+          "Same as the system ({language})",
+          language = Language::detect().native_name()
+        );
+        egui::ComboBox::from_id_salt("language_combo")
+          .selected_text(match self.language {
+            Some(language) => language.native_name().to_string(),
+            None => system.clone(),
+          })
+          .show_ui(ui, |ui| {
+            ui.selectable_value(&mut self.language, None, system);
+            for language in Language::iter() {
+              ui.selectable_value(&mut self.language, Some(language), language.native_name());
+            }
+          })
+          .response
+          .labelled_by(label.id);
+        if self.language != before {
+          crate::i18n::set_language(self.language.unwrap_or_else(Language::detect));
+          self.save_settings();
+        }
+      });
+
+      ui.horizontal(|ui| {
+        let label = ui.label(t!("If a file exists:"));
+        egui::ComboBox::from_id_salt("overwrite_combo")
+          .selected_text(crate::i18n::tr(self.options.overwrite.display_name()))
+          .show_ui(ui, |ui| {
+            for &policy in OverwritePolicy::variants() {
+              ui.selectable_value(
+                &mut self.options.overwrite,
+                policy,
+                crate::i18n::tr(policy.display_name()),
+              );
+            }
+          })
+          .response
+          .labelled_by(label.id)
+          .on_hover_text(t!(
+            "What to do with an output that's already in the output folder"
+          ));
+      });
+
+      ui.horizontal(|ui| {
+        let label = ui.label(t!("Give up on a stuck FFmpeg after:"));
+        ui.add(
+          egui::DragValue::new(&mut self.options.stall_timeout_secs)
+            .range(0..=3600)
+            .custom_formatter(|secs, _| {
+              if secs == 0.0 {
+                t!("Never").to_string()
+              } else {
+                format!("{secs} s")
+              }
+            }),
+        )
+        .labelled_by(label.id)
+        .on_hover_text(t!(
+          "How long FFmpeg may go without making progress before it's stopped, 0 to wait forever"
+        ));
+      });
+
+      ui.add_enabled_ui(self.options.mix, |ui| {
+        ui.horizontal(|ui| {
+          let label = ui.label(t!("Mix length:"));
+          egui::ComboBox::from_id_salt("mix_duration_combo")
+            .selected_text(crate::i18n::tr(self.options.mix_duration.display_name()))
+            .show_ui(ui, |ui| {
+              for &duration in MixDuration::variants() {
+                ui.selectable_value(
+                  &mut self.options.mix_duration,
+                  duration,
+                  crate::i18n::tr(duration.display_name()),
+                );
+              }
+            })
+            .response
+            .labelled_by(label.id)
+            .on_hover_text(t!("Which track decides when the mix ends"));
+        });
+        ui.horizontal(|ui| {
+          let label = ui.label(t!("Dropout transition:"));
+          ui.add(
+            egui::DragValue::new(&mut self.options.dropout_transition)
+              .range(0.0..=10.0)
+              .speed(0.1)
+              .suffix(" s"),
+          )
+          .labelled_by(label.id)
+          .on_hover_text(t!(
+            "How long the volume takes to even out when a track in the mix ends"
+          ));
+        });
+      });
+
+      self.show_command_preview(ui);
+    });
+  }
+
+  /// Shows the presets to switch the options to, and a button to manage them.
+  fn show_presets(&mut self, ui: &mut egui::Ui) {
+    let label = ui.label(t!("Preset:"));
+    let builtin = crate::settings::builtin_presets();
+    let current = builtin
+      .iter()
+      .chain(&self.presets)
+      .find(|preset| preset.apply_to(&self.options) == self.options)
+      .map_or_else(|| t!("Custom").to_string(), |preset| preset.name.clone());
+    let mut chosen = None;
+    egui::ComboBox::from_id_salt("preset_combo")
+      .selected_text(current)
+      .show_ui(ui, |ui| {
+        for preset in builtin.iter().chain(&self.presets) {
+          if ui.selectable_label(false, &preset.name).clicked() {
+            chosen = Some(preset.apply_to(&self.options));
+          }
+        }
+      })
+      .response
+      .labelled_by(label.id)
+      .on_hover_text(t!("Switch every option to a saved set in one go"));
+    if let Some(options) = chosen {
+      self.options = options;
+    }
+    let manage = ui.button("⚙");
+    if with_name(manage, egui::WidgetType::Button, t!("Manage presets"))
+      .on_hover_text(t!(
+        "Save the current options as a preset, or rename and delete presets"
+      ))
+      .clicked()
+    {
+      self.preset_manager = Some(PresetManager::default());
+    }
+  }
+
+  /// A name for a new preset based on `name` that no other preset has.
+  fn unused_preset_name(&self, name: &str) -> String {
+    let builtin = crate::settings::builtin_presets();
+    let taken = |candidate: &str| {
+      builtin
+        .iter()
+        .chain(&self.presets)
+        .any(|preset| preset.name == candidate)
+    };
+    if !taken(name) {
+      return name.to_string();
+    }
+    (2..)
+      .map(|n| format!("{name} ({n})"))
+      .find(|candidate| !taken(candidate))
+      .unwrap_or_default()
+  }
+
+  /// Shows the preset manager while it's open.
+  fn show_preset_manager(&mut self, ctx: &egui::Context) {
+    let Some(mut manager) = self.preset_manager.take() else {
+      return;
+    };
+    let builtin = crate::settings::builtin_presets();
+    let mut open = true;
+    let mut changed = false;
+    let mut duplicate = None;
+    let mut delete = None;
+    // Whether renaming is over, one way or the other
+    let mut renamed = false;
+    let names: Vec<String> = self
+      .presets
+      .iter()
+      .map(|preset| preset.name.clone())
+      .collect();
+
+    let response = egui::Modal::new(egui::Id::new("preset_manager")).show(ctx, |ui| {
+      ui.heading(t!("Presets"));
+      ui.add_space(4.0);
+      egui::Grid::new("presets").num_columns(2).show(ui, |ui| {
+        for preset in &builtin {
+          ui.label(&preset.name)
+            .on_hover_text(t!("Comes with the app, so it can't be changed"));
+          if ui.button(t!("Duplicate")).clicked() {
+            duplicate = Some(preset.clone());
+          }
+          ui.end_row();
+        }
+        for (index, preset) in self.presets.iter_mut().enumerate() {
+          match &mut manager.renaming {
+            Some((renaming, name)) if *renaming == index => {
+              let field = ui.text_edit_singleline(name);
+              let submitted = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+              with_name(field, egui::WidgetType::TextEdit, t!("New name"));
+              ui.horizontal(|ui| {
+                let name = name.trim();
+                let taken = builtin.iter().any(|preset| preset.name == name)
+                  || names
+                    .iter()
+                    .enumerate()
+                    .any(|(other, other_name)| other != index && other_name == name);
+                let usable = !name.is_empty() && !taken;
+                if ui
+                  .add_enabled(usable, egui::Button::new(t!("Rename")))
+                  .clicked()
+                  || (submitted && usable)
+                {
+                  preset.name = name.to_string();
+                  changed = true;
+                  renamed = true;
+                } else if ui.button(t!("Cancel")).clicked() {
+                  renamed = true;
+                }
+              });
+            }
+            _ => {
+              ui.label(&preset.name);
+              ui.horizontal(|ui| {
+                if ui.button(t!("Rename")).clicked() {
+                  manager.renaming = Some((index, preset.name.clone()));
+                }
+                if ui.button(t!("Duplicate")).clicked() {
+                  duplicate = Some(preset.clone());
+                }
+                if ui.button(t!("Delete")).clicked() {
+                  delete = Some(index);
+                }
+              });
+            }
+          }
+          ui.end_row();
+        }
+      });
+
+      ui.separator();
+      ui.horizontal(|ui| {
+        let label = ui.label(t!("Save the current options as:"));
+        ui.text_edit_singleline(&mut manager.new_name)
+          .labelled_by(label.id);
+      });
+      let name = manager.new_name.trim().to_string();
+      let existing = self.presets.iter().position(|preset| preset.name == name);
+      let usable = !name.is_empty() && !builtin.iter().any(|preset| preset.name == name);
+      ui.horizontal(|ui| {
+        let save = if existing.is_some() {
+          t!("Replace")
+        } else {
+          t!("Save")
+        };
+        if ui.add_enabled(usable, egui::Button::new(save)).clicked() {
+          let preset = Preset {
+            name,
+            options: ProcessOptions {
+              password: None,
+              tracks: None,
+              ..self.options.clone()
+            },
+          };
+          match existing {
+            Some(index) => self.presets[index] = preset,
+            None => self.presets.push(preset),
+          }
+          manager.new_name.clear();
+          changed = true;
+        }
+        if ui.button(t!("Close")).clicked() {
+          open = false;
+        }
+      });
+    });
+    if response.should_close() {
+      open = false;
+    }
+
+    if renamed {
+      manager.renaming = None;
+    }
+    if let Some(preset) = duplicate {
+      let name = self.unused_preset_name(&t!("{name} copy", name = preset.name));
+      self.presets.push(Preset { name, ..preset });
+      changed = true;
+    }
+    if let Some(index) = delete {
+      self.presets.remove(index);
+      manager.renaming = None;
+      changed = true;
+    }
+    if changed {
+      self.save_settings();
+    }
+    if open {
+      self.preset_manager = Some(manager);
+    }
+  }
+
+  /// Shows whether all the options are on one screen or a few at a time.
+  fn show_layout_toggle(&mut self, ui: &mut egui::Ui) {
+    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+      let before = self.layout;
+      // Right to left, so the last one goes first
+      for layout in OptionsLayout::iter().rev() {
+        ui.selectable_value(
+          &mut self.layout,
+          layout,
+          crate::i18n::tr(layout.display_name()),
+        )
+        .on_hover_text(match layout {
+          OptionsLayout::Simple => t!("Every option on one screen"),
+          OptionsLayout::Guided => {
+            t!("A few options at a time, with a summary to check before starting")
+          }
+        });
+      }
+      if self.layout != before {
+        self.wizard_step = self.wizard_step.next(WizardEvent::Restart, true);
+        self.save_settings();
+      }
+    });
+  }
+
+  /// Shows the options for the guided layout's current step.
+  fn show_wizard_step(&mut self, ui: &mut egui::Ui) {
+    ui.heading(t!(
+      "Step {number} of {total}: {title}",
+      number = self.wizard_step.number(),
+      total = WizardStep::COUNT,
+      title = crate::i18n::tr(self.wizard_step.title())
+    ));
+    ui.add_space(4.0);
+    match self.wizard_step {
+      WizardStep::Output => {
+        self.show_source(ui);
+        self.show_output_folder(ui);
+        self.show_formats(ui);
+      }
+      WizardStep::Tracks => {
+        self.show_tracks(ui);
+        ui.add_space(8.0);
+        self.show_processing_options(ui);
+        self.show_advanced_options(ui);
+      }
+      WizardStep::Confirm => self.show_summary(ui),
+    }
+  }
+
+  /// Sums up what a run with the current options will make, for the guided layout's last step.
+  fn show_summary(&self, ui: &mut egui::Ui) {
+    egui::Grid::new("summary").num_columns(2).show(ui, |ui| {
+      if let Some(PackSource::UserZip(path) | PackSource::UserFolder(path)) = &self.source {
+        ui.label(t!("Recording:"));
+        ui.label(
+          path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default(),
+        );
+        ui.end_row();
+      }
+
+      ui.label(t!("Output folder:"));
+      ui.label(self.output_path.display().to_string());
+      ui.end_row();
+
+      ui.label(t!("Formats:"));
+      let formats: Vec<&str> = self
+        .options
+        .formats()
+        .into_iter()
+        .map(|format| crate::i18n::tr(format.display_name()))
+        .collect();
+      ui.label(formats.join(", "));
+      ui.end_row();
+
+      if let Some(tracks) = &self.tracks {
+        ui.label(t!("Tracks:"));
+        ui.label(t!(
+          "{selected} of {total}",
+          selected = tracks.len() - self.excluded_tracks.len(),
+          total = tracks.len()
+        ));
+        ui.end_row();
+      }
+
+      ui.label(t!("Result:"));
+      ui.label(if self.options.mix {
+        t!("One file with every track mixed together")
+      } else {
+        t!("A separate file for each track")
+      });
+      ui.end_row();
+
+      ui.label(t!("Volume:"));
+      ui.label(match self.options.leveling {
+        Leveling::Dynaudnorm => t!("Levelled automatically"),
+        Leveling::Off => t!("As recorded"),
+      });
+      ui.end_row();
+    });
+    if let Some(estimate) = self.output_size_estimate() {
+      ui.weak(estimate);
+    }
+    ui.add_space(8.0);
+    self.show_command_preview(ui);
+  }
+
+  /// Shows Next and Back under the guided layout's steps before the last one.
+  fn show_wizard_nav(&mut self, ui: &mut egui::Ui) {
+    let can_continue = self.wizard_step_complete();
+    if ui
+      .add_enabled_ui(can_continue, |ui| {
+        ui.add_sized([ui.available_width(), 20.0], primary_button(ui, t!("Next")))
+          .on_hover_text(t!(
+            "Go on to the next step ({shortcut})",
+            shortcut = ui.ctx().format_shortcut(&GO_SHORTCUT)
+          ))
+      })
+      .inner
+      .clicked()
+    {
+      self.wizard_step = self.wizard_step.next(WizardEvent::Next, can_continue);
+    }
+    ui.horizontal(|ui| {
+      if ui
+        .add_enabled(
+          self.wizard_step != WizardStep::Output,
+          egui::Button::new(t!("Back")),
+        )
+        .on_hover_text(t!("Go back to the previous step"))
+        .clicked()
+      {
+        self.wizard_step = self.wizard_step.next(WizardEvent::Back, can_continue);
+      }
+      if ui.button(t!("📁 Choose recording ZIP...")).clicked() {
+        self.choose_recording_zip();
+      }
+    });
+  }
+
+  /// Roughly how much space the selected tracks take up converted with the current options, or a
+  /// note that their lengths are still being read.
+  fn output_size_estimate(&self) -> Option<String> {
+    let mut durations = Vec::new();
+    for track in self.tracks.iter().flatten() {
+      if self.excluded_tracks.contains(&track.name) {
+        continue;
+      }
+      match self.track_duration(track) {
+        Some(Ok(duration)) => durations.push(duration),
+        // Left out, the run can't tell how long these are either
+        Some(Err(_)) => {}
+        None => return Some(t!("Estimating the output size…").to_string()),
+      }
+    }
+    if durations.is_empty() {
+      return None;
+    }
+    let size = self
+      .options
+      .formats()
+      .into_iter()
+      .map(|format| estimate_output_size(&durations, format, self.options.mix))
+      .sum::<u64>();
+    Some(t!(
+      "Approx. {size} once converted",
+      size = format_bytes(size)
+    ))
+  }
+
+  /// A track's length from the manifest or read from the track, `None` while it's being read.
+  fn track_duration(&self, track: &PackEntry) -> Option<Result<f64, String>> {
+    track
+      .duration
+      .map(Ok)
+      .or_else(|| self.durations.get(&track.name).cloned())
+  }
+
+  /// Renders a summary of the tracks in the recording, expandable into a sortable table where
+  /// tracks can be left out.
+  fn show_tracks(&mut self, ui: &mut egui::Ui) {
+    let Some(tracks) = &self.tracks else {
+      return;
+    };
+    if tracks.is_empty() {
+      ui.colored_label(
+        ui.visuals().warn_fg_color,
+        t!("There are no tracks in this recording"),
+      );
+      return;
+    }
+
+    let size = tracks.iter().map(|track| track.size).sum::<u64>();
+    let packed = tracks
+      .iter()
+      .map(|track| track.compressed_size)
+      .sum::<u64>();
+    let mut summary = t_n!(
+      tracks.len(),
+      "1 track, {size}",
+      "{n} tracks, {size}",
+      size = format_bytes(size)
+    );
+    if packed < size {
+      summary += &t!(" ({size} packed)", size = format_bytes(packed));
+    }
+    // The tracks all start together, so the longest one is as long as the recording
+    let durations: Option<Vec<f64>> = tracks
+      .iter()
+      .map(|track| self.track_duration(track).and_then(Result::ok))
+      .collect();
+    if let Some(length) = durations.and_then(|durations| durations.into_iter().reduce(f64::max)) {
+      summary += &t!(", {length} long", length = format_track_length(length));
+    }
+    if !self.excluded_tracks.is_empty() {
+      summary += &t!(
+        ", {selected} of {total} selected",
+        selected = tracks.len() - self.excluded_tracks.len(),
+        total = tracks.len()
+      );
+    }
+
+    let (column, ascending) = self.track_sort;
+    let mut sorted: Vec<&PackEntry> = tracks.iter().collect();
+    sorted.sort_by(|a, b| {
+      let order = match column {
+        TrackColumn::Name => natural_cmp(&a.name, &b.name),
+        TrackColumn::Size => a.size.cmp(&b.size),
+        // Unknown lengths go last either way round
+        TrackColumn::Length => {
+          let length = |track| self.track_duration(track).and_then(Result::ok);
+          match (length(a), length(b)) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+          }
+        }
+      };
+      if ascending { order } else { order.reverse() }
+    });
+
+    let mut sort_by = None;
+    let mut toggled = Vec::new();
+    let mut select_all = None;
+    // Some(None) stops the preview, Some(Some(track)) starts one
+    let mut preview = None;
+    let mut volume = self.preview_volume;
+    egui::CollapsingHeader::new(summary)
+      .id_salt("tracks")
+      .show(ui, |ui| {
+        egui::ScrollArea::vertical()
+          .max_height(150.0)
+          .show(ui, |ui| {
+            egui::Grid::new("track_list")
+              .num_columns(5)
+              .striped(true)
+              .show(ui, |ui| {
+                let mut all = self.excluded_tracks.is_empty();
+                let checkbox = ui.checkbox(&mut all, "");
+                if with_state(checkbox, all, t!("Select all tracks"))
+                  .on_hover_text(t!("Select all or none"))
+                  .changed()
+                {
+                  select_all = Some(all);
+                }
+                for header in TrackColumn::iter() {
+                  let arrow = match (header == column, ascending) {
+                    (false, _) => "",
+                    (true, true) => " ⬆",
+                    (true, false) => " ⬇",
+                  };
+                  if ui
+                    .selectable_label(header == column, format!("{}{arrow}", header.label()))
+                    .clicked()
+                  {
+                    sort_by = Some(header);
+                  }
+                }
+                ui.end_row();
+
+                for track in sorted {
+                  let mut included = !self.excluded_tracks.contains(&track.name);
+                  let checkbox = ui.checkbox(&mut included, "");
+                  let name = t!("Include {track}", track = track.name);
+                  if with_state(checkbox, included, &name).changed() {
+                    toggled.push(track.name.clone());
+                  }
+                  ui.label(&track.name);
+                  ui.label(format_bytes(track.size));
+                  match self.track_duration(track) {
+                    Some(Ok(length)) => {
+                      ui.label(format_track_length(length));
+                    }
+                    Some(Err(e)) => {
+                      let icon = ui.colored_label(ui.visuals().warn_fg_color, "⚠");
+                      with_name(icon, egui::WidgetType::Label, &e).on_hover_text(e);
+                    }
+                    None => {
+                      ui.spinner();
+                    }
+                  }
+                  let previewing = self
+                    .preview
+                    .as_ref()
+                    .filter(|preview| preview.track == track.name);
+                  ui.horizontal(|ui| match previewing {
+                    Some(previewing) => {
+                      let stop = ui.small_button("⏹");
+                      let name = t!("Stop {track}", track = track.name);
+                      if with_name(stop, egui::WidgetType::Button, &name)
+                        .on_hover_text(t!("Stop the preview"))
+                        .clicked()
+                      {
+                        preview = Some(None);
+                      }
+                      if previewing.player.is_none() {
+                        ui.spinner();
+                      }
+                    }
+                    None => {
+                      let play = ui.add_enabled(
+                        self.preview_unavailable.is_none(),
+                        egui::Button::new("▶").small(),
+                      );
+                      let name = t!("Play {track}", track = track.name);
+                      let play =
+                        with_name(play, egui::WidgetType::Button, &name).on_hover_text(t!(
+                          "Play the first {secs} seconds, with volume levelling if it's on",
+                          secs = PREVIEW_SECS
+                        ));
+                      let play = match &self.preview_unavailable {
+                        Some(reason) => play.on_disabled_hover_text(reason),
+                        None => play,
+                      };
+                      if play.clicked() {
+                        preview = Some(Some(track.name.clone()));
+                      }
+                    }
+                  });
+                  ui.end_row();
+                }
+              });
+          });
+        ui.horizontal(|ui| {
+          let label = ui.label(t!("Preview volume:"));
+          ui.add(
+            egui::Slider::new(&mut volume, 0.0..=1.0)
+              .custom_formatter(|volume, _| format!("{:.0}%", volume * 100.0)),
+          )
+          .labelled_by(label.id)
+          .on_hover_text(t!("How loud previews play, from the next one on"));
+        });
+      });
+
+    if let Some(all) = select_all {
+      self.excluded_tracks = if all {
+        HashSet::new()
+      } else {
+        tracks.iter().map(|track| track.name.clone()).collect()
+      };
+    }
+    for name in toggled {
+      if !self.excluded_tracks.remove(&name) {
+        self.excluded_tracks.insert(name);
+      }
+    }
+    if let Some(header) = sort_by {
+      self.track_sort = (header, header != column || !ascending);
+    }
+    self.preview_volume = volume;
+    match preview {
+      Some(Some(track)) => self.start_preview(track),
+      Some(None) => self.preview = None,
+      None => {}
+    }
+  }
+
+  /// Takes a recording ZIP or folder dropped onto the window as the new source.
+  fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+    let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+    // Nothing can change while a run is using the current recording
+    if dropped.is_empty() || self.status != AppStatus::Ready {
+      return;
+    }
+    match dropped_source(&dropped) {
+      Ok(source) => self.set_source(source),
+      Err(e) => self.source_error = Some(e),
+    }
+  }
+
+  /// Runs the ticked tracks that failed last time again, with the same options.
+  fn retry_failed(&mut self) {
+    let (Some(report), Some(options)) = (self.report.take(), self.last_options.clone()) else {
+      return;
+    };
+
+    let options = ProcessOptions {
+      tracks: Some(
+        report
+          .failed
+          .iter()
+          .map(|f| f.filename.clone())
+          .filter(|filename| !self.retry_excluded.contains(filename))
+          .collect(),
+      ),
+      // Whatever a failed conversion left behind is incomplete
+      overwrite: OverwritePolicy::Overwrite,
+      ..options
+    };
+    // The tracks that aren't retried keep showing how they went last time
+    let track_states = std::mem::take(&mut self.track_states);
+    self.spawn_run(options, Some(report));
+    self.track_states = track_states;
+  }
+
+  /// Goes back to the options after a run, forgetting how it went.
+  ///
+  /// The recording, its unpacked files, track list and lengths are kept, so the next run starts
+  /// straight away, along with the options and the output folder that was confirmed.
+  fn reset_for_new_run(&mut self) {
+    self.status = AppStatus::Ready;
+    self.progress = None;
+    self.eta.reset();
+    self.failed_tracks = 0;
+    self.track_states.clear();
+    self.retry_excluded.clear();
+    self.report = None;
+    self.last_options = None;
+    self.extracting_tracks = false;
+    self.queue_results.clear();
+    self.log.clear();
+    self.wizard_step = self.wizard_step.next(WizardEvent::Restart, true);
+  }
+
+  /// Starts a run in the background, merging its results into `previous` if there is one.
+  fn spawn_run(&mut self, options: ProcessOptions, previous: Option<ProcessReport>) {
+    let Some(source) = self.source.clone() else {
+      return;
+    };
+    self.queue_results.clear();
+    self.spawn_run_for(
+      source,
+      self.output_path.clone(),
+      options,
+      self.resources.clone(),
+      previous,
+    );
+  }
+
+  /// Starts a run of `source` into `output_path` in the background, see [`Self::spawn_run`].
+  fn spawn_run_for(
+    &mut self,
+    source: PackSource,
+    output_path: PathBuf,
+    options: ProcessOptions,
+    resources: Option<Arc<ExtractedResources>>,
+    previous: Option<ProcessReport>,
+  ) {
+    let (progress_tx, cancel) = self.begin_task(Some(options.clone()));
+
+    // Spawn the async task
+    let task = self.runtime.spawn(async move {
+      let setup = RunSetup {
+        resources,
+        ..Default::default()
+      };
+      let result = crate::run(
+        source,
+        output_path,
+        options,
+        setup,
+        |event| {
+          let _ = progress_tx.send(event);
+        },
+        cancel,
+      )
+      .await;
+      let _ = progress_tx.send(RunEvent::Process(match result {
+        Ok(report) => ProcessProgress::Finished(match previous {
+          Some(mut previous) => {
+            previous.merge(report);
+            previous
+          }
+          None => report,
+        }),
+        Err(e) => ProcessProgress::Error(e),
+      }));
+    });
+    self.task = Some(task);
+  }
+
+  /// Puts the current recording in the queue with the current options and output folder.
+  fn add_to_queue(&mut self) {
+    let Some(source) = self.source.clone() else {
+      return;
+    };
+    self.queue.push(QueuedJob {
+      source,
+      options: ProcessOptions {
+        tracks: self.selected_tracks(),
+        ..self.options.clone()
+      },
+      output_path: self.output_path.clone(),
+    });
+  }
+
+  /// Starts working through the queue from the top.
+  fn start_queue(&mut self) {
+    self.queue_results.clear();
+    // Each recording in the queue is unpacked for itself
+    self.resources = None;
+    self.start_next_job();
+  }
+
+  /// Starts the next recording in the queue, returning `false` if there are none left.
+  fn start_next_job(&mut self) -> bool {
+    if self.queue.is_empty() {
+      return false;
+    }
+    let job = self.queue.remove(0);
+    self.spawn_run_for(
+      job.source.clone(),
+      job.output_path.clone(),
+      job.options.clone(),
+      None,
+      None,
+    );
+    self.active_job = Some(job);
+    true
+  }
+
+  /// Records how the queued recording that was running went, then moves on to the next one unless
+  /// the queue is done or has to stop.
+  fn finish_job(&mut self, ctx: &egui::Context, outcome: Result<ProcessReport, ProcessError>) {
+    self.progress_rx = None;
+    self.progress = None;
+    self.cancel = None;
+    self.task = None;
+    let Some(job) = self.active_job.take() else {
+      return;
+    };
+
+    // Cancelling, like closing the window, stops the whole queue
+    let cancelled = matches!(outcome, Err(ProcessError::Cancelled));
+    let stop = match &outcome {
+      Err(ProcessError::Cancelled) => true,
+      Err(_) => self.stop_queue_on_error,
+      Ok(_) => false,
+    };
+    let error = outcome.as_ref().err().map(|e| {
+      (
+        t!(
+          "Failed to process {name}: {error}",
+          name = job.name(),
+          error = e
+        ),
+        error_details(e),
+        ErrorAction::for_error(e),
+      )
+    });
+    self.queue_results.push(JobResult {
+      name: job.name(),
+      output_path: job.output_path,
+      outcome: outcome.map_err(|e| e.to_string()),
+    });
+    if !stop && self.start_next_job() {
+      return;
+    }
+
+    if !cancelled {
+      self.start_queue_hook();
+      self.notify_webhook(WebhookMessage::queue_finished(
+        self.queue_results.iter().map(|result| {
+          (
+            result.name.as_str(),
+            result.outcome.as_ref().map_err(String::as_str),
+          )
+        }),
+      ));
+    }
+
+    // What the last recording unpacked isn't the recording on screen
+    self.resources = None;
+    self.track_states.clear();
+    self.report = None;
+    match error.filter(|_| stop) {
+      Some((message, details, action)) => {
+        self.notify_if_unfocused(ctx, &message);
+        self.status = AppStatus::Error {
+          message,
+          details,
+          action,
+        };
+      }
+      None => {
+        let failed = self
+          .queue_results
+          .iter()
+          .filter(|result| result.outcome.is_err())
+          .count();
+        self.notify_if_unfocused(
+          ctx,
+          &match failed {
+            0 => t_n!(
+              self.queue_results.len(),
+              "Finished 1 recording",
+              "Finished {n} recordings"
+            ),
+            failed => t_n!(
+              self.queue_results.len(),
+              "Finished the queue, but {failed} of 1 recording failed",
+              "Finished the queue, but {failed} of {n} recordings failed",
+              failed = failed
+            ),
+          },
+        );
+        self.status = AppStatus::Done;
+      }
+    }
+    ctx.send_viewport_cmd(egui::viewport::ViewportCommand::RequestUserAttention(
+      egui::UserAttentionType::Critical,
+    ));
+  }
+
+  /// Lists the queued recordings with buttons to reorder and remove them, and to start the queue.
+  fn show_queue(&mut self, ui: &mut egui::Ui) {
+    if self.queue.is_empty() {
+      return;
+    }
+    let mut moved = None;
+    let mut removed = None;
+    egui::CollapsingHeader::new(t!("Queue ({count})", count = self.queue.len()))
+      .id_salt("queue")
+      .default_open(true)
+      .show(ui, |ui| {
+        egui::Grid::new("queue_list")
+          .num_columns(3)
+          .striped(true)
+          .show(ui, |ui| {
+            let last = self.queue.len() - 1;
+            for (i, job) in self.queue.iter().enumerate() {
+              ui.label(job.name());
+              ui.weak(job.output_path.display().to_string());
+              ui.horizontal(|ui| {
+                let name = job.name();
+                let up = ui.add_enabled(i > 0, egui::Button::new("⬆").small());
+                if with_name(
+                  up,
+                  egui::WidgetType::Button,
+                  &t!("Move {name} up", name = name),
+                )
+                .clicked()
+                {
+                  moved = Some((i, i - 1));
+                }
+                let down = ui.add_enabled(i < last, egui::Button::new("⬇").small());
+                if with_name(
+                  down,
+                  egui::WidgetType::Button,
+                  &t!("Move {name} down", name = name),
+                )
+                .clicked()
+                {
+                  moved = Some((i, i + 1));
+                }
+                let remove = ui.small_button("🗑");
+                if with_name(
+                  remove,
+                  egui::WidgetType::Button,
+                  &t!("Remove {name}", name = name),
+                )
+                .on_hover_text(t!("Remove"))
+                .clicked()
+                {
+                  removed = Some(i);
+                }
+              });
+              ui.end_row();
+            }
+          });
+        ui.checkbox(
+          &mut self.stop_queue_on_error,
+          t!("Stop the queue if a recording fails"),
+        );
+      });
+
+    if let Some((from, to)) = moved {
+      self.queue.swap(from, to);
+    }
+    if let Some(i) = removed {
+      self.queue.remove(i);
+    }
+    if !self.queue.is_empty()
+      && ui
+        .add_sized(
+          [ui.available_width(), 20.0],
+          egui::Button::new(t_n!(
+            self.queue.len(),
+            "Start the queue (1 recording)",
+            "Start the queue ({n} recordings)"
+          )),
+        )
+        .clicked()
+    {
+      self.start_queue();
+    }
+  }
+
+  /// Switches to showing the progress of a new run, returning the channel it reports over and the
+  /// token that cancels it.
+  fn begin_task(
+    &mut self,
+    options: Option<ProcessOptions>,
+  ) -> (mpsc::UnboundedSender<RunEvent>, CancellationToken) {
+    let (progress_tx, progress_rx) = mpsc::unbounded_channel();
+    self.progress_rx = Some(progress_rx);
+    self.status = AppStatus::Processing;
+    self.eta.reset();
+    self.failed_tracks = 0;
+    // Another run into the same folder, like a retry, is expected to find this one's files there
+    self.confirmed_output = Some(self.output_path.clone());
+    self.track_states.clear();
+    self.retry_excluded.clear();
+    self.report = None;
+    self.last_options = options;
+    self.extracting_tracks = false;
+    self.log.clear();
+    self.save_settings();
+
+    let cancel = CancellationToken::new();
+    self.cancel = Some(cancel.clone());
+    (progress_tx, cancel)
+  }
+}
+
+/// What led to an error and what ffmpeg said about it, for the error screen's details.
+fn error_details(error: &ProcessError) -> Option<String> {
+  let mut sections = Vec::new();
+  let causes = error.causes();
+  if !causes.is_empty() {
+    let causes: Vec<String> = causes.iter().map(|cause| format!("  {cause}")).collect();
+    sections.push(t!("Caused by:\n{causes}", causes = causes.join("\n")));
+  }
+  if let Some(log) = error.ffmpeg_log() {
+    sections.push(t!("ffmpeg output:\n{log}", log = log));
+  }
+  (!sections.is_empty()).then(|| sections.join("\n\n"))
+}
+
+fn writable_error_message(error: &std::io::Error) -> String {
+  match error.kind() {
+    std::io::ErrorKind::PermissionDenied => {
+      t!("You don't have permission to write to this folder — choose a different output folder")
+        .to_string()
+    }
+    std::io::ErrorKind::ReadOnlyFilesystem => {
+      t!("This folder is on a read-only drive — choose a different output folder").to_string()
+    }
+    _ => t!(
+      "Can't write to this folder ({error}) — choose a different output folder",
+      error = error
+    ),
+  }
+}
+
+/// Works out which recording the files dropped onto the window refer to.
+fn dropped_source(files: &[egui::DroppedFile]) -> Result<PackSource, String> {
+  let [file] = files else {
+    return Err(t!("Drop a single recording ZIP or folder").to_string());
+  };
+  let Some(path) = &file.path else {
+    return Err(t!("The dropped file couldn't be opened").to_string());
+  };
+
+  if path.is_dir() {
+    let has_tracks = std::fs::read_dir(path).is_ok_and(|entries| {
+      entries.flatten().any(|entry| {
+        entry
+          .path()
+          .extension()
+          .is_some_and(|ext| ext.eq_ignore_ascii_case("flac"))
+      })
+    });
+    if !has_tracks {
+      return Err(t!(
+        "There are no FLAC tracks in {folder}",
+        folder = path.display()
+      ));
+    }
+    Ok(PackSource::UserFolder(path.clone()))
+  } else if path
+    .extension()
+    .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+  {
+    Ok(PackSource::UserZip(path.clone()))
+  } else {
+    Err(t!("Only recording ZIPs and folders of FLAC tracks can be opened").to_string())
+  }
+}
+
+/// Dims the window and says what dropping will do while files are dragged over it.
+fn show_drop_overlay(ctx: &egui::Context) {
+  if ctx.input(|i| i.raw.hovered_files.is_empty()) {
+    return;
+  }
+
+  let painter = ctx.layer_painter(egui::LayerId::new(
+    egui::Order::Foreground,
+    egui::Id::new("drop_overlay"),
+  ));
+  let screen = ctx.screen_rect();
+  painter.rect_filled(screen, 0.0, egui::Color32::from_black_alpha(192));
+  painter.rect_stroke(
+    screen.shrink(8.0),
+    8.0,
+    egui::Stroke::new(2.0, ctx.style().visuals.selection.stroke.color),
+    egui::StrokeKind::Inside,
+  );
+  painter.text(
+    screen.center(),
+    egui::Align2::CENTER_CENTER,
+    t!("Drop a recording ZIP or folder to convert it"),
+    egui::TextStyle::Heading.resolve(&ctx.style()),
+    egui::Color32::WHITE,
+  );
+}
+
+/// What a typed-in output path looks like before a run is started.
+#[derive(Debug, Clone, PartialEq)]
+enum OutputPathCheck {
+  Fine,
+  /// Usable, with something worth knowing about it.
+  Note(String),
+  /// Usable, but maybe not what was meant.
+  Warning(String),
+  /// Can't be used, so nothing can be started.
+  Error(String),
+}
+
+/// Checks a typed-in output path for mistakes, going by no more than the metadata of what's on
+/// disk. Whether files can really be written there is only found out once a run starts.
+///
+/// `resource_dir` is where the recording was unpacked, if it has been.
+fn check_output_path(
+  path: &std::path::Path,
+  resource_dir: Option<&std::path::Path>,
+) -> OutputPathCheck {
+  if path.as_os_str().is_empty() {
+    return OutputPathCheck::Error(t!("Choose an output folder").to_string());
+  }
+  if let Err(e) = crate::check_output_location(path, resource_dir) {
+    return OutputPathCheck::Error(e.to_string());
+  }
+
+  let existing = path
+    .ancestors()
+    .find(|ancestor| !ancestor.as_os_str().is_empty() && std::fs::metadata(ancestor).is_ok());
+  let metadata = existing.and_then(|existing| std::fs::metadata(existing).ok());
+  if let Some(metadata) = &metadata {
+    if !metadata.is_dir() {
+      return OutputPathCheck::Error(if existing == Some(path) {
+        t!("That's a file, not a folder").to_string()
+      } else {
+        t!(
+          "{path} is a file, not a folder",
+          path = existing.unwrap_or(path).display()
+        )
+      });
+    }
+    // Windows folders often have the read-only attribute without it meaning anything
+    if cfg!(unix) && metadata.permissions().readonly() {
+      return OutputPathCheck::Error(t!("That folder is read-only").to_string());
+    }
+  }
+
+  if !path.is_absolute() {
+    let base = std::env::current_dir().unwrap_or_default();
+    return OutputPathCheck::Warning(t!(
+      "This isn't a full path, so it's taken to be inside {folder}",
+      folder = base.display()
+    ));
+  }
+  if existing != Some(path) {
+    return OutputPathCheck::Note(t!("The folder will be created").to_string());
+  }
+  OutputPathCheck::Fine
+}
+
+/// Renders the heading and progress bar for the current processing stage.
+fn show_progress(ui: &mut egui::Ui, progress: Option<&ProcessProgress>, eta: &EtaEstimator) {
+  match progress {
+    Some(ProcessProgress::Extracting {
+      file,
+      bytes_done,
+      bytes_total,
+    }) => {
+      status_heading(ui, t!("Unpacking files..."));
+      ui.add_space(8.0);
+      let label = ui.label(t!(
+        "{file}: {done} of {total}",
+        file = file,
+        done = format_bytes(*bytes_done),
+        total = format_bytes(*bytes_total)
+      ));
+      let progress = if *bytes_total == 0 {
+        0.0
+      } else {
+        *bytes_done as f32 / *bytes_total as f32
+      };
+      ui.add(egui::ProgressBar::new(progress).show_percentage())
+        .labelled_by(label.id);
+    }
+    Some(ProcessProgress::Probing { current, total }) => {
+      status_heading(ui, t!("Reading tracks..."));
+      ui.add_space(8.0);
+      let label = ui.label(t!(
+        "Checking track {current} of {total}",
+        current = current + 1,
+        total = total
+      ));
+      ui.add(egui::ProgressBar::new(*current as f32 / (*total).max(1) as f32).show_percentage())
+        .labelled_by(label.id);
+    }
+    Some(ProcessProgress::Converting(info)) => {
+      status_heading(ui, t!("Processing files..."));
+      ui.add_space(8.0);
+      let label = ui.label(t!(
+        "Converting file {current} of {total}: {file}",
+        current = info.current + 1,
+        total = info.total,
+        file = info.filename
+      ));
+      // Files already done plus however far ffmpeg is through the current one
+      let progress =
+        (info.current as f32 + info.file_fraction.unwrap_or(0.0)) / (info.total.max(1) as f32);
+      ui.add(
+        egui::ProgressBar::new(progress)
+          .show_percentage()
+          .animate(true),
+      )
+      .labelled_by(label.id);
+      if let Some(eta) = eta.eta() {
+        ui.label(format_eta(eta));
+      }
+    }
+    Some(ProcessProgress::Packaging) => {
+      status_heading(ui, t!("Finishing up..."));
+    }
+    Some(ProcessProgress::WritingProject) => {
+      status_heading(ui, t!("Writing Audacity project..."));
+    }
+    Some(_) | None => {
+      status_heading(ui, t!("Unpacking files..."));
+    }
+  }
+}
 
-use crate::{
-  AudioFormat, PackSource, ProcessProgress, ProgressInfo, process_files, setup_resources,
-};
+/// The window title for a run that's going, done or failed, or the plain app name otherwise.
+fn window_title(status: &AppStatus, progress: Option<&ProcessProgress>) -> String {
+  match (status, progress) {
+    (AppStatus::Processing, Some(ProcessProgress::Converting(info))) => {
+      let fraction =
+        (info.current as f32 + info.file_fraction.unwrap_or(0.0)) / (info.total.max(1) as f32);
+      t!(
+        "{percent}% — {title}",
+        percent = (fraction * 100.0).floor(),
+        title = APP_TITLE
+      )
+    }
+    (AppStatus::Processing, _) => t!("Processing — {title}", title = APP_TITLE),
+    (AppStatus::Done, _) => t!("Done — {title}", title = APP_TITLE),
+    (AppStatus::Error { .. }, _) => t!("Error — {title}", title = APP_TITLE),
+    _ => APP_TITLE.to_string(),
+  }
+}
 
-#[derive(PartialEq)]
-enum AppStatus {
-  Ready,
-  Processing,
-  Error(String),
-  Done,
+/// Sums up how a run went in a line, for the notification once it's done.
+fn completion_message(
+  report: &ProcessReport,
+  options: Option<&ProcessOptions>,
+  extracting: bool,
+) -> String {
+  let done = report.converted.len() + report.skipped.len();
+  if options.is_some_and(|options| options.mix) && !extracting {
+    return t!("Finished mixing the tracks").to_string();
+  }
+  match (report.failed.len(), extracting) {
+    (0, true) => t_n!(
+      done,
+      "Finished extracting 1 track",
+      "Finished extracting {n} tracks"
+    ),
+    (0, false) => t_n!(
+      done,
+      "Finished converting 1 track",
+      "Finished converting {n} tracks"
+    ),
+    (failed, _) => t_n!(
+      report.total(),
+      "Finished, but {failed} of 1 track failed",
+      "Finished, but {failed} of {n} tracks failed",
+      failed = failed
+    ),
+  }
 }
 
-pub enum AppProgress {
-  Process(ProcessProgress),
+/// Renders where each track is in a run, with how far along the one being converted is.
+///
+/// Once the run is done, failed tracks can be ticked and unticked for retrying through
+/// `retry_excluded`, and the outputs in its `report` opened.
+fn show_track_states(
+  ui: &mut egui::Ui,
+  states: &[(String, TrackState)],
+  progress: Option<&ProcessProgress>,
+  mut retry_excluded: Option<&mut HashSet<String>>,
+  report: Option<&ProcessReport>,
+) {
+  if states.is_empty() {
+    return;
+  }
+  let converting = match progress {
+    Some(ProcessProgress::Converting(info)) => Some(info),
+    _ => None,
+  };
+
+  egui::CollapsingHeader::new(t!("Tracks"))
+    .id_salt("track_states")
+    .default_open(true)
+    .show(ui, |ui| {
+      egui::ScrollArea::vertical()
+        .id_salt("track_states")
+        .max_height(150.0)
+        .show(ui, |ui| {
+          egui::Grid::new("track_states")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+              let error_color = ui.visuals().error_fg_color;
+              for (name, state) in states {
+                match state {
+                  TrackState::Queued => {
+                    let icon = ui.weak("⏳");
+                    with_name(icon, egui::WidgetType::Label, t!("Queued"))
+                      .on_hover_text(t!("Waiting for the tracks before it"));
+                  }
+                  TrackState::Started => {
+                    ui.spinner();
+                  }
+                  TrackState::Finished { .. } => {
+                    let icon = ui.label("✔");
+                    with_name(icon, egui::WidgetType::Label, t!("Converted"))
+                      .on_hover_text(t!("Converted"));
+                  }
+                  TrackState::Skipped => {
+                    let icon = ui.weak("✔");
+                    with_name(icon, egui::WidgetType::Label, t!("Already done"))
+                      .on_hover_text(t!("Already converted, so it was left alone"));
+                  }
+                  TrackState::Failed { error } => {
+                    let icon = ui.colored_label(error_color, "❌");
+                    with_name(icon, egui::WidgetType::Label, t!("Failed")).on_hover_text(error);
+                  }
+                }
+
+                match (state, retry_excluded.as_deref_mut()) {
+                  (TrackState::Failed { .. }, Some(excluded)) => {
+                    let mut retry = !excluded.contains(name);
+                    if ui
+                      .checkbox(&mut retry, egui::RichText::new(name).color(error_color))
+                      .on_hover_text(t!("Try this track again with \"Retry failed\""))
+                      .changed()
+                    {
+                      if retry {
+                        excluded.remove(name);
+                      } else {
+                        excluded.insert(name.clone());
+                      }
+                    }
+                  }
+                  (TrackState::Failed { .. }, None) => {
+                    ui.colored_label(error_color, name);
+                  }
+                  _ => {
+                    ui.label(name);
+                  }
+                }
+
+                match state {
+                  TrackState::Queued => {
+                    ui.label("");
+                  }
+                  TrackState::Started => {
+                    let fraction = converting
+                      .filter(|info| info.filename == *name)
+                      .and_then(|info| info.file_fraction);
+                    ui.label(
+                      fraction
+                        .map(|fraction| format!("{:.0}%", fraction * 100.0))
+                        .unwrap_or_default(),
+                    );
+                  }
+                  TrackState::Finished { output_size } => {
+                    ui.label(format_bytes(*output_size));
+                  }
+                  TrackState::Skipped => {
+                    ui.weak(t!("Already done"));
+                  }
+                  TrackState::Failed { .. } => {
+                    ui.colored_label(error_color, t!("Failed"));
+                  }
+                }
+
+                if let Some(output) = report.and_then(|report| report.output(name))
+                  && ui.small_button(t!("Open")).clicked()
+                {
+                  open_or_reveal(output);
+                }
+                ui.end_row();
+              }
+            });
+        });
+    });
 }
 
-pub struct TemplateApp {
-  status: AppStatus,
-  output_path: PathBuf,
-  runtime: tokio::runtime::Handle,
-  source: Option<PackSource>,
-  progress_rx: Option<mpsc::UnboundedReceiver<AppProgress>>,
-  progress_info: Option<ProgressInfo>,
-  selected_format: AudioFormat,
-  dynaudnorm: bool,
-  mix: bool,
+/// Names a widget whose text doesn't say what it is, like an icon, for screen readers.
+fn with_name(response: egui::Response, typ: egui::WidgetType, name: &str) -> egui::Response {
+  response.widget_info(|| egui::WidgetInfo::labeled(typ, response.enabled(), name));
+  response
 }
 
-impl Default for TemplateApp {
-  fn default() -> Self {
-    let runtime = tokio::runtime::Handle::current();
-    let mut app = Self {
-      status: AppStatus::Ready,
-      runtime,
-      source: None,
-      progress_rx: None,
-      progress_info: None,
-      output_path: {
-        let folder = if cfg!(debug_assertions) {
-          "out".to_string()
-        } else {
-          std::env::current_exe()
-            .ok()
-            .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()))
-            .unwrap_or_else(|| "otterpack-out".to_string())
-        };
-        std::env::current_dir().unwrap_or_default().join(folder)
-      },
-      selected_format: AudioFormat::FLAC,
-      dynaudnorm: false,
-      mix: false,
-    };
+/// Names a checkbox that has no text next to it for screen readers, along with whether it's ticked.
+fn with_state(response: egui::Response, selected: bool, name: &str) -> egui::Response {
+  response.widget_info(|| {
+    egui::WidgetInfo::selected(
+      egui::WidgetType::Checkbox,
+      response.enabled(),
+      selected,
+      name,
+    )
+  });
+  response
+}
 
-    match crate::self_extract::find_pack_source() {
-      Ok(source) => {
-        app.source = Some(source);
-      }
-      Err(e) => {
-        app.status = AppStatus::Error(format!("Failed to setup resources: {}", e));
-      }
-    }
+/// Shows the heading saying where a run is at. Screen readers announce it as it changes, so
+/// hearing that a run started or finished doesn't depend on going looking for it.
+fn status_heading(ui: &mut egui::Ui, text: impl Into<egui::RichText>) -> egui::Response {
+  let response = ui.heading(text);
+  announce(ui, &response, egui::accesskit::Live::Polite);
+  response
+}
 
-    app
+/// Makes `response` a live region, read out by screen readers whenever its text changes.
+fn announce(ui: &egui::Ui, response: &egui::Response, live: egui::accesskit::Live) {
+  ui.ctx()
+    .accesskit_node_builder(response.id, |node| node.set_live(live));
+}
+
+/// Opens a file in whatever it's associated with, or shows it in the file manager if nothing is.
+fn open_or_reveal(path: &std::path::Path) {
+  if let Err(e) = opener::open(path) {
+    println!(
+      "Failed to open {}, showing it instead: {}",
+      path.display(),
+      e
+    );
+    if let Err(e) = opener::reveal(path) {
+      println!("Failed to show {}: {}", path.display(), e);
+    }
   }
 }
 
-impl TemplateApp {
-  /// Called once before the first frame.
-  pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-    // This is also where you can customize the look and feel of egui using
-    // `cc.egui_ctx.set_visuals` and `cc.egui_ctx.set_fonts`.
+/// Switches the window to `theme`, leaving egui to follow the system's light or dark mode for
+/// [`Theme::System`].
+fn apply_theme(ctx: &egui::Context, theme: Theme) {
+  ctx.set_visuals_of(egui::Theme::Dark, accented(egui::Visuals::dark()));
+  ctx.set_visuals_of(egui::Theme::Light, accented(egui::Visuals::light()));
+  ctx.set_theme(match theme {
+    Theme::System => egui::ThemePreference::System,
+    Theme::Dark => egui::ThemePreference::Dark,
+    Theme::Light => egui::ThemePreference::Light,
+  });
+}
+
+/// Draws everything `scale` times bigger on top of the system's scaling, growing the smallest the
+/// window can get along with it so nothing gets cut off.
+fn apply_ui_scale(ctx: &egui::Context, scale: f32) {
+  ctx.set_zoom_factor(scale);
+  ctx.send_viewport_cmd(egui::ViewportCommand::MinInnerSize(MIN_WINDOW_SIZE * scale));
+}
+
+/// A button filled with the accent colour, for the one that moves things along.
+fn primary_button<'a>(ui: &egui::Ui, text: &'a str) -> egui::Button<'a> {
+  egui::Button::new(egui::RichText::new(text).color(ui.visuals().selection.stroke.color))
+    .fill(ui.visuals().selection.bg_fill)
+}
+
+/// Gives `visuals` an accent that progress bars and the Go button stay readable on.
+///
+/// The stock selection colour is too faint on the light theme to tell a progress bar's fill from
+/// its background.
+fn accented(mut visuals: egui::Visuals) -> egui::Visuals {
+  visuals.selection.bg_fill = if visuals.dark_mode {
+    egui::Color32::from_rgb(0x1e, 0x6a, 0xb0)
+  } else {
+    egui::Color32::from_rgb(0x3b, 0x82, 0xd6)
+  };
+  visuals.selection.stroke.color = egui::Color32::WHITE;
+  visuals
+}
+
+/// Renders how each recording in the queue went.
+fn show_queue_results(ui: &mut egui::Ui, results: &[JobResult]) {
+  status_heading(ui, t!("Finished the queue"));
+  ui.add_space(4.0);
+  egui::ScrollArea::vertical()
+    .id_salt("queue_results")
+    .max_height(160.0)
+    .show(ui, |ui| {
+      egui::Grid::new("queue_results")
+        .num_columns(4)
+        .striped(true)
+        .show(ui, |ui| {
+          for result in results {
+            match &result.outcome {
+              Ok(report) => {
+                with_name(ui.label("✔"), egui::WidgetType::Label, t!("Converted"));
+                ui.label(&result.name);
+                ui.label(
+                  match (
+                    report.converted.len() + report.skipped.len(),
+                    report.failed.len(),
+                  ) {
+                    (done, 0) => t_n!(done, "1 track", "{n} tracks"),
+                    (done, failed) => t_n!(
+                      done,
+                      "1 track, {failed} failed",
+                      "{n} tracks, {failed} failed",
+                      failed = failed
+                    ),
+                  },
+                );
+              }
+              Err(e) => {
+                let icon = ui.colored_label(ui.visuals().error_fg_color, "❌");
+                with_name(icon, egui::WidgetType::Label, t!("Failed"));
+                ui.label(&result.name);
+                ui.colored_label(ui.visuals().error_fg_color, e);
+              }
+            }
+            if ui.small_button(t!("Open folder")).clicked() {
+              let _ = opener::reveal(&result.output_path);
+            }
+            ui.end_row();
+          }
+        });
+    });
+}
+
+/// Renders the summary of a run where some tracks failed, with each failure's log expandable.
+fn show_failures(ui: &mut egui::Ui, report: &ProcessReport) {
+  status_heading(ui, t!("Finished, but some tracks failed"));
+  ui.add_space(4.0);
+  ui.label(t_n!(
+    report.total(),
+    "{done} of 1 track converted, {failed} failed",
+    "{done} of {n} tracks converted, {failed} failed",
+    done = report.converted.len() + report.skipped.len(),
+    failed = report.failed.len()
+  ));
+  ui.add_space(4.0);
+
+  egui::ScrollArea::vertical()
+    .max_height(160.0)
+    .show(ui, |ui| {
+      for failure in &report.failed {
+        egui::CollapsingHeader::new(&failure.filename).show(ui, |ui| {
+          ui.colored_label(ui.visuals().error_fg_color, &failure.message);
+          if let Some(stderr) = &failure.stderr {
+            ui.label(egui::RichText::new(stderr).monospace().small());
+          }
+        });
+      }
+    });
+}
 
-    Default::default()
+/// Formats the time left coarsely so it doesn't jitter between frames.
+/// Shows what ffmpeg logged in a collapsed section, with a button to copy all of it.
+fn show_log(ui: &mut egui::Ui, log: &LogBuffer) {
+  if log.is_empty() {
+    return;
+  }
+  egui::CollapsingHeader::new(t!("Log")).show(ui, |ui| {
+    let row_height = ui.text_style_height(&egui::TextStyle::Small);
+    egui::ScrollArea::both()
+      .max_height(150.0)
+      .stick_to_bottom(true)
+      .auto_shrink([false, true])
+      .show_rows(ui, row_height, log.len(), |ui, rows| {
+        for line in rows.filter_map(|row| log.get(row)) {
+          ui.add(egui::Label::new(egui::RichText::new(line).monospace().small()).extend());
+        }
+      });
+    if ui.button(t!("Copy log")).clicked() {
+      ui.ctx().copy_text(log.text());
+    }
+  });
+}
+
+fn format_eta(eta: std::time::Duration) -> String {
+  let secs = eta.as_secs();
+  if secs < 60 {
+    // Round up to the next 5 seconds
+    let secs = (secs.div_ceil(5) * 5).max(5);
+    t!("About {secs} seconds left", secs = secs)
+  } else {
+    t_n!(
+      secs.div_ceil(60),
+      "About a minute left",
+      "About {n} minutes left"
+    )
   }
 }
 
 impl eframe::App for TemplateApp {
   fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    self.poll_pack_source(ctx);
+    self.poll_tracks(ctx);
+    self.poll_durations(ctx);
+    self.poll_preview(ctx);
+    self.poll_hook(ctx);
+    self.poll_webhook_test(ctx);
+    self.handle_dropped_files(ctx);
+    self.handle_shortcuts(ctx);
+    self.handle_close(ctx);
+    // Going back to the options or cancelling doesn't come with a progress event
+    self.sync_title(ctx);
+    self.sync_keep_awake();
+    // Previews would play over a run, and use the same unpacked files
+    if self.status != AppStatus::Ready {
+      self.preview = None;
+    }
+    if self.status == AppStatus::Ready {
+      show_drop_overlay(ctx);
+    }
+    if self.status == AppStatus::NeedsPassword {
+      self.show_password_prompt(ctx);
+    }
+    self.show_output_prompt(ctx);
+    self.show_preset_manager(ctx);
+
     egui::CentralPanel::default().show(ctx, |ui| {
-      if let AppStatus::Error(error) = &self.status {
-        // Show error message at the top if there is one
-        ui.colored_label(egui::Color32::RED, error);
-        ui.add_space(32.0);
+      if let AppStatus::Error {
+        message,
+        details,
+        action,
+      } = &self.status
+      {
+        let action = *action;
+        let mut chosen = None;
+        let mut back = false;
+
+        // Show error message at the top if there is one, read out straight away
+        let heading = ui.colored_label(egui::Color32::RED, message);
+        announce(ui, &heading, egui::accesskit::Live::Assertive);
 
-        if ui.button("Close").clicked() {
-          ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        if let Some(details) = details {
+          ui.add_space(8.0);
+          egui::CollapsingHeader::new(t!("Details")).show(ui, |ui| {
+            egui::ScrollArea::vertical()
+              .max_height(120.0)
+              .stick_to_bottom(true)
+              .show(ui, |ui| {
+                ui.label(egui::RichText::new(details).monospace().small());
+              });
+          });
         }
-      } else {
-        ui.vertical(|ui| {
-          if self.status != AppStatus::Ready {
-            ui.disable();
+        show_log(ui, &self.log);
+
+        ui.add_space(32.0);
+
+        ui.horizontal(|ui| {
+          if let Some(action) = action
+            && ui.button(action.label()).clicked()
+          {
+            chosen = Some(action);
+          }
+          // Most errors can be fixed from the options, like a bad output folder
+          if ui
+            .button(t!("Back"))
+            .on_hover_text(t!("Go back to the options"))
+            .clicked()
+          {
+            back = true;
+          }
+          if ui.button(t!("Copy details")).clicked() {
+            ctx.copy_text(match details {
+              Some(details) => format!("{message}\n\n{details}"),
+              None => message.clone(),
+            });
+          }
+          if ui
+            .button(t!("Close"))
+            .on_hover_text(t!(
+              "Close the window ({shortcut})",
+              shortcut = ui.ctx().format_shortcut(&CLOSE_SHORTCUT)
+            ))
+            .clicked()
+          {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
           }
+        });
 
-          ui.horizontal(|ui| {
-            ui.label("Output folder:");
-            let mut path_string = self.output_path.to_string_lossy().to_string();
-            let output_field = ui.text_edit_singleline(&mut path_string);
-            if ui.button("📁 Browse...").clicked() {
-              if let Some(path) = rfd::FileDialog::new()
-                .set_directory(&self.output_path)
-                .pick_folder()
-              {
-                self.output_path = path;
-              }
+        if back {
+          self.status = AppStatus::Ready;
+        }
+
+        match chosen {
+          Some(ErrorAction::ChooseFolder) => {
+            if let Some(path) = rfd::FileDialog::new()
+              .set_directory(&self.output_path)
+              .pick_folder()
+            {
+              self.output_path = path;
+              self.output_error = None;
+              self.status = AppStatus::Ready;
             }
-            // Update PathBuf if text was manually edited
-            if output_field.changed() {
-              self.output_path = PathBuf::from(&path_string);
+          }
+          Some(ErrorAction::ChooseWorkDir) => {
+            if let Some(path) = rfd::FileDialog::new()
+              .set_title(t!("Choose a folder to unpack the recording into"))
+              .pick_folder()
+            {
+              self.options.work_dir = Some(path);
+              self.resources = None;
+              self.status = AppStatus::Ready;
+              self.start_processing();
             }
-            output_field.on_hover_text("The folder where extracted files will be saved");
-          });
-
+          }
+          Some(ErrorAction::Retry) => {
+            // If the folder has become unusable this lands back on the options with the reason
+            self.status = AppStatus::Ready;
+            if self.extracting_tracks {
+              self.start_extracting_tracks();
+            } else {
+              self.start_processing();
+            }
+          }
+          None => {}
+        }
+      } else {
+        ui.add_enabled_ui(self.status == AppStatus::Ready, |ui| {
           ui.horizontal(|ui| {
-            ui.label("Format:");
-            egui::ComboBox::from_id_salt("format_combo")
-              .selected_text(self.selected_format.display_name())
-              .width(ui.available_width())
-              .show_ui(ui, |ui| {
-                for format in AudioFormat::iter() {
-                  ui.selectable_value(&mut self.selected_format, format, format.display_name());
-                }
-              });
+            self.show_presets(ui);
+            self.show_layout_toggle(ui);
           });
-
-          ui.add_space(8.0);
-
-          ui.checkbox(&mut self.mix, "Mix into single track")
-            .on_hover_text("Mix all tracks into one file");
-
-          ui.checkbox(&mut self.dynaudnorm, "Automatically level volume")
-            .on_hover_text("Normalize audio volume using FFmpeg's dynaudnorm filter");
         });
+        // The options outgrow the window once the advanced ones are open
+        egui::ScrollArea::vertical()
+          .id_salt("options")
+          .max_height((ui.available_height() - 100.0).max(100.0))
+          .show(ui, |ui| {
+            if self.status != AppStatus::Ready {
+              ui.disable();
+            }
+
+            match self.layout {
+              OptionsLayout::Simple => {
+                self.show_source(ui);
+                self.show_tracks(ui);
+                self.show_output_folder(ui);
+                self.show_formats(ui);
+                ui.add_space(8.0);
+                self.show_processing_options(ui);
+                self.show_advanced_options(ui);
+              }
+              OptionsLayout::Guided => self.show_wizard_step(ui),
+            }
+          });
 
         ui.separator();
 
-        if self.status == AppStatus::Ready {
+        if self.status == AppStatus::Checking {
+          ui.horizontal(|ui| {
+            ui.spinner();
+            ui.label(t!("Checking package..."));
+          });
+        } else if self.status == AppStatus::Ready && self.source.is_none() {
+          ui.label(t!(
+            "No recording is packed with this program. Choose the ZIP downloaded from Craig to convert it."
+          ));
           if ui
-            .add_sized([ui.available_width(), 20.0], egui::Button::new("Go"))
+            .add_sized(
+              [ui.available_width(), 20.0],
+              egui::Button::new(t!("📁 Choose recording ZIP...")),
+            )
             .clicked()
           {
-            let (progress_tx, progress_rx) = mpsc::unbounded_channel();
-            self.progress_rx = Some(progress_rx);
-            self.status = AppStatus::Processing;
-
-            let output_path = self.output_path.clone();
-            let format = self.selected_format;
-            let use_dynaudnorm = self.dynaudnorm;
-            let mix = self.mix;
-
-            // Spawn the async task
-            self.runtime.spawn(async move {
-              let result = setup_resources().await;
-              match result {
-                Ok(resources) => {
-                  let result = process_files(
-                    resources.resource_path,
-                    output_path,
-                    format,
-                    use_dynaudnorm,
-                    mix,
-                    progress_tx.clone(),
-                  )
-                  .await;
-                  match result {
-                    Ok(_) => {
-                      let _ = progress_tx.send(AppProgress::Process(ProcessProgress::Finished));
-                    }
-                    Err(e) => {
-                      let _ = progress_tx.send(AppProgress::Process(ProcessProgress::Error(e)));
-                    }
-                  }
-                }
-                Err(e) => {
-                  let _ = progress_tx.send(AppProgress::Process(ProcessProgress::Error(e)));
-                }
-              }
-            });
+            self.choose_recording_zip();
+          }
+        } else if self.status == AppStatus::Ready
+          && self.layout == OptionsLayout::Guided
+          && self.wizard_step != WizardStep::Confirm
+        {
+          self.show_wizard_nav(ui);
+        } else if self.status == AppStatus::Ready {
+          let can_start = self.can_start();
+          if ui
+            .add_enabled_ui(can_start, |ui| {
+              ui.add_sized([ui.available_width(), 20.0], primary_button(ui, t!("Go")))
+              .on_hover_text(t!(
+                "Start converting ({shortcut})",
+                shortcut = ui.ctx().format_shortcut(&GO_SHORTCUT)
+              ))
+            })
+            .inner
+            .clicked()
+          {
+            self.start_processing();
           }
+          ui.horizontal(|ui| {
+            if self.layout == OptionsLayout::Guided
+              && ui
+                .button(t!("Back"))
+                .on_hover_text(t!("Go back to the previous step"))
+                .clicked()
+            {
+              self.wizard_step = self.wizard_step.next(WizardEvent::Back, true);
+            }
+            if ui.button(t!("📁 Choose recording ZIP...")).clicked() {
+              self.choose_recording_zip();
+            }
+            if ui
+              .add_enabled(can_start, egui::Button::new(t!("Just extract the original files")))
+              .on_hover_text(t!(
+                "Save the FLAC tracks into the output folder as they are, without converting them"
+              ))
+              .clicked()
+            {
+              self.start_extracting_tracks();
+            }
+            if ui
+              .add_enabled(can_start, egui::Button::new(t!("Add to queue")))
+              .on_hover_text(t!(
+                "Convert this recording later with these options, after the others in the queue"
+              ))
+              .clicked()
+            {
+              self.add_to_queue();
+            }
+          });
+          self.show_queue(ui);
         } else if self.status == AppStatus::Processing {
-          if let Some(info) = &self.progress_info {
-            ui.heading("Processing files...");
-            ui.add_space(8.0);
-            ui.label(format!(
-              "Converting file {} of {}: {}",
-              info.current + 1,
-              info.total,
-              info.filename
+          if let Some(job) = &self.active_job {
+            ui.label(t!(
+              "Recording {current} of {total}: {name}",
+              current = self.queue_results.len() + 1,
+              total = self.queue_results.len() + 1 + self.queue.len(),
+              name = job.name()
             ));
-            let progress = (info.current as f32) / (info.total as f32);
-            ui.add(
-              egui::ProgressBar::new(progress)
-                .show_percentage()
-                .animate(true),
+          }
+          show_progress(ui, self.progress.as_ref(), &self.eta);
+          if let Some(cancel) = &self.cancel {
+            // Closing the window can leave the run going, so it needs its own way to stop
+            if ui
+              .add_enabled(!cancel.is_cancelled(), egui::Button::new(t!("Cancel processing")))
+              .on_hover_text(t!("Stop the run. Tracks that are already done are kept"))
+              .clicked()
+            {
+              cancel.cancel();
+            }
+          }
+          if self.failed_tracks > 0 {
+            ui.colored_label(
+              ui.visuals().warn_fg_color,
+              t_n!(
+                self.failed_tracks,
+                "1 track couldn't be converted, carrying on with the rest",
+                "{n} tracks couldn't be converted, carrying on with the rest"
+              ),
             );
-          } else {
-            ui.heading("Unpacking files...");
           }
+          show_track_states(ui, &self.track_states, self.progress.as_ref(), None, None);
+          show_log(ui, &self.log);
 
           // Check for completion
+          let mut events = Vec::new();
           if let Some(rx) = &mut self.progress_rx {
-            if let Ok(AppProgress::Process(progress)) = rx.try_recv() {
-              match progress {
-                ProcessProgress::Error(e) => {
-                  self.progress_rx = None;
-                  self.progress_info = None;
-                  self.status = AppStatus::Error(format!("Failed to process: {}", e));
-                  ctx.send_viewport_cmd(egui::viewport::ViewportCommand::RequestUserAttention(
-                    egui::UserAttentionType::Critical,
-                  ));
-                }
-                ProcessProgress::Finished => {
-                  self.progress_rx = None;
-                  self.progress_info = None;
-                  self.status = AppStatus::Done;
-                  ctx.send_viewport_cmd(egui::viewport::ViewportCommand::RequestUserAttention(
-                    egui::UserAttentionType::Critical,
-                  ));
-                }
-                ProcessProgress::Processing(info) => {
-                  self.progress_info = Some(info);
-                }
-              }
+            while let Ok(event) = rx.try_recv() {
+              events.push(event);
             }
             ctx.request_repaint_after(std::time::Duration::from_millis(100));
           }
+
+          for event in events {
+            self.apply_progress(ctx, event);
+          }
         } else if self.status == AppStatus::Done {
-          ui.heading("Finished processing files!");
+          match &self.report {
+            None if !self.queue_results.is_empty() => show_queue_results(ui, &self.queue_results),
+            Some(report) if !report.failed.is_empty() => show_failures(ui, report),
+            Some(report) if self.extracting_tracks => {
+              status_heading(
+                ui,
+                t_n!(
+                report.converted.len(),
+                "Extracted 1 track!",
+                "Extracted {n} tracks!"
+              ));
+            }
+            _ => {
+              status_heading(ui, t!("Finished processing files!"));
+            }
+          }
+          show_track_states(
+            ui,
+            &self.track_states,
+            None,
+            Some(&mut self.retry_excluded),
+            self.report.as_ref(),
+          );
+          if let (Some(resources), Some(options)) = (&self.resources, &self.last_options)
+            && options.keep_extracted
+          {
+            ui.label(t!(
+              "The unpacked recording was kept in {folder}",
+              folder = resources.audio_dir.display()
+            ));
+          }
           ui.add_space(4.0);
           ui.horizontal(|ui| {
-            if ui.button("Open output folder").clicked() {
+            if let Some(report) = self.report.as_ref().filter(|r| !r.failed.is_empty()) {
+              let any_selected = report
+                .failed
+                .iter()
+                .any(|failure| !self.retry_excluded.contains(&failure.filename));
+              if ui
+                .add_enabled(any_selected, egui::Button::new(t!("Retry failed")))
+                .clicked()
+              {
+                self.retry_failed();
+              }
+            }
+            let mixed = self
+              .last_options
+              .as_ref()
+              .is_some_and(|options| options.mix && !self.extracting_tracks);
+            if let Some(report) = &self.report {
+              if let Some((_, mix)) = report.outputs.first().filter(|_| mixed)
+                && ui.button(t!("▶ Play mixed file")).clicked()
+              {
+                open_or_reveal(mix);
+              }
+              if let Some(project) = &report.project
+                && ui.button(t!("Open in Audacity")).clicked()
+              {
+                open_or_reveal(project);
+              }
+            }
+            if ui
+              .button(t!("Process again"))
+              .on_hover_text(t!(
+                "Go back to the options to convert this recording again, without unpacking it again"
+              ))
+              .clicked()
+            {
+              self.reset_for_new_run();
+            }
+            if ui.button(t!("Open output folder")).clicked() {
               let _ = opener::reveal(&self.output_path);
             }
-            if ui.button("Close").clicked() {
+            if ui
+              .button(t!("Close"))
+              .on_hover_text(t!(
+                "Close the window ({shortcut})",
+                shortcut = ui.ctx().format_shortcut(&CLOSE_SHORTCUT)
+              ))
+              .clicked()
+            {
               ctx.send_viewport_cmd(egui::ViewportCommand::Close);
             }
           });
+          show_log(ui, &self.log);
         }
       }
 
       ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
-        ui.horizontal(|ui| {
+        ui.horizontal_wrapped(|ui| {
           ui.spacing_mut().item_spacing.x = 0.0;
-          ui.label("Executable created with ");
+          ui.label(t!("Executable created with "));
           ui.hyperlink_to("Craig", "https://craig.chat");
-          ui.label(" using ");
+          ui.label(t!(" using "));
           ui.hyperlink_to("otterpack", "https://github.com/CraigChat/otterpack");
           ui.label(egui::RichText::new(format!(" ({})", env!("CARGO_PKG_VERSION"))).small());
           ui.label(".");
         });
+        if let (Some(resources), Some(info)) = (&self.resources, &self.ffmpeg_info) {
+          let message = if resources.system_ffmpeg {
+            t!(
+              "Using installed ffmpeg {version} at {path}",
+              version = info.version,
+              path = resources.ffmpeg_path.display()
+            )
+          } else {
+            t!(
+              "Using bundled ffmpeg {version} at {path}",
+              version = info.version,
+              path = resources.ffmpeg_path.display()
+            )
+          };
+          // The path can be long, and mustn't push the window wider at large UI scales
+          ui.add(egui::Label::new(egui::RichText::new(message).small().weak()).truncate());
+        }
         egui::warn_if_debug_build(ui);
       });
     });
   }
+
+  fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+    self.save_settings();
+    // Stop any running ffmpeg processes and give the task a moment to clean up the temp dir
+    if let Some(cancel) = self.cancel.take() {
+      cancel.cancel();
+    }
+    if let Some(task) = self.task.take() {
+      let _ = self
+        .runtime
+        .block_on(async { tokio::time::timeout(std::time::Duration::from_secs(5), task).await });
+    }
+    // Deletes the unpacked files, unless a task that didn't stop in time still holds them
+    self.resources = None;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use egui::accesskit::{Live, Node, Role};
+
+  use super::*;
+  use crate::ProgressInfo;
+
+  /// An app on the options screen without a recording, as if the pack search had come up empty.
+  fn app() -> TemplateApp {
+    TemplateApp {
+      status: AppStatus::Ready,
+      pack_rx: None,
+      ..Default::default()
+    }
+  }
+
+  /// Draws a frame of `app` and returns the AccessKit tree a screen reader would be given.
+  fn accesskit_tree(app: &mut TemplateApp) -> Vec<Node> {
+    let ctx = egui::Context::default();
+    ctx.enable_accesskit();
+    let mut frame = eframe::Frame::_new_kittest();
+    let output = ctx.run(egui::RawInput::default(), |ctx| {
+      eframe::App::update(app, ctx, &mut frame)
+    });
+    let update = output
+      .platform_output
+      .accesskit_update
+      .expect("AccessKit was enabled");
+    update.nodes.into_iter().map(|(_, node)| node).collect()
+  }
+
+  /// Whether a screen reader has something to read out for `node`.
+  fn is_named(node: &Node) -> bool {
+    node.label().is_some_and(|label| !label.trim().is_empty())
+      || node.value().is_some_and(|value| !value.trim().is_empty())
+      || !node.labelled_by().is_empty()
+  }
+
+  /// The widget called `text`, rather than the runs of text inside it.
+  fn find<'a>(tree: &'a [Node], text: &str) -> &'a Node {
+    tree
+      .iter()
+      .filter(|node| node.role() != Role::TextRun)
+      .find(|node| node.label() == Some(text) || node.value() == Some(text))
+      .unwrap_or_else(|| panic!("nothing in the tree is called {text:?}"))
+  }
+
+  /// Every control on `tree` that can be used, and so needs a name to be found by.
+  fn unnamed_controls(tree: &[Node]) -> Vec<&Node> {
+    tree
+      .iter()
+      .filter(|node| {
+        matches!(
+          node.role(),
+          Role::Button
+            | Role::CheckBox
+            | Role::ComboBox
+            | Role::TextInput
+            | Role::MultilineTextInput
+            | Role::SpinButton
+            | Role::Slider
+            | Role::RadioButton
+            | Role::ProgressIndicator
+            | Role::Link
+        )
+      })
+      .filter(|node| !is_named(node))
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn options_controls_have_names() {
+    let tree = accesskit_tree(&mut app());
+
+    assert_eq!(
+      unnamed_controls(&tree).len(),
+      0,
+      "{:#?}",
+      unnamed_controls(&tree)
+    );
+    assert_eq!(
+      find(&tree, t!("📁 Choose recording ZIP...")).role(),
+      Role::Button
+    );
+    assert_eq!(
+      find(&tree, t!("Choose the output folder")).role(),
+      Role::Button
+    );
+  }
+
+  #[tokio::test]
+  async fn run_status_is_announced() {
+    let mut app = app();
+    app.status = AppStatus::Processing;
+    app.progress = Some(ProcessProgress::Converting(ProgressInfo {
+      filename: "1-alice.flac".to_string(),
+      current: 0,
+      total: 2,
+      file_fraction: Some(0.5),
+      audio_done_secs: 30.0,
+      audio_total_secs: Some(120.0),
+    }));
+    let tree = accesskit_tree(&mut app);
+    assert_eq!(
+      unnamed_controls(&tree).len(),
+      0,
+      "{:#?}",
+      unnamed_controls(&tree)
+    );
+    assert_eq!(
+      find(&tree, t!("Processing files...")).live(),
+      Some(Live::Polite)
+    );
+    let bar = tree
+      .iter()
+      .find(|node| node.role() == Role::ProgressIndicator)
+      .expect("a progress bar");
+    assert!(!bar.labelled_by().is_empty());
+
+    app.status = AppStatus::Done;
+    let tree = accesskit_tree(&mut app);
+    assert_eq!(
+      find(&tree, t!("Finished processing files!")).live(),
+      Some(Live::Polite)
+    );
+
+    app.status = AppStatus::error("The output folder can't be written to".to_string());
+    let tree = accesskit_tree(&mut app);
+    assert_eq!(
+      find(&tree, "The output folder can't be written to").live(),
+      Some(Live::Assertive)
+    );
+  }
+
+  const STEPS: [WizardStep; WizardStep::COUNT] =
+    [WizardStep::Output, WizardStep::Tracks, WizardStep::Confirm];
+
+  #[test]
+  fn wizard_next_waits_for_usable_options() {
+    assert_eq!(
+      WizardStep::Output.next(WizardEvent::Next, true),
+      WizardStep::Tracks
+    );
+    assert_eq!(
+      WizardStep::Tracks.next(WizardEvent::Next, true),
+      WizardStep::Confirm
+    );
+    for step in STEPS {
+      assert_eq!(step.next(WizardEvent::Next, false), step, "{step:?}");
+    }
+  }
+
+  #[test]
+  fn wizard_back_goes_to_the_step_before() {
+    for can_continue in [true, false] {
+      assert_eq!(
+        WizardStep::Tracks.next(WizardEvent::Back, can_continue),
+        WizardStep::Output
+      );
+      assert_eq!(
+        WizardStep::Confirm.next(WizardEvent::Back, can_continue),
+        WizardStep::Tracks
+      );
+    }
+  }
+
+  #[test]
+  fn wizard_restarts_from_any_step() {
+    for step in STEPS {
+      for can_continue in [true, false] {
+        assert_eq!(
+          step.next(WizardEvent::Restart, can_continue),
+          WizardStep::Output,
+          "{step:?}"
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn wizard_stays_put_past_either_end() {
+    assert_eq!(
+      WizardStep::Output.next(WizardEvent::Back, true),
+      WizardStep::Output
+    );
+    assert_eq!(
+      WizardStep::Confirm.next(WizardEvent::Next, true),
+      WizardStep::Confirm
+    );
+    // The steps are numbered in the order Next goes through them
+    let numbers: Vec<usize> = STEPS.iter().map(WizardStep::number).collect();
+    assert_eq!(numbers, [1, 2, 3]);
+  }
+
+  #[tokio::test]
+  async fn new_run_forgets_the_last_one_but_not_the_recording() {
+    let mut app = app();
+    let source = PackSource::UserZip(PathBuf::from("craig-abc123.zip"));
+    let resources = Arc::new(ExtractedResources {
+      temp_dirs: Vec::new(),
+      work_dir: None,
+      audio_dir: PathBuf::from("unpacked"),
+      ffmpeg_path: PathBuf::from("unpacked/ffmpeg"),
+      system_ffmpeg: false,
+      ffprobe_path: None,
+      shared_ffmpeg: None,
+    });
+    let tracks = vec![PackEntry {
+      name: "1-alice.flac".to_string(),
+      size: 100,
+      compressed_size: 90,
+      duration: Some(60.0),
+    }];
+    let options = ProcessOptions {
+      mix: true,
+      format: AudioFormat::AAC,
+      ..ProcessOptions::default()
+    };
+    app.source = Some(source);
+    app.resources = Some(resources.clone());
+    app.tracks = Some(tracks.clone());
+    app.durations.insert("1-alice.flac".to_string(), Ok(60.0));
+    app.options = options.clone();
+    app.output_path = PathBuf::from("converted");
+    app.confirmed_output = Some(PathBuf::from("converted"));
+    app.layout = OptionsLayout::Guided;
+    app.wizard_step = WizardStep::Confirm;
+
+    // How the run went
+    app.status = AppStatus::Done;
+    app.progress = Some(ProcessProgress::Packaging);
+    app.failed_tracks = 1;
+    app.track_states = vec![(
+      "1-alice.flac".to_string(),
+      TrackState::Failed {
+        error: "ffmpeg crashed".to_string(),
+      },
+    )];
+    app.retry_excluded.insert("1-alice.flac".to_string());
+    app.report = Some(ProcessReport {
+      converted: vec!["1-alice.mp3".to_string()],
+      ..Default::default()
+    });
+    app.last_options = Some(options.clone());
+    app.extracting_tracks = true;
+    app.log.push("Converting 1-alice.flac".to_string());
+
+    app.reset_for_new_run();
+
+    assert!(matches!(app.status, AppStatus::Ready));
+    assert!(app.progress.is_none());
+    assert_eq!(app.failed_tracks, 0);
+    assert!(app.track_states.is_empty());
+    assert!(app.retry_excluded.is_empty());
+    assert!(app.report.is_none());
+    assert!(app.last_options.is_none());
+    assert!(!app.extracting_tracks);
+    assert!(app.log.is_empty());
+    assert_eq!(app.wizard_step, WizardStep::Output);
+
+    assert!(
+      matches!(&app.source, Some(PackSource::UserZip(path)) if path == Path::new("craig-abc123.zip"))
+    );
+    assert!(Arc::ptr_eq(app.resources.as_ref().unwrap(), &resources));
+    assert_eq!(app.tracks, Some(tracks));
+    assert_eq!(app.durations.get("1-alice.flac"), Some(&Ok(60.0)));
+    assert_eq!(app.options, options);
+    assert_eq!(app.output_path, PathBuf::from("converted"));
+    assert_eq!(app.confirmed_output, Some(PathBuf::from("converted")));
+  }
+
+  /// How many of the widgets in `tree` are buttons called `name`.
+  fn count_buttons(tree: &[Node], name: &str) -> usize {
+    tree
+      .iter()
+      .filter(|node| node.role() == Role::Button)
+      .filter(|node| node.label() == Some(name) || node.value() == Some(name))
+      .count()
+  }
+
+  #[tokio::test]
+  async fn builtin_presets_cant_be_deleted() {
+    let builtin = crate::settings::builtin_presets().len();
+    let mut app = app();
+    app.preset_manager = Some(PresetManager::default());
+
+    let tree = accesskit_tree(&mut app);
+    assert_eq!(count_buttons(&tree, t!("Duplicate")), builtin);
+    assert_eq!(count_buttons(&tree, t!("Delete")), 0);
+    assert_eq!(count_buttons(&tree, t!("Rename")), 0);
+
+    // Only what the user saved can go
+    app.presets.push(Preset {
+      name: "Stems".to_string(),
+      options: ProcessOptions::default(),
+    });
+    let tree = accesskit_tree(&mut app);
+    assert_eq!(count_buttons(&tree, t!("Duplicate")), builtin + 1);
+    assert_eq!(count_buttons(&tree, t!("Delete")), 1);
+  }
 }
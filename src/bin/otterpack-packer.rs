@@ -0,0 +1,41 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+use clap::Parser;
+use otterpack::{PackOptions, format_bytes, pack};
+use std::path::PathBuf;
+
+/// Builds a self-extracting otterpack executable out of a stub and a recording.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+  /// The otterpack executable to append the recording to
+  #[arg(long)]
+  stub: PathBuf,
+  /// Folder or ZIP holding the FLAC tracks and ffmpeg.exe
+  #[arg(long)]
+  input: PathBuf,
+  /// Where to write the packed executable
+  #[arg(long)]
+  output: PathBuf,
+  /// Pack without ffmpeg, for recordings that rely on a tools pack or an installed ffmpeg
+  #[arg(long)]
+  no_ffmpeg: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+  let args = Args::parse();
+  let summary = pack(&PackOptions {
+    stub: args.stub,
+    input: args.input,
+    output: args.output.clone(),
+    allow_missing_ffmpeg: args.no_ffmpeg,
+  })?;
+  println!(
+    "Packed {} files ({} tracks, {}) into {}",
+    summary.files,
+    summary.tracks,
+    format_bytes(summary.payload_size),
+    args.output.display()
+  );
+  Ok(())
+}
@@ -0,0 +1,578 @@
+use anyhow::Context;
+use serde::Serialize;
+use std::{
+  fs::File,
+  io::Write,
+  path::{Path, PathBuf},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+  CliExit, CompletionHook, JsonEvent, PackEntry, PackSource, ProcessError, ProcessOptions,
+  ProcessProgress, ProcessReport, RunEvent, RunSetup, RunStatus, SharedFfmpeg, TrackState,
+  format_bytes, format_track_length, run_completion_hook, write_temp_report,
+};
+
+/// A recording to convert from the command line, and where to.
+#[derive(Debug, Clone)]
+pub struct CliJob {
+  /// The recording as it was given, to name it by in messages.
+  pub input: String,
+  pub source: PackSource,
+  pub output_path: PathBuf,
+}
+
+/// A run started from the command line instead of the window.
+#[derive(Debug, Clone)]
+pub struct CliRun {
+  /// The recordings to convert. More than one makes it a batch, where each job starts with a
+  /// [`JsonEvent::Job`] and the run ends with a summary.
+  pub jobs: Vec<CliJob>,
+  pub options: ProcessOptions,
+  /// Print progress as [`JsonEvent`]s, one per line, instead of as text.
+  pub json: bool,
+  /// File to write the final [`ProcessReport`] into as JSON, or a list of [`JobResult`]s for a
+  /// batch.
+  pub report_path: Option<PathBuf>,
+  /// The output folder, which a batch's jobs are in subfolders of.
+  pub output_path: PathBuf,
+  /// Command to run once the run is done, unless it was cancelled.
+  pub on_complete: Option<CompletionHook>,
+}
+
+/// How one job of a [`CliRun`] went.
+#[derive(Debug, Serialize)]
+pub struct JobResult {
+  pub input: String,
+  pub output_path: PathBuf,
+  /// What happened to each track, unless the job failed as a whole.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub report: Option<ProcessReport>,
+  /// Why the job failed as a whole.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+  #[serde(skip)]
+  pub exit: CliExit,
+}
+
+/// Writes each event as a line of JSON.
+struct JsonPrinter {
+  out: File,
+}
+
+impl JsonPrinter {
+  fn print(&mut self, event: &JsonEvent<'_>) {
+    let result = serde_json::to_writer(&mut self.out, event)
+      .map_err(std::io::Error::from)
+      .and_then(|()| writeln!(self.out))
+      .and_then(|()| self.out.flush());
+    if let Err(e) = result {
+      eprintln!("Failed to write a progress event: {e}");
+    }
+  }
+}
+
+/// Prints the progress worth reading as it scrolls by, leaving out the rapid updates.
+#[derive(Default)]
+struct TextPrinter {
+  extracting: String,
+  /// The file and the tens of percent through it last printed, for outputs with no track events.
+  percent: Option<(String, u32)>,
+}
+
+impl TextPrinter {
+  fn print(&mut self, progress: &ProcessProgress) {
+    match progress {
+      ProcessProgress::Extracting { file, .. } if *file != self.extracting => {
+        println!("Unpacking {file}");
+        self.extracting = file.clone();
+      }
+      ProcessProgress::Converting(info) => {
+        let Some(fraction) = info.file_fraction else {
+          return;
+        };
+        let percent = (fraction * 100.0) as u32 / 10 * 10;
+        let progress = Some((info.filename.clone(), percent));
+        if progress != self.percent {
+          println!("Converting {} {percent}%", info.filename);
+          self.percent = progress;
+        }
+      }
+      ProcessProgress::Track { filename, state } => match state {
+        TrackState::Started => println!("Converting {filename}"),
+        TrackState::Finished { output_size } => {
+          println!("Converted {filename} ({})", format_bytes(*output_size))
+        }
+        TrackState::Skipped => println!("Skipped {filename}, it was already converted"),
+        TrackState::Failed { error } => println!("Failed to convert {filename}: {error}"),
+        TrackState::Queued => {}
+      },
+      ProcessProgress::Packaging => println!("Packaging"),
+      ProcessProgress::WritingProject => println!("Writing the Audacity project"),
+      _ => {}
+    }
+  }
+
+  fn finish(report: &ProcessReport) {
+    println!(
+      "Converted {}, skipped {} and failed {} of {} tracks",
+      report.converted.len(),
+      report.skipped.len(),
+      report.failed.len(),
+      report.total()
+    );
+    for failure in &report.failed {
+      println!("{}: {}", failure.filename, failure.message);
+    }
+  }
+}
+
+fn write_report(path: &Path, report: &(impl Serialize + ?Sized)) -> anyhow::Result<()> {
+  let file = File::create(path).context(format!("Failed to create {}", path.display()))?;
+  serde_json::to_writer_pretty(file, report)
+    .context(format!("Failed to write the report to {}", path.display()))
+}
+
+/// Lists the tracks in `source` without unpacking it, as a table or, with `json`, as a JSON object
+/// with a `tracks` array.
+///
+/// The lengths come from the pack's manifest. With `probe`, the ones it doesn't give are read from
+/// the start of each track, decrypting with `password` if needed.
+pub fn list_tracks(
+  source: &PackSource,
+  probe: bool,
+  password: Option<&str>,
+  json: bool,
+) -> anyhow::Result<Vec<PackEntry>> {
+  let mut tracks = crate::list_pack_entries(source).context("Failed to read the recording")?;
+  if probe {
+    let names: Vec<String> = tracks
+      .iter()
+      .filter(|track| track.duration.is_none())
+      .map(|track| track.name.clone())
+      .collect();
+    crate::read_track_durations(source, &names, password, |name, duration| match duration {
+      Ok(duration) => {
+        if let Some(track) = tracks.iter_mut().find(|track| track.name == name) {
+          track.duration = Some(duration);
+        }
+      }
+      Err(e) => eprintln!("Failed to read the length of {name}: {e:#}"),
+    })
+    .context("Failed to read the track lengths")?;
+  }
+
+  if json {
+    #[derive(Serialize)]
+    struct TrackList<'a> {
+      tracks: &'a [PackEntry],
+    }
+    println!("{}", serde_json::to_string(&TrackList { tracks: &tracks })?);
+    return Ok(tracks);
+  }
+  let width = tracks
+    .iter()
+    .map(|track| track.name.chars().count())
+    .chain(["Track".len()])
+    .max()
+    .unwrap_or_default();
+  println!("{:<width$}  {:>10}  {:>8}", "Track", "Size", "Length");
+  for track in &tracks {
+    let length = track.duration.map_or("-".to_string(), format_track_length);
+    println!(
+      "{:<width$}  {:>10}  {:>8}",
+      track.name,
+      format_bytes(track.size),
+      length
+    );
+  }
+  let total: u64 = tracks.iter().map(|track| track.size).sum();
+  println!("{} tracks, {}", tracks.len(), format_bytes(total));
+  Ok(tracks)
+}
+
+/// Turns the `--input` values into the recordings they name, expanding any that are glob patterns
+/// rather than existing paths, such as `recordings/*.zip`.
+///
+/// Every value has to name at least one recording. The same recording named twice is only
+/// converted once.
+pub fn expand_inputs(inputs: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+  let mut expanded = Vec::new();
+  for input in inputs {
+    let matches = if input.exists() {
+      vec![input.clone()]
+    } else {
+      let pattern = input
+        .to_str()
+        .context(format!("{} doesn't exist", input.display()))?;
+      let mut matches = glob::glob(pattern)
+        .context(format!("{pattern} isn't a valid pattern"))?
+        .collect::<Result<Vec<_>, _>>()?;
+      matches.sort();
+      matches
+    };
+    if matches.is_empty() {
+      anyhow::bail!("{} doesn't match any recordings", input.display());
+    }
+    for path in matches {
+      if !expanded.contains(&path) {
+        expanded.push(path);
+      }
+    }
+  }
+  Ok(expanded)
+}
+
+/// The subfolder of the output folder each of `inputs` is converted into in a batch.
+///
+/// That's the ZIP's name without the extension, or the folder's name. Names that clash, ignoring
+/// case so they can't end up in the same folder on Windows or macOS either, get `-2`, `-3` and so
+/// on added like [`crate::next_free_folder`] does, in the order the inputs are given.
+pub fn batch_folder_names(inputs: &[PathBuf]) -> Vec<String> {
+  let mut names: Vec<String> = Vec::with_capacity(inputs.len());
+  for input in inputs {
+    let base = match input.is_dir() {
+      true => input.file_name(),
+      false => input.file_stem(),
+    }
+    .map(|name| name.to_string_lossy().into_owned())
+    .filter(|name| !name.is_empty() && name != "..")
+    .unwrap_or_else(|| "recording".to_string());
+    let taken = |name: &str| names.iter().any(|other| other.eq_ignore_ascii_case(name));
+    let name = match taken(&base) {
+      false => base,
+      true => (2..)
+        .map(|number| format!("{base}-{number}"))
+        .find(|name| !taken(name))
+        .expect("there's always a free number"),
+    };
+    names.push(name);
+  }
+  names
+}
+
+/// Converts each job of `run` in turn without the window, printing the progress to stdout.
+///
+/// With `json`, stdout only gets [`JsonEvent`]s and everything else that would be printed goes
+/// to stderr instead. A job that fails doesn't stop the ones after it, but Ctrl+C cancels the
+/// whole run. Jobs after the first use the ffmpeg the first one found, unless their recording
+/// bundles its own.
+///
+/// The `on_complete` command runs last, and its output is printed like the rest. It failing is
+/// only reported, since the recordings were converted either way.
+pub async fn run_cli(run: CliRun) -> anyhow::Result<Vec<JobResult>> {
+  let CliRun {
+    jobs,
+    options,
+    json,
+    report_path,
+    output_path,
+    on_complete,
+  } = run;
+  let mut json = match json {
+    true => Some(JsonPrinter {
+      out: crate::platform::take_stdout().context("Failed to set up stdout for JSON")?,
+    }),
+    false => None,
+  };
+
+  let cancel = CancellationToken::new();
+  let on_interrupt = tokio::spawn({
+    let cancel = cancel.clone();
+    async move {
+      if tokio::signal::ctrl_c().await.is_ok() {
+        println!("Cancelling");
+        cancel.cancel();
+      }
+    }
+  });
+
+  let batch = jobs.len() > 1;
+  let total = jobs.len();
+  let mut shared_ffmpeg = None;
+  let mut results = Vec::with_capacity(total);
+  for (index, job) in jobs.into_iter().enumerate() {
+    if batch {
+      match &mut json {
+        Some(json) => json.print(&JsonEvent::Job {
+          input: &job.input,
+          output_path: &job.output_path,
+          current: index + 1,
+          total,
+        }),
+        None => println!(
+          "Recording {} of {total}: {} into {}",
+          index + 1,
+          job.input,
+          job.output_path.display()
+        ),
+      }
+    }
+    let result = run_job(
+      job.source,
+      job.output_path.clone(),
+      options.clone(),
+      &mut shared_ffmpeg,
+      &mut json,
+      cancel.clone(),
+    )
+    .await;
+    let result = match result {
+      Ok(report) => JobResult {
+        input: job.input,
+        output_path: job.output_path,
+        exit: CliExit::for_report(&report),
+        report: Some(report),
+        error: None,
+      },
+      Err(e) => {
+        eprintln!("Error: {e:#}");
+        JobResult {
+          input: job.input,
+          output_path: job.output_path,
+          exit: CliExit::for_anyhow(&e),
+          report: None,
+          error: Some(format!("{e:#}")),
+        }
+      }
+    };
+    results.push(result);
+    if cancel.is_cancelled() {
+      break;
+    }
+  }
+  on_interrupt.abort();
+
+  if batch {
+    let succeeded = results
+      .iter()
+      .filter(|result| result.report.is_some())
+      .count();
+    match &mut json {
+      Some(json) => json.print(&JsonEvent::BatchFinished {
+        succeeded,
+        failed: results.len() - succeeded,
+        not_started: total - results.len(),
+      }),
+      None => {
+        for result in &results {
+          match (&result.report, &result.error) {
+            (Some(report), _) => println!(
+              "{}: converted {}, skipped {} and failed {} of {} tracks",
+              result.input,
+              report.converted.len(),
+              report.skipped.len(),
+              report.failed.len(),
+              report.total()
+            ),
+            (None, error) => println!("{}: {}", result.input, error.as_deref().unwrap_or_default()),
+          }
+        }
+        println!("Converted {succeeded} of {total} recordings");
+      }
+    }
+  }
+  let report = match (batch, results.as_slice()) {
+    (
+      false,
+      [
+        JobResult {
+          report: Some(report),
+          ..
+        },
+      ],
+    ) => Some(serde_json::to_value(report)?),
+    (false, _) => None,
+    (true, results) => Some(serde_json::to_value(results)?),
+  };
+  if let (Some(path), Some(report)) = (&report_path, &report) {
+    write_report(path, report)?;
+  }
+
+  if let Some(hook) = on_complete.filter(|_| !cancel.is_cancelled()) {
+    let status = RunStatus::combine(results.iter().map(|result| {
+      result
+        .report
+        .as_ref()
+        .map_or(RunStatus::Failed, RunStatus::for_report)
+    }));
+    // Without --report the command still gets one, in a file that's gone once it's done
+    let temp_report = match (&report, &report_path) {
+      (Some(report), None) => write_temp_report(report)
+        .inspect_err(|e| eprintln!("Error: {e:#}"))
+        .ok(),
+      _ => None,
+    };
+    let hook_report = report
+      .as_ref()
+      .and(report_path.as_deref().or(temp_report.as_deref()));
+    println!("Running {}", hook.command);
+    let result = run_completion_hook(&hook, &output_path, status, hook_report, |line| {
+      println!("{line}")
+    })
+    .await;
+    if let Err(e) = result {
+      eprintln!("Error: The command run when finished failed: {e:#}");
+    }
+  }
+  Ok(results)
+}
+
+/// Converts one recording, printing its progress, and picks up the ffmpeg it found for the next.
+async fn run_job(
+  source: PackSource,
+  output_path: PathBuf,
+  options: ProcessOptions,
+  shared_ffmpeg: &mut Option<SharedFfmpeg>,
+  json: &mut Option<JsonPrinter>,
+  cancel: CancellationToken,
+) -> anyhow::Result<ProcessReport> {
+  let setup = RunSetup {
+    shared_ffmpeg: shared_ffmpeg.clone(),
+    ..Default::default()
+  };
+  let mut text = TextPrinter::default();
+  let progress = |event| {
+    let progress = match event {
+      RunEvent::Process(progress) => progress,
+      RunEvent::Resources(resources) => {
+        if resources.shared_ffmpeg.is_some() {
+          *shared_ffmpeg = resources.shared_ffmpeg.clone();
+        }
+        return;
+      }
+      _ => return,
+    };
+    match json {
+      Some(json) => json.print(&JsonEvent::from_progress(&progress)),
+      None => text.print(&progress),
+    }
+  };
+
+  let result = crate::run(source, output_path, options, setup, progress, cancel)
+    .await
+    .map_err(|e| match e {
+      // Unwrapped again, so --json lists what caused it rather than just the wrapper
+      ProcessError::Other(e) => e,
+      e => e.into(),
+    });
+  match &result {
+    Ok(report) => match json {
+      Some(json) => json.print(&JsonEvent::Finished { report }),
+      None => TextPrinter::finish(report),
+    },
+    Err(e) => {
+      if let Some(json) = json {
+        json.print(&JsonEvent::from_error(e));
+      }
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+
+  use super::*;
+
+  /// A folder with an empty file for each of `names`.
+  fn recordings(names: &[&str]) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for name in names {
+      fs::write(dir.path().join(name), "").unwrap();
+    }
+    dir
+  }
+
+  #[test]
+  fn inputs_are_taken_in_order_once_each() {
+    let dir = recordings(&["craig-b.zip", "craig-a.zip"]);
+    let b = dir.path().join("craig-b.zip");
+    let a = dir.path().join("craig-a.zip");
+    assert_eq!(
+      expand_inputs(&[b.clone(), a.clone(), b.clone()]).unwrap(),
+      [b, a]
+    );
+  }
+
+  #[test]
+  fn patterns_are_expanded_in_name_order() {
+    let dir = recordings(&["craig-b.zip", "craig-a.zip", "notes.txt"]);
+    let a = dir.path().join("craig-a.zip");
+    let b = dir.path().join("craig-b.zip");
+    let pattern = dir.path().join("*.zip");
+    assert_eq!(
+      expand_inputs(std::slice::from_ref(&pattern)).unwrap(),
+      [a.as_path(), b.as_path()]
+    );
+    // Named again by the pattern, but already in the list
+    assert_eq!(
+      expand_inputs(&[b.clone(), pattern]).unwrap(),
+      [b.as_path(), a.as_path()]
+    );
+  }
+
+  #[test]
+  fn existing_paths_are_not_patterns() {
+    let dir = recordings(&["craig [1].zip", "craig 1.zip"]);
+    let literal = dir.path().join("craig [1].zip");
+    assert_eq!(
+      expand_inputs(std::slice::from_ref(&literal)).unwrap(),
+      [literal]
+    );
+  }
+
+  #[test]
+  fn inputs_naming_nothing_are_errors() {
+    let dir = recordings(&["craig-a.zip"]);
+    let error = expand_inputs(&[dir.path().join("*.flac")]).unwrap_err();
+    assert!(
+      error.to_string().ends_with("doesn't match any recordings"),
+      "{error:#}"
+    );
+    let error = expand_inputs(&[dir.path().join("craig-[a.zip")]).unwrap_err();
+    assert!(
+      error.to_string().ends_with("isn't a valid pattern"),
+      "{error:#}"
+    );
+  }
+
+  fn folder_names(inputs: &[&str]) -> Vec<String> {
+    let inputs: Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+    batch_folder_names(&inputs)
+  }
+
+  #[test]
+  fn batch_folders_are_named_after_the_recordings() {
+    assert_eq!(
+      folder_names(&["downloads/craig-abc.zip", "craig-def.v2.zip", "craig-ghi"]),
+      ["craig-abc", "craig-def.v2", "craig-ghi"]
+    );
+
+    // A folder's whole name is kept, dots and all
+    let dir = tempfile::tempdir().unwrap();
+    let folder = dir.path().join("craig-abc.tracks");
+    fs::create_dir(&folder).unwrap();
+    assert_eq!(batch_folder_names(&[folder]), ["craig-abc.tracks"]);
+  }
+
+  #[test]
+  fn clashing_batch_folders_are_numbered() {
+    assert_eq!(
+      folder_names(&["a/craig.zip", "b/Craig.zip", "c/CRAIG.zip"]),
+      ["craig", "Craig-2", "CRAIG-3"]
+    );
+    // Numbers already taken by a recording's own name are skipped
+    assert_eq!(
+      folder_names(&["a/craig-2.zip", "b/craig.zip", "c/craig.zip"]),
+      ["craig-2", "craig", "craig-3"]
+    );
+  }
+
+  #[test]
+  fn batch_folders_without_a_name_get_one() {
+    assert_eq!(folder_names(&["..", ".zip"]), ["recording", ".zip"]);
+    assert_eq!(folder_names(&["..", ".."]), ["recording", "recording-2"]);
+  }
+}
@@ -0,0 +1,413 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+  ffi::OsString,
+  fmt::Display,
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+use crate::{
+  AudioFormat, ExtractLimits, Leveling, MixDuration, NamedOption, OverwritePolicy, ProcessOptions,
+};
+
+/// Name of the folder inside the platform config directory that holds otterpack's files.
+pub(crate) const CONFIG_DIR_NAME: &str = "otterpack";
+/// Name of the config file looked for next to the executable and in the platform config
+/// directory.
+pub const CONFIG_FILE_NAME: &str = "otterpack.toml";
+
+/// Options set by one of the places they can come from, like a config file or the command line.
+/// Anything left as `None` is up to the places with a lower precedence.
+///
+/// The keys are named after the fields of [`ProcessOptions`], apart from the password and track
+/// selection, which only make sense for one recording. `output_path` is the folder to write into.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OptionsLayer {
+  pub output_path: Option<PathBuf>,
+  pub format: Option<AudioFormat>,
+  pub extra_formats: Option<Vec<AudioFormat>>,
+  pub leveling: Option<Leveling>,
+  pub mix: Option<bool>,
+  pub mix_duration: Option<MixDuration>,
+  pub dropout_transition: Option<f64>,
+  pub overwrite: Option<OverwritePolicy>,
+  pub stall_timeout_secs: Option<u64>,
+  pub fail_fast: Option<bool>,
+  pub low_priority: Option<bool>,
+  pub threads: Option<usize>,
+  pub verify: Option<bool>,
+  pub reprocess_all: Option<bool>,
+  pub bundled_ffmpeg_only: Option<bool>,
+  pub work_dir: Option<PathBuf>,
+  pub keep_extracted: Option<bool>,
+  pub extract_limits: Option<ExtractLimits>,
+}
+
+impl OptionsLayer {
+  /// A layer setting every key to what `options` and `output_path` have.
+  pub fn from_options(options: &ProcessOptions, output_path: Option<&Path>) -> Self {
+    Self {
+      output_path: output_path.map(Path::to_path_buf),
+      format: Some(options.format),
+      extra_formats: Some(options.extra_formats.clone()),
+      leveling: Some(options.leveling),
+      mix: Some(options.mix),
+      mix_duration: Some(options.mix_duration),
+      dropout_transition: Some(options.dropout_transition),
+      overwrite: Some(options.overwrite),
+      stall_timeout_secs: Some(options.stall_timeout_secs),
+      fail_fast: Some(options.fail_fast),
+      low_priority: Some(options.low_priority),
+      threads: Some(options.threads),
+      verify: Some(options.verify),
+      reprocess_all: Some(options.reprocess_all),
+      bundled_ffmpeg_only: Some(options.bundled_ffmpeg_only),
+      work_dir: options.work_dir.clone(),
+      keep_extracted: Some(options.keep_extracted),
+      extract_limits: Some(options.extract_limits),
+    }
+  }
+
+  /// Overrides the options with whatever this layer sets, and the output folder if it sets one.
+  fn apply_to(self, options: &mut ProcessOptions, output_path: &mut Option<PathBuf>) {
+    if self.output_path.is_some() {
+      *output_path = self.output_path;
+    }
+    options.format = self.format.unwrap_or(options.format);
+    if let Some(extra_formats) = self.extra_formats {
+      options.extra_formats = extra_formats;
+    }
+    options.leveling = self.leveling.unwrap_or(options.leveling);
+    options.mix = self.mix.unwrap_or(options.mix);
+    options.mix_duration = self.mix_duration.unwrap_or(options.mix_duration);
+    options.dropout_transition = self
+      .dropout_transition
+      .unwrap_or(options.dropout_transition);
+    options.overwrite = self.overwrite.unwrap_or(options.overwrite);
+    options.stall_timeout_secs = self
+      .stall_timeout_secs
+      .unwrap_or(options.stall_timeout_secs);
+    options.fail_fast = self.fail_fast.unwrap_or(options.fail_fast);
+    options.low_priority = self.low_priority.unwrap_or(options.low_priority);
+    options.threads = self.threads.unwrap_or(options.threads);
+    options.verify = self.verify.unwrap_or(options.verify);
+    options.reprocess_all = self.reprocess_all.unwrap_or(options.reprocess_all);
+    options.bundled_ffmpeg_only = self
+      .bundled_ffmpeg_only
+      .unwrap_or(options.bundled_ffmpeg_only);
+    if self.work_dir.is_some() {
+      options.work_dir = self.work_dir;
+    }
+    options.keep_extracted = self.keep_extracted.unwrap_or(options.keep_extracted);
+    options.extract_limits = self.extract_limits.unwrap_or(options.extract_limits);
+  }
+
+  /// Reads a config file, naming the offending key and line if it can't be understood.
+  pub fn parse(text: &str) -> anyhow::Result<Self> {
+    Ok(toml::from_str(text)?)
+  }
+
+  /// Reads the config file at `path`, or returns `None` if there isn't one.
+  pub fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+    let text = match fs::read_to_string(path) {
+      Ok(text) => text,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(e) => return Err(e).context(format!("Failed to read {}", path.display())),
+    };
+    Self::parse(&text)
+      .map(Some)
+      .context(format!("{} isn't a valid config file", path.display()))
+  }
+
+  /// The layer as the contents of a config file.
+  pub fn to_toml(&self) -> anyhow::Result<String> {
+    Ok(toml::to_string(self)?)
+  }
+}
+
+/// The config files that may exist, lowest precedence first: the one in the platform config
+/// directory, then the one next to the executable.
+pub fn config_paths() -> Vec<PathBuf> {
+  let user = dirs::config_dir().map(|dir| dir.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME));
+  let portable = std::env::current_exe()
+    .ok()
+    .and_then(|exe| Some(exe.parent()?.join(CONFIG_FILE_NAME)));
+  user.into_iter().chain(portable).collect()
+}
+
+/// The layers of every config file that exists, lowest precedence first.
+pub fn load_config_layers() -> anyhow::Result<Vec<OptionsLayer>> {
+  let mut layers = Vec::new();
+  for path in config_paths() {
+    layers.extend(OptionsLayer::load(&path)?);
+  }
+  Ok(layers)
+}
+
+/// The options and output folder `layers` add up to, each one overriding those before it, on top
+/// of the built-in defaults.
+pub fn resolve_options(layers: &[OptionsLayer]) -> (ProcessOptions, Option<PathBuf>) {
+  let mut options = ProcessOptions::default();
+  let mut output_path = None;
+  for layer in layers {
+    layer.clone().apply_to(&mut options, &mut output_path);
+  }
+  (options, output_path)
+}
+
+/// The environment variables [`env_layer`] reads, with what each one sets.
+pub fn env_vars() -> Vec<(&'static str, String)> {
+  vec![
+    (
+      "OTTERPACK_OUTPUT",
+      "Folder to write the converted files into".into(),
+    ),
+    (
+      "OTTERPACK_FORMAT",
+      format!("Format to convert to: {}", AudioFormat::names()),
+    ),
+    (
+      "OTTERPACK_MIX",
+      "Mix every track into a single file: true or false".into(),
+    ),
+    (
+      "OTTERPACK_LEVEL",
+      "Even out the volume with dynaudnorm: true or false".into(),
+    ),
+    (
+      "OTTERPACK_OVERWRITE",
+      format!(
+        "What to do with existing outputs: {}",
+        OverwritePolicy::names()
+      ),
+    ),
+    (
+      "OTTERPACK_THREADS",
+      "Threads each ffmpeg may use, or 0 to leave it up to ffmpeg".into(),
+    ),
+    (
+      "OTTERPACK_WORK_DIR",
+      "Folder to unpack the recording into".into(),
+    ),
+  ]
+}
+
+/// The environment variables as a section of the command line help.
+pub fn env_help() -> String {
+  let rows: Vec<String> = env_vars()
+    .iter()
+    .map(|(name, description)| format!("  {name}  {description}"))
+    .collect();
+  format!(
+    "Environment variables, which win over config files but not arguments:\n{}",
+    rows.join("\n")
+  )
+}
+
+/// Reads a yes or no the ways environment variables usually spell it.
+fn parse_bool(s: &str) -> Result<bool, String> {
+  match s.to_ascii_lowercase().as_str() {
+    "1" | "true" | "yes" | "on" => Ok(true),
+    "0" | "false" | "no" | "off" => Ok(false),
+    _ => Err(format!("{s} isn't true or false")),
+  }
+}
+
+/// Parses the variable `name` with `parse` if `var` gives a value for it, naming the variable if
+/// that fails.
+fn env_value<T, E: Display>(
+  var: &impl Fn(&str) -> Option<OsString>,
+  name: &str,
+  parse: impl FnOnce(&str) -> Result<T, E>,
+) -> anyhow::Result<Option<T>> {
+  let Some(value) = var(name).filter(|value| !value.is_empty()) else {
+    return Ok(None);
+  };
+  let Some(value) = value.to_str() else {
+    anyhow::bail!("{name} isn't valid Unicode");
+  };
+  parse(value.trim())
+    .map(Some)
+    .map_err(|e| anyhow::anyhow!("{name}: {e}"))
+}
+
+/// The layer set by the [`env_vars`] that `var` gives a value for, which is meant to go between
+/// the config files and the arguments.
+///
+/// Empty variables count as unset. Any other value that can't be understood is an error naming the
+/// variable, rather than being left out quietly.
+pub fn env_layer(var: impl Fn(&str) -> Option<OsString>) -> anyhow::Result<OptionsLayer> {
+  let path = |name: &str| {
+    var(name)
+      .filter(|value| !value.is_empty())
+      .map(PathBuf::from)
+  };
+  Ok(OptionsLayer {
+    output_path: path("OTTERPACK_OUTPUT"),
+    format: env_value(&var, "OTTERPACK_FORMAT", str::parse)?,
+    mix: env_value(&var, "OTTERPACK_MIX", parse_bool)?,
+    leveling: env_value(&var, "OTTERPACK_LEVEL", parse_bool)?.map(|level| match level {
+      true => Leveling::Dynaudnorm,
+      false => Leveling::Off,
+    }),
+    overwrite: env_value(&var, "OTTERPACK_OVERWRITE", str::parse)?,
+    threads: env_value(&var, "OTTERPACK_THREADS", |s: &str| {
+      s.parse().map_err(|_| format!("{s} isn't a number"))
+    })?,
+    work_dir: path("OTTERPACK_WORK_DIR"),
+    ..Default::default()
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn nothing_set_leaves_the_defaults() {
+    let (options, output_path) = resolve_options(&[OptionsLayer::default()]);
+    assert_eq!(options, ProcessOptions::default());
+    assert_eq!(output_path, None);
+  }
+
+  #[test]
+  fn arguments_beat_config_files_which_beat_defaults() {
+    let config = OptionsLayer::parse(
+      r#"
+        output_path = "from-config"
+        format = "wav"
+        mix = true
+        threads = 4
+      "#,
+    )
+    .unwrap();
+    // What the arguments set, which go last
+    let args = OptionsLayer {
+      output_path: Some(PathBuf::from("from-args")),
+      format: Some(AudioFormat::AAC),
+      mix: Some(false),
+      ..Default::default()
+    };
+
+    let (options, output_path) = resolve_options(&[config.clone(), args]);
+    assert_eq!(output_path, Some(PathBuf::from("from-args")));
+    assert_eq!(options.format, AudioFormat::AAC);
+    assert!(!options.mix);
+    assert_eq!(options.threads, 4);
+    assert_eq!(options.leveling, ProcessOptions::default().leveling);
+
+    let (options, output_path) = resolve_options(&[config]);
+    assert_eq!(output_path, Some(PathBuf::from("from-config")));
+    assert_eq!(options.format, AudioFormat::WAV);
+    assert!(options.mix);
+  }
+
+  #[test]
+  fn unknown_keys_are_named_with_their_line() {
+    let error = OptionsLayer::parse("format = \"wav\"\nformats = \"flac\"\n").unwrap_err();
+    let message = format!("{error:#}");
+    assert!(message.contains("formats"), "{message}");
+    assert!(message.contains("line 2"), "{message}");
+  }
+
+  #[test]
+  fn bad_values_are_errors() {
+    assert!(OptionsLayer::parse("format = \"mp3\"").is_err());
+    assert!(OptionsLayer::parse("threads = -1").is_err());
+    assert!(OptionsLayer::parse("mix = \"sometimes\"").is_err());
+  }
+
+  #[test]
+  fn config_files_round_trip() {
+    let options = ProcessOptions {
+      format: AudioFormat::ALAC,
+      extra_formats: vec![AudioFormat::WAV, AudioFormat::Audacity],
+      leveling: Leveling::Dynaudnorm,
+      mix: true,
+      mix_duration: MixDuration::Shortest,
+      dropout_transition: 0.5,
+      overwrite: OverwritePolicy::Fail,
+      threads: 3,
+      work_dir: Some(PathBuf::from("scratch")),
+      ..Default::default()
+    };
+    let layer = OptionsLayer::from_options(&options, Some(Path::new("out")));
+
+    let text = layer.to_toml().unwrap();
+    let parsed = OptionsLayer::parse(&text).unwrap();
+    assert_eq!(parsed, layer, "{text}");
+    let (resolved, output_path) = resolve_options(&[parsed]);
+    assert_eq!(resolved, options);
+    assert_eq!(output_path, Some(PathBuf::from("out")));
+  }
+
+  /// Looks variables up in `vars` instead of the environment.
+  fn vars<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+    |name| {
+      vars
+        .iter()
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| OsString::from(value))
+    }
+  }
+
+  #[test]
+  fn env_layer_reads_every_variable() {
+    let layer = env_layer(vars(&[
+      ("OTTERPACK_OUTPUT", "/recordings"),
+      ("OTTERPACK_FORMAT", "WAV"),
+      ("OTTERPACK_MIX", "yes"),
+      ("OTTERPACK_LEVEL", "1"),
+      ("OTTERPACK_OVERWRITE", "skip"),
+      ("OTTERPACK_THREADS", " 2 "),
+      ("OTTERPACK_WORK_DIR", "/scratch"),
+      ("OTTERPACK_UNRELATED", "ignored"),
+    ]))
+    .unwrap();
+    assert_eq!(
+      layer,
+      OptionsLayer {
+        output_path: Some(PathBuf::from("/recordings")),
+        format: Some(AudioFormat::WAV),
+        mix: Some(true),
+        leveling: Some(Leveling::Dynaudnorm),
+        overwrite: Some(OverwritePolicy::Skip),
+        threads: Some(2),
+        work_dir: Some(PathBuf::from("/scratch")),
+        ..Default::default()
+      }
+    );
+
+    let layer = env_layer(vars(&[
+      ("OTTERPACK_MIX", "off"),
+      ("OTTERPACK_LEVEL", "False"),
+    ]))
+    .unwrap();
+    assert_eq!(layer.mix, Some(false));
+    assert_eq!(layer.leveling, Some(Leveling::Off));
+  }
+
+  #[test]
+  fn empty_env_vars_count_as_unset() {
+    assert_eq!(env_layer(vars(&[])).unwrap(), OptionsLayer::default());
+    let empty: Vec<(&str, &str)> = env_vars().iter().map(|(name, _)| (*name, "")).collect();
+    assert_eq!(env_layer(vars(&empty)).unwrap(), OptionsLayer::default());
+  }
+
+  #[test]
+  fn bad_env_vars_are_named() {
+    for (name, value) in [
+      ("OTTERPACK_FORMAT", "mp3"),
+      ("OTTERPACK_MIX", "sometimes"),
+      ("OTTERPACK_LEVEL", "loud"),
+      ("OTTERPACK_OVERWRITE", "maybe"),
+      ("OTTERPACK_THREADS", "many"),
+    ] {
+      let message = env_layer(vars(&[(name, value)])).unwrap_err().to_string();
+      assert!(message.starts_with(name), "{message}");
+      assert!(message.contains(value), "{message}");
+    }
+  }
+}
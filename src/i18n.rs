@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::HashMap,
+  fmt::Display,
+  sync::{
+    OnceLock,
+    atomic::{AtomicU8, Ordering},
+  },
+};
+use strum::{EnumIter, IntoEnumIterator};
+
+/// A language the window can be shown in.
+///
+/// Messages are looked up by their English text, so English needs no table of its own and any
+/// message missing from another language's table shows up in English.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumIter, Serialize, Deserialize)]
+pub enum Language {
+  #[default]
+  English,
+  German,
+}
+
+impl Language {
+  /// The ISO 639-1 code locales for this language start with.
+  pub fn code(&self) -> &'static str {
+    match self {
+      Language::English => "en",
+      Language::German => "de",
+    }
+  }
+
+  /// The language's name in the language itself, so it can be found in the picker without being
+  /// able to read the current one.
+  pub fn native_name(&self) -> &'static str {
+    match self {
+      Language::English => "English",
+      Language::German => "Deutsch",
+    }
+  }
+
+  /// The language of a locale such as `de-DE` or `de_AT.UTF-8`, if there's a translation for it.
+  pub fn from_locale(locale: &str) -> Option<Self> {
+    let code = locale.split(['-', '_', '.', '@']).next()?;
+    Language::iter().find(|language| code.eq_ignore_ascii_case(language.code()))
+  }
+
+  /// The language of the system's locale, falling back to English.
+  pub fn detect() -> Self {
+    crate::platform::user_locale()
+      .and_then(|locale| Language::from_locale(&locale))
+      .unwrap_or_default()
+  }
+
+  /// The translations for this language, keyed by the English message.
+  fn messages(&self) -> Option<&'static HashMap<String, String>> {
+    static GERMAN: OnceLock<HashMap<String, String>> = OnceLock::new();
+    let (cell, source) = match self {
+      Language::English => return None,
+      Language::German => (&GERMAN, include_str!("i18n/de.json")),
+    };
+    Some(cell.get_or_init(|| {
+      serde_json::from_str(source)
+        .inspect_err(|e| println!("The {} translation is damaged: {}", self.code(), e))
+        .unwrap_or_default()
+    }))
+  }
+}
+
+/// The language messages are currently shown in, as its index in [`Language::iter`].
+static CURRENT: AtomicU8 = AtomicU8::new(0);
+
+/// The language messages are currently shown in.
+pub fn language() -> Language {
+  Language::iter()
+    .nth(CURRENT.load(Ordering::Relaxed) as usize)
+    .unwrap_or_default()
+}
+
+/// Switches the language messages are shown in from now on.
+pub fn set_language(language: Language) {
+  let index = Language::iter().position(|l| l == language).unwrap_or(0);
+  CURRENT.store(index as u8, Ordering::Relaxed);
+}
+
+/// `message` in `language`, or as it is if there's no translation for it.
+pub fn translate(language: Language, message: &'static str) -> &'static str {
+  language
+    .messages()
+    .and_then(|messages| messages.get(message))
+    .map_or(message, |translated| translated.as_str())
+}
+
+/// `message` in the current language. Use [`t!`] rather than calling this directly.
+pub fn tr(message: &'static str) -> &'static str {
+  translate(language(), message)
+}
+
+/// The `one` or `other` form of a message about `count` things, in the current language.
+///
+/// Both shipped languages only set one apart from every other count, which is all this handles.
+pub fn tr_n(count: u64, one: &'static str, other: &'static str) -> &'static str {
+  tr(if count == 1 { one } else { other })
+}
+
+/// Fills the `{name}` placeholders in `template` with `args`, leaving unknown ones as they are.
+pub fn fill(template: &str, args: &[(&str, &dyn Display)]) -> String {
+  let mut out = String::with_capacity(template.len());
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    out.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let value = rest.find('}').and_then(|end| {
+      let name = &rest[1..end];
+      args
+        .iter()
+        .find(|(arg, _)| *arg == name)
+        .map(|(_, value)| (end, value))
+    });
+    match value {
+      Some((end, value)) => {
+        out.push_str(&value.to_string());
+        rest = &rest[end + 1..];
+      }
+      None => {
+        out.push('{');
+        rest = &rest[1..];
+      }
+    }
+  }
+  out.push_str(rest);
+  out
+}
+
+/// Translates a message into the current language, filling in any `name = value` placeholders.
+///
+/// The message is the English text, so it reads as what's shown and doubles as the fallback.
+macro_rules! t {
+  ($message:literal) => {
+    $crate::i18n::tr($message)
+  };
+  ($message:literal, $($name:ident = $value:expr),+ $(,)?) => {
+    $crate::i18n::fill(
+      $crate::i18n::tr($message),
+      &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+],
+    )
+  };
+}
+
+/// Translates the singular or plural form of a message about `count` things, which is available
+/// to both as `{n}`.
+macro_rules! t_n {
+  ($count:expr, $one:literal, $other:literal $(, $name:ident = $value:expr)* $(,)?) => {{
+    let count = $count;
+    $crate::i18n::fill(
+      $crate::i18n::tr_n(count as u64, $one, $other),
+      &[("n", &count as &dyn std::fmt::Display) $(, (stringify!($name), &$value as &dyn std::fmt::Display))*],
+    )
+  }};
+}
+
+pub(crate) use {t, t_n};
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The files messages are written in, as [`t!`] arguments or names passed to [`tr`].
+  const SOURCES: [&str; 4] = [
+    include_str!("app.rs"),
+    include_str!("settings.rs"),
+    include_str!("webhook.rs"),
+    include_str!("../crates/otterpack-core/src/process.rs"),
+  ];
+
+  #[test]
+  fn untranslated_messages_stay_in_english() {
+    let message = "A message nobody has translated";
+    assert_eq!(translate(Language::German, message), message);
+    assert_eq!(translate(Language::English, "Password"), "Password");
+    assert_eq!(translate(Language::German, "Password"), "Passwort");
+  }
+
+  #[test]
+  fn every_translation_is_for_a_message_in_use() {
+    for language in Language::iter() {
+      let Some(messages) = language.messages() else {
+        continue;
+      };
+      assert!(!messages.is_empty(), "{language:?} has no messages");
+      // Written out the way the message would be as a string literal, escapes and all
+      let stale: Vec<&String> = messages
+        .keys()
+        .filter(|message| {
+          let literal = format!("{message:?}");
+          !SOURCES.iter().any(|source| source.contains(&literal))
+        })
+        .collect();
+      assert!(
+        stale.is_empty(),
+        "{language:?} translates messages that aren't used: {stale:#?}"
+      );
+    }
+  }
+}
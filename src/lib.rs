@@ -1,9 +1,19 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
+#[cfg(feature = "gui")]
 mod app;
-mod process;
-mod self_extract;
+mod cli;
+mod config;
+#[cfg(feature = "gui")]
+mod i18n;
+mod platform;
+#[cfg(feature = "gui")]
+mod settings;
+#[cfg(feature = "gui")]
+mod webhook;
 
-pub use app::TemplateApp;
-pub use process::*;
-pub use self_extract::*;
+#[cfg(feature = "gui")]
+pub use app::{APP_TITLE, TemplateApp};
+pub use cli::*;
+pub use config::*;
+pub use otterpack_core::*;
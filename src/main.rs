@@ -1,17 +1,239 @@
 #![warn(clippy::all, rust_2018_idioms)]
-#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
+#![cfg_attr(
+  all(not(debug_assertions), feature = "gui"),
+  windows_subsystem = "windows"
+)] // hide console window on Windows in release
 
-use otterpack::TemplateApp;
+use clap::Parser;
+use otterpack::{
+  AudioFormat, CONFIG_FILE_NAME, CliExit, CliJob, CliRun, CompletionHook, Leveling, NamedOption,
+  OptionsLayer, PackSource, batch_folder_names, env_help, env_layer, expand_inputs,
+  find_pack_source, list_tracks, load_config_layers, resolve_options, run_cli,
+};
+use std::{ffi::OsString, path::PathBuf};
 
-fn main() -> eframe::Result {
-  env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+/// Converts a Craig recording, in a window or, with --cli, without one.
+#[derive(Parser, Default)]
+#[command(version, after_help = format!("{}\n\n{}", CliExit::help(), env_help()))]
+struct Args {
+  /// Convert without opening a window, printing the progress instead
+  #[arg(long)]
+  cli: bool,
+  /// Recording ZIP or folder of tracks to convert, instead of the one packed into this executable.
+  /// Several recordings or a glob pattern like 'recordings/*.zip' convert each into its own
+  /// subfolder of the output folder
+  #[arg(long, requires = "cli", num_args = 1..)]
+  input: Vec<PathBuf>,
+  /// Folder to write the converted files into, unless a config file sets one
+  #[arg(long, requires = "cli")]
+  output: Option<PathBuf>,
+  #[arg(long, requires = "cli", help = format!("Format to convert to: {}", AudioFormat::names()))]
+  format: Option<AudioFormat>,
+  /// Mix every track into a single file
+  #[arg(long, requires = "cli")]
+  mix: bool,
+  /// Even out the volume with ffmpeg's dynaudnorm filter
+  #[arg(long, requires = "cli")]
+  level: bool,
+  /// Password the recording's files are encrypted with
+  #[arg(long, requires = "cli")]
+  password: Option<String>,
+  /// Print the progress as JSON events, one per line, instead of as text
+  #[arg(long, requires = "cli")]
+  json: bool,
+  /// Write what happened to each track into this file as JSON once the run is done
+  #[arg(long, requires = "cli")]
+  report: Option<PathBuf>,
+  /// Command to run once everything is converted, like a script that uploads the files. It's run
+  /// with OUTPUT_DIR, STATUS (success, partial or failed) and REPORT_PATH set, and split into
+  /// arguments with quotes keeping spaces together, without going through a shell
+  #[arg(long, requires = "cli", value_name = "COMMAND")]
+  on_complete: Option<String>,
+  /// Run the --on-complete command through sh or cmd, for pipes and redirects
+  #[arg(long, requires = "on_complete")]
+  on_complete_shell: bool,
+  /// List the recording's tracks with their sizes instead of converting
+  #[arg(long, requires = "cli", conflicts_with = "write_config")]
+  list: bool,
+  /// Read the length of every track for --list, even where the recording doesn't say
+  #[arg(long, requires = "list")]
+  probe: bool,
+  /// Print the options the config files and arguments add up to as a config file, to start one
+  /// from, instead of converting
+  #[arg(long, requires = "cli")]
+  write_config: bool,
+}
 
-  let runtime = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
-  let _guard = runtime.enter();
+impl Args {
+  /// The options given as arguments, which win over the config files.
+  fn layer(&self) -> OptionsLayer {
+    OptionsLayer {
+      output_path: self.output.clone(),
+      format: self.format,
+      mix: self.mix.then_some(true),
+      leveling: self.level.then_some(Leveling::Dynaudnorm),
+      ..Default::default()
+    }
+  }
+}
+
+/// The recording at `input`, or the one packed into this executable, printing why if there isn't
+/// one.
+fn pack_source(input: Option<PathBuf>) -> Result<PackSource, CliExit> {
+  match input {
+    Some(input) if input.is_dir() => Ok(PackSource::UserFolder(input)),
+    Some(input) if input.is_file() => Ok(PackSource::UserZip(input)),
+    Some(input) => {
+      eprintln!("Error: {} doesn't exist", input.display());
+      Err(CliExit::NoRecording)
+    }
+    None => find_pack_source().map_err(|e| {
+      eprintln!("Error: {e:#}");
+      CliExit::NoRecording
+    }),
+  }
+}
+
+/// Converts the recording as `args`, the environment and the config files say without opening a
+/// window, printing any error.
+fn run_headless(runtime: &tokio::runtime::Runtime, args: Args) -> CliExit {
+  let layers = load_config_layers().and_then(|mut layers| {
+    layers.push(env_layer(|name| std::env::var_os(name))?);
+    Ok(layers)
+  });
+  let mut layers = match layers {
+    Ok(layers) => layers,
+    Err(e) => {
+      eprintln!("Error: {e:#}");
+      return CliExit::BadArguments;
+    }
+  };
+  layers.push(args.layer());
+  let (mut options, output_path) = resolve_options(&layers);
+
+  if args.write_config {
+    return match OptionsLayer::from_options(&options, output_path.as_deref()).to_toml() {
+      Ok(config) => {
+        print!("{config}");
+        CliExit::Success
+      }
+      Err(e) => {
+        eprintln!("Error: {e:#}");
+        CliExit::Failed
+      }
+    };
+  }
+  let inputs = match expand_inputs(&args.input) {
+    Ok(inputs) => inputs,
+    Err(e) => {
+      eprintln!("Error: {e:#}");
+      return CliExit::NoRecording;
+    }
+  };
+  // A pattern is a batch even when it only matches one recording, so where it goes doesn't
+  // depend on how many there happen to be
+  let batch = inputs.len() > 1 || inputs != args.input;
+  if args.list {
+    let input = match inputs.as_slice() {
+      [] => None,
+      [input] => Some(input.clone()),
+      _ => {
+        eprintln!("Error: --list takes one recording at a time");
+        return CliExit::BadArguments;
+      }
+    };
+    let source = match pack_source(input) {
+      Ok(source) => source,
+      Err(exit) => return exit,
+    };
+    return match list_tracks(&source, args.probe, args.password.as_deref(), args.json) {
+      Ok(_) => CliExit::Success,
+      Err(e) => {
+        eprintln!("Error: {e:#}");
+        CliExit::NoRecording
+      }
+    };
+  }
+  let Some(output_path) = output_path else {
+    eprintln!(
+      "Error: There's no output folder. Pass --output or set output_path in {CONFIG_FILE_NAME}"
+    );
+    return CliExit::BadArguments;
+  };
+  options.password = args.password;
+
+  let mut jobs = Vec::new();
+  if batch {
+    for (input, name) in inputs.iter().zip(batch_folder_names(&inputs)) {
+      let source = match pack_source(Some(input.clone())) {
+        Ok(source) => source,
+        Err(exit) => return exit,
+      };
+      jobs.push(CliJob {
+        input: input.display().to_string(),
+        source,
+        output_path: output_path.join(name),
+      });
+    }
+  } else {
+    let input = inputs.into_iter().next();
+    let label = input
+      .as_ref()
+      .map_or("The packed recording".to_string(), |input| {
+        input.display().to_string()
+      });
+    let source = match pack_source(input) {
+      Ok(source) => source,
+      Err(exit) => return exit,
+    };
+    jobs.push(CliJob {
+      input: label,
+      source,
+      output_path: output_path.clone(),
+    });
+  }
 
+  let result = runtime.block_on(run_cli(CliRun {
+    jobs,
+    options,
+    json: args.json,
+    report_path: args.report,
+    output_path,
+    on_complete: args.on_complete.map(|command| CompletionHook {
+      command,
+      use_shell: args.on_complete_shell,
+      ..Default::default()
+    }),
+  }));
+  match result {
+    Ok(results) => CliExit::for_batch(results.iter().map(|result| result.exit)),
+    Err(e) => {
+      eprintln!("Error: {e:#}");
+      CliExit::for_anyhow(&e)
+    }
+  }
+}
+
+/// Parses the arguments, with --cli implied when there's no window to open.
+fn parse_args() -> Args {
+  let mut argv: Vec<OsString> = std::env::args_os().collect();
+  if !cfg!(feature = "gui") && !argv.iter().any(|arg| arg == "--cli") {
+    argv.insert(argv.len().min(1), "--cli".into());
+  }
+  match Args::try_parse_from(&argv) {
+    Ok(args) => args,
+    // Help and version go through here too
+    Err(e) if !e.use_stderr() || argv.iter().any(|arg| arg == "--cli") => e.exit(),
+    // File managers can pass arguments of their own, which shouldn't stop the window opening
+    Err(_) => Args::default(),
+  }
+}
+
+#[cfg(feature = "gui")]
+fn run_window() -> eframe::Result {
   let native_options = eframe::NativeOptions {
     viewport: egui::ViewportBuilder::default()
-      .with_inner_size([500.0, 300.0])
+      .with_inner_size([500.0, 400.0])
       .with_min_inner_size([500.0, 300.0])
       .with_icon(
         eframe::icon_data::from_png_bytes(&include_bytes!("../assets/otter.png")[..])
@@ -21,8 +243,38 @@ fn main() -> eframe::Result {
     ..Default::default()
   };
   eframe::run_native(
-    "Craig Audio Processor",
+    otterpack::APP_TITLE,
     native_options,
-    Box::new(|cc| Ok(Box::new(TemplateApp::new(cc)))),
+    Box::new(|cc| Ok(Box::new(otterpack::TemplateApp::new(cc)))),
   )
 }
+
+fn main() {
+  env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+
+  let args = parse_args();
+
+  let runtime = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
+  let _guard = runtime.enter();
+
+  if args.cli {
+    // Release builds on Windows don't get a console, so borrow the one they were started from
+    // unless the output is going somewhere else already
+    #[cfg(all(windows, feature = "gui"))]
+    unsafe {
+      use windows_sys::Win32::System::Console::{
+        ATTACH_PARENT_PROCESS, AttachConsole, GetStdHandle, STD_OUTPUT_HANDLE,
+      };
+      if GetStdHandle(STD_OUTPUT_HANDLE).is_null() {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+      }
+    }
+    std::process::exit(run_headless(&runtime, args).code());
+  }
+
+  #[cfg(feature = "gui")]
+  if let Err(e) = run_window() {
+    eprintln!("Error: {e:?}");
+    std::process::exit(1);
+  }
+}
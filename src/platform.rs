@@ -0,0 +1,286 @@
+#[cfg(feature = "gui")]
+use std::path::Path;
+
+/// Shows a desktop notification, through whatever the platform has for it without extra
+/// dependencies.
+///
+/// That's a toast through PowerShell on Windows, `osascript` on macOS and `notify-send`
+/// elsewhere. The text is passed as arguments or environment variables so it never needs quoting.
+/// Nothing happens if there's no way to show one, like on a system without a notification daemon.
+#[cfg(feature = "gui")]
+pub fn notify(title: &str, body: &str) {
+  #[cfg(windows)]
+  let mut command = {
+    use std::os::windows::process::CommandExt;
+    use windows_sys::Win32::System::Threading::CREATE_NO_WINDOW;
+
+    const SCRIPT: &str = "\
+      [Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] | Out-Null; \
+      $xml = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+      $text = $xml.GetElementsByTagName('text'); \
+      $text.Item(0).AppendChild($xml.CreateTextNode($env:OTTERPACK_TITLE)) | Out-Null; \
+      $text.Item(1).AppendChild($xml.CreateTextNode($env:OTTERPACK_BODY)) | Out-Null; \
+      [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier($env:OTTERPACK_TITLE).Show([Windows.UI.Notifications.ToastNotification]::new($xml))";
+    let mut command = std::process::Command::new("powershell");
+    command
+      .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+      .env("OTTERPACK_TITLE", title)
+      .env("OTTERPACK_BODY", body)
+      .creation_flags(CREATE_NO_WINDOW);
+    command
+  };
+
+  #[cfg(target_os = "macos")]
+  let mut command = {
+    let mut command = std::process::Command::new("osascript");
+    command.args([
+      "-e",
+      "on run argv",
+      "-e",
+      "display notification (item 2 of argv) with title (item 1 of argv)",
+      "-e",
+      "end run",
+      title,
+      body,
+    ]);
+    command
+  };
+
+  #[cfg(not(any(windows, target_os = "macos")))]
+  let mut command = {
+    let mut command = std::process::Command::new("notify-send");
+    command.args(["--app-name", title, title, body]);
+    command
+  };
+
+  command
+    .stdin(std::process::Stdio::null())
+    .stdout(std::process::Stdio::null())
+    .stderr(std::process::Stdio::null());
+  // Waited on in the background so it doesn't hold up the UI or linger as a zombie
+  std::thread::spawn(move || match command.status() {
+    Ok(status) if !status.success() => println!("Failed to show a notification: {status}"),
+    Err(e) => println!("Failed to show a notification: {e}"),
+    Ok(_) => {}
+  });
+}
+
+/// Starts playing the audio file at `path`, returning the player so it can be stopped by killing
+/// it.
+///
+/// Like [`notify`], this goes through whatever the platform has without extra dependencies:
+/// `afplay` on macOS, a `SoundPlayer` through PowerShell on Windows and `paplay` elsewhere, which
+/// PipeWire provides too, falling back to ALSA's `aplay`. The player exits once it's done, and
+/// unsuccessfully if there's nothing to play the file on.
+#[cfg(feature = "gui")]
+pub fn play_audio(path: &Path) -> std::io::Result<std::process::Child> {
+  #[cfg(windows)]
+  let mut commands = {
+    use std::os::windows::process::CommandExt;
+    use windows_sys::Win32::System::Threading::CREATE_NO_WINDOW;
+
+    let mut command = std::process::Command::new("powershell");
+    command
+      .args([
+        "-NoProfile",
+        "-NonInteractive",
+        "-Command",
+        "(New-Object Media.SoundPlayer $env:OTTERPACK_AUDIO).PlaySync()",
+      ])
+      .env("OTTERPACK_AUDIO", path)
+      .creation_flags(CREATE_NO_WINDOW);
+    vec![command]
+  };
+
+  #[cfg(target_os = "macos")]
+  let mut commands = {
+    let mut command = std::process::Command::new("afplay");
+    command.arg(path);
+    vec![command]
+  };
+
+  #[cfg(not(any(windows, target_os = "macos")))]
+  let mut commands = {
+    let mut paplay = std::process::Command::new("paplay");
+    paplay.arg(path);
+    let mut aplay = std::process::Command::new("aplay");
+    aplay.arg("-q").arg(path);
+    vec![paplay, aplay]
+  };
+
+  let mut last_error = std::io::Error::from(std::io::ErrorKind::NotFound);
+  for command in &mut commands {
+    match command
+      .stdin(std::process::Stdio::null())
+      .stdout(std::process::Stdio::null())
+      .stderr(std::process::Stdio::null())
+      .spawn()
+    {
+      Ok(child) => return Ok(child),
+      // Only a player that isn't installed is worth trying the next one over
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => last_error = e,
+      Err(e) => return Err(e),
+    }
+  }
+  Err(last_error)
+}
+
+/// Keeps the computer from going to sleep on its own while it's held. Dropping it lets the
+/// computer sleep again.
+///
+/// The screen can still turn off, it's only the system that's kept running.
+#[cfg(feature = "gui")]
+pub struct KeepAwake {
+  /// The `caffeinate` or `systemd-inhibit` holding the inhibition for as long as it runs.
+  #[cfg(not(windows))]
+  child: std::process::Child,
+}
+
+/// Stops the computer from going to sleep on its own until the returned guard is dropped, giving
+/// `reason` where the system shows one.
+///
+/// The request belongs to the calling thread, so the guard has to be dropped on the same thread.
+#[cfg(all(windows, feature = "gui"))]
+pub fn keep_awake(_reason: &str) -> Option<KeepAwake> {
+  use windows_sys::Win32::System::Power::{
+    ES_CONTINUOUS, ES_SYSTEM_REQUIRED, SetThreadExecutionState,
+  };
+
+  let previous = unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) };
+  if previous == 0 {
+    println!("Failed to keep the computer awake");
+    return None;
+  }
+  Some(KeepAwake {})
+}
+
+/// Stops the computer from going to sleep on its own until the returned guard is dropped, giving
+/// `reason` where the system shows one.
+///
+/// That's a power assertion through `caffeinate` on macOS and a logind inhibitor through
+/// `systemd-inhibit` elsewhere. Either one runs `cat`, which waits on a pipe that closes when the
+/// guard is dropped or the app goes away, so the inhibition can't outlive it. Returns `None` if
+/// there's no way to hold one.
+#[cfg(all(not(windows), feature = "gui"))]
+pub fn keep_awake(reason: &str) -> Option<KeepAwake> {
+  let mut command = if cfg!(target_os = "macos") {
+    let mut command = std::process::Command::new("caffeinate");
+    command.args(["-i", "cat"]);
+    command
+  } else {
+    let mut command = std::process::Command::new("systemd-inhibit");
+    command
+      .args(["--what=idle:sleep", "--mode=block", "--who=otterpack"])
+      .arg(format!("--why={reason}"))
+      .arg("cat");
+    command
+  };
+  let child = command
+    .stdin(std::process::Stdio::piped())
+    .stdout(std::process::Stdio::null())
+    .stderr(std::process::Stdio::null())
+    .spawn()
+    .inspect_err(|e| println!("Failed to keep the computer awake: {e}"))
+    .ok()?;
+  Some(KeepAwake { child })
+}
+
+#[cfg(feature = "gui")]
+impl Drop for KeepAwake {
+  fn drop(&mut self) {
+    #[cfg(windows)]
+    {
+      use windows_sys::Win32::System::Power::{ES_CONTINUOUS, SetThreadExecutionState};
+
+      unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+    }
+
+    #[cfg(not(windows))]
+    {
+      // Closing the pipe ends cat, and the inhibition with it
+      drop(self.child.stdin.take());
+      let _ = self.child.kill();
+      let _ = self.child.wait();
+    }
+  }
+}
+
+/// The user's locale, such as `de-DE`, if the system says.
+#[cfg(all(windows, feature = "gui"))]
+pub fn user_locale() -> Option<String> {
+  use windows_sys::Win32::Globalization::GetUserDefaultLocaleName;
+
+  // LOCALE_NAME_MAX_LENGTH
+  let mut buffer = [0u16; 85];
+  let len = unsafe { GetUserDefaultLocaleName(buffer.as_mut_ptr(), buffer.len() as i32) };
+  // The length includes the terminating null
+  (len > 1).then(|| String::from_utf16_lossy(&buffer[..len as usize - 1]))
+}
+
+/// The user's locale, such as `de_DE.UTF-8`, if the system says.
+///
+/// Apps started from the Dock or Finder on macOS don't get `LANG`, so the system preference is
+/// read there when it isn't set.
+#[cfg(all(not(windows), feature = "gui"))]
+pub fn user_locale() -> Option<String> {
+  let from_env = ["LC_ALL", "LC_MESSAGES", "LANG"]
+    .into_iter()
+    .filter_map(|var| std::env::var(var).ok())
+    .find(|locale| !locale.is_empty() && locale != "C" && locale != "POSIX");
+  if from_env.is_some() || !cfg!(target_os = "macos") {
+    return from_env;
+  }
+  let output = std::process::Command::new("defaults")
+    .args(["read", "-g", "AppleLocale"])
+    .stdin(std::process::Stdio::null())
+    .stderr(std::process::Stdio::null())
+    .output()
+    .ok()?;
+  let locale = String::from_utf8_lossy(&output.stdout).trim().to_string();
+  (output.status.success() && !locale.is_empty()).then_some(locale)
+}
+
+/// Points stdout at stderr from now on, returning where stdout went before, so output meant for
+/// another program can be written there without anything else that's printed getting mixed in.
+#[cfg(unix)]
+pub fn take_stdout() -> std::io::Result<std::fs::File> {
+  use std::{io::Write, os::fd::FromRawFd};
+
+  let _ = std::io::stdout().flush();
+  let fd = unsafe { libc::dup(libc::STDOUT_FILENO) };
+  if fd < 0 {
+    return Err(std::io::Error::last_os_error());
+  }
+  if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+    let error = std::io::Error::last_os_error();
+    unsafe { libc::close(fd) };
+    return Err(error);
+  }
+  Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+}
+
+/// Points stdout at stderr from now on, returning where stdout went before, so output meant for
+/// another program can be written there without anything else that's printed getting mixed in.
+///
+/// The standard library looks the handle up on every write, so swapping it is enough.
+#[cfg(windows)]
+pub fn take_stdout() -> std::io::Result<std::fs::File> {
+  use std::{io::Write, os::windows::io::FromRawHandle};
+  use windows_sys::Win32::{
+    Foundation::INVALID_HANDLE_VALUE,
+    System::Console::{GetStdHandle, STD_ERROR_HANDLE, STD_OUTPUT_HANDLE, SetStdHandle},
+  };
+
+  let _ = std::io::stdout().flush();
+  let stdout = unsafe { GetStdHandle(STD_OUTPUT_HANDLE) };
+  if stdout.is_null() || stdout == INVALID_HANDLE_VALUE {
+    return Err(std::io::Error::new(
+      std::io::ErrorKind::NotFound,
+      "There's no stdout to write to",
+    ));
+  }
+  if unsafe { SetStdHandle(STD_OUTPUT_HANDLE, GetStdHandle(STD_ERROR_HANDLE)) } == 0 {
+    return Err(std::io::Error::last_os_error());
+  }
+  Ok(unsafe { std::fs::File::from_raw_handle(stdout) })
+}
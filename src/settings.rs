@@ -0,0 +1,286 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+};
+use strum::EnumIter;
+
+use crate::{
+  AudioFormat, CompletionHook, Leveling, ProcessOptions,
+  config::CONFIG_DIR_NAME,
+  i18n::{Language, t},
+};
+
+const SETTINGS_FILE_NAME: &str = "settings.json";
+/// How many output folders are remembered for picking again.
+pub const MAX_RECENT_OUTPUTS: usize = 8;
+/// The sizes the window's contents can be scaled to, on top of the system's own scaling.
+pub const UI_SCALES: [f32; 8] = [0.75, 0.9, 1.0, 1.1, 1.25, 1.5, 1.75, 2.0];
+
+/// Which colours the window uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum Theme {
+  /// Follow the desktop's light or dark mode, falling back to dark if it can't be told.
+  #[default]
+  System,
+  Dark,
+  Light,
+}
+
+impl Theme {
+  pub fn display_name(&self) -> &'static str {
+    match self {
+      Theme::System => "Same as the system",
+      Theme::Dark => "Dark",
+      Theme::Light => "Light",
+    }
+  }
+}
+
+/// How the options are laid out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, EnumIter, Serialize, Deserialize)]
+pub enum OptionsLayout {
+  /// Every option on one screen.
+  #[default]
+  Simple,
+  /// The options a few at a time, ending on a summary to check before starting.
+  Guided,
+}
+
+impl OptionsLayout {
+  pub fn display_name(&self) -> &'static str {
+    match self {
+      OptionsLayout::Simple => "Simple",
+      OptionsLayout::Guided => "Guided",
+    }
+  }
+}
+
+/// Options saved under a name, to switch to in one go.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+  pub name: String,
+  pub options: ProcessOptions,
+}
+
+impl Preset {
+  /// `options` switched over to the preset's, apart from what's about this computer or this
+  /// recording, like the folder to unpack into.
+  pub fn apply_to(&self, options: &ProcessOptions) -> ProcessOptions {
+    ProcessOptions {
+      bundled_ffmpeg_only: options.bundled_ffmpeg_only,
+      work_dir: options.work_dir.clone(),
+      keep_extracted: options.keep_extracted,
+      extract_limits: options.extract_limits,
+      password: options.password.clone(),
+      tracks: options.tracks.clone(),
+      ..self.options.clone()
+    }
+  }
+}
+
+/// The presets that come with the app, named in the current language. They can't be changed or
+/// deleted, only duplicated.
+pub fn builtin_presets() -> Vec<Preset> {
+  vec![
+    Preset {
+      name: t!("Separate tracks for editing").to_string(),
+      options: ProcessOptions::default(),
+    },
+    Preset {
+      name: t!("One mixed file for sharing").to_string(),
+      options: ProcessOptions {
+        format: AudioFormat::AAC,
+        mix: true,
+        leveling: Leveling::Dynaudnorm,
+        ..Default::default()
+      },
+    },
+  ]
+}
+
+/// Reads the saved presets one by one, leaving out any this version can't read, like one with a
+/// format from a newer version, rather than losing every other setting over it.
+fn lenient_presets<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Preset>, D::Error> {
+  let presets = Vec::<serde_json::Value>::deserialize(deserializer)?;
+  Ok(
+    presets
+      .into_iter()
+      .filter_map(|preset| {
+        serde_json::from_value(preset)
+          .inspect_err(|e| println!("Leaving out a preset that can't be read: {}", e))
+          .ok()
+      })
+      .collect(),
+  )
+}
+
+/// What the app remembers between runs.
+///
+/// Anything missing from the file falls back to its default, so settings saved by an older version
+/// still load.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+  pub options: ProcessOptions,
+  /// The output folder the user picked, or `None` to use the default next to the executable.
+  pub output_path: Option<PathBuf>,
+  /// Whether to show a desktop notification when a run ends while the window isn't focused.
+  pub notifications: bool,
+  /// Whether the computer may go to sleep on its own in the middle of a run.
+  pub allow_sleep: bool,
+  /// Whether closing the window during a run minimizes it instead, leaving the run going.
+  pub minimize_while_processing: bool,
+  /// Command to run when a run or the queue finishes.
+  pub on_complete: CompletionHook,
+  /// Discord webhook to post a summary to when a run or the queue finishes, or empty for none.
+  pub webhook_url: String,
+  pub theme: Theme,
+  pub layout: OptionsLayout,
+  /// The language the window is shown in, or `None` to follow the system's.
+  pub language: Option<Language>,
+  /// How much bigger than normal everything in the window is drawn, one of [`UI_SCALES`].
+  pub ui_scale: f32,
+  /// Output folders runs finished in, most recent first.
+  pub recent_outputs: Vec<PathBuf>,
+  /// The presets the user saved, on top of [`builtin_presets`].
+  #[serde(deserialize_with = "lenient_presets")]
+  pub presets: Vec<Preset>,
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Self {
+      options: ProcessOptions::default(),
+      output_path: None,
+      notifications: true,
+      allow_sleep: false,
+      minimize_while_processing: true,
+      on_complete: CompletionHook::default(),
+      webhook_url: String::new(),
+      theme: Theme::default(),
+      layout: OptionsLayout::default(),
+      language: None,
+      ui_scale: 1.0,
+      recent_outputs: Vec::new(),
+      presets: Vec::new(),
+    }
+  }
+}
+
+/// Where the settings are saved, if the platform has a config directory.
+pub fn settings_path() -> Option<PathBuf> {
+  dirs::config_dir().map(|dir| dir.join(CONFIG_DIR_NAME).join(SETTINGS_FILE_NAME))
+}
+
+/// Loads the saved settings, falling back to the defaults if there are none or they can't be read.
+///
+/// Recent output folders that have since been deleted are left out.
+pub fn load() -> Settings {
+  let mut settings: Settings = settings_path()
+    .and_then(|path| load_from(&path))
+    .unwrap_or_default();
+  settings.recent_outputs.retain(|path| path.is_dir());
+  settings.ui_scale = nearest_ui_scale(settings.ui_scale);
+  settings
+}
+
+/// The entry in [`UI_SCALES`] closest to `scale`, so a hand-edited file can't make the window
+/// unusably small or huge.
+pub fn nearest_ui_scale(scale: f32) -> f32 {
+  UI_SCALES
+    .into_iter()
+    .min_by(|a, b| (a - scale).abs().total_cmp(&(b - scale).abs()))
+    .unwrap_or(1.0)
+}
+
+/// Moves `path` to the front of the recent output folders, dropping the oldest past
+/// [`MAX_RECENT_OUTPUTS`].
+pub fn remember_output(recent: &mut Vec<PathBuf>, path: &Path) {
+  recent.retain(|recent| recent != path);
+  recent.insert(0, path.to_path_buf());
+  recent.truncate(MAX_RECENT_OUTPUTS);
+}
+
+fn load_from(path: &Path) -> Option<Settings> {
+  let data = match fs::read(path) {
+    Ok(data) => data,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+    Err(e) => {
+      println!("Failed to read the settings, using the defaults: {}", e);
+      return None;
+    }
+  };
+  serde_json::from_slice(&data)
+    .inspect_err(|e| println!("The saved settings are damaged, using the defaults: {}", e))
+    .ok()
+}
+
+/// Saves `settings` for the next run.
+///
+/// The file is replaced in one go, so a crash while saving leaves the previous settings intact.
+pub fn save(settings: &Settings) -> io::Result<()> {
+  let Some(path) = settings_path() else {
+    return Ok(());
+  };
+  let dir = path.parent().unwrap_or(Path::new("."));
+  fs::create_dir_all(dir)?;
+  let mut file = tempfile::Builder::new()
+    .prefix(".settings-")
+    .tempfile_in(dir)?;
+  serde_json::to_writer_pretty(&mut file, settings)?;
+  file.persist(&path)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn unreadable_presets_are_left_out_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(SETTINGS_FILE_NAME);
+    fs::write(
+      &path,
+      r#"{
+        "theme": "Light",
+        "webhook_url": "https://discord.com/api/webhooks/1/abc",
+        "presets": [
+          { "name": "Stems", "options": { "format": "flac", "a_newer_option": true } },
+          { "name": "From a newer version", "options": { "format": "opus" } },
+          { "name": "Small", "icon": "📦", "options": { "format": "aac", "mix": true } },
+          { "options": { "format": "wav" } }
+        ],
+        "a_newer_setting": 3
+      }"#,
+    )
+    .unwrap();
+
+    let settings = load_from(&path).expect("the settings are readable");
+
+    assert_eq!(
+      settings.presets,
+      [
+        Preset {
+          name: "Stems".to_string(),
+          options: ProcessOptions::default(),
+        },
+        Preset {
+          name: "Small".to_string(),
+          options: ProcessOptions {
+            format: AudioFormat::AAC,
+            mix: true,
+            ..Default::default()
+          },
+        },
+      ]
+    );
+    assert_eq!(settings.theme, Theme::Light);
+    assert_eq!(
+      settings.webhook_url,
+      "https://discord.com/api/webhooks/1/abc"
+    );
+    assert_eq!(settings.options, ProcessOptions::default());
+  }
+}
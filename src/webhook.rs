@@ -0,0 +1,346 @@
+use anyhow::Context;
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::{
+  ProcessReport, RunStatus, format_bytes, format_track_length,
+  i18n::{t, t_n},
+};
+
+/// How long a webhook gets to take a message before it's given up on.
+const SEND_TIMEOUT: Duration = Duration::from_secs(15);
+/// The most fields Discord shows on an embed.
+const MAX_FIELDS: usize = 25;
+/// The longest a field's value can be on Discord.
+const MAX_FIELD_VALUE: usize = 1024;
+
+/// A message for a Discord webhook, with the parts of one that otterpack fills in.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WebhookMessage {
+  /// The name the message is posted under, instead of the one the webhook was set up with.
+  pub username: String,
+  pub embeds: Vec<Embed>,
+}
+
+/// A box in a Discord message with a coloured edge.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Embed {
+  pub title: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+  /// The colour of the edge as `0xRRGGBB`.
+  pub color: u32,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub fields: Vec<EmbedField>,
+}
+
+/// A heading with a value under it in an [`Embed`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EmbedField {
+  pub name: String,
+  pub value: String,
+  /// Whether the field may sit next to others on the same row.
+  pub inline: bool,
+}
+
+impl EmbedField {
+  fn new(name: impl Into<String>, value: impl Into<String>, inline: bool) -> Self {
+    let mut value = value.into();
+    if value.chars().count() > MAX_FIELD_VALUE {
+      value = value.chars().take(MAX_FIELD_VALUE - 1).collect();
+      value.push('…');
+    }
+    Self {
+      name: name.into(),
+      value,
+      inline,
+    }
+  }
+}
+
+/// Discord's own green, yellow and red, for how a run went.
+fn status_color(status: RunStatus) -> u32 {
+  match status {
+    RunStatus::Success => 0x57f287,
+    RunStatus::Partial => 0xfee75c,
+    RunStatus::Failed => 0xed4245,
+  }
+}
+
+impl WebhookMessage {
+  fn new(embed: Embed) -> Self {
+    Self {
+      username: crate::APP_TITLE.to_string(),
+      embeds: vec![embed],
+    }
+  }
+
+  /// A message about a run of `recording` that got to the end with `report`.
+  ///
+  /// `length_secs` is how long the recording is, if that's known, and `output_size` how much the
+  /// outputs take up.
+  pub fn finished(
+    recording: &str,
+    title: String,
+    report: &ProcessReport,
+    length_secs: Option<f64>,
+    output_size: u64,
+  ) -> Self {
+    let mut fields = vec![
+      EmbedField::new(
+        t!("Converted"),
+        t!(
+          "{done} of {total}",
+          done = report.converted.len() + report.skipped.len(),
+          total = report.total()
+        ),
+        true,
+      ),
+      EmbedField::new(t!("Output size"), format_bytes(output_size), true),
+    ];
+    if let Some(length) = length_secs {
+      fields.insert(
+        1,
+        EmbedField::new(t!("Length"), format_track_length(length), true),
+      );
+    }
+    if !report.failed.is_empty() {
+      let failures: Vec<String> = report
+        .failed
+        .iter()
+        .map(|failure| format!("{}: {}", failure.filename, failure.message))
+        .collect();
+      fields.push(EmbedField::new(t!("Failed"), failures.join("\n"), false));
+    }
+    Self::new(Embed {
+      title,
+      description: Some(recording.to_string()),
+      color: status_color(RunStatus::for_report(report)),
+      fields,
+    })
+  }
+
+  /// A message about a run of `recording` that stopped with `error`.
+  pub fn failed(recording: &str, error: &str) -> Self {
+    Self::new(Embed {
+      title: t!("Failed to process {name}", name = recording),
+      description: None,
+      color: status_color(RunStatus::Failed),
+      fields: vec![EmbedField::new(t!("Error"), error, false)],
+    })
+  }
+
+  /// A message about a queue that got through `results`, each a recording with its report or
+  /// why it failed.
+  pub fn queue_finished<'a>(
+    results: impl IntoIterator<Item = (&'a str, Result<&'a ProcessReport, &'a str>)>,
+  ) -> Self {
+    let results: Vec<_> = results.into_iter().collect();
+    let status = RunStatus::combine(results.iter().map(|(_, outcome)| match outcome {
+      Ok(report) => RunStatus::for_report(report),
+      Err(_) => RunStatus::Failed,
+    }));
+    let fields = results
+      .iter()
+      .take(MAX_FIELDS)
+      .map(|(name, outcome)| {
+        let value = match outcome {
+          Ok(report) => t_n!(
+            report.total(),
+            "Converted {done} of 1 track",
+            "Converted {done} of {n} tracks",
+            done = report.converted.len() + report.skipped.len()
+          ),
+          Err(error) => t!("Failed: {error}", error = error),
+        };
+        EmbedField::new(*name, value, false)
+      })
+      .collect();
+    Self::new(Embed {
+      title: t_n!(
+        results.len(),
+        "Finished 1 recording",
+        "Finished {n} recordings"
+      ),
+      description: None,
+      color: status_color(status),
+      fields,
+    })
+  }
+
+  /// A message to check the webhook works with.
+  pub fn test() -> Self {
+    Self::new(Embed {
+      title: t!("Test notification").to_string(),
+      description: Some(t!("Finished recordings will show up here").to_string()),
+      color: status_color(RunStatus::Success),
+      fields: Vec::new(),
+    })
+  }
+}
+
+/// Posts `message` to the Discord webhook at `url`.
+///
+/// The URL holds the webhook's token, so it's left out of the error.
+pub async fn send_webhook(url: &str, message: &WebhookMessage) -> anyhow::Result<()> {
+  let client = reqwest::Client::builder()
+    .timeout(SEND_TIMEOUT)
+    .build()
+    .context("Failed to set up the connection")?;
+  client
+    .post(url)
+    .json(message)
+    .send()
+    .await
+    .and_then(reqwest::Response::error_for_status)
+    .map_err(reqwest::Error::without_url)
+    .context("Failed to send the message to the webhook")?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+  use crate::TrackFailure;
+
+  fn report(converted: &[&str], failed: &[(&str, &str)]) -> ProcessReport {
+    ProcessReport {
+      converted: converted.iter().map(|name| name.to_string()).collect(),
+      failed: failed
+        .iter()
+        .map(|(filename, message)| TrackFailure {
+          filename: filename.to_string(),
+          message: message.to_string(),
+          stderr: None,
+        })
+        .collect(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn finished_runs_are_summed_up() {
+    let report = report(&["1-alice.flac", "2-bob.flac"], &[]);
+    let message = WebhookMessage::finished(
+      "craig-abc123",
+      "Processed craig-abc123".to_string(),
+      &report,
+      Some(3723.0),
+      2048,
+    );
+    assert_eq!(
+      serde_json::to_value(&message).unwrap(),
+      json!({
+        "username": crate::APP_TITLE,
+        "embeds": [{
+          "title": "Processed craig-abc123",
+          "description": "craig-abc123",
+          "color": 0x57f287,
+          "fields": [
+            {"name": "Converted", "value": "2 of 2", "inline": true},
+            {"name": "Length", "value": "1:02:03", "inline": true},
+            {"name": "Output size", "value": "2.0 KB", "inline": true},
+          ],
+        }],
+      })
+    );
+  }
+
+  #[test]
+  fn failures_are_listed_and_cut_to_fit() {
+    let long_error = "ö".repeat(MAX_FIELD_VALUE * 2);
+    let report = report(
+      &["1-alice.flac"],
+      &[
+        ("2-bob.flac", "ffmpeg failed"),
+        ("3-carol.flac", &long_error),
+      ],
+    );
+    let message = WebhookMessage::finished(
+      "craig-abc123",
+      "Processed craig-abc123".to_string(),
+      &report,
+      None,
+      0,
+    );
+    let embed = &message.embeds[0];
+    assert_eq!(embed.color, 0xfee75c);
+    let names: Vec<&str> = embed
+      .fields
+      .iter()
+      .map(|field| field.name.as_str())
+      .collect();
+    assert_eq!(names, ["Converted", "Output size", "Failed"]);
+    assert_eq!(embed.fields[0].value, "1 of 3");
+
+    let failed = &embed.fields[2];
+    assert!(!failed.inline);
+    assert!(
+      failed
+        .value
+        .starts_with("2-bob.flac: ffmpeg failed\n3-carol.flac: ööö")
+    );
+    // Counted in characters, like Discord does, not bytes
+    assert_eq!(failed.value.chars().count(), MAX_FIELD_VALUE);
+    assert!(failed.value.ends_with("ö…"));
+
+    let exact = EmbedField::new("Error", "x".repeat(MAX_FIELD_VALUE), false);
+    assert_eq!(exact.value, "x".repeat(MAX_FIELD_VALUE));
+  }
+
+  #[test]
+  fn failed_runs_carry_the_error() {
+    let message = WebhookMessage::failed("craig-abc123", "No space left on device");
+    assert_eq!(
+      serde_json::to_value(&message).unwrap(),
+      json!({
+        "username": crate::APP_TITLE,
+        "embeds": [{
+          "title": "Failed to process craig-abc123",
+          "color": 0xed4245,
+          "fields": [
+            {"name": "Error", "value": "No space left on device", "inline": false},
+          ],
+        }],
+      })
+    );
+  }
+
+  #[test]
+  fn queues_list_as_many_recordings_as_fit() {
+    let reports: Vec<(String, ProcessReport)> = (1..=30)
+      .map(|i| (format!("craig-{i}"), report(&["1-alice.flac"], &[])))
+      .collect();
+    let mut results: Vec<(&str, Result<&ProcessReport, &str>)> = reports
+      .iter()
+      .map(|(name, report)| (name.as_str(), Ok(report)))
+      .collect();
+    results.push(("craig-31", Err("The file is not a ZIP")));
+
+    let message = WebhookMessage::queue_finished(results);
+    let embed = &message.embeds[0];
+    assert_eq!(embed.title, "Finished 31 recordings");
+    assert_eq!(embed.color, 0xfee75c);
+    assert_eq!(embed.fields.len(), MAX_FIELDS);
+    assert_eq!(embed.fields[0].name, "craig-1");
+    assert_eq!(embed.fields[0].value, "Converted 1 of 1 track");
+    assert_eq!(embed.fields[MAX_FIELDS - 1].name, "craig-25");
+
+    let message = WebhookMessage::queue_finished([(
+      "craig-1",
+      Err::<&ProcessReport, _>("The file is not a ZIP"),
+    )]);
+    let embed = &message.embeds[0];
+    assert_eq!(embed.title, "Finished 1 recording");
+    assert_eq!(embed.color, 0xed4245);
+    assert_eq!(embed.fields[0].value, "Failed: The file is not a ZIP");
+  }
+
+  #[test]
+  fn test_messages_have_no_fields() {
+    let value = serde_json::to_value(WebhookMessage::test()).unwrap();
+    assert!(value["embeds"][0].get("fields").is_none());
+  }
+}